@@ -0,0 +1,13 @@
+//! Generates the gRPC service `serve --grpc` offers from proto/tasks.proto,
+//! with the protoc that ships in `protoc-bin-vendored` so building needs no
+//! system install.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = tonic_prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+    config.btree_map(["."]);
+    tonic_prost_build::configure()
+        .build_transport(false)
+        .compile_with_config(config, &["proto/tasks.proto"], &["proto"])?;
+    Ok(())
+}
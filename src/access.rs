@@ -0,0 +1,93 @@
+//! Who may see and change which tasks when several people share one server.
+//!
+//! A users file lists each person's token and what they may do per project:
+//!
+//! ```json
+//! { "users": [
+//!     { "name": "me", "token": "...", "projects": { "*": "write" } },
+//!     { "name": "sam", "token": "...", "projects": { "household": "write" } }
+//! ] }
+//! ```
+//!
+//! `"*"` covers every project not named explicitly, including tasks with no
+//! project at all.
+
+use crate::task::Task;
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+}
+
+#[derive(Deserialize)]
+pub struct User {
+    pub name: String,
+    pub token: String,
+    #[serde(default)]
+    pub projects: BTreeMap<String, Permission>,
+}
+
+#[derive(Deserialize)]
+struct UsersFile {
+    users: Vec<User>,
+}
+
+/// What an authenticated request may touch.
+pub enum Access<'a> {
+    /// The single `--token` holder owns everything.
+    Full,
+    Scoped(&'a User),
+}
+
+impl Access<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            Access::Full => "owner",
+            Access::Scoped(user) => &user.name,
+        }
+    }
+
+    fn permission(&self, project: Option<&str>) -> Option<Permission> {
+        match self {
+            Access::Full => Some(Permission::Write),
+            Access::Scoped(user) => project
+                .and_then(|p| user.projects.get(p))
+                .or_else(|| user.projects.get("*"))
+                .copied(),
+        }
+    }
+
+    pub fn can_read(&self, task: &Task) -> bool {
+        self.permission(task.project.as_deref()).is_some()
+    }
+
+    pub fn can_write(&self, task: &Task) -> bool {
+        self.permission(task.project.as_deref()) == Some(Permission::Write)
+    }
+}
+
+pub fn load_users(path: &Path) -> anyhow::Result<Vec<User>> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read users file at {}", path.display()))?;
+    let file: UsersFile = serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse users file at {}", path.display()))?;
+
+    for (i, user) in file.users.iter().enumerate() {
+        if user.token.is_empty() {
+            bail!(
+                "User {} in {} has an empty token",
+                user.name,
+                path.display()
+            );
+        }
+        if file.users[..i].iter().any(|u| u.token == user.token) {
+            bail!("Users in {} must have distinct tokens", path.display());
+        }
+    }
+    Ok(file.users)
+}
@@ -0,0 +1,59 @@
+//! Priority aging: open tasks become more urgent as their due date nears.
+//!
+//! Rules are set as `aging` in config.json and applied whenever tasks are
+//! listed; the stored priority is never rewritten.
+//!
+//! ```json
+//! { "aging": [
+//!     { "within_days": 0, "bump": 2 },
+//!     { "project": "work", "within_days": 3 }
+//! ] }
+//! ```
+//!
+//! A rule matches an open task due in `within_days` days or fewer (overdue
+//! tasks always qualify), optionally only in one project, and raises its
+//! priority by `bump` levels (default 1). The bumps of every matching rule
+//! add up; a task with no priority starts from `low`.
+
+use crate::task::{Priority, Task};
+use chrono::Local;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default)]
+    pub project: Option<String>,
+    pub within_days: i64,
+    #[serde(default = "one")]
+    pub bump: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// Raises each matching task's in-memory priority; callers must not save
+/// the result.
+pub fn apply(rules: &[Rule], tasks: &mut [Task]) {
+    let today = Local::now().date_naive();
+    for task in tasks.iter_mut().filter(|t| !t.completed) {
+        let Some(due) = task.due else { continue };
+        let days = (due - today).num_days();
+        let bump: u32 = rules
+            .iter()
+            .filter(|r| days <= r.within_days)
+            .filter(|r| {
+                r.project.as_deref().is_none_or(|p| {
+                    task.project
+                        .as_deref()
+                        .is_some_and(|tp| tp.eq_ignore_ascii_case(p))
+                })
+            })
+            .map(|r| r.bump)
+            .sum();
+        if bump > 0 {
+            task.priority = Some(task.priority.unwrap_or(Priority::Low).raised(bump));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Suggestions for `clarify --ai` (also `triage --ai`) from a language model
+//! behind an OpenAI-style chat completions API, set in config.json:
+//!
+//! ```json
+//! {
+//!   "ai": { "url": "http://localhost:11434/v1", "model": "llama3.1" }
+//! }
+//! ```
+//!
+//! The API key, if the endpoint wants one, comes from
+//! `CLI_TASK_MANAGER_AI_KEY` or `auth set ai`. The URL may be `http://`, which
+//! suits a model served on this machine, or `https://` for a hosted one.
+//!
+//! The model sees the inbox items, masked by the redaction rules, and the
+//! projects already in use. What it suggests is only shown; each item is
+//! still accepted, changed, or passed over by hand.
+
+use crate::{
+    http::Endpoint,
+    keyring, redact,
+    task::{Priority, Task},
+};
+use anyhow::{Context, bail};
+use chrono::{Local, NaiveDate};
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+
+const INSTRUCTIONS: &str = "You help triage a to-do inbox. For each item, suggest a priority \
+(low, medium, high or urgent), a due date (YYYY-MM-DD) only if the item implies one, and a \
+project, reusing an existing project where one fits and giving related items the same project. \
+Answer with JSON only: {\"suggestions\": [{\"id\": 1, \"priority\": \"high\", \"due\": \
+\"2026-01-31\", \"project\": \"home\", \"reason\": \"a few words\"}]}. Leave out fields you \
+have no suggestion for.";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// The API's base URL, to which `/chat/completions` is added.
+    url: String,
+    model: String,
+}
+
+#[derive(Default)]
+pub struct Suggestion {
+    pub priority: Option<Priority>,
+    pub due: Option<NaiveDate>,
+    pub project: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl Suggestion {
+    pub fn is_empty(&self) -> bool {
+        self.priority.is_none() && self.due.is_none() && self.project.is_none()
+    }
+
+    /// The suggestion in a line, as `clarify` shows it.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(project) = &self.project {
+            parts.push(format!("project: {}", project));
+        }
+        if let Some(priority) = self.priority {
+            parts.push(format!("priority: {}", priority.name()));
+        }
+        if let Some(due) = self.due {
+            parts.push(format!("due {}", due));
+        }
+        let mut line = parts.join(", ");
+        if let Some(reason) = &self.reason {
+            line.push_str(&format!(" ({})", reason));
+        }
+        line
+    }
+}
+
+fn key() -> anyhow::Result<Option<String>> {
+    if let Ok(key) = std::env::var("CLI_TASK_MANAGER_AI_KEY")
+        && !key.is_empty()
+    {
+        return Ok(Some(key));
+    }
+    keyring::get(keyring::Service::Ai, "default")
+}
+
+/// Asks the model about the tasks with `ids`, returning its suggestions by
+/// task id. Suggestions it gets wrong, such as a date that is no date or a
+/// task that does not exist, are dropped.
+pub fn suggest(
+    settings: &Settings,
+    tasks: &[Task],
+    ids: &[u32],
+    redact: &redact::Rules,
+) -> anyhow::Result<HashMap<u32, Suggestion>> {
+    let items: Vec<Value> = tasks
+        .iter()
+        .filter(|t| ids.contains(&t.id))
+        .map(|t| json!({ "id": t.id, "description": redact.mask(&t.description) }))
+        .collect();
+    let projects: BTreeSet<&str> = tasks.iter().filter_map(|t| t.project.as_deref()).collect();
+    let question = json!({
+        "today": Local::now().date_naive().to_string(),
+        "existing_projects": projects,
+        "items": items,
+    });
+    let body = json!({
+        "model": settings.model,
+        "messages": [
+            { "role": "system", "content": INSTRUCTIONS },
+            { "role": "user", "content": question.to_string() },
+        ],
+        "response_format": { "type": "json_object" },
+    });
+
+    let endpoint = Endpoint::parse(&settings.url)?;
+    let body = serde_json::to_vec(&body).context("Failed to serialize the request")?;
+    let key = key()?.unwrap_or_default();
+    let response = endpoint.send("POST", "/chat/completions", &key, Some(&body))?;
+    if response.status != 200 {
+        bail!(
+            "{} answered {}: {}",
+            settings.url,
+            response.status,
+            String::from_utf8_lossy(&response.body).trim()
+        );
+    }
+    let reply: Value = serde_json::from_slice(&response.body)
+        .with_context(|| format!("Malformed answer from {}", settings.url))?;
+    let content = reply["choices"][0]["message"]["content"]
+        .as_str()
+        .with_context(|| format!("No message in the answer from {}", settings.url))?;
+    // Models asked for JSON still like to fence it.
+    let content = content
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```");
+    let answer: Value = serde_json::from_str(content)
+        .with_context(|| format!("{} did not answer with JSON", settings.model))?;
+
+    let mut suggestions = HashMap::new();
+    for item in answer["suggestions"].as_array().into_iter().flatten() {
+        let Some(id) = item["id"].as_u64().map(|id| id as u32) else {
+            continue;
+        };
+        if !ids.contains(&id) {
+            continue;
+        }
+        let text = |field: &str| {
+            item[field]
+                .as_str()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+        };
+        let suggestion = Suggestion {
+            priority: text("priority").and_then(|p| Priority::from_str(&p, true).ok()),
+            due: text("due").and_then(|d| d.parse().ok()),
+            project: text("project"),
+            reason: text("reason"),
+        };
+        if !suggestion.is_empty() {
+            suggestions.insert(id, suggestion);
+        }
+    }
+    Ok(suggestions)
+}
@@ -0,0 +1,95 @@
+//! Keeping the tasks file small: completed tasks older than a cutoff move
+//! into one file per month of completion, `archive-2025-01.json` beside the
+//! tasks file, in the same format.
+//!
+//! With `archive_after_days` in config.json this happens before every
+//! command. Archived tasks keep their ids, which new tasks may reuse, and
+//! are no longer synced; a replica that has not archived them yet sends
+//! them back, and the next rotation files them away again.
+
+use crate::task::{self, Task};
+use anyhow::{Context, bail};
+use chrono::{Duration, Local, NaiveDate, Utc};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+fn archive_path(tasks_path: &Path, month: &str) -> PathBuf {
+    tasks_path.with_file_name(format!("archive-{}.json", month))
+}
+
+/// Accepts a month as `YYYY-MM`.
+pub fn parse_month(input: &str) -> anyhow::Result<String> {
+    let first = NaiveDate::parse_from_str(&format!("{}-01", input.trim()), "%Y-%m-%d")
+        .with_context(|| format!("Invalid month '{}' (use YYYY-MM)", input))?;
+    Ok(first.format("%Y-%m").to_string())
+}
+
+/// Moves completed tasks finished more than `days` ago into their month's
+/// archive. The archives are written first, so if saving the tasks file
+/// fails afterwards a task is in both rather than in neither. Returns the
+/// number of tasks moved.
+pub fn rotate(tasks_path: &Path, tasks: &mut Vec<Task>, days: u32) -> anyhow::Result<usize> {
+    let cutoff = Utc::now() - Duration::days(days.into());
+    let old = |t: &Task| t.completed && t.completed_at.is_some_and(|at| at < cutoff);
+    if !tasks.iter().any(old) {
+        return Ok(0);
+    }
+
+    let mut months: BTreeMap<String, Vec<Task>> = BTreeMap::new();
+    let (moved, kept): (Vec<Task>, Vec<Task>) = tasks.drain(..).partition(old);
+    *tasks = kept;
+    let count = moved.len();
+    for task in moved {
+        let month = task
+            .completed_at
+            .expect("only tasks with a completion time are moved")
+            .with_timezone(&Local)
+            .format("%Y-%m")
+            .to_string();
+        months.entry(month).or_default().push(task);
+    }
+    for (month, moved) in months {
+        let path = archive_path(tasks_path, &month);
+        let mut archived = task::load_tasks(&path)?;
+        // A task archived before and synced back replaces its old copy.
+        archived.retain(|a| !moved.iter().any(|m| m.uuid == a.uuid));
+        archived.extend(moved);
+        archived.sort_by_key(|t| t.completed_at);
+        task::save_tasks(&path, &archived)?;
+    }
+    Ok(count)
+}
+
+/// The archived tasks of `month`, or of every month.
+pub fn load_archived(tasks_path: &Path, month: Option<&str>) -> anyhow::Result<Vec<Task>> {
+    if let Some(month) = month {
+        let path = archive_path(tasks_path, month);
+        if !path.exists() {
+            bail!("Nothing archived for {}", month);
+        }
+        return task::load_tasks(&path);
+    }
+
+    let Some(dir) = tasks_path.parent().filter(|d| d.exists()) else {
+        return Ok(Vec::new());
+    };
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix("archive-")?.strip_suffix(".json"))
+                .is_some_and(|m| parse_month(m).is_ok())
+        })
+        .collect();
+    files.sort();
+    let mut tasks = Vec::new();
+    for path in files {
+        tasks.extend(task::load_tasks(&path)?);
+    }
+    Ok(tasks)
+}
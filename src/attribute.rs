@@ -0,0 +1,140 @@
+//! User-defined attributes: extra fields per task, declared in config.json
+//! with a type and optionally the values they may take,
+//!
+//! ```json
+//! {
+//!   "attributes": {
+//!     "customer": { "type": "text", "values": ["acme", "globex"] },
+//!     "billable_hours": { "type": "number" },
+//!     "renewal": { "type": "date" }
+//!   }
+//! }
+//! ```
+//!
+//! then set with `edit <id> --set customer=acme`, listed with `list --where
+//! customer=acme`, and shown as `{attr.customer}` in templates. They are
+//! stored with the task, so exports and sync carry them; a value stays when
+//! its declaration goes, and is only checked again when next set.
+
+use crate::{crdt::Clock, task::Task};
+use anyhow::{Context, bail};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    Text,
+    Number,
+    /// A day, as YYYY-MM-DD
+    Date,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Definition {
+    #[serde(default, rename = "type")]
+    kind: Kind,
+    /// The only values allowed; any value of the type when empty.
+    #[serde(default)]
+    values: Vec<String>,
+}
+
+pub type Definitions = BTreeMap<String, Definition>;
+
+/// Checks the declarations, as loaded from config.json.
+pub fn validate(definitions: &Definitions) -> anyhow::Result<()> {
+    for (name, definition) in definitions {
+        if name.is_empty() || name.contains(['=', ' ', '{', '}']) {
+            bail!(
+                "attribute name {:?} must not be empty or hold =, spaces or braces",
+                name
+            );
+        }
+        for value in &definition.values {
+            definition
+                .check(value)
+                .with_context(|| format!("Invalid allowed value for attribute {}", name))?;
+        }
+    }
+    Ok(())
+}
+
+impl Definition {
+    /// `value` in its stored form, if it is one this attribute may take.
+    fn check(&self, value: &str) -> anyhow::Result<String> {
+        let value = value.trim();
+        let value = match self.kind {
+            Kind::Text if value.is_empty() => bail!("a value cannot be empty"),
+            Kind::Text => value.to_owned(),
+            Kind::Number => match value.parse::<f64>() {
+                Ok(n) if n.is_finite() => value.to_owned(),
+                _ => bail!("{:?} is not a number", value),
+            },
+            Kind::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .with_context(|| format!("{:?} is not a date (YYYY-MM-DD)", value))?
+                .to_string(),
+        };
+        if !self.values.is_empty() && !self.values.iter().any(|v| v.trim() == value) {
+            bail!("{} is not one of {}", value, self.values.join(", "));
+        }
+        Ok(value)
+    }
+}
+
+/// Splits `name=value`, for `--set` and `--where`.
+pub fn parse_assignment(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_owned(), value.trim().to_owned()))
+        }
+        _ => Err(format!("expected name=value, got {:?}", text)),
+    }
+}
+
+/// Sets and removes attributes on `task`, checking each against its
+/// declaration.
+pub fn edit(
+    task: &mut Task,
+    definitions: &Definitions,
+    set: &[(String, String)],
+    unset: &[String],
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    for (name, value) in set {
+        let Some(definition) = definitions.get(name) else {
+            bail!(
+                "No attribute named {}; declare it under \"attributes\" in config.json",
+                name
+            );
+        };
+        let value = definition
+            .check(value)
+            .with_context(|| format!("Invalid value for {}", name))?;
+        task.attributes.insert(name.clone(), value);
+    }
+    for name in unset {
+        if task.attributes.remove(name).is_none() {
+            bail!("Task {} has no attribute {}", task.id, name);
+        }
+    }
+    task.touch("attributes", clock);
+    Ok(())
+}
+
+/// Whether `task` has every attribute in `wanted` with the given value;
+/// numbers compare by value, so `--where hours=2` matches `2.0`.
+pub fn matches(task: &Task, wanted: &[(String, String)]) -> bool {
+    wanted.iter().all(|(name, value)| {
+        task.attributes.get(name).is_some_and(|actual| {
+            actual.eq_ignore_ascii_case(value)
+                || matches!(
+                    (actual.parse::<f64>(), value.parse::<f64>()),
+                    (Ok(a), Ok(b)) if a == b
+                )
+        })
+    })
+}
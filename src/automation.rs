@@ -0,0 +1,328 @@
+//! If-this-then-that automations, set as `automations` in config.json:
+//!
+//! ```json
+//! { "automations": [
+//!     { "name": "chase payment", "when": "completed", "tag": "finance",
+//!       "then": [{ "add_task": { "description": "Check payment for {description}", "due_in_days": 7 } }] },
+//!     { "name": "escalate", "when": "overdue", "project": "work",
+//!       "then": [{ "set_priority": "urgent" }] },
+//!     { "name": "page", "when": { "tag_added": "urgent" },
+//!       "then": [{ "webhook": "https://hooks.example.com/tasks" }] }
+//! ] }
+//! ```
+//!
+//! `when` is `completed`, `overdue`, or `{ "tag_added": "<tag>" }`; `project` and
+//! `tag` narrow an automation to tasks that have them. The actions are
+//! `add_task` (a follow-up in the same project, where `{description}` and
+//! `{id}` stand for the triggering task's), `set_priority`, and `webhook`,
+//! which POSTs the event and the task as JSON.
+//!
+//! Completions and new tags are noticed when a command changes the list on
+//! this machine, not when a sync brings them in, so a follow-up is only
+//! made once. A task fires `overdue` once per automation, recorded in its
+//! history, the first time any command runs after it falls due.
+
+use crate::{
+    crdt::Clock,
+    http, redact,
+    task::{self, NewTask, Priority, Task},
+};
+use anyhow::{Context, bail};
+use chrono::{Days, Local};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, time::Duration};
+use uuid::Uuid;
+
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Trigger {
+    Completed,
+    Overdue,
+    TagAdded(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum Action {
+    AddTask {
+        description: String,
+        #[serde(default)]
+        due_in_days: Option<u64>,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    SetPriority(Priority),
+    Webhook(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Automation {
+    #[serde(default)]
+    name: Option<String>,
+    when: Trigger,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    tag: Option<String>,
+    then: Vec<Action>,
+}
+
+/// Checks the automations, as loaded from config.json.
+pub fn validate(automations: &[Automation]) -> anyhow::Result<()> {
+    for automation in automations {
+        let tags = automation.tag.iter().chain(match &automation.when {
+            Trigger::TagAdded(tag) => Some(tag),
+            _ => None,
+        });
+        for tag in tags {
+            if task::parse_tag(tag).ok().as_ref() != Some(tag) {
+                bail!("automations: tag {:?} must be one lowercase word", tag);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Automation {
+    fn label(&self, index: usize) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("automation {}", index + 1))
+    }
+
+    fn applies_to(&self, task: &Task) -> bool {
+        self.project.as_ref().is_none_or(|p| {
+            task.project
+                .as_ref()
+                .is_some_and(|tp| tp.eq_ignore_ascii_case(p))
+        }) && self.tag.as_ref().is_none_or(|t| task.tags.contains(t))
+    }
+}
+
+/// Which tasks were completed and what tags each had before a command ran.
+pub struct Snapshot(HashMap<Uuid, (bool, Vec<String>)>);
+
+impl Snapshot {
+    pub fn of(tasks: &[Task]) -> Self {
+        Self(
+            tasks
+                .iter()
+                .map(|t| (t.uuid, (t.completed, t.tags.clone())))
+                .collect(),
+        )
+    }
+
+    /// The events that turned `before` into `tasks`.
+    fn events(&self, tasks: &[Task]) -> Vec<(Uuid, Trigger)> {
+        let mut events = Vec::new();
+        for task in tasks {
+            let (completed, tags) = match self.0.get(&task.uuid) {
+                Some((completed, tags)) => (*completed, tags.as_slice()),
+                None => (false, &[][..]),
+            };
+            if task.completed && !completed {
+                events.push((task.uuid, Trigger::Completed));
+            }
+            for tag in task.tags.iter().filter(|t| !tags.contains(t)) {
+                events.push((task.uuid, Trigger::TagAdded(tag.clone())));
+            }
+        }
+        events
+    }
+}
+
+fn trigger_name(trigger: &Trigger) -> String {
+    match trigger {
+        Trigger::Completed => "completed".to_owned(),
+        Trigger::Overdue => "overdue".to_owned(),
+        Trigger::TagAdded(tag) => format!("tag_added +{}", tag),
+    }
+}
+
+/// POSTs `event` for `task` to `url`.
+fn webhook(url: &str, name: &str, trigger: &Trigger, task: &Task) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(&json!({
+        "automation": name,
+        "event": trigger_name(trigger),
+        "task": task,
+    }))?;
+    let response = http::agent(Duration::from_secs(10))
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(&body[..])
+        .with_context(|| format!("Webhook {} failed", url))?;
+    if response.status().as_u16() >= 400 {
+        bail!("Webhook {} failed: {}", url, response.status());
+    }
+    Ok(())
+}
+
+/// Runs the actions of every automation `trigger` sets off for task `uuid`,
+/// or of automation number `only`. Returns whether the tasks changed.
+fn fire(
+    automations: &[Automation],
+    only: Option<usize>,
+    tasks: &mut Vec<Task>,
+    uuid: Uuid,
+    trigger: &Trigger,
+    redaction: &redact::Rules,
+    clock: &Clock,
+) -> anyhow::Result<bool> {
+    let mut changed = false;
+    for (index, automation) in automations.iter().enumerate() {
+        let Some(task) = tasks.iter().find(|t| t.uuid == uuid) else {
+            break;
+        };
+        if only.is_some_and(|only| only != index)
+            || automation.when != *trigger
+            || !automation.applies_to(task)
+        {
+            continue;
+        }
+        let name = automation.label(index);
+        let (id, description, project) = (task.id, task.description.clone(), task.project.clone());
+        for action in &automation.then {
+            match action {
+                Action::AddTask {
+                    description: template,
+                    due_in_days,
+                    tags,
+                } => {
+                    let text = template
+                        .replace("{description}", &description)
+                        .replace("{id}", &id.to_string());
+                    let details = NewTask {
+                        project: project.clone(),
+                        due: due_in_days.and_then(|days| {
+                            Local::now().date_naive().checked_add_days(Days::new(days))
+                        }),
+                        tags: tags
+                            .iter()
+                            .filter_map(|t| task::parse_tag(t).ok())
+                            .collect(),
+                        ..Default::default()
+                    };
+                    let new_id = task::add_task(tasks, text, details, clock)?;
+                    eprintln!("{}: added task {} after task {}.", name, new_id, id);
+                }
+                Action::SetPriority(priority) => {
+                    task::set_priority(tasks, id, Some(*priority), clock)?;
+                    eprintln!("{}: task {} is now {}.", name, id, priority.name());
+                }
+                Action::Webhook(url) => {
+                    let task = tasks.iter().find(|t| t.uuid == uuid).expect("found above");
+                    if let Err(err) = webhook(url, &name, trigger, &redaction.masked(task)) {
+                        eprintln!("Warning: {}: {:#}", name, err);
+                    }
+                }
+            }
+        }
+        let task = task::find_task_mut(tasks, id)?;
+        task.log(&format!("{} ran ({})", name, trigger_name(trigger)), clock);
+        changed = true;
+    }
+    Ok(changed)
+}
+
+/// Fires the automations for whatever the command just run completed or
+/// tagged. Returns whether the tasks changed, so the caller saves them.
+pub fn after_command(
+    automations: &[Automation],
+    before: &Snapshot,
+    tasks: &mut Vec<Task>,
+    redaction: &redact::Rules,
+    clock: &Clock,
+) -> anyhow::Result<bool> {
+    let mut changed = false;
+    // Follow-ups added here are not themselves events, so automations
+    // cannot set each other off in a loop.
+    for (uuid, trigger) in before.events(tasks) {
+        changed |= fire(automations, None, tasks, uuid, &trigger, redaction, clock)?;
+    }
+    Ok(changed)
+}
+
+/// Fires `overdue` automations for open tasks past their due date that
+/// have not set them off before. Returns whether the tasks changed.
+pub fn check_overdue(
+    automations: &[Automation],
+    tasks: &mut Vec<Task>,
+    redaction: &redact::Rules,
+    clock: &Clock,
+) -> anyhow::Result<bool> {
+    let today = Local::now().date_naive();
+    let mut changed = false;
+    for (index, automation) in automations.iter().enumerate() {
+        if automation.when != Trigger::Overdue {
+            continue;
+        }
+        let marker = format!("{} ran (overdue)", automation.label(index));
+        let due: Vec<Uuid> = tasks
+            .iter()
+            .filter(|t| !t.completed && t.due.is_some_and(|d| d < today))
+            .filter(|t| !t.history.iter().any(|h| h.event == marker))
+            .map(|t| t.uuid)
+            .collect();
+        for uuid in due {
+            changed |= fire(
+                automations,
+                Some(index),
+                tasks,
+                uuid,
+                &Trigger::Overdue,
+                redaction,
+                clock,
+            )?;
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn webhooks_post_the_event_and_task() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let request = http::read_request(&stream).unwrap();
+            http::write_response(&stream, 204, "application/json", b"").unwrap();
+            request
+        });
+        let task: Task = serde_json::from_value(json!({
+            "id": 3,
+            "uuid": "00000000-0000-4000-8000-000000000003",
+            "description": "pay rent",
+            "completed": true,
+        }))
+        .unwrap();
+        webhook(&url, "notify", &Trigger::Completed, &task).unwrap();
+
+        let request = server.join().unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/hook")
+        );
+        assert_eq!(request.header("Content-Type"), Some("application/json"));
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["automation"], "notify");
+        assert_eq!(body["event"], "completed");
+        assert_eq!(body["task"]["description"], "pay rent");
+
+        assert!(
+            webhook(
+                "http://127.0.0.1:1/hook",
+                "notify",
+                &Trigger::Completed,
+                &task
+            )
+            .is_err()
+        );
+    }
+}
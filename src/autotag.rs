@@ -0,0 +1,93 @@
+//! Classification rules applied whenever a task is added or its
+//! description is edited, set in config.json:
+//!
+//! ```json
+//! {
+//!   "auto_tag": [
+//!     { "match": "invoice|tax", "tags": ["finance"], "priority": "high" },
+//!     { "match": "^review|pull request", "project": "work" }
+//!   ]
+//! }
+//! ```
+//!
+//! `match` is a pattern, in the syntax of the `regex` crate, looked for in the description,
+//! ignoring case. Every matching rule adds its tags, and sets its project
+//! and priority when the task has none yet, so a value given by hand always
+//! wins. Other edits leave the rules alone, so a tag removed by hand stays
+//! off.
+
+use crate::{
+    crdt::Clock,
+    task::{self, Priority, Task},
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(rename = "match", deserialize_with = "ignoring_case")]
+    pattern: Regex,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    priority: Option<Priority>,
+}
+
+fn ignoring_case<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|err| serde::de::Error::custom(format!("bad pattern {:?}: {}", pattern, err)))
+}
+
+/// Checks the rules, as loaded from config.json.
+pub fn validate(rules: &[Rule]) -> anyhow::Result<()> {
+    for rule in rules {
+        for tag in &rule.tags {
+            task::parse_tag(tag).map_err(|err| anyhow::anyhow!("auto_tag: {}", err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies every rule matching `task`'s description. Returns what changed,
+/// for telling the user, or nothing when no rule changed anything.
+pub fn apply(rules: &[Rule], task: &mut Task, clock: &Clock) -> Vec<String> {
+    let mut changes = Vec::new();
+    let description = task.description.clone();
+    for rule in rules.iter().filter(|r| r.pattern.is_match(&description)) {
+        let tags: Vec<String> = rule
+            .tags
+            .iter()
+            .filter_map(|t| task::parse_tag(t).ok())
+            .collect();
+        for tag in task::retag(task, &tags, &[], clock) {
+            changes.push(format!("+{}", tag));
+        }
+        if let (None, Some(project)) = (&task.project, &rule.project) {
+            task.project = Some(project.clone());
+            task.touch("project", clock);
+            changes.push(format!("project {}", project));
+        }
+        if let (None, Some(priority)) = (task.priority, rule.priority) {
+            task.priority = Some(priority);
+            task.touch("priority", clock);
+            changes.push(format!("priority {}", priority.name()));
+        }
+    }
+    changes
+}
+
+/// Applies the rules to task `id` and says what they changed.
+pub fn classify(rules: &[Rule], tasks: &mut [Task], id: u32, clock: &Clock) -> anyhow::Result<()> {
+    let task = task::find_task_mut(tasks, id)?;
+    let changes = apply(rules, task, clock);
+    if !changes.is_empty() {
+        println!("Classified task {}: {}.", id, changes.join(", "));
+    }
+    Ok(())
+}
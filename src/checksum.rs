@@ -0,0 +1,328 @@
+//! A checksum of each tasks file, kept in its first line with the length
+//! and task count of the rest, to catch a file that something other than
+//! this program cut short, most often a cloud sync tool that uploaded or
+//! downloaded half of it. Kept inside the file, it travels with it wherever
+//! it is copied, and survives whatever cuts off the end.
+//!
+//! ```text
+//! # cli_task_manager: tasks 0000000012, bytes 00000000000000004096, crc32 0a1b2c3d
+//! [
+//!   ...
+//! ```
+//!
+//! Every save writes the line and every load checks it. A file with fewer
+//! tasks than it was saved with is refused, since the next save would make
+//! the loss permanent; one that merely differs, as after a hand edit, loads
+//! with a warning until it is next saved. Either way `repair` accepts the
+//! file as it now is. A file without the line, as written by hand, is not
+//! checked.
+
+use crate::task::{self, Task};
+use anyhow::{Context, bail};
+use std::{
+    fs,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const MARK: &str = "# cli_task_manager: tasks ";
+
+/// The length of the first line, which is fixed so that a save can fill it
+/// in once it knows what the rest sums to.
+pub const HEADER_LEN: usize = MARK.len() + 10 + ", bytes ".len() + 20 + ", crc32 ".len() + 8 + 1;
+
+/// The length and CRC-32 of bytes fed through it so far.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sum {
+    len: u64,
+    crc: u32,
+}
+
+impl Sum {
+    pub fn of(bytes: &[u8]) -> Self {
+        let mut sum = Self::default();
+        sum.update(bytes);
+        sum
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        let mut hasher = crc32fast::Hasher::new_with_initial_len(self.crc, self.len);
+        hasher.update(bytes);
+        self.crc = hasher.finalize();
+        self.len += bytes.len() as u64;
+    }
+}
+
+/// A reader or writer that sums the bytes passing through it.
+pub struct Summing<T> {
+    pub inner: T,
+    pub sum: Sum,
+}
+
+impl<T> Summing<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            sum: Sum::default(),
+        }
+    }
+}
+
+impl<R: Read> Read for Summing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.sum.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Summing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.sum.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// What a tasks file's first line says about the rest of it.
+pub struct Seal {
+    tasks: usize,
+    sum: Sum,
+}
+
+/// The first line of a tasks file whose rest sums to `sum` with `count`
+/// tasks; always `HEADER_LEN` bytes.
+pub fn header(sum: Sum, count: usize) -> Vec<u8> {
+    format!(
+        "{}{:010}, bytes {:020}, crc32 {:08x}\n",
+        MARK, count, sum.len, sum.crc
+    )
+    .into_bytes()
+}
+
+fn parse(line: &[u8]) -> Option<Seal> {
+    let line = std::str::from_utf8(line).ok()?.strip_prefix(MARK)?;
+    let (tasks, rest) = line.split_once(", bytes ")?;
+    let (len, crc) = rest.strip_suffix('\n')?.split_once(", crc32 ")?;
+    Some(Seal {
+        tasks: tasks.parse().ok()?,
+        sum: Sum {
+            len: len.parse().ok()?,
+            crc: u32::from_str_radix(crc, 16).ok()?,
+        },
+    })
+}
+
+/// Splits a tasks file into what its first line says, if it has the line,
+/// and the rest.
+pub fn split(data: &[u8]) -> (Option<Seal>, &[u8]) {
+    match data.get(..HEADER_LEN).and_then(parse) {
+        Some(seal) => (Some(seal), &data[HEADER_LEN..]),
+        None => (None, data),
+    }
+}
+
+/// Reads past the first line of an open tasks file, returning what it says,
+/// or leaves the file at its start when it has no such line.
+pub fn skip_header(file: &mut fs::File) -> io::Result<Option<Seal>> {
+    let mut line = [0; HEADER_LEN];
+    let mut read = 0;
+    while read < HEADER_LEN {
+        match file.read(&mut line[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    let seal = parse(&line[..read]);
+    if seal.is_none() {
+        file.seek(SeekFrom::Start(0))?;
+    }
+    Ok(seal)
+}
+
+/// `data` with the first line a tasks file of `count` tasks starts with.
+pub fn sealed(data: &[u8], count: usize) -> Vec<u8> {
+    let mut out = header(Sum::of(data), count);
+    out.extend_from_slice(data);
+    out
+}
+
+/// Writes the tasks file at `path` atomically with `count` tasks, as
+/// produced by `write`, after the first line saying what they sum to.
+pub fn write_sealed(
+    path: &Path,
+    count: usize,
+    write: &mut dyn FnMut(&mut dyn Write) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    task::write_atomic_file(path, &mut |file| {
+        // Held in place until the rest is written and summed.
+        file.write_all(&header(Sum::default(), 0))?;
+        let mut out = Summing::new(BufWriter::new(&mut *file));
+        write(&mut out)?;
+        out.flush()?;
+        let sum = out.sum;
+        drop(out);
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header(sum, count))?;
+        Ok(())
+    })
+}
+
+/// What the first line of the tasks file at `tasks_path` says.
+fn load(tasks_path: &Path) -> anyhow::Result<Option<Seal>> {
+    match fs::File::open(tasks_path) {
+        Ok(mut file) => skip_header(&mut file)
+            .with_context(|| format!("Failed to read {}", tasks_path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", tasks_path.display())),
+    }
+}
+
+/// Checks `tasks`, read from the tasks file at `tasks_path` after its first
+/// line, whose rest summed to `sum`, against what that line says. `sum` is
+/// `None` when reading stopped early at damage, which never matches.
+pub fn verify(tasks_path: &Path, sum: Option<Sum>, tasks: &[Task]) -> anyhow::Result<()> {
+    let seal = load(tasks_path)?;
+    check(
+        &tasks_path.display().to_string(),
+        seal.as_ref(),
+        sum,
+        tasks.len(),
+    )
+}
+
+/// `verify` for the contents of a tasks file `name` read some other way.
+pub fn check(
+    name: &str,
+    seal: Option<&Seal>,
+    sum: Option<Sum>,
+    count: usize,
+) -> anyhow::Result<()> {
+    let Some(seal) = seal else {
+        return Ok(());
+    };
+    if sum == Some(seal.sum) {
+        return Ok(());
+    }
+    if count < seal.tasks {
+        bail!(
+            "{} holds {} task(s) but was saved with {}; something may have cut it short. Restore it from a backup or your sync tool's history, or run `repair` to keep what is there.",
+            name,
+            count,
+            seal.tasks
+        );
+    }
+    eprintln!(
+        "Warning: {} has changed since it was last saved (checksum mismatch); run `repair` to accept it as it is.",
+        name
+    );
+    Ok(())
+}
+
+/// Reseals a tasks file that no longer matches its checksum. Returns the
+/// number of tasks it holds, or `None` when it already matched or was
+/// never sealed.
+pub fn accept(tasks_path: &Path) -> anyhow::Result<Option<usize>> {
+    let Some(seal) = load(tasks_path)? else {
+        return Ok(None);
+    };
+    let (tasks, sum) = task::read_tasks(tasks_path)?;
+    if sum == Some(seal.sum) {
+        return Ok(None);
+    }
+    task::save_tasks(tasks_path, &tasks)?;
+    Ok(Some(tasks.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tasks(count: u32) -> Vec<Task> {
+        (1..=count)
+            .map(|id| {
+                serde_json::from_value(json!({
+                    "id": id,
+                    "uuid": format!("00000000-0000-4000-8000-{:012}", id),
+                    "description": format!("task {}", id),
+                    "completed": false,
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn tasks_path(test: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cli_task_manager-checksum-{}-{}",
+            test,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("tasks.json")
+    }
+
+    #[test]
+    fn headers_read_back() {
+        let sum = Sum::of(b"[]");
+        let line = header(sum, 12);
+        assert_eq!(line.len(), HEADER_LEN);
+        let data = sealed(b"[]", 12);
+        let (seal, body) = split(&data);
+        let seal = seal.unwrap();
+        assert_eq!((seal.tasks, body), (12, &b"[]"[..]));
+        assert!(seal.sum == sum);
+        // A file that does not start with the line is all body.
+        assert!(split(b"[]").0.is_none());
+        assert!(split(&data[..HEADER_LEN - 1]).0.is_none());
+    }
+
+    #[test]
+    fn truncation_is_detected() {
+        let path = tasks_path("truncated");
+        task::save_tasks(&path, &tasks(3)).unwrap();
+        assert_eq!(task::load_tasks(&path).unwrap().len(), 3);
+
+        // Cut off after the second task, leaving a list that still parses.
+        let data = fs::read_to_string(&path).unwrap();
+        let cut = data.rfind(",\n  {").unwrap();
+        fs::write(&path, format!("{}\n]", &data[..cut])).unwrap();
+        let err = task::load_tasks(&path).err().unwrap().to_string();
+        assert!(
+            err.contains("holds 2 task(s) but was saved with 3"),
+            "{}",
+            err
+        );
+
+        // Cut off partway through a task.
+        fs::write(&path, &data[..data.len() - 40]).unwrap();
+        assert!(task::load_tasks(&path).is_err());
+
+        // `repair` accepts what is left, after which it loads.
+        fs::write(&path, format!("{}\n]", &data[..cut])).unwrap();
+        assert_eq!(accept(&path).unwrap(), Some(2));
+        assert_eq!(task::load_tasks(&path).unwrap().len(), 2);
+        assert_eq!(accept(&path).unwrap(), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn edits_that_keep_every_task_load() {
+        let path = tasks_path("edited");
+        task::save_tasks(&path, &tasks(2)).unwrap();
+        let data = fs::read_to_string(&path).unwrap();
+        fs::write(&path, data.replace("task 1", "task one")).unwrap();
+        let loaded = task::load_tasks(&path).unwrap();
+        assert_eq!(loaded[0].description, "task one");
+        // Without the line, as written by hand, nothing is checked.
+        fs::write(&path, "[]").unwrap();
+        assert!(task::load_tasks(&path).unwrap().is_empty());
+        assert_eq!(accept(&path).unwrap(), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+//! Shrinking the tasks file and its sidecars by dropping bookkeeping that has
+//! outlived its use: tombstones and history entries older than a cutoff, and
+//! repeated notes and comments left behind by merges.
+//!
+//! A tombstone only matters until every replica has synced past it, so the
+//! cutoff should be longer than any replica goes without syncing; one that
+//! comes back later can bring a removed task back. History is merged as a
+//! union, so entries trimmed here return from replicas that still have them
+//! until those are compacted too. The entry saying when a task was added is
+//! kept, since `--sort created` goes by it.
+
+use crate::{
+    crdt::{self, Clock},
+    task::{self, Task},
+};
+use chrono::{DateTime, Duration, Utc};
+use std::{fs, path::Path};
+
+#[derive(Default)]
+struct Removed {
+    tombstones: usize,
+    history: usize,
+    notes: usize,
+    comments: usize,
+}
+
+/// Drops the parts of a `; `-joined note that repeat an earlier part, as
+/// merging the same duplicate twice leaves behind.
+fn dedupe_note(note: &str) -> (String, usize) {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut dropped = 0;
+    for part in note.split("; ") {
+        if parts.iter().any(|p| p.eq_ignore_ascii_case(part)) {
+            dropped += 1;
+        } else {
+            parts.push(part);
+        }
+    }
+    (parts.join("; "), dropped)
+}
+
+fn compact_task(task: &mut Task, cutoff: DateTime<Utc>, clock: &Clock) -> Removed {
+    let mut removed = Removed::default();
+
+    let before = task.history.len();
+    task.history
+        .retain(|e| e.at >= cutoff || e.event == task::ADDED);
+    removed.history = before - task.history.len();
+    if removed.history > 0 {
+        task.touch("history", clock);
+    }
+
+    if let Some(note) = &task.note {
+        let (note, dropped) = dedupe_note(note);
+        if dropped > 0 {
+            task.note = Some(note);
+            task.touch("note", clock);
+            removed.notes = dropped;
+        }
+    }
+
+    let before = task.comments.len();
+    let mut seen = Vec::new();
+    task.comments.retain(|c| {
+        let repeat = seen.contains(&c.id);
+        seen.push(c.id);
+        !repeat
+    });
+    removed.comments = before - task.comments.len();
+    if removed.comments > 0 {
+        task.touch("comments", clock);
+    }
+    removed
+}
+
+fn size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Compacts the tasks file at `data_path`, keeping tombstones and history
+/// from the last `keep_days` days, and prints what went.
+pub fn compact(data_path: &Path, keep_days: u32) -> anyhow::Result<()> {
+    let cutoff = Utc::now() - Duration::days(keep_days.into());
+    let tombstone_path = crdt::tombstone_path(data_path);
+    let before = size(data_path) + size(&tombstone_path);
+
+    let mut tasks = task::load_tasks(data_path)?;
+    let clock = Clock::load(data_path, &tasks)?;
+    let mut total = Removed::default();
+    for task in &mut tasks {
+        let removed = compact_task(task, cutoff, &clock);
+        total.history += removed.history;
+        total.notes += removed.notes;
+        total.comments += removed.comments;
+    }
+
+    let mut tombstones = crdt::load_tombstones(data_path)?;
+    let cutoff_ms = u64::try_from(cutoff.timestamp_millis()).unwrap_or(0);
+    let count = tombstones.len();
+    tombstones.retain(|_, stamp| stamp.0 >= cutoff_ms);
+    total.tombstones = count - tombstones.len();
+
+    if total.tombstones > 0 {
+        crdt::save_tombstones(data_path, &tombstones)?;
+    }
+    // Rewritten even with nothing removed, which drops empty fields and
+    // anything unknown left by hand edits.
+    if data_path.exists() {
+        task::save_tasks(data_path, &tasks)?;
+    }
+    let after = size(data_path) + size(&tombstone_path);
+
+    println!(
+        "Removed {} tombstone(s) and {} history entr{} older than {} day(s), {} repeated note part(s), and {} duplicate comment(s).",
+        total.tombstones,
+        total.history,
+        if total.history == 1 { "y" } else { "ies" },
+        keep_days,
+        total.notes,
+        total.comments
+    );
+    println!(
+        "The tasks file and its tombstones now take {} bytes (was {}).",
+        after, before
+    );
+    Ok(())
+}
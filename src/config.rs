@@ -0,0 +1,493 @@
+//! Settings from `config.json` in the config directory. Every setting is
+//! optional:
+//!
+//! ```json
+//! {
+//!   "working_hours": { "default": 6, "fri": 4, "sat": 0, "sun": 0 },
+//!   "archive_after_days": 90,
+//!   "sync_encryption": { "recipients": ["age1..."], "identity": "~/.config/age/tasks.key" },
+//!   "redact": ["sk-[A-Za-z0-9]{20,}"],
+//!   "attributes": { "customer": { "type": "text", "values": ["acme", "globex"] } },
+//!   "lint": { "overdue_days": 14, "max_description": null },
+//!   "auto_tag": [{ "match": "invoice|tax", "tags": ["finance"], "priority": "high" }],
+//!   "views": { "urgent": "--filter \"priority:high status:open\" --sort due" },
+//!   "contexts": { "work": "project:work" },
+//!   "context": "work",
+//!   "remotes": [{ "file": "/mnt/share/tasks.json" }, { "url": "http://nas:7373" }],
+//!   "ai": { "url": "http://localhost:11434/v1", "model": "llama3.1" },
+//!   "score": { "base": 1, "per_hour": 0.5 },
+//!   "aging": [{ "within_days": 0, "bump": 2 }],
+//!   "automations": [{ "when": "overdue", "then": [{ "set_priority": "urgent" }] }],
+//!   "columns": ["id", "priority", "due", "description"],
+//!   "pager": "less -S"
+//! }
+//! ```
+//!
+//! `config get` and `config set` read and change one setting by its dotted
+//! path, such as `working_hours.fri` or `remotes.0.url`; `config edit` opens
+//! the whole file in `$VISUAL` or `$EDITOR`. Both refuse to save what
+//! would not load.
+//!
+//! Environment variables override the file, so scripts and containers need
+//! none: `CLI_TASK_MANAGER_` and the setting's name in capitals, with `__`
+//! between the parts of a nested one, such as
+//! `CLI_TASK_MANAGER_ARCHIVE_AFTER_DAYS=30`,
+//! `CLI_TASK_MANAGER_WORKING_HOURS__FRI=4`, or
+//! `CLI_TASK_MANAGER_REMOTES='[{"url": "http://nas:7373"}]'`. Values are
+//! read as in `config set`. The one exception is `context`, because
+//! `CLI_TASK_MANAGER_CONTEXT` already stands for `--in`.
+
+use crate::{
+    age, aging, ai, attribute, automation, autotag, focus, inbox, lint, redact, stats, sync, task,
+    template, view,
+};
+use anyhow::{Context, bail};
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, fs, io::IsTerminal, path::Path, process::Command};
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub working_hours: WorkingHours,
+    /// Archive completed tasks this long after they were finished, before
+    /// every command.
+    pub archive_after_days: Option<u32>,
+    /// Encrypt what every sync remote holds; see `age`.
+    pub sync_encryption: Option<age::Encryption>,
+    /// Patterns masked in everything shown or handed out; see `redact`.
+    pub redact: redact::Rules,
+    /// Extra fields tasks may carry; see `attribute`.
+    pub attributes: attribute::Definitions,
+    /// Which hygiene rules `lint` checks; see `lint`.
+    pub lint: lint::Rules,
+    /// Rules classifying tasks as they are added or edited; see `autotag`.
+    pub auto_tag: Vec<autotag::Rule>,
+    /// Saved `list` arguments by name; see `view`.
+    pub views: BTreeMap<String, String>,
+    /// Named filters narrowing `list`, `next`, and `report`; see `focus`.
+    pub contexts: BTreeMap<String, String>,
+    /// The one of `contexts` in effect.
+    pub context: Option<String>,
+    /// Where `daemon` syncs to; see `sync::Remote`.
+    pub remotes: Vec<sync::Remote>,
+    /// The model `clarify --ai` asks; see `ai`.
+    pub ai: Option<ai::Settings>,
+    /// Weights for `stats --score`; see `stats::Weights`.
+    pub score: stats::Weights,
+    /// Rules raising the priority of tasks as they near their due date;
+    /// see `aging`.
+    pub aging: Vec<aging::Rule>,
+    /// What happens when tasks are completed, fall due, or get tags; see
+    /// `automation`.
+    pub automations: Vec<automation::Automation>,
+    /// The fields `list`, `next`, and `today` show, as for `--columns`.
+    pub columns: Vec<String>,
+    /// The command long output is paged through, or `""` for none; see
+    /// `pager`.
+    pub pager: Option<String>,
+}
+
+impl Config {
+    /// `columns`, else `task::DEFAULT_COLUMNS`.
+    pub fn columns(&self) -> Vec<String> {
+        if self.columns.is_empty() {
+            task::DEFAULT_COLUMNS
+                .iter()
+                .map(|c| c.to_string())
+                .collect()
+        } else {
+            self.columns.clone()
+        }
+    }
+}
+
+/// Hours of estimated work that fit in a day, for `schedule` and the
+/// overcommit warnings. Weekdays not named get `default`.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkingHours {
+    default: f64,
+    mon: Option<f64>,
+    tue: Option<f64>,
+    wed: Option<f64>,
+    thu: Option<f64>,
+    fri: Option<f64>,
+    sat: Option<f64>,
+    sun: Option<f64>,
+}
+
+impl Default for WorkingHours {
+    fn default() -> Self {
+        Self {
+            default: 6.0,
+            mon: None,
+            tue: None,
+            wed: None,
+            thu: None,
+            fri: None,
+            sat: None,
+            sun: None,
+        }
+    }
+}
+
+impl WorkingHours {
+    /// Minutes of work that fit on `day`.
+    pub fn minutes(&self, day: NaiveDate) -> u32 {
+        let hours = match day.weekday() {
+            Weekday::Mon => self.mon,
+            Weekday::Tue => self.tue,
+            Weekday::Wed => self.wed,
+            Weekday::Thu => self.thu,
+            Weekday::Fri => self.fri,
+            Weekday::Sat => self.sat,
+            Weekday::Sun => self.sun,
+        };
+        (hours.unwrap_or(self.default).max(0.0) * 60.0).round() as u32
+    }
+}
+
+pub fn load(config_dir: &Path) -> anyhow::Result<Config> {
+    let path = config_dir.join("config.json");
+    let overrides = overrides();
+    if !path.exists() && overrides.is_empty() {
+        return Ok(Config::default());
+    }
+    let mut root = Value::Object(Map::new());
+    if path.exists() {
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config at {}", path.display()))?;
+        let config: Config = serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse config at {}", path.display()))?;
+        if overrides.is_empty() {
+            check(&config, &path)?;
+            return Ok(config);
+        }
+        root = serde_json::from_str(&data)?;
+    }
+    let names: Vec<&str> = overrides.iter().map(|o| o.variable.as_str()).collect();
+    for state in &overrides {
+        let key: Vec<&str> = state.key.iter().map(String::as_str).collect();
+        place(&mut root, &key, state.value.clone())
+            .with_context(|| format!("Failed to apply {}", state.variable))?;
+    }
+    let config: Config = serde_json::from_value(root).with_context(|| {
+        format!(
+            "Failed to parse config at {} with {} set",
+            path.display(),
+            names.join(", ")
+        )
+    })?;
+    check(&config, &path)?;
+    Ok(config)
+}
+
+const PREFIX: &str = "CLI_TASK_MANAGER_";
+
+/// A setting given in the environment.
+struct Override {
+    variable: String,
+    key: Vec<String>,
+    value: Value,
+}
+
+/// The settings given in the environment, groups before what they hold so
+/// `..._AI__MODEL` can refine `..._AI`.
+fn overrides() -> Vec<Override> {
+    let mut overrides: Vec<Override> = std::env::vars_os()
+        .filter_map(|(name, value)| {
+            let variable = name.into_string().ok()?;
+            let key: Vec<String> = variable
+                .strip_prefix(PREFIX)?
+                .to_ascii_lowercase()
+                .split("__")
+                .map(str::to_owned)
+                .collect();
+            let known = KEYS.contains(&key[0].as_str()) && key[0] != "context";
+            if !known || key.iter().any(String::is_empty) {
+                return None;
+            }
+            let value = parse_value(&value.into_string().ok()?);
+            Some(Override {
+                variable,
+                key,
+                value,
+            })
+        })
+        .collect();
+    overrides.sort_by(|a, b| (a.key.len(), &a.variable).cmp(&(b.key.len(), &b.variable)));
+    overrides
+}
+
+/// A value as `config set` and the environment give it: JSON when it is
+/// JSON, such as `90`, `true`, `null`, or `["a", "b"]`, else a string.
+fn parse_value(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned()))
+}
+
+/// The checks beyond parsing that settings must pass to load.
+fn check(config: &Config, path: &Path) -> anyhow::Result<()> {
+    let week = NaiveDate::from_isoywd_opt(2024, 1, Weekday::Mon).expect("valid week");
+    if week
+        .iter_days()
+        .take(7)
+        .all(|d| config.working_hours.minutes(d) == 0)
+    {
+        bail!(
+            "working_hours in {} leaves no time on any day",
+            path.display()
+        );
+    }
+    if let Some(encryption) = &config.sync_encryption {
+        encryption
+            .validate()
+            .with_context(|| format!("Invalid config at {}", path.display()))?;
+    }
+    attribute::validate(&config.attributes)
+        .and_then(|()| autotag::validate(&config.auto_tag))
+        .and_then(|()| automation::validate(&config.automations))
+        .and_then(|()| focus::validate(&config.contexts, config.context.as_deref()))
+        .and_then(|()| {
+            config
+                .columns
+                .iter()
+                .try_for_each(|c| template::parse_field(c).map(drop))
+        })
+        .with_context(|| format!("Invalid config at {}", path.display()))
+}
+
+/// Changes config.json through `change`, which gets its top-level object,
+/// for the commands that save settings. The result must still load.
+pub fn update(
+    config_dir: &Path,
+    change: impl FnOnce(&mut Map<String, Value>),
+) -> anyhow::Result<()> {
+    let path = config_dir.join("config.json");
+    let mut root = if path.exists() {
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config at {}", path.display()))?;
+        match serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse config at {}", path.display()))?
+        {
+            Value::Object(root) => root,
+            _ => bail!("Config at {} is not a JSON object", path.display()),
+        }
+    } else {
+        Map::new()
+    };
+    change(&mut root);
+    let data = serde_json::to_string_pretty(&root)? + "\n";
+    serde_json::from_str::<Config>(&data)
+        .map_err(anyhow::Error::from)
+        .and_then(|config| check(&config, &path))
+        .with_context(|| format!("The change would leave {} invalid", path.display()))?;
+    fs::create_dir_all(config_dir)
+        .with_context(|| format!("Failed to create {}", config_dir.display()))?;
+    task::write_atomic(&path, data.as_bytes())
+}
+
+/// The settings config.json may hold at its top level.
+const KEYS: &[&str] = &[
+    "working_hours",
+    "archive_after_days",
+    "sync_encryption",
+    "redact",
+    "attributes",
+    "lint",
+    "auto_tag",
+    "views",
+    "contexts",
+    "context",
+    "remotes",
+    "ai",
+    "score",
+    "aging",
+    "automations",
+    "columns",
+    "pager",
+];
+
+/// `working_hours.fri` as `["working_hours", "fri"]`, refusing settings
+/// that do not exist at the top level; deeper ones are checked on save.
+fn key_path(key: &str) -> anyhow::Result<Vec<&str>> {
+    let path: Vec<&str> = key.split('.').collect();
+    if path.iter().any(|part| part.is_empty()) {
+        bail!("Invalid setting name {}", key);
+    }
+    if !KEYS.contains(&path[0]) {
+        bail!(
+            "Unknown setting {}; settings are {}",
+            path[0],
+            KEYS.join(", ")
+        );
+    }
+    Ok(path)
+}
+
+fn read_root(config_dir: &Path) -> anyhow::Result<Value> {
+    let path = config_dir.join("config.json");
+    if !path.exists() {
+        return Ok(Value::Object(Map::new()));
+    }
+    let data = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config at {}", path.display()))?;
+    serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse config at {}", path.display()))
+}
+
+/// One step into `value`: a field of an object or, by number, an element of
+/// an array.
+fn child<'a>(value: &'a Value, part: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map.get(part),
+        Value::Array(items) => part.parse().ok().and_then(|i: usize| items.get(i)),
+        _ => None,
+    }
+}
+
+/// `config get`: prints the setting at `key`, strings bare and anything
+/// else as JSON.
+pub fn get(config_dir: &Path, key: &str) -> anyhow::Result<()> {
+    let path = key_path(key)?;
+    let mut root = read_root(config_dir)?;
+    for state in overrides() {
+        let key: Vec<&str> = state.key.iter().map(String::as_str).collect();
+        let covers = |a: &[&str], b: &[&str]| a.iter().zip(b).all(|(a, b)| a == b);
+        if covers(&key, &path) {
+            eprintln!("Note: {} overrides config.json here.", state.variable);
+        }
+        place(&mut root, &key, state.value)
+            .with_context(|| format!("Failed to apply {}", state.variable))?;
+    }
+    let Some(value) = path
+        .iter()
+        .try_fold(&root, |value, part| child(value, part))
+    else {
+        bail!("{} is not set; the default applies", key);
+    };
+    match value {
+        Value::String(text) => println!("{}", text),
+        value => println!("{}", serde_json::to_string_pretty(value)?),
+    }
+    Ok(())
+}
+
+/// `config set`: stores `value` at `key`, read by `parse_value`.
+pub fn set(config_dir: &Path, key: &str, value: &str) -> anyhow::Result<()> {
+    let path = key_path(key)?;
+    let value = parse_value(value);
+    let mut result = Ok(());
+    update(config_dir, |root| {
+        let mut whole = Value::Object(std::mem::take(root));
+        result = place(&mut whole, &path, value.clone());
+        if let Value::Object(whole) = whole {
+            *root = whole;
+        }
+    })?;
+    result?;
+    println!("Set {} to {}.", key, value);
+    Ok(())
+}
+
+/// Puts `value` at `path` inside `whole`, adding the groups on the way
+/// that are not there yet.
+fn place(whole: &mut Value, path: &[&str], value: Value) -> anyhow::Result<()> {
+    let mut current = whole;
+    for (depth, part) in path.iter().enumerate() {
+        let last = depth + 1 == path.len();
+        current = match current {
+            Value::Object(map) if last => {
+                map.insert(part.to_string(), value);
+                return Ok(());
+            }
+            Value::Object(map) => map
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Object(Map::new())),
+            Value::Array(items) => {
+                let Some(item) = part.parse().ok().and_then(|i: usize| items.get_mut(i)) else {
+                    bail!("{} has no item {}", path[..depth].join("."), part);
+                };
+                if last {
+                    *item = value;
+                    return Ok(());
+                }
+                item
+            }
+            _ => bail!("{} is not a group of settings", path[..depth].join(".")),
+        };
+    }
+    Ok(())
+}
+
+/// `config edit`: opens config.json in the user's editor and saves it once
+/// it loads, offering the editor again when it does not.
+pub fn edit(config_dir: &Path) -> anyhow::Result<()> {
+    let path = config_dir.join("config.json");
+    let draft = config_dir.join("config.json.edit");
+    let current = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => "{\n}\n".to_owned(),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to read config at {}", path.display()));
+        }
+    };
+    fs::create_dir_all(config_dir)
+        .with_context(|| format!("Failed to create {}", config_dir.display()))?;
+    fs::write(&draft, &current).with_context(|| format!("Failed to write {}", draft.display()))?;
+
+    let editor = std::env::var("VISUAL")
+        .ok()
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_owned());
+    let words = view::split(&editor)?;
+    let Some((program, args)) = words.split_first() else {
+        bail!("No editor set; set $EDITOR");
+    };
+    loop {
+        let status = Command::new(program)
+            .args(args)
+            .arg(&draft)
+            .status()
+            .with_context(|| format!("Failed to run {}", program))?;
+        if !status.success() {
+            let _ = fs::remove_file(&draft);
+            bail!(
+                "{} failed ({}); config.json was left unchanged",
+                program,
+                status
+            );
+        }
+        let data = fs::read_to_string(&draft)
+            .with_context(|| format!("Failed to read {}", draft.display()))?;
+        if data == current {
+            let _ = fs::remove_file(&draft);
+            println!("No changes.");
+            return Ok(());
+        }
+        let loaded = serde_json::from_str::<Config>(&data)
+            .with_context(|| format!("Failed to parse {}", draft.display()))
+            .and_then(|config| check(&config, &path));
+        let Err(err) = loaded else {
+            break;
+        };
+        eprintln!("Error: {:#}", err);
+        let again = std::io::stdin().is_terminal()
+            && inbox::ask("Edit again? [Y/n] ")?
+                .is_some_and(|answer| !answer.to_lowercase().starts_with('n'));
+        if !again {
+            bail!(
+                "config.json was left unchanged; your edit is kept at {}",
+                draft.display()
+            );
+        }
+    }
+    let data = fs::read(&draft).with_context(|| format!("Failed to read {}", draft.display()))?;
+    task::write_atomic(&path, &data)?;
+    let _ = fs::remove_file(&draft);
+    println!("Saved {}.", path.display());
+    Ok(())
+}
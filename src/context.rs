@@ -0,0 +1,93 @@
+//! Contexts: separate task lists, each a tasks file of its own in
+//! `contexts/<name>/` under the data directory, with its own sidecar files.
+//! The unnamed default context is the usual `tasks.json`.
+
+use crate::{
+    crdt,
+    task::{self, Task},
+};
+use anyhow::{Context as _, bail};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT: &str = "default";
+
+/// The tasks file for context `name`, given the default context's file.
+pub fn tasks_path(default_path: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let name = name.trim();
+    if name.is_empty() || name == DEFAULT {
+        return Ok(default_path.to_owned());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Context names may only use letters, digits, - and _");
+    }
+    Ok(default_path
+        .with_file_name("contexts")
+        .join(name)
+        .join("tasks.json"))
+}
+
+/// Moves task `id` from the list at `from` to the list at `to`, keeping its
+/// uuid and history; it gets the next free id there. The task is buried in
+/// the source so sync does not bring it back.
+///
+/// The destination is written first. If the source then fails to save, the
+/// destination is put back as it was, so the task is never lost or copied.
+pub fn move_task(
+    from: &Path,
+    tasks: &mut Vec<Task>,
+    id: u32,
+    to: &Path,
+    to_name: &str,
+) -> anyhow::Result<u32> {
+    if from == to {
+        bail!("Task {} is already in context {}", id, to_name);
+    }
+    let mut target = task::load_tasks(to)?;
+    let Some(index) = tasks.iter().position(|t| t.id == id) else {
+        bail!("No task with id {}", id);
+    };
+    if target.iter().any(|t| t.uuid == tasks[index].uuid) {
+        bail!("Task {} is already in context {}", id, to_name);
+    }
+
+    let source_clock = crdt::Clock::load(from, tasks)?;
+    let target_clock = crdt::Clock::load(to, &target)?;
+    let mut moved = tasks.remove(index);
+    moved.id = target.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+    moved.log(&format!("moved to context {}", to_name), &target_clock);
+    let (uuid, new_id) = (moved.uuid, moved.id);
+    target.push(moved);
+
+    // Bringing a task back to a list it once left must lift the old
+    // tombstone, or the next sync would delete it again.
+    let mut target_tombstones = crdt::load_tombstones(to)?;
+    if target_tombstones.remove(&uuid).is_some() {
+        crdt::save_tombstones(to, &target_tombstones)?;
+    }
+
+    let previous = fs::read(to).ok();
+    task::save_tasks(to, &target)?;
+    if let Err(err) = task::save_tasks(from, tasks) {
+        let restored = match &previous {
+            Some(data) => task::write_atomic(to, data),
+            None => fs::remove_file(to).context("Failed to remove the new tasks file"),
+        };
+        if let Err(restore_err) = restored {
+            return Err(err.context(format!(
+                "and restoring {} failed too: {:#}",
+                to.display(),
+                restore_err
+            )));
+        }
+        return Err(err);
+    }
+    crdt::bury(from, uuid, source_clock.tick())
+        .context("Moved the task, but failed to record its removal for sync")?;
+    Ok(new_id)
+}
@@ -0,0 +1,142 @@
+//! Conflict-free bookkeeping for offline edits.
+//!
+//! Every task field is a last-writer-wins register: mutations record a
+//! [`Stamp`] for the field they touch, and merging keeps whichever side's
+//! value carries the greater stamp. Removals leave a tombstone so the deletion
+//! travels to other machines instead of the task reappearing on the next sync.
+
+use crate::task::{self, Task};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// Milliseconds since the epoch plus the replica that wrote it; the replica
+/// breaks ties so every machine orders the same two edits the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Stamp(pub u64, pub Uuid);
+
+pub type Tombstones = BTreeMap<Uuid, Stamp>;
+
+pub struct Clock {
+    replica: Uuid,
+    last: Cell<u64>,
+}
+
+impl Clock {
+    /// Loads (or creates) this machine's replica id next to the tasks file,
+    /// starting past every stamp already seen so a lagging wall clock cannot
+    /// make a new edit lose to an older one.
+    pub fn load(data_path: &Path, tasks: &[Task]) -> anyhow::Result<Self> {
+        let latest = tasks.iter().flat_map(|t| t.stamps.values()).max().copied();
+        Self::load_after(data_path, latest)
+    }
+
+    /// Like `load`, for callers that know the latest task stamp without
+    /// having every task at hand.
+    pub fn load_after(data_path: &Path, latest: Option<Stamp>) -> anyhow::Result<Self> {
+        let id_path = data_path.with_file_name("replica-id");
+        let replica = match fs::read_to_string(&id_path) {
+            Ok(text) => text
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid replica id in {}", id_path.display()))?,
+            Err(_) => {
+                let replica = Uuid::new_v4();
+                if let Some(parent) = id_path.parent() {
+                    fs::create_dir_all(parent).with_context(|| {
+                        format!("Failed to create data directory at {}", parent.display())
+                    })?;
+                }
+                fs::write(&id_path, replica.to_string())
+                    .with_context(|| format!("Failed to write {}", id_path.display()))?;
+                replica
+            }
+        };
+
+        let tombstones = load_tombstones(data_path)?;
+        let last = latest
+            .iter()
+            .chain(tombstones.values())
+            .map(|s| s.0)
+            .max()
+            .unwrap_or(0);
+        Ok(Self {
+            replica,
+            last: Cell::new(last),
+        })
+    }
+
+    pub fn replica(&self) -> Uuid {
+        self.replica
+    }
+
+    pub fn tick(&self) -> Stamp {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let at = now.max(self.last.get() + 1);
+        self.last.set(at);
+        Stamp(at, self.replica)
+    }
+}
+
+/// The newest edit recorded on a task, if it has ever been edited with stamps.
+pub fn latest(task_stamps: &BTreeMap<String, Stamp>) -> Option<Stamp> {
+    task_stamps.values().max().copied()
+}
+
+/// Tombstones for `tasks.json` live beside it in `tasks.tombstones.json`.
+pub fn tombstone_path(tasks_path: &Path) -> PathBuf {
+    tasks_path.with_extension("tombstones.json")
+}
+
+pub fn load_tombstones(tasks_path: &Path) -> anyhow::Result<Tombstones> {
+    let path = tombstone_path(tasks_path);
+    if !path.exists() {
+        return Ok(Tombstones::new());
+    }
+    let data = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read tombstones at {}", path.display()))?;
+    if data.trim().is_empty() {
+        return Ok(Tombstones::new());
+    }
+    serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse tombstones at {}", path.display()))
+}
+
+pub fn save_tombstones(tasks_path: &Path, tombstones: &Tombstones) -> anyhow::Result<()> {
+    let path = tombstone_path(tasks_path);
+    if tombstones.is_empty() && !path.exists() {
+        return Ok(());
+    }
+    let data =
+        serde_json::to_string_pretty(tombstones).context("Failed to serialize tombstones")?;
+    task::write_atomic(&path, data.as_bytes())
+}
+
+/// Records that the task `uuid` was removed at `stamp`.
+pub fn bury(tasks_path: &Path, uuid: Uuid, stamp: Stamp) -> anyhow::Result<()> {
+    let mut tombstones = load_tombstones(tasks_path)?;
+    tombstones.insert(uuid, stamp);
+    save_tombstones(tasks_path, &tombstones)
+}
+
+/// Combines tombstones from two replicas, keeping the later stamp per task.
+pub fn union(a: &Tombstones, b: &Tombstones) -> Tombstones {
+    let mut merged = a.clone();
+    for (uuid, stamp) in b {
+        merged
+            .entry(*uuid)
+            .and_modify(|s| *s = (*s).max(*stamp))
+            .or_insert(*stamp);
+    }
+    merged
+}
@@ -0,0 +1,523 @@
+//! `daemon`: a long-running process that keeps the tasks in memory, so
+//! `list` answers at once however large the file, and syncs with every
+//! remote under `"remotes"` in config.json in the background, every so
+//! often and shortly after the tasks file changes. Changes are debounced,
+//! so a burst of commands syncs once.
+//!
+//! While it runs, the daemon answers on a Unix socket beside the tasks file
+//! (`tasks.daemon.sock`). `list` hands it the command line and prints what
+//! comes back; `sync status` asks how the last syncs went. The protocol is
+//! one request line, answered with text until the daemon closes the
+//! connection; after a NUL byte comes the error, if the request failed.
+//!
+//! The tasks in memory are read again whenever the file's size or
+//! modification time changes, so the answers are never stale. A `list`
+//! the daemon answers skips the upkeep other commands do first, such as
+//! archiving; the next command that changes tasks does it.
+
+use crate::{
+    config, crdt,
+    merge::{self, Side},
+    sync, table, task,
+    task::Task,
+    watch,
+};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const POLL: Duration = Duration::from_millis(250);
+
+/// Prints `list` for the command line given, over the tasks in memory.
+pub type Render = dyn Fn(Vec<String>, &[Task]) -> anyhow::Result<()> + Send;
+
+/// How the last sync with one remote went.
+struct Outcome {
+    remote: String,
+    at: DateTime<Local>,
+    error: Option<String>,
+}
+
+struct State {
+    started: DateTime<Local>,
+    interval: Duration,
+    cached: Option<usize>,
+    syncing: bool,
+    outcomes: Vec<Outcome>,
+    next: DateTime<Local>,
+}
+
+impl State {
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "Daemon {} running since {}.",
+            std::process::id(),
+            self.started.format("%Y-%m-%d %H:%M"),
+        );
+        if let Some(count) = self.cached {
+            let _ = writeln!(out, "Holding {} task(s) in memory.", count);
+        }
+        if !self.syncing {
+            let _ = writeln!(out, "No remotes to sync with.");
+            return out;
+        }
+        let _ = writeln!(out, "Syncing every {}s.", self.interval.as_secs());
+        if self.outcomes.is_empty() {
+            let _ = writeln!(out, "No sync has finished yet.");
+        }
+        for outcome in &self.outcomes {
+            let _ = match &outcome.error {
+                None => writeln!(
+                    out,
+                    "  {}: synced at {}",
+                    outcome.remote,
+                    outcome.at.format("%H:%M:%S")
+                ),
+                Some(err) => writeln!(
+                    out,
+                    "  {}: failed at {}: {}",
+                    outcome.remote,
+                    outcome.at.format("%H:%M:%S"),
+                    err
+                ),
+            };
+        }
+        let _ = writeln!(out, "Next sync by {}.", self.next.format("%H:%M:%S"));
+        out
+    }
+}
+
+/// What `list` sends: its command line, with views expanded, and the width
+/// of the terminal it prints to.
+#[derive(Serialize, Deserialize)]
+struct ListRequest {
+    args: Vec<String>,
+    width: Option<usize>,
+}
+
+pub fn socket_path(tasks_path: &Path) -> PathBuf {
+    tasks_path.with_extension("daemon.sock")
+}
+
+/// One round: syncs with each remote in turn, then saves what they merged.
+fn sync_all(
+    tasks_path: &Path,
+    config_dir: &Path,
+    prefer: Option<Side>,
+) -> anyhow::Result<Vec<Outcome>> {
+    // Read afresh each round, so remotes can be added without a restart.
+    let config = config::load(config_dir)?;
+    if config.remotes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(prefer) = prefer else {
+        bail!("Restart the daemon with --prefer local|remote to sync with the remotes");
+    };
+    let seen = watch::signature(tasks_path);
+    let loaded = task::load_tasks(tasks_path)?;
+    let mut tasks = loaded.clone();
+    let mut outcomes = Vec::new();
+    for remote in &config.remotes {
+        println!(
+            "[{}] Syncing with {}",
+            Local::now().format("%H:%M:%S"),
+            remote
+        );
+        let result = sync::sync_with(
+            tasks_path,
+            &mut tasks,
+            remote,
+            Some(prefer),
+            config.sync_encryption.as_ref(),
+        );
+        if let Err(err) = &result {
+            eprintln!("Error syncing with {}: {:#}", remote, err);
+        }
+        outcomes.push(Outcome {
+            remote: remote.to_string(),
+            at: Local::now(),
+            error: result.err().map(|err| format!("{:#}", err)),
+        });
+    }
+    save_round(tasks_path, loaded, seen, tasks)?;
+    Ok(outcomes)
+}
+
+/// Saves what a round merged. Commands may have saved while the remotes were
+/// synced, from `base` as it was when the file looked like `seen`; their
+/// edits are merged in rather than overwritten, as often as the file moves.
+fn save_round(
+    tasks_path: &Path,
+    mut base: Vec<Task>,
+    mut seen: Option<(std::time::SystemTime, u64)>,
+    mut tasks: Vec<Task>,
+) -> anyhow::Result<()> {
+    loop {
+        let signature = watch::signature(tasks_path);
+        if signature == seen {
+            return task::save_tasks(tasks_path, &tasks);
+        }
+        let current = task::load_tasks(tasks_path)?;
+        let tombstones = crdt::load_tombstones(tasks_path)?;
+        // Whatever edited the file since is the newer change.
+        tasks = merge::three_way(
+            &base,
+            &current,
+            &tasks,
+            &tombstones,
+            &mut merge::resolver(Some(Side::Local)),
+        )?
+        .tasks;
+        base = current;
+        seen = signature;
+    }
+}
+
+/// Runs until interrupted, answering `list` with `render` and syncing every
+/// `interval` and `debounce` after the tasks file last changed.
+pub fn run(
+    tasks_path: &Path,
+    config_dir: &Path,
+    interval: Duration,
+    debounce: Duration,
+    prefer: Option<Side>,
+    render: Box<Render>,
+) -> anyhow::Result<()> {
+    let syncing = !config::load(config_dir)?.remotes.is_empty();
+    if syncing && prefer.is_none() {
+        bail!(
+            "Pass --prefer local|remote: the daemon syncs with the remotes in config.json, and there is nobody to ask about conflicts"
+        );
+    }
+    let state = Arc::new(Mutex::new(State {
+        started: Local::now(),
+        interval,
+        cached: None,
+        syncing,
+        outcomes: Vec::new(),
+        next: Local::now(),
+    }));
+    let server = Server {
+        tasks_path: tasks_path.to_path_buf(),
+        config_dir: config_dir.to_path_buf(),
+        state: Arc::clone(&state),
+        render,
+    };
+    listen(&socket_path(tasks_path), server)?;
+    if syncing {
+        println!(
+            "Serving {} and syncing it every {}s and after changes; `sync status` shows how it goes.",
+            tasks_path.display(),
+            interval.as_secs()
+        );
+    } else {
+        println!("Serving {}; no remotes to sync with.", tasks_path.display());
+    }
+
+    let mut last_sync: Option<Instant> = None;
+    let mut changed: Option<Instant> = None;
+    let mut seen = watch::signature(tasks_path);
+    loop {
+        let signature = watch::signature(tasks_path);
+        if signature != seen {
+            seen = signature;
+            changed = Some(Instant::now());
+        }
+        let due = last_sync.is_none_or(|at| at.elapsed() >= interval)
+            || changed.is_some_and(|at| at.elapsed() >= debounce);
+        if !due {
+            thread::sleep(POLL);
+            continue;
+        }
+        let outcomes = sync_all(tasks_path, config_dir, prefer).unwrap_or_else(|err| {
+            eprintln!("Error: {:#}", err);
+            vec![Outcome {
+                remote: "(all)".to_owned(),
+                at: Local::now(),
+                error: Some(format!("{:#}", err)),
+            }]
+        });
+        // The round's own save is not a change to sync again.
+        seen = watch::signature(tasks_path);
+        changed = None;
+        last_sync = Some(Instant::now());
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.syncing = !outcomes.is_empty();
+        state.outcomes = outcomes;
+        state.next = Local::now() + interval;
+    }
+}
+
+/// What the socket thread needs to answer requests.
+struct Server {
+    tasks_path: PathBuf,
+    config_dir: PathBuf,
+    state: Arc<Mutex<State>>,
+    render: Box<Render>,
+}
+
+/// The tasks as read from the file, masked as for any display, and the
+/// file's signature when they were read.
+type Cache = Option<(Option<(std::time::SystemTime, u64)>, Vec<Task>)>;
+
+impl Server {
+    /// The tasks in memory, read again if the file has changed.
+    fn tasks<'a>(&self, cache: &'a mut Cache) -> anyhow::Result<&'a [Task]> {
+        let signature = watch::signature(&self.tasks_path);
+        if cache.as_ref().is_none_or(|(seen, _)| *seen != signature) {
+            let mut tasks = task::load_tasks(&self.tasks_path)?;
+            config::load(&self.config_dir)?.redact.apply(&mut tasks);
+            self.state.lock().unwrap_or_else(|e| e.into_inner()).cached = Some(tasks.len());
+            *cache = Some((signature, tasks));
+        }
+        Ok(cache
+            .as_ref()
+            .map(|(_, tasks)| tasks.as_slice())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(unix)]
+fn listen(path: &Path, server: Server) -> anyhow::Result<()> {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::{UnixListener, UnixStream},
+    };
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("A daemon is already running for this tasks file");
+        }
+        // Left behind by a daemon that was killed.
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    thread::spawn(move || {
+        let mut cache = None;
+        if let Err(err) = server.tasks(&mut cache) {
+            eprintln!("Error: {:#}", err);
+        }
+        for stream in listener.incoming().flatten() {
+            let mut request = String::new();
+            let mut reader = BufReader::new(&stream);
+            if reader.read_line(&mut request).is_err() {
+                continue;
+            }
+            let (kind, body) = request
+                .trim()
+                .split_once(' ')
+                .unwrap_or((request.trim(), ""));
+            let result = match kind {
+                "status" => {
+                    let status = server.state.lock().map(|s| s.render()).unwrap_or_default();
+                    (&stream).write_all(status.as_bytes()).map_err(Into::into)
+                }
+                "list" => serde_json::from_str::<ListRequest>(body)
+                    .map_err(Into::into)
+                    .and_then(|request| {
+                        let tasks = server.tasks(&mut cache)?;
+                        printing_to(&stream, request.width, || {
+                            (server.render)(request.args, tasks)
+                        })
+                    }),
+                other => Err(anyhow::anyhow!("Unknown request {:?}", other)),
+            };
+            if let Err(err) = result {
+                let _ = write!(&stream, "\0{:#}", err);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Runs `print` with stdout and stderr sent to `stream`, and tables sized
+/// for a terminal `width` wide, as the client's would be.
+#[cfg(unix)]
+fn printing_to(
+    stream: &std::os::unix::net::UnixStream,
+    width: Option<usize>,
+    print: impl FnOnce() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    use std::{io::Write, os::fd::AsRawFd, panic};
+
+    // Held throughout, so the sync log waits rather than reaching the client.
+    let stdout = std::io::stdout().lock();
+    let stderr = std::io::stderr().lock();
+    // SAFETY: plain descriptor juggling on fds this process owns, undone
+    // below before the locks are released, as `pager` does.
+    let saved = unsafe {
+        let saved = (
+            libc::dup(libc::STDOUT_FILENO),
+            libc::dup(libc::STDERR_FILENO),
+        );
+        libc::dup2(stream.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(stream.as_raw_fd(), libc::STDERR_FILENO);
+        saved
+    };
+    table::force_width(Some(width));
+    // A client that hangs up early makes printing panic; that ends the
+    // answer, not the daemon.
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(print))
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("the client went away")));
+    table::force_width(None);
+    let _ = std::io::stdout().flush();
+    // SAFETY: restores the descriptors saved above.
+    unsafe {
+        libc::dup2(saved.0, libc::STDOUT_FILENO);
+        libc::dup2(saved.1, libc::STDERR_FILENO);
+        libc::close(saved.0);
+        libc::close(saved.1);
+    }
+    drop((stdout, stderr));
+    result
+}
+
+#[cfg(not(unix))]
+fn listen(_path: &Path, _server: Server) -> anyhow::Result<()> {
+    eprintln!(
+        "Warning: `list` and `sync status` reach the daemon through Unix sockets, which this system lacks."
+    );
+    Ok(())
+}
+
+/// Asks the daemon for `tasks_path`, if one is running, to print `list`
+/// for `args`. Returns whether it did.
+#[cfg(unix)]
+pub fn list(tasks_path: &Path, args: Vec<String>) -> anyhow::Result<bool> {
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixStream,
+    };
+
+    let path = socket_path(tasks_path);
+    let Ok(mut stream) = UnixStream::connect(&path) else {
+        return Ok(false);
+    };
+    let request = ListRequest {
+        args,
+        width: table::terminal_width(),
+    };
+    let line = serde_json::to_string(&request).context("Failed to serialize the request")?;
+    stream
+        .write_all(format!("list {}\n", line).as_bytes())
+        .with_context(|| format!("Failed to ask the daemon on {}", path.display()))?;
+    let mut reply = Vec::new();
+    stream
+        .read_to_end(&mut reply)
+        .with_context(|| format!("Failed to read from the daemon on {}", path.display()))?;
+    let (output, error) = match reply.iter().position(|b| *b == 0) {
+        Some(at) => (&reply[..at], Some(&reply[at + 1..])),
+        None => (&reply[..], None),
+    };
+    std::io::stdout()
+        .write_all(output)
+        .context("Failed to print the daemon's answer")?;
+    if let Some(error) = error {
+        bail!("{}", String::from_utf8_lossy(error));
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+pub fn list(_tasks_path: &Path, _args: Vec<String>) -> anyhow::Result<bool> {
+    Ok(false)
+}
+
+/// Asks the daemon for `tasks_path` how its syncs went, for `sync status`.
+#[cfg(unix)]
+pub fn print_status(tasks_path: &Path) -> anyhow::Result<()> {
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixStream,
+    };
+
+    let path = socket_path(tasks_path);
+    let Ok(mut stream) = UnixStream::connect(&path) else {
+        println!(
+            "No daemon is running for {}; start one with `daemon`.",
+            tasks_path.display()
+        );
+        return Ok(());
+    };
+    stream
+        .write_all(b"status\n")
+        .with_context(|| format!("Failed to ask the daemon on {}", path.display()))?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .with_context(|| format!("Failed to read from the daemon on {}", path.display()))?;
+    print!("{}", reply);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn print_status(_tasks_path: &Path) -> anyhow::Result<()> {
+    bail!("`sync status` needs Unix sockets, which this system lacks")
+}
+
+/// Whether a daemon answers on the socket for `tasks_path`, or `None` when
+/// there is no socket. A socket nobody answers on was left by a daemon
+/// that did not shut down cleanly.
+#[cfg(unix)]
+pub fn is_running(tasks_path: &Path) -> Option<bool> {
+    let path = socket_path(tasks_path);
+    path.exists()
+        .then(|| std::os::unix::net::UnixStream::connect(&path).is_ok())
+}
+
+#[cfg(not(unix))]
+pub fn is_running(_tasks_path: &Path) -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    fn task(id: u32, uuid: &str, description: &str, stamp: u64) -> Task {
+        serde_json::from_value(json!({
+            "id": id,
+            "uuid": uuid,
+            "description": description,
+            "completed": false,
+            "stamps": { "description": [stamp, "00000000-0000-4000-8000-0000000000ff"] }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn a_save_during_the_round_is_kept() {
+        let dir =
+            std::env::temp_dir().join(format!("cli_task_manager-daemon-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tasks.json");
+        let a = "00000000-0000-4000-8000-00000000000a";
+        let b = "00000000-0000-4000-8000-00000000000b";
+
+        task::save_tasks(&path, &[task(1, a, "Report", 1)]).unwrap();
+        let seen = watch::signature(&path);
+        let loaded = task::load_tasks(&path).unwrap();
+        // A command adds a task while the remote edits the first one.
+        task::save_tasks(&path, &[task(1, a, "Report", 1), task(2, b, "Milk", 2)]).unwrap();
+        let synced = vec![task(1, a, "Write the report", 3)];
+        save_round(&path, loaded, seen, synced).unwrap();
+
+        let saved = task::load_tasks(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let descriptions: Vec<&str> = saved.iter().map(|t| t.description.as_str()).collect();
+        assert_eq!(descriptions, ["Write the report", "Milk"]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Dates as `--due` and the other date options take them, and as `do`
+//! finds them in a sentence: `2026-11-03`, `today`, `tomorrow`, `day after
+//! tomorrow`, a weekday (the next one, or today), `next friday`, `next week`
+//! (its Monday), `next month`, `in 3 days`, `in two weeks`, `this weekend`,
+//! `end of week`, `end of month`, `nov 3`, or `3rd of november`.
+
+use anyhow::Context;
+use chrono::{Datelike, Days, Local, Months, NaiveDate, Weekday};
+
+/// Reads `input` as a whole date phrase.
+pub fn parse(input: &str) -> anyhow::Result<NaiveDate> {
+    let words: Vec<String> = input.split_whitespace().map(str::to_lowercase).collect();
+    at(&words, Local::now().date_naive())
+        .filter(|(_, len)| *len == words.len())
+        .map(|(date, _)| date)
+        .with_context(|| {
+            format!(
+                "Invalid date '{}' (use YYYY-MM-DD, today, a weekday, or e.g. `next week` or `in 3 days`)",
+                input
+            )
+        })
+}
+
+fn number(word: &str) -> Option<u32> {
+    let words = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+    match word {
+        "a" | "an" => Some(1),
+        _ => word
+            .parse()
+            .ok()
+            .or_else(|| words.iter().position(|w| *w == word).map(|n| n as u32)),
+    }
+}
+
+/// Full weekday names; the short ones are words of their own too often.
+fn weekday(word: &str) -> Option<Weekday> {
+    [
+        "monday",
+        "tuesday",
+        "wednesday",
+        "thursday",
+        "friday",
+        "saturday",
+        "sunday",
+    ]
+    .contains(&word)
+    .then(|| word.parse().ok())
+    .flatten()
+}
+
+fn month(word: &str) -> Option<u32> {
+    let names = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    names
+        .iter()
+        .position(|name| *name == word || word.len() >= 3 && name.starts_with(word))
+        .map(|i| i as u32 + 1)
+}
+
+/// A day of the month, as `3`, `3rd`, or `21st`.
+fn day(word: &str) -> Option<u32> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &word[digits.len()..];
+    if !["", "st", "nd", "rd", "th"].contains(&suffix) {
+        return None;
+    }
+    digits.parse().ok().filter(|d| (1..=31).contains(d))
+}
+
+/// The first `weekday` on or after `from`.
+fn next_weekday(from: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let ahead = (7 + weekday.num_days_from_monday() - from.weekday().num_days_from_monday()) % 7;
+    from + Days::new(ahead.into())
+}
+
+/// `month`/`day` this year, or next year once it has passed.
+fn next_date(today: NaiveDate, month: u32, day: u32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(today.year(), month, day)
+        .filter(|date| *date >= today)
+        .or_else(|| NaiveDate::from_ymd_opt(today.year() + 1, month, day))
+}
+
+/// The date `words` start with and how many words it takes. The words must
+/// be lowercase.
+pub fn at(words: &[String], today: NaiveDate) -> Option<(NaiveDate, usize)> {
+    let words: Vec<&str> = words.iter().map(String::as_str).take(4).collect();
+    let tomorrow = today + Days::new(1);
+    let end_of_month = (today.with_day(1)? + Months::new(1)).pred_opt()?;
+    match words.as_slice() {
+        ["day", "after", "tomorrow", ..] => Some((today + Days::new(2), 3)),
+        ["today" | "tonight", ..] => Some((today, 1)),
+        ["tomorrow", ..] => Some((tomorrow, 1)),
+        ["next", "week", ..] => Some((next_weekday(tomorrow, Weekday::Mon), 2)),
+        ["next", "month", ..] => Some((today + Months::new(1), 2)),
+        ["next", "year", ..] => Some((today + Months::new(12), 2)),
+        ["this", "weekend", ..] => Some((next_weekday(today, Weekday::Sat), 2)),
+        ["end", "of", "the", "week", ..] => Some((next_weekday(today, Weekday::Sun), 4)),
+        ["end", "of", "the", "month", ..] => Some((end_of_month, 4)),
+        ["end", "of", "week", ..] => Some((next_weekday(today, Weekday::Sun), 3)),
+        ["end", "of", "month", ..] => Some((end_of_month, 3)),
+        ["in", count, unit, ..] => {
+            let count = number(count)?;
+            let date = match unit.trim_end_matches('s') {
+                "day" => today + Days::new(count.into()),
+                "week" => today + Days::new(u64::from(count) * 7),
+                "month" => today + Months::new(count),
+                "year" => today + Months::new(count * 12),
+                _ => return None,
+            };
+            Some((date, 3))
+        }
+        ["next", name, ..] if weekday(name).is_some() => {
+            Some((next_weekday(tomorrow, weekday(name)?), 2))
+        }
+        ["this", name, ..] if weekday(name).is_some() => {
+            Some((next_weekday(today, weekday(name)?), 2))
+        }
+        [name, ..] if weekday(name).is_some() => Some((next_weekday(today, weekday(name)?), 1)),
+        [first, "of", second, ..] if day(first).is_some() && month(second).is_some() => {
+            Some((next_date(today, month(second)?, day(first)?)?, 3))
+        }
+        [first, second, ..] if month(first).is_some() && day(second).is_some() => {
+            Some((next_date(today, month(first)?, day(second)?)?, 2))
+        }
+        [first, second, ..] if day(first).is_some() && month(second).is_some() => {
+            Some((next_date(today, month(second)?, day(first)?)?, 2))
+        }
+        [first, ..] => NaiveDate::parse_from_str(first, "%Y-%m-%d")
+            .ok()
+            .map(|date| (date, 1)),
+        [] => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(text: &str) -> Option<(NaiveDate, usize)> {
+        let words: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+        // A Wednesday.
+        at(&words, NaiveDate::from_ymd_opt(2026, 10, 14).unwrap())
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn reads_relative_days() {
+        assert_eq!(on("today"), Some((date(2026, 10, 14), 1)));
+        assert_eq!(on("tomorrow"), Some((date(2026, 10, 15), 1)));
+        assert_eq!(on("day after tomorrow"), Some((date(2026, 10, 16), 3)));
+        assert_eq!(on("in 3 days"), Some((date(2026, 10, 17), 3)));
+        assert_eq!(on("in two weeks"), Some((date(2026, 10, 28), 3)));
+        assert_eq!(on("in a month"), Some((date(2026, 11, 14), 3)));
+        assert_eq!(on("in 3 fortnights"), None);
+    }
+
+    #[test]
+    fn reads_weekdays_and_weeks() {
+        assert_eq!(on("friday"), Some((date(2026, 10, 16), 1)));
+        assert_eq!(on("wednesday"), Some((date(2026, 10, 14), 1)));
+        assert_eq!(on("next wednesday"), Some((date(2026, 10, 21), 2)));
+        assert_eq!(on("next week"), Some((date(2026, 10, 19), 2)));
+        assert_eq!(on("this weekend"), Some((date(2026, 10, 17), 2)));
+        assert_eq!(on("end of the week"), Some((date(2026, 10, 18), 4)));
+        assert_eq!(on("end of month"), Some((date(2026, 10, 31), 3)));
+        // Short names are too often words of their own.
+        assert_eq!(on("fri"), None);
+    }
+
+    #[test]
+    fn reads_month_days_in_the_coming_year() {
+        assert_eq!(on("nov 3"), Some((date(2026, 11, 3), 2)));
+        assert_eq!(on("3rd of november"), Some((date(2026, 11, 3), 3)));
+        assert_eq!(on("march 1st"), Some((date(2027, 3, 1), 2)));
+        assert_eq!(on("october 14"), Some((date(2026, 10, 14), 2)));
+        assert_eq!(on("2025-01-31"), Some((date(2025, 1, 31), 1)));
+        assert_eq!(on("feb 30"), None);
+    }
+
+    #[test]
+    fn parse_needs_the_whole_input() {
+        assert!(parse("Friday").is_ok());
+        assert!(parse(" in  3 days ").is_ok());
+        assert!(parse("friday please").is_err());
+        assert!(parse("2026-13-01").is_err());
+        assert!(parse("").is_err());
+    }
+}
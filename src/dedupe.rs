@@ -0,0 +1,155 @@
+//! Catching duplicate tasks: `add` warns when an open task already says
+//! nearly the same thing, and `dedupe` walks through the pairs already in
+//! the list, offering to merge each.
+//!
+//! Descriptions are compared ignoring case, punctuation, and spacing, and
+//! count as the same when at most one character in seven differs.
+
+use crate::{
+    crdt::{self, Clock},
+    inbox,
+    task::{self, Task},
+};
+use anyhow::bail;
+use std::{
+    io::{self, IsTerminal},
+    path::Path,
+};
+
+const THRESHOLD: f64 = 6.0 / 7.0;
+
+/// Lowercase words of `text`, punctuation dropped, one space apart.
+fn normalize(text: &str) -> Vec<char> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.join(" ").chars().collect()
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Whether two normalized descriptions are near enough to be the same task.
+fn alike(a: &[char], b: &[char]) -> bool {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return true;
+    }
+    let allowed = ((1.0 - THRESHOLD) * longest as f64).floor() as usize;
+    // The distance is at least the difference in length.
+    a.len().abs_diff(b.len()) <= allowed && edit_distance(a, b) <= allowed
+}
+
+fn is_candidate(task: &Task) -> bool {
+    !task.completed && task.habit.is_none()
+}
+
+/// The open tasks `description` duplicates.
+pub fn matching<'a>(tasks: &'a [Task], description: &str) -> Vec<&'a Task> {
+    let wanted = normalize(description);
+    tasks
+        .iter()
+        .filter(|t| is_candidate(t) && alike(&normalize(&t.description), &wanted))
+        .collect()
+}
+
+/// Before `add`: warns about open tasks that `description` duplicates, or
+/// with `strict` refuses to add it.
+pub fn check_new(tasks: &[Task], description: &str, strict: bool) -> anyhow::Result<()> {
+    let found = matching(tasks, description);
+    if found.is_empty() {
+        return Ok(());
+    }
+    let lines: Vec<String> = found
+        .iter()
+        .map(|t| format!("  {}", task::format_line(tasks, t)))
+        .collect();
+    if strict {
+        bail!(
+            "An open task already says this:\n{}\n(leave out --strict to add it anyway)",
+            lines.join("\n")
+        );
+    }
+    eprintln!(
+        "Warning: an open task already says this:\n{}",
+        lines.join("\n")
+    );
+    Ok(())
+}
+
+/// Pairs of open tasks that look like duplicates, the older one first.
+fn pairs(tasks: &[Task]) -> Vec<(u32, u32)> {
+    let candidates: Vec<(u32, Vec<char>)> = tasks
+        .iter()
+        .filter(|t| is_candidate(t))
+        .map(|t| (t.id, normalize(&t.description)))
+        .collect();
+    let mut pairs = Vec::new();
+    for (i, (a, text_a)) in candidates.iter().enumerate() {
+        for (b, text_b) in &candidates[i + 1..] {
+            if alike(text_a, text_b) {
+                pairs.push(((*a).min(*b), (*a).max(*b)));
+            }
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+/// Lists the likely duplicates and, on a terminal, offers to merge each
+/// newer task into the older one. Returns whether any were merged.
+pub fn dedupe(data_path: &Path, tasks: &mut Vec<Task>, clock: &Clock) -> anyhow::Result<bool> {
+    let pairs = pairs(tasks);
+    if pairs.is_empty() {
+        println!("No duplicate tasks found.");
+        return Ok(false);
+    }
+    let interactive = io::stdin().is_terminal();
+    let mut merged: Vec<u32> = Vec::new();
+    for (i, (keep, duplicate)) in pairs.iter().enumerate() {
+        if merged.contains(keep) || merged.contains(duplicate) {
+            continue;
+        }
+        let (Some(a), Some(b)) = (
+            tasks.iter().find(|t| t.id == *keep),
+            tasks.iter().find(|t| t.id == *duplicate),
+        ) else {
+            continue;
+        };
+        println!("[{}/{}]", i + 1, pairs.len());
+        println!("  {}", task::format_line(tasks, a));
+        println!("  {}", task::format_line(tasks, b));
+        if !interactive {
+            continue;
+        }
+        let question = format!("Merge task {} into task {}? [y/N/q] ", duplicate, keep);
+        match inbox::ask(&question)?.as_deref() {
+            Some("y" | "yes") => {
+                let uuid = task::merge_tasks(tasks, *duplicate, *keep, clock)?;
+                crdt::bury(data_path, uuid, clock.tick())?;
+                merged.push(*duplicate);
+            }
+            None | Some("q" | "quit") => break,
+            Some(_) => {}
+        }
+    }
+    if !interactive {
+        println!("(run `dedupe` in a terminal to merge them, or use `merge`)");
+    } else if !merged.is_empty() {
+        println!("Merged {} duplicate(s).", merged.len());
+    }
+    Ok(!merged.is_empty())
+}
@@ -0,0 +1,158 @@
+//! Relationships between tasks: dependencies ("finish A before B") and
+//! parent/child subtasks. Both refer to tasks by uuid so they survive the
+//! renumbering that sync may do.
+
+use crate::{
+    crdt::Clock,
+    task::{self, Task},
+};
+use anyhow::bail;
+use uuid::Uuid;
+
+fn uuid_of(tasks: &[Task], id: u32) -> anyhow::Result<Uuid> {
+    match tasks.iter().find(|t| t.id == id) {
+        Some(task) => Ok(task.uuid),
+        None => bail!("No task with id {}", id),
+    }
+}
+
+fn by_uuid(tasks: &[Task], uuid: Uuid) -> Option<&Task> {
+    tasks.iter().find(|t| t.uuid == uuid)
+}
+
+/// The unfinished tasks that `task` is still waiting for. Dependencies on
+/// tasks that have since been removed no longer hold anything up.
+pub fn blockers<'a>(tasks: &'a [Task], task: &Task) -> Vec<&'a Task> {
+    task.depends_on
+        .iter()
+        .filter_map(|uuid| by_uuid(tasks, *uuid))
+        .filter(|t| !t.completed)
+        .collect()
+}
+
+pub fn is_blocked(tasks: &[Task], task: &Task) -> bool {
+    !task.completed && !blockers(tasks, task).is_empty()
+}
+
+/// Open tasks that were waiting on `finished` and have nothing else left
+/// holding them up.
+pub fn unblocked_by(tasks: &[Task], finished: Uuid) -> Vec<&Task> {
+    tasks
+        .iter()
+        .filter(|t| !t.completed && t.depends_on.contains(&finished))
+        .filter(|t| !is_blocked(tasks, t))
+        .collect()
+}
+
+/// Points every dependency on and parent link to `old` at `new` instead, as
+/// when `old` is merged into `new`.
+pub fn repoint(tasks: &mut [Task], old: Uuid, new: Uuid, clock: &Clock) {
+    let old_parent = by_uuid(tasks, old).and_then(|t| t.parent);
+    for task in tasks.iter_mut().filter(|t| t.uuid != old) {
+        if task.depends_on.contains(&old) {
+            task.depends_on.retain(|u| *u != old);
+            if task.uuid != new && !task.depends_on.contains(&new) {
+                task.depends_on.push(new);
+            }
+            task.touch("depends_on", clock);
+        }
+        if task.parent == Some(old) {
+            task.parent = if task.uuid == new {
+                old_parent
+            } else {
+                Some(new)
+            };
+            task.touch("parent", clock);
+        }
+    }
+}
+
+/// Whether `to` can be reached from `from` by following `next`.
+fn reaches(tasks: &[Task], from: Uuid, to: Uuid, next: fn(&Task) -> Vec<Uuid>) -> bool {
+    let mut stack = vec![from];
+    let mut seen = Vec::new();
+    while let Some(uuid) = stack.pop() {
+        if uuid == to {
+            return true;
+        }
+        if seen.contains(&uuid) {
+            continue;
+        }
+        seen.push(uuid);
+        if let Some(task) = by_uuid(tasks, uuid) {
+            stack.extend(next(task));
+        }
+    }
+    false
+}
+
+/// Records that task `id` cannot start until task `on` is done.
+pub fn add_dependency(tasks: &mut [Task], id: u32, on: u32, clock: &Clock) -> anyhow::Result<()> {
+    let (uuid, on_uuid) = (uuid_of(tasks, id)?, uuid_of(tasks, on)?);
+    if uuid == on_uuid {
+        bail!("A task cannot depend on itself");
+    }
+    if reaches(tasks, on_uuid, uuid, |t| t.depends_on.clone()) {
+        bail!(
+            "Task {} already depends on task {}; that would be a cycle",
+            on,
+            id
+        );
+    }
+
+    let task = task::find_task_mut(tasks, id)?;
+    if task.depends_on.contains(&on_uuid) {
+        bail!("Task {} already depends on task {}", id, on);
+    }
+    task.depends_on.push(on_uuid);
+    task.touch("depends_on", clock);
+    Ok(())
+}
+
+pub fn remove_dependency(
+    tasks: &mut [Task],
+    id: u32,
+    on: u32,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let on_uuid = uuid_of(tasks, on)?;
+    let task = task::find_task_mut(tasks, id)?;
+    if !task.depends_on.contains(&on_uuid) {
+        bail!("Task {} does not depend on task {}", id, on);
+    }
+    task.depends_on.retain(|u| *u != on_uuid);
+    task.touch("depends_on", clock);
+    Ok(())
+}
+
+/// Makes task `id` a subtask of `parent`, or a top-level task with `None`.
+pub fn set_parent(
+    tasks: &mut [Task],
+    id: u32,
+    parent: Option<u32>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let uuid = uuid_of(tasks, id)?;
+    let parent = match parent {
+        Some(parent) => {
+            let parent_uuid = uuid_of(tasks, parent)?;
+            if parent_uuid == uuid {
+                bail!("A task cannot be its own parent");
+            }
+            if reaches(tasks, parent_uuid, uuid, |t| t.parent.into_iter().collect()) {
+                bail!(
+                    "Task {} is above task {} already; that would be a cycle",
+                    id,
+                    parent
+                );
+            }
+            Some(parent_uuid)
+        }
+        None => None,
+    };
+
+    let task = task::find_task_mut(tasks, id)?;
+    task.parent = parent;
+    task.touch("parent", clock);
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! `diff`: how the tasks differ from those in another file, such as a
+//! backup, an export, or a copy set aside by `repair`, field by field. It
+//! reads as going from the other file to the current tasks: `+` tasks are
+//! only in the current ones, `-` only in the other file.
+
+use crate::{
+    redact, table,
+    task::{self, Task},
+};
+use anyhow::{Context, bail};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, path::Path};
+use uuid::Uuid;
+
+/// Fields that change with every edit and only matter to sync.
+const SKIPPED: &[&str] = &["stamps"];
+
+fn fields(task: &Task) -> anyhow::Result<Map<String, Value>> {
+    match serde_json::to_value(task).context("Failed to serialize a task")? {
+        Value::Object(fields) => Ok(fields),
+        _ => bail!("a task did not serialize to an object"),
+    }
+}
+
+/// A field's value as shown in the diff.
+fn shown(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "(none)".to_owned(),
+        Some(Value::String(text)) => table::truncate(text, 60),
+        Some(value) => table::truncate(&value.to_string(), 60),
+    }
+}
+
+pub fn print(tasks: &[Task], path: &Path, redact: &redact::Rules) -> anyhow::Result<()> {
+    if !path.exists() {
+        bail!("No file at {}", path.display());
+    }
+    let (mut other, _) = task::read_tasks(path)?;
+    redact.apply(&mut other);
+    let mut theirs: HashMap<Uuid, &Task> = other.iter().map(|t| (t.uuid, t)).collect();
+
+    let (mut added, mut changed) = (0, 0);
+    for task in tasks {
+        let Some(before) = theirs.remove(&task.uuid) else {
+            println!("+ {}: {}", task.id, task.description);
+            added += 1;
+            continue;
+        };
+        let (old, new) = (fields(before)?, fields(task)?);
+        let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+        names.sort();
+        names.dedup();
+        let lines: Vec<String> = names
+            .into_iter()
+            .filter(|name| !SKIPPED.contains(&name.as_str()))
+            .filter(|name| old.get(*name) != new.get(*name))
+            .map(|name| {
+                format!(
+                    "    {}: {} -> {}",
+                    name,
+                    shown(old.get(name)),
+                    shown(new.get(name))
+                )
+            })
+            .collect();
+        if !lines.is_empty() {
+            println!("~ {}: {}", task.id, task.description);
+            for line in lines {
+                println!("{}", line);
+            }
+            changed += 1;
+        }
+    }
+    let mut removed: Vec<&Task> = theirs.into_values().collect();
+    removed.sort_by_key(|t| t.id);
+    for task in &removed {
+        println!("- {}: {}", task.id, task.description);
+    }
+
+    if added + changed + removed.len() == 0 {
+        println!("No differences from {}.", path.display());
+    } else {
+        println!(
+            "{} added, {} removed, {} changed compared with {}.",
+            added,
+            removed.len(),
+            changed,
+            path.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,407 @@
+//! `doctor`: looks for what would stop commands from working and says how to
+//! fix each thing it finds. It checks that the data, config, and cache
+//! directories can be written, that config.json and the tasks file parse,
+//! that no interrupted save or stale daemon socket is left holding the tasks
+//! file, that notifications can be shown, and that every remote under
+//! `"remotes"` answers. Nothing is changed.
+//!
+//! Exits 1 when a check failed; warnings alone leave it at 0.
+
+use crate::{
+    checksum, config, daemon, http::Endpoint, journal, keyring, notify, paths::Dirs, ssh,
+    sync::Remote, validate,
+};
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Prints findings as they come and counts the bad ones.
+#[derive(Default)]
+struct Findings {
+    failed: usize,
+    warned: usize,
+}
+
+impl Findings {
+    fn ok(&mut self, what: impl Into<String>) {
+        self.push(Level::Ok, what.into(), None);
+    }
+
+    fn warn(&mut self, what: impl Into<String>, fix: impl Into<String>) {
+        self.push(Level::Warn, what.into(), Some(fix.into()));
+    }
+
+    fn fail(&mut self, what: impl Into<String>, fix: impl Into<String>) {
+        self.push(Level::Fail, what.into(), Some(fix.into()));
+    }
+
+    fn push(&mut self, level: Level, what: String, fix: Option<String>) {
+        let mark = match level {
+            Level::Ok => "ok  ",
+            Level::Warn => {
+                self.warned += 1;
+                "warn"
+            }
+            Level::Fail => {
+                self.failed += 1;
+                "FAIL"
+            }
+        };
+        println!("{}  {}", mark, what);
+        if let Some(fix) = fix {
+            println!("      Fix: {}", fix);
+        }
+    }
+}
+
+/// Whether `program` is a file in one of the `PATH` directories.
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Creates and removes a file in `dir` to see whether it can be written.
+fn try_write(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+fn check_dir(findings: &mut Findings, name: &str, dir: &Path) {
+    if dir.is_dir() {
+        match try_write(dir) {
+            Ok(()) => findings.ok(format!("{} {} is writable", name, dir.display())),
+            Err(err) => findings.fail(
+                format!("{} {} cannot be written: {}", name, dir.display(), err),
+                format!(
+                    "give yourself write access, e.g. `chmod u+w {}`, or point XDG variables elsewhere",
+                    dir.display()
+                ),
+            ),
+        }
+        return;
+    }
+    if dir.exists() {
+        findings.fail(
+            format!("{} {} is not a directory", name, dir.display()),
+            format!("move {} out of the way", dir.display()),
+        );
+        return;
+    }
+    // It is created on first use, so what matters is that it can be.
+    let Some(parent) = dir.ancestors().skip(1).find(|a| a.is_dir()) else {
+        return;
+    };
+    match try_write(parent) {
+        Ok(()) => findings.ok(format!(
+            "{} {} does not exist yet and will be created when needed",
+            name,
+            dir.display()
+        )),
+        Err(err) => findings.fail(
+            format!(
+                "{} {} does not exist and cannot be created in {}: {}",
+                name,
+                dir.display(),
+                parent.display(),
+                err
+            ),
+            format!("create it by hand: `mkdir -p {}`", dir.display()),
+        ),
+    }
+}
+
+fn check_tasks(findings: &mut Findings, data_path: &Path) {
+    let report = match validate::inspect(data_path) {
+        Ok(Some(report)) => report,
+        Ok(None) => {
+            findings.ok(format!(
+                "{} does not exist yet; the first `add` creates it",
+                data_path.display()
+            ));
+            return;
+        }
+        Err(err) => {
+            findings.fail(
+                format!("{:#}", err),
+                "run `repair` to salvage the readable tasks into a fresh file",
+            );
+            return;
+        }
+    };
+    if let Err(err) = checksum::verify(data_path, Some(report.sum), &report.tasks) {
+        findings.fail(
+            format!("{:#}", err),
+            "restore the file from a backup, or run `repair` to keep what is there",
+        );
+        return;
+    }
+    let problems = &report.problems;
+    if problems.is_empty() {
+        findings.ok(format!(
+            "{} parses, with {} task(s)",
+            data_path.display(),
+            report.tasks.len()
+        ));
+    } else {
+        findings.warn(
+            format!(
+                "{} parses, but has {} problem(s), such as {}",
+                data_path.display(),
+                problems.len(),
+                problems[0]
+            ),
+            "run `validate` to list them, then fix them with `edit`",
+        );
+    }
+}
+
+fn check_locks(findings: &mut Findings, data_path: &Path) {
+    let tmp = data_path.with_extension("tmp");
+    if tmp.exists() {
+        findings.warn(
+            format!("{} was left by a save that did not finish", tmp.display()),
+            format!(
+                "make sure no other command is running, then remove it: `rm {}`",
+                tmp.display()
+            ),
+        );
+    }
+    if journal::is_pending(data_path) {
+        findings.warn(
+            format!(
+                "the last save of {} did not finish; its journal holds the edits",
+                data_path.display()
+            ),
+            "run any command, such as `list`, to replay them into the tasks file",
+        );
+    }
+    let socket = daemon::socket_path(data_path);
+    match daemon::is_running(data_path) {
+        Some(true) => findings.ok(format!("a daemon is running for {}", data_path.display())),
+        Some(false) => findings.warn(
+            format!(
+                "{} is left from a daemon that is no longer running",
+                socket.display()
+            ),
+            format!(
+                "remove it with `rm {}`, or start the daemon again with `daemon`",
+                socket.display()
+            ),
+        ),
+        None => {}
+    }
+    if !tmp.exists() && !journal::is_pending(data_path) {
+        findings.ok(format!(
+            "nothing is holding {} from an unfinished save",
+            data_path.display()
+        ));
+    }
+}
+
+fn check_notifications(findings: &mut Findings, send: bool) {
+    let program = notify::program();
+    if !on_path(program) {
+        findings.warn(
+            format!("{} was not found, so reminders cannot be shown", program),
+            if cfg!(target_os = "macos") {
+                "osascript ships with macOS; check that /usr/bin is on PATH".to_owned()
+            } else {
+                "install libnotify (the `libnotify-bin` or `libnotify` package)".to_owned()
+            },
+        );
+        return;
+    }
+    if !send {
+        findings.ok(format!(
+            "{} is installed for notifications (`doctor --notify` sends a test one)",
+            program
+        ));
+        return;
+    }
+    match notify::send("cli_task_manager", "Notifications work.") {
+        Ok(()) => findings.ok(format!(
+            "sent a test notification through {}; check that it appeared",
+            program
+        )),
+        Err(err) => findings.fail(
+            format!("the test notification failed: {:#}", err),
+            "check that a notification daemon is running in this desktop session",
+        ),
+    }
+}
+
+fn check_remote(findings: &mut Findings, remote: &Remote, sealed: bool) {
+    match remote {
+        Remote::File(path) => {
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+            match dir {
+                Some(dir) if !dir.is_dir() => findings.fail(
+                    format!("remote {}: {} is not reachable", remote, dir.display()),
+                    "mount the share, or fix the path under \"remotes\" in config.json",
+                ),
+                _ if path.exists() && fs::File::open(path).is_err() => findings.fail(
+                    format!("remote {}: cannot be read", remote),
+                    format!("give yourself read access to {}", path.display()),
+                ),
+                _ => findings.ok(format!("remote {} is reachable", remote)),
+            }
+        }
+        Remote::Ssh(target) => {
+            let target = match ssh::Target::parse(target) {
+                Ok(target) => target,
+                Err(err) => {
+                    findings.fail(
+                        format!("remote {}: {:#}", remote, err),
+                        "write it as user@host:path under \"remotes\" in config.json",
+                    );
+                    return;
+                }
+            };
+            let status = Command::new("ssh")
+                .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+                .arg(&target.host)
+                .arg("true")
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            match status {
+                Ok(status) if status.success() => {
+                    findings.ok(format!("remote {} answers over ssh", remote))
+                }
+                Ok(status) => findings.fail(
+                    format!("remote {}: ssh {} failed ({})", remote, target.host, status),
+                    format!(
+                        "check that `ssh {}` logs in without a password prompt (load your key into ssh-agent)",
+                        target.host
+                    ),
+                ),
+                Err(err) => findings.fail(
+                    format!("remote {}: failed to run ssh: {}", remote, err),
+                    "install an OpenSSH client",
+                ),
+            }
+        }
+        Remote::Url(url) => {
+            let endpoint = match Endpoint::parse(url) {
+                Ok(endpoint) => endpoint,
+                Err(err) => {
+                    findings.fail(
+                        format!("remote {}: {:#}", remote, err),
+                        "use an http:// or https:// URL under \"remotes\" in config.json",
+                    );
+                    return;
+                }
+            };
+            let token = keyring::Service::Sync
+                .account(Some(url.clone()))
+                .and_then(|account| keyring::get(keyring::Service::Sync, &account));
+            let token = match token {
+                Ok(Some(token)) => token,
+                Ok(None) => {
+                    findings.fail(
+                        format!("remote {}: no token is stored for it", remote),
+                        format!("run `auth set sync {}`", url),
+                    );
+                    return;
+                }
+                Err(err) => {
+                    findings.fail(
+                        format!("remote {}: {:#}", remote, err),
+                        format!("run `auth set sync {}`", url),
+                    );
+                    return;
+                }
+            };
+            let path = if sealed { "/sync/sealed" } else { "/sync" };
+            match endpoint.send("GET", path, &token, None) {
+                Ok(response) if response.status == 200 => {
+                    findings.ok(format!("remote {} answers", remote))
+                }
+                Ok(response) if response.status == 401 || response.status == 403 => findings.fail(
+                    format!(
+                        "remote {}: the token was refused ({})",
+                        remote, response.status
+                    ),
+                    format!(
+                        "store the server's current token with `auth set sync {}`",
+                        url
+                    ),
+                ),
+                Ok(response) => findings.fail(
+                    format!(
+                        "remote {}: answered {}: {}",
+                        remote,
+                        response.status,
+                        String::from_utf8_lossy(&response.body).trim()
+                    ),
+                    "check that the server runs `serve --sync` at that address",
+                ),
+                Err(err) => findings.fail(
+                    format!("remote {}: {:#}", remote, err),
+                    "check that the server is up and runs `serve --sync` at that address",
+                ),
+            }
+        }
+    }
+}
+
+/// Runs every check, printing each finding as it goes, and returns the exit
+/// code.
+pub fn run(dirs: &Dirs, data_path: &Path, send_notification: bool) -> i32 {
+    let mut findings = Findings::default();
+    if let Some(dir) = data_path.parent() {
+        check_dir(&mut findings, "Data directory", dir);
+    }
+    check_dir(&mut findings, "Config directory", &dirs.config);
+    check_dir(&mut findings, "Cache directory", &dirs.cache);
+
+    let config_path = dirs.config.join("config.json");
+    let config = match config::load(&dirs.config) {
+        Ok(config) => {
+            if config_path.exists() {
+                findings.ok(format!("{} parses", config_path.display()));
+            }
+            Some(config)
+        }
+        Err(err) => {
+            let fix = if format!("{:#}", err).contains("CLI_TASK_MANAGER_") {
+                "correct or unset the environment variables named above".to_owned()
+            } else {
+                format!(
+                    "correct {} by hand, or move it aside to start from the defaults",
+                    config_path.display()
+                )
+            };
+            findings.fail(format!("{:#}", err), fix);
+            None
+        }
+    };
+    check_tasks(&mut findings, data_path);
+    check_locks(&mut findings, data_path);
+    check_notifications(&mut findings, send_notification);
+
+    if let Some(config) = &config {
+        for remote in &config.remotes {
+            check_remote(&mut findings, remote, config.sync_encryption.is_some());
+        }
+    }
+
+    let Findings { failed, warned } = findings;
+    println!();
+    match (failed, warned) {
+        (0, 0) => println!("Everything looks fine."),
+        (0, _) => println!("{} warning(s); commands should still work.", warned),
+        _ => println!("{} problem(s) and {} warning(s) found.", failed, warned),
+    }
+    i32::from(failed > 0)
+}
@@ -0,0 +1,44 @@
+//! Effort durations such as `2h`, `45m`, `1h30m`, or `1.5h`, kept as whole
+//! minutes.
+
+use anyhow::bail;
+
+pub fn parse_minutes(input: &str) -> anyhow::Result<u32> {
+    let text = input.trim().to_ascii_lowercase();
+    if text.is_empty() {
+        bail!("Duration cannot be empty");
+    }
+
+    let mut total = 0.0;
+    let mut number = String::new();
+    for c in text.chars() {
+        match c {
+            '0'..='9' | '.' => number.push(c),
+            'h' | 'm' => {
+                let Ok(value) = number.parse::<f64>() else {
+                    bail!("Invalid duration '{}' (try 2h, 45m, or 1h30m)", input);
+                };
+                total += if c == 'h' { value * 60.0 } else { value };
+                number.clear();
+            }
+            _ => bail!("Invalid duration '{}' (try 2h, 45m, or 1h30m)", input),
+        }
+    }
+    if !number.is_empty() {
+        bail!("Missing unit in duration '{}' (use h or m)", input);
+    }
+
+    let minutes = total.round();
+    if minutes < 1.0 || minutes > f64::from(u32::MAX) {
+        bail!("Duration '{}' is out of range", input);
+    }
+    Ok(minutes as u32)
+}
+
+pub fn format_minutes(minutes: u32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m),
+    }
+}
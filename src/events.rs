@@ -0,0 +1,401 @@
+//! The event-log storage format: the tasks file is a list of what happened,
+//! one JSON event per line, and the tasks are what replaying it gives. A
+//! save never rewrites the file; it appends an event for each task that was
+//! added, edited, completed, reopened, or removed since the last one, with
+//! only the fields that changed. The whole history of every task stays in
+//! the file, cheap to read back or to compare with another copy's.
+//!
+//! ```text
+//! {"event_log":1}
+//! {"at":"2026-10-14T09:00:00Z","event":"added","uuid":"…","changes":{"id":1,"description":"Call Ann",…}}
+//! {"at":"2026-10-14T17:30:00Z","event":"completed","uuid":"…","changes":{"completed":true,…}}
+//! ```
+//!
+//! A field set to `null` in `changes` went back to its default. The first
+//! line after the checksum (see `checksum`) marks the file, so an empty log
+//! keeps its format.
+
+use crate::{checksum, task::Task};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+use uuid::Uuid;
+
+const HEADER: &[u8] = b"{\"event_log\":1}\n";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Added,
+    Edited,
+    Completed,
+    Reopened,
+    Removed,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Event {
+    pub at: DateTime<Utc>,
+    pub event: Kind,
+    pub uuid: Uuid,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub changes: Map<String, Value>,
+}
+
+/// The events in a log, up to `len` bytes into it. A last line cut off
+/// partway, as a crash during a save leaves it, is left out and `cut` set.
+pub struct Log {
+    pub events: Vec<Event>,
+    pub len: usize,
+    pub cut: bool,
+}
+
+/// Whether `byte` can open an event log, which a list of tasks never
+/// starts with.
+pub fn is_log(byte: u8) -> bool {
+    byte == b'{'
+}
+
+pub fn parse(data: &[u8]) -> anyhow::Result<Log> {
+    let Some(body) = data.strip_prefix(HEADER) else {
+        bail!("not an event log (the first line should be {{\"event_log\":1}})");
+    };
+    let mut log = Log {
+        events: Vec::new(),
+        len: HEADER.len(),
+        cut: false,
+    };
+    // Line 1 is the header.
+    for (number, line) in (2..).zip(body.split_inclusive(|b| *b == b'\n')) {
+        if !line.ends_with(b"\n") {
+            if serde_json::from_slice::<Event>(line).is_err() {
+                log.cut = true;
+                break;
+            }
+        } else if line.iter().all(u8::is_ascii_whitespace) {
+            log.len += line.len();
+            continue;
+        }
+        let event = serde_json::from_slice(line)
+            .with_context(|| format!("bad event on line {}", number))?;
+        log.events.push(event);
+        log.len += line.len();
+    }
+    Ok(log)
+}
+
+/// Replays `events` into the fields of each task, in the order added.
+pub fn replay(events: &[Event]) -> Vec<Map<String, Value>> {
+    let mut tasks: Vec<(Uuid, Map<String, Value>)> = Vec::new();
+    let mut at: HashMap<Uuid, usize> = HashMap::new();
+    for event in events {
+        match (event.event, at.get(&event.uuid)) {
+            (Kind::Added, None) => {
+                at.insert(event.uuid, tasks.len());
+                tasks.push((event.uuid, event.changes.clone()));
+            }
+            (Kind::Removed, Some(&i)) => {
+                tasks.remove(i);
+                at.remove(&event.uuid);
+                for position in at.values_mut() {
+                    if *position > i {
+                        *position -= 1;
+                    }
+                }
+            }
+            (Kind::Removed, None) => {}
+            (_, Some(&i)) => apply(&mut tasks[i].1, &event.changes),
+            // An edit to a task the log never added, as when lines were
+            // lost; it starts the task over.
+            (_, None) => {
+                at.insert(event.uuid, tasks.len());
+                tasks.push((event.uuid, event.changes.clone()));
+            }
+        }
+    }
+    tasks.into_iter().map(|(_, fields)| fields).collect()
+}
+
+pub fn apply(fields: &mut Map<String, Value>, changes: &Map<String, Value>) {
+    for (name, value) in changes {
+        if value.is_null() {
+            fields.remove(name);
+        } else {
+            fields.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+/// The tasks the log in `data` holds, as JSON, and whether its last line
+/// was cut off.
+pub fn values(data: &[u8]) -> anyhow::Result<(Vec<Value>, bool)> {
+    let log = parse(data)?;
+    let values = replay(&log.events).into_iter().map(Value::Object).collect();
+    Ok((values, log.cut))
+}
+
+/// `values`, read as tasks.
+pub fn tasks(data: &[u8]) -> anyhow::Result<(Vec<Task>, bool)> {
+    let (values, cut) = values(data)?;
+    let tasks = values
+        .into_iter()
+        .map(|value| serde_json::from_value(value).map_err(Into::into))
+        .collect::<anyhow::Result<_>>()?;
+    Ok((tasks, cut))
+}
+
+/// The events that take `before` to `after`.
+fn diff(
+    before: &[Map<String, Value>],
+    after: &[Task],
+    at: DateTime<Utc>,
+) -> anyhow::Result<Vec<Event>> {
+    let mut old: HashMap<Uuid, &Map<String, Value>> = before
+        .iter()
+        .filter_map(|fields| {
+            let uuid = fields.get("uuid")?.as_str()?.parse().ok()?;
+            Some((uuid, fields))
+        })
+        .collect();
+    let mut events = Vec::new();
+    for task in after {
+        let Value::Object(fields) =
+            serde_json::to_value(task).context("Failed to serialize tasks to JSON")?
+        else {
+            bail!("a task did not serialize to an object");
+        };
+        let Some(previous) = old.remove(&task.uuid) else {
+            events.push(Event {
+                at,
+                event: Kind::Added,
+                uuid: task.uuid,
+                changes: fields,
+            });
+            continue;
+        };
+        let mut changes: Map<String, Value> = fields
+            .iter()
+            .filter(|(name, value)| previous.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        for name in previous.keys().filter(|name| !fields.contains_key(*name)) {
+            changes.insert(name.clone(), Value::Null);
+        }
+        if changes.is_empty() {
+            continue;
+        }
+        let event = match changes.get("completed") {
+            Some(Value::Bool(true)) => Kind::Completed,
+            Some(_) => Kind::Reopened,
+            None => Kind::Edited,
+        };
+        events.push(Event {
+            at,
+            event,
+            uuid: task.uuid,
+            changes,
+        });
+    }
+    // Removed, in the order they were in.
+    for fields in before {
+        if let Some(uuid) = fields.get("uuid").and_then(Value::as_str)
+            && let Ok(uuid) = uuid.parse::<Uuid>()
+            && old.contains_key(&uuid)
+        {
+            events.push(Event {
+                at,
+                event: Kind::Removed,
+                uuid,
+                changes: Map::new(),
+            });
+        }
+    }
+    Ok(events)
+}
+
+/// Appends the events that make the log at `path` hold `tasks`, starting a
+/// new log when the file is not one, and rewrites its first line to match
+/// (see `checksum`).
+pub fn save(path: &Path, tasks: &[Task]) -> anyhow::Result<()> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to read tasks file at {}", path.display()));
+        }
+    };
+    let now = Utc::now();
+    let (seal, data) = checksum::split(&data);
+    if seal.is_none() || !data.first().copied().is_some_and(is_log) {
+        let mut out = HEADER.to_vec();
+        let old = if data.first().copied().is_some_and(is_log) {
+            let log = parse(data)
+                .with_context(|| format!("Failed to parse tasks file at {}", path.display()))?;
+            // A log without the first line, as from a hand edit, keeps its
+            // events; it is rewritten whole to make room for the line.
+            out = data[..log.len].to_vec();
+            if !out.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+            replay(&log.events)
+        } else {
+            Vec::new()
+        };
+        for event in diff(&old, tasks, now)? {
+            line(&mut out, &event)?;
+        }
+        return checksum::write_sealed(path, tasks.len(), &mut |w| Ok(w.write_all(&out)?));
+    }
+
+    let log =
+        parse(data).with_context(|| format!("Failed to parse tasks file at {}", path.display()))?;
+    let mut out = Vec::new();
+    // A last line edited by hand may lack its newline.
+    if !data[..log.len].ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    for event in diff(&replay(&log.events), tasks, now)? {
+        line(&mut out, &event)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open tasks file at {}", path.display()))?;
+    let mut whole = data[..log.len].to_vec();
+    whole.extend(&out);
+    let header = checksum::header(checksum::Sum::of(&whole), tasks.len());
+    // Drops a line a crash cut off, which the journal has restored. The
+    // first line is rewritten last, once what it describes is on disk.
+    file.set_len((checksum::HEADER_LEN + log.len) as u64)
+        .and_then(|_| file.seek(SeekFrom::End(0)))
+        .and_then(|_| file.write_all(&out))
+        .and_then(|_| file.sync_data())
+        .and_then(|_| file.seek(SeekFrom::Start(0)))
+        .and_then(|_| file.write_all(&header))
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("Failed to write tasks file at {}", path.display()))?;
+    Ok(())
+}
+
+fn line(out: &mut Vec<u8>, event: &Event) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *out, event).context("Failed to serialize an event")?;
+    out.push(b'\n');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(id: u32, description: &str) -> Task {
+        serde_json::from_value(json!({
+            "id": id,
+            "uuid": format!("00000000-0000-4000-8000-{:012}", id),
+            "description": description,
+            "completed": false,
+        }))
+        .unwrap()
+    }
+
+    fn log(lines: &[&str]) -> Vec<u8> {
+        let mut data = HEADER.to_vec();
+        for line in lines {
+            data.extend(line.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn events_read_back_as_written() {
+        let event = Event {
+            at: "2026-10-14T09:00:00Z".parse().unwrap(),
+            event: Kind::Reopened,
+            uuid: task(1, "").uuid,
+            changes: json!({ "completed": false, "project": null })
+                .as_object()
+                .unwrap()
+                .clone(),
+        };
+        let mut data = HEADER.to_vec();
+        line(&mut data, &event).unwrap();
+        assert!(String::from_utf8_lossy(&data).contains(r#""event":"reopened""#));
+
+        let parsed = parse(&data).unwrap();
+        assert_eq!((parsed.len, parsed.cut), (data.len(), false));
+        let [back] = &parsed.events[..] else {
+            panic!("expected one event");
+        };
+        assert_eq!(
+            (back.at, back.event, back.uuid, &back.changes),
+            (event.at, event.event, event.uuid, &event.changes)
+        );
+    }
+
+    #[test]
+    fn parsing_skips_blanks_and_stops_at_a_cut_line() {
+        let added = r#"{"at":"2026-10-14T09:00:00Z","event":"added","uuid":"00000000-0000-4000-8000-000000000001","changes":{"id":1}}"#;
+        let whole = format!("{}\n", added);
+        let data = log(&[&whole, "\n", r#"{"at":"2026-10-14T"#]);
+        let parsed = parse(&data).unwrap();
+        assert_eq!(parsed.events.len(), 1);
+        assert!(parsed.cut);
+        assert_eq!(parsed.len, HEADER.len() + whole.len() + 1);
+
+        // A whole last line without its newline still counts.
+        assert!(!parse(&log(&[added])).unwrap().cut);
+
+        let err = parse(&log(&[&whole, "not json\n"])).err().unwrap();
+        assert!(format!("{:#}", err).contains("line 3"));
+        assert!(parse(b"[]").is_err());
+    }
+
+    #[test]
+    fn replay_matches_the_tasks_saved() {
+        let dir =
+            std::env::temp_dir().join(format!("cli_task_manager-events-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tasks.json");
+
+        let mut tasks = vec![task(1, "call Ann"), task(2, "file taxes"), task(3, "water")];
+        tasks[1].project = Some("home".to_owned());
+        save(&path, &tasks).unwrap();
+        tasks[0].description = "call Ann back".to_owned();
+        tasks[1].completed = true;
+        tasks[1].project = None;
+        tasks.remove(2);
+        tasks.push(task(4, "buy stamps"));
+        save(&path, &tasks).unwrap();
+        tasks[1].completed = false;
+        save(&path, &tasks).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let (seal, body) = checksum::split(&data);
+        assert!(seal.is_some());
+        let kinds: Vec<Kind> = parse(body)
+            .unwrap()
+            .events
+            .iter()
+            .map(|e| e.event)
+            .collect();
+        use Kind::*;
+        assert_eq!(
+            kinds,
+            [
+                Added, Added, Added, Edited, Completed, Added, Removed, Reopened
+            ]
+        );
+        let (values, cut) = values(body).unwrap();
+        assert!(!cut);
+        assert_eq!(Value::Array(values), serde_json::to_value(&tasks).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! Whole-list exports for other tools and other people: the raw JSON, or a
+//! standalone HTML page to share with someone who does not use the CLI.
+
+use crate::{
+    task::{ListFilter, Sort, Task},
+    template,
+};
+use chrono::Local;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    Json,
+    /// A styled page grouped by project, with overdue tasks highlighted
+    Html,
+    /// One row per task, for spreadsheets
+    Csv,
+}
+
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "status",
+    "priority",
+    "due",
+    "project",
+    "tags",
+    "assignee",
+    "estimate",
+    "description",
+];
+
+/// The tasks `filter` picks, in `sort` order, written out as `format`.
+pub fn export(
+    tasks: &[Task],
+    format: Format,
+    filter: &ListFilter,
+    sort: &Sort,
+) -> anyhow::Result<String> {
+    let mut shown: Vec<&Task> = tasks.iter().filter(|t| filter.matches(tasks, t)).collect();
+    sort.sort(&mut shown);
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(&shown)?,
+        Format::Html => html(&shown),
+        Format::Csv => csv(tasks, &shown),
+    })
+}
+
+fn csv(tasks: &[Task], shown: &[&Task]) -> String {
+    let mut out = CSV_COLUMNS.join(",") + "\n";
+    for task in shown {
+        let row: Vec<String> = CSV_COLUMNS
+            .iter()
+            .map(|column| csv_field(&template::field(tasks, task, column)))
+            .collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quotes a CSV field when it contains a separator, quote, or line break.
+pub fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Escapes text for HTML and XML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = "
+body { font: 15px/1.4 system-ui, sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; color: #222; }
+h1 { font-size: 1.5em; margin-bottom: 0; }
+.meta { color: #777; margin-top: 0.2em; }
+h2 { font-size: 1.15em; border-bottom: 1px solid #ddd; padding-bottom: 0.2em; margin-top: 1.8em; }
+table { border-collapse: collapse; width: 100%; }
+td, th { text-align: left; padding: 0.3em 0.6em; vertical-align: top; }
+th { font-weight: 600; color: #555; font-size: 0.85em; }
+tr:nth-child(even) td { background: #f7f7f7; }
+.id { color: #888; width: 3em; }
+.done td { color: #999; text-decoration: line-through; }
+.overdue td { background: #fde8e8 !important; }
+.overdue .due { color: #b00020; font-weight: 600; }
+.today .due { color: #a15c00; font-weight: 600; }
+@media print { body { margin: 0; } tr { break-inside: avoid; } }
+";
+
+fn html(tasks: &[&Task]) -> String {
+    let today = Local::now().date_naive();
+    let mut projects: BTreeMap<&str, Vec<&Task>> = BTreeMap::new();
+    for task in tasks {
+        projects
+            .entry(task.project.as_deref().unwrap_or(""))
+            .or_default()
+            .push(task);
+    }
+    let overdue = tasks
+        .iter()
+        .filter(|t| !t.completed && t.due.is_some_and(|d| d < today))
+        .count();
+
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Tasks</title>\n<style>{}</style>\n</head>\n<body>\n\
+         <h1>Tasks</h1>\n<p class=\"meta\">{} task(s), {} overdue, as of {}</p>\n",
+        STYLE,
+        tasks.len(),
+        overdue,
+        today
+    );
+    // Tasks without a project come last, under their own heading.
+    let mut groups: Vec<(&str, Vec<&Task>)> = projects.into_iter().collect();
+    groups.sort_by_key(|(name, _)| name.is_empty());
+    for (name, group) in groups {
+        let heading = if name.is_empty() { "No project" } else { name };
+        page.push_str(&format!(
+            "<h2>{}</h2>\n<table>\n<tr><th class=\"id\">#</th><th>Task</th>\
+             <th>Priority</th><th>Due</th><th>Assignee</th></tr>\n",
+            escape(heading)
+        ));
+        for task in group {
+            let class = match task.due {
+                _ if task.completed => "done",
+                Some(due) if due < today => "overdue",
+                Some(due) if due == today => "today",
+                _ => "",
+            };
+            page.push_str(&format!(
+                "<tr class=\"{}\"><td class=\"id\">{}</td><td>{}</td><td>{}</td>\
+                 <td class=\"due\">{}</td><td>{}</td></tr>\n",
+                class,
+                task.id,
+                escape(&task.description),
+                task.priority.map(|p| p.name()).unwrap_or_default(),
+                task.due.map(|d| d.to_string()).unwrap_or_default(),
+                escape(task.assignee.as_deref().unwrap_or_default())
+            ));
+        }
+        page.push_str("</table>\n");
+    }
+    if tasks.is_empty() {
+        page.push_str("<p>No tasks.</p>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(id: u32, description: &str) -> Task {
+        serde_json::from_value(json!({
+            "id": id,
+            "uuid": format!("00000000-0000-4000-8000-{:012}", id),
+            "description": description,
+            "completed": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain text"), "plain text");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), r#""a,b""#);
+        assert_eq!(csv_field(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\rhere"), "\"cr\rhere\"");
+    }
+
+    #[test]
+    fn csv_rows_follow_the_columns() {
+        let mut tagged = task(2, r#"Buy "good" paint, white"#);
+        tagged.tags = vec!["home".to_owned(), "diy".to_owned()];
+        let tasks = [task(1, "Call Ann"), tagged];
+        let shown: Vec<&Task> = tasks.iter().collect();
+        assert_eq!(
+            csv(&tasks, &shown),
+            "id,status,priority,due,project,tags,assignee,estimate,description\n\
+             1,open,,,,,,,Call Ann\n\
+             2,open,,,,\"home,diy\",,,\"Buy \"\"good\"\" paint, white\"\n"
+        );
+    }
+
+    #[test]
+    fn html_escapes_what_tasks_say() {
+        assert_eq!(
+            escape(r#"<b>"Tom" & 'Jerry'</b>"#),
+            "&lt;b&gt;&quot;Tom&quot; &amp; 'Jerry'&lt;/b&gt;"
+        );
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+
+        let mut task = task(1, "<script>alert(1)</script>");
+        task.project = Some("R&D <lab>".to_owned());
+        task.assignee = Some("\"Ann\"".to_owned());
+        let page = html(&[&task]);
+        assert!(page.contains("<td>&lt;script&gt;alert(1)&lt;/script&gt;</td>"));
+        assert!(page.contains("<h2>R&amp;D &lt;lab&gt;</h2>"));
+        assert!(page.contains("<td>&quot;Ann&quot;</td>"));
+        assert!(!page.contains("<script>"));
+    }
+}
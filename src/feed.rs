@@ -0,0 +1,76 @@
+//! An Atom feed of tasks for feed readers and dashboards, served by `serve`
+//! at `/feed.atom` (open tasks) and `/feed.atom?completed` (tasks finished
+//! in the last two weeks).
+
+use crate::{
+    crdt,
+    export::escape,
+    task::{self, Task},
+};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+/// How far back the completed feed reaches.
+const COMPLETED_DAYS: i64 = 14;
+
+/// When the task last changed, as far as its stamps tell.
+fn updated(task: &Task) -> Option<DateTime<Utc>> {
+    let stamp = crdt::latest(&task.stamps)?;
+    DateTime::from_timestamp_millis(stamp.0 as i64)
+}
+
+/// Renders the feed. `feed_id` should stay the same for the same task list,
+/// so readers recognise it across restarts.
+pub fn atom(tasks: &[Task], feed_id: Uuid, completed: bool) -> String {
+    let now = Utc::now();
+    let mut entries: Vec<(&Task, DateTime<Utc>)> = if completed {
+        let since = now - Duration::days(COMPLETED_DAYS);
+        tasks
+            .iter()
+            .filter(|t| t.completed)
+            .filter_map(|t| Some((t, t.completed_at.filter(|at| *at >= since)?)))
+            .collect()
+    } else {
+        tasks
+            .iter()
+            .filter(|t| !t.completed && !t.someday)
+            .map(|t| (t, updated(t).unwrap_or(now)))
+            .collect()
+    };
+    entries.sort_by_key(|(t, at)| (std::cmp::Reverse(*at), t.id));
+
+    let title = if completed {
+        "Recently completed tasks"
+    } else {
+        "Open tasks"
+    };
+    let feed_updated = entries.first().map_or(now, |(_, at)| *at);
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n  \
+         <id>urn:uuid:{}{}</id>\n  \
+         <title>{}</title>\n  \
+         <updated>{}</updated>\n  \
+         <author><name>cli_task_manager</name></author>\n",
+        feed_id,
+        if completed { "#completed" } else { "" },
+        title,
+        feed_updated.to_rfc3339()
+    );
+    for (task, at) in entries {
+        xml.push_str(&format!(
+            "  <entry>\n    \
+             <id>urn:uuid:{}</id>\n    \
+             <title>{}</title>\n    \
+             <updated>{}</updated>\n    \
+             <content type=\"text\">{}</content>\n  \
+             </entry>\n",
+            task.uuid,
+            escape(&task.description),
+            at.to_rfc3339(),
+            escape(&task::format_line(tasks, task))
+        ));
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
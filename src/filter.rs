@@ -0,0 +1,224 @@
+//! `--filter` expressions: space-separated terms that a task must all
+//! match, in the spirit of Taskwarrior's, e.g.
+//! `"priority:high status:open +finance -blocked report"`.
+//!
+//! - `+tag` and `-tag`: has, or lacks, the tag
+//! - `+OVERDUE`, `-BLOCKED`, ...: has, or lacks, a virtual tag, worked out
+//!   when the filter runs rather than stored
+//! - `field:value`: a `--format` field (`project`, `priority`, `due`,
+//!   `attr.customer`, ...) has the value, ignoring case; `field:` means it
+//!   has none. `due` takes the dates `--due` does, and `status` is `open`
+//!   or `done`.
+//! - any other word: the description contains it
+//!
+//! The virtual tags are `OVERDUE` (open and past due), `TODAY`, `TOMORROW`,
+//! `WEEK` (due this week, Monday to Sunday), `MONTH` (due this month),
+//! `BLOCKED`, `UNBLOCKED`, `ACTIVE` (a timer is running), `PENDING`,
+//! `COMPLETED`, `TAGGED`, `WAITING` (delegated), `SOMEDAY`, `INBOX`,
+//! `HABIT`, `CHILD` (a subtask), and `PARENT` (has subtasks).
+//!
+//! A filter that names a status, or `+COMPLETED` or `+PENDING`, picks
+//! completed tasks as well as open ones, so `status:done` needs no `--all`.
+
+use crate::{deps, report, task, task::Task, template};
+use anyhow::bail;
+use chrono::{Datelike, Days, Local, NaiveDate};
+use std::str::FromStr;
+
+const VIRTUAL_TAGS: &[&str] = &[
+    "OVERDUE",
+    "TODAY",
+    "TOMORROW",
+    "WEEK",
+    "MONTH",
+    "BLOCKED",
+    "UNBLOCKED",
+    "ACTIVE",
+    "PENDING",
+    "COMPLETED",
+    "TAGGED",
+    "WAITING",
+    "SOMEDAY",
+    "INBOX",
+    "HABIT",
+    "CHILD",
+    "PARENT",
+];
+
+#[derive(Clone)]
+enum Term {
+    Tag(String, bool),
+    Virtual(&'static str, bool),
+    Field(String, String),
+    Word(String),
+}
+
+#[derive(Clone)]
+pub struct Filter(Vec<Term>);
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> anyhow::Result<Self> {
+        let mut terms = Vec::new();
+        for word in input.split_whitespace() {
+            let term = if let Some(tag) = word.strip_prefix('+') {
+                tag_term(tag, true)?
+            } else if let Some(tag) = word.strip_prefix('-')
+                && !tag.is_empty()
+            {
+                tag_term(tag, false)?
+            } else if let Some((name, value)) = word.split_once(':') {
+                let name = template::parse_field(&name.to_lowercase())?;
+                let value = match name.as_str() {
+                    "due" if !value.is_empty() => report::parse_date(value)?.to_string(),
+                    "status" if !["open", "done"].contains(&value) => {
+                        bail!("Unknown status {} (use open or done)", value)
+                    }
+                    _ => value.to_lowercase(),
+                };
+                Term::Field(name, value)
+            } else {
+                Term::Word(word.to_lowercase())
+            };
+            terms.push(term);
+        }
+        Ok(Filter(terms))
+    }
+}
+
+/// `+tag` or `-tag`; an all-capitals name is a virtual tag.
+fn tag_term(tag: &str, has: bool) -> anyhow::Result<Term> {
+    if tag.chars().any(|c| c.is_lowercase()) || !tag.chars().any(char::is_alphabetic) {
+        return Ok(Term::Tag(
+            task::parse_tag(tag).map_err(anyhow::Error::msg)?,
+            has,
+        ));
+    }
+    match VIRTUAL_TAGS.iter().find(|name| **name == tag) {
+        Some(name) => Ok(Term::Virtual(name, has)),
+        None => bail!(
+            "Unknown virtual tag {} (known: {}; tags are lowercase)",
+            tag,
+            VIRTUAL_TAGS.join(", ")
+        ),
+    }
+}
+
+/// Whether `task` has virtual tag `name`.
+fn has_virtual(tasks: &[Task], task: &Task, name: &str, today: NaiveDate) -> bool {
+    let due_within =
+        |from: NaiveDate, to: NaiveDate| task.due.is_some_and(|d| from <= d && d <= to);
+    match name {
+        "OVERDUE" => !task.completed && task.due.is_some_and(|d| d < today),
+        "TODAY" => task.due == Some(today),
+        "TOMORROW" => task.due == today.checked_add_days(Days::new(1)),
+        "WEEK" => {
+            let monday = today - Days::new(today.weekday().num_days_from_monday().into());
+            due_within(monday, monday + Days::new(6))
+        }
+        "MONTH" => task
+            .due
+            .is_some_and(|d| (d.year(), d.month()) == (today.year(), today.month())),
+        "BLOCKED" => deps::is_blocked(tasks, task),
+        "UNBLOCKED" => !deps::is_blocked(tasks, task),
+        "ACTIVE" => task.is_running(),
+        "PENDING" => !task.completed,
+        "COMPLETED" => task.completed,
+        "TAGGED" => !task.tags.is_empty(),
+        "WAITING" => task.waiting_on.is_some(),
+        "SOMEDAY" => task.someday,
+        "INBOX" => task.inbox,
+        "HABIT" => task.habit.is_some(),
+        "CHILD" => task.parent.is_some(),
+        "PARENT" => tasks.iter().any(|t| t.parent == Some(task.uuid)),
+        _ => unreachable!("virtual tags are checked when the filter is parsed"),
+    }
+}
+
+impl Filter {
+    /// Whether the filter chooses between open and completed tasks itself.
+    pub fn sets_status(&self) -> bool {
+        self.0.iter().any(|term| match term {
+            Term::Field(name, _) => name == "status",
+            Term::Virtual(name, _) => ["PENDING", "COMPLETED"].contains(name),
+            _ => false,
+        })
+    }
+
+    pub fn matches(&self, tasks: &[Task], task: &Task) -> bool {
+        let today = Local::now().date_naive();
+        self.0.iter().all(|term| match term {
+            Term::Tag(tag, has) => task.tags.contains(tag) == *has,
+            Term::Virtual(name, has) => has_virtual(tasks, task, name, today) == *has,
+            Term::Field(name, value) => template::field(tasks, task, name).to_lowercase() == *value,
+            Term::Word(word) => task.description.to_lowercase().contains(word),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tasks() -> Vec<Task> {
+        [
+            json!({ "id": 1, "description": "Send Invoice", "completed": false,
+                    "priority": "high", "tags": ["finance"], "due": "2000-01-01" }),
+            json!({ "id": 2, "description": "water plants", "completed": true,
+                    "project": "Home" }),
+            json!({ "id": 3, "description": "file taxes", "completed": false,
+                    "project": "home", "tags": ["finance", "blocked"] }),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut task)| {
+            task["uuid"] = json!(format!("00000000-0000-4000-8000-{:012}", i + 1));
+            serde_json::from_value(task).unwrap()
+        })
+        .collect()
+    }
+
+    fn ids(filter: &str) -> Vec<u32> {
+        let tasks = tasks();
+        let filter: Filter = filter.parse().unwrap();
+        tasks
+            .iter()
+            .filter(|task| filter.matches(&tasks, task))
+            .map(|task| task.id)
+            .collect()
+    }
+
+    #[test]
+    fn terms_must_all_match() {
+        assert_eq!(ids(""), [1, 2, 3]);
+        assert_eq!(ids("+finance"), [1, 3]);
+        assert_eq!(ids("+finance -blocked"), [1]);
+        assert_eq!(ids("project:HOME"), [2, 3]);
+        assert_eq!(ids("project:"), [1]);
+        assert_eq!(ids("priority:high invoice"), [1]);
+        assert_eq!(ids("status:done"), [2]);
+        assert_eq!(ids("due:2000-01-01 +OVERDUE"), [1]);
+        assert_eq!(ids("+PENDING -TAGGED"), Vec::<u32>::new());
+        assert_eq!(ids("-COMPLETED file"), [3]);
+    }
+
+    #[test]
+    fn statuses_are_noticed() {
+        let sets = |text: &str| text.parse::<Filter>().unwrap().sets_status();
+        assert!(sets("status:open"));
+        assert!(sets("+COMPLETED"));
+        assert!(sets("-PENDING +finance"));
+        assert!(!sets("+OVERDUE project:home"));
+    }
+
+    #[test]
+    fn bad_terms_are_refused() {
+        for bad in ["+NOPE", "status:closed", "colour:red", "due:someday-soon"] {
+            assert!(bad.parse::<Filter>().is_err(), "{} parsed", bad);
+        }
+        // A lone `-` is a word, not an empty tag.
+        assert_eq!(ids("-"), Vec::<u32>::new());
+    }
+}
@@ -0,0 +1,147 @@
+//! Taskwarrior-style contexts: named `--filter` expressions, one of which
+//! can be made active so that `list`, `next`, and `report` only see the
+//! tasks it matches until it is cleared. Unlike the contexts of `--in`,
+//! which are separate task lists, these only narrow what is shown.
+//!
+//! ```text
+//! context define work '--filter project:work'
+//! context set work
+//! context clear
+//! ```
+//!
+//! Both the definitions and the active one are kept in config.json, under
+//! `"contexts"` and `"context"`.
+
+use crate::{config, filter::Filter, view};
+use anyhow::{Context, bail};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, path::Path};
+
+/// The expression in a definition, given either as `--filter EXPR` as for
+/// `list`, or as the bare expression.
+fn expression(definition: &str) -> anyhow::Result<String> {
+    let words = view::split(definition)?;
+    let expression = match words.as_slice() {
+        [flag, expression] if flag == "--filter" => expression.clone(),
+        [word] if word.starts_with("--filter=") => word["--filter=".len()..].to_owned(),
+        _ if words.first().is_some_and(|w| w.starts_with("--")) => {
+            bail!("A context is one --filter expression, e.g. '--filter project:work'")
+        }
+        _ => definition.trim().to_owned(),
+    };
+    expression
+        .parse::<Filter>()
+        .with_context(|| format!("Invalid filter {:?}", expression))?;
+    Ok(expression)
+}
+
+/// Checks the definitions and the active context, as loaded from
+/// config.json.
+pub fn validate(contexts: &BTreeMap<String, String>, active: Option<&str>) -> anyhow::Result<()> {
+    for (name, expression) in contexts {
+        expression
+            .parse::<Filter>()
+            .with_context(|| format!("Invalid filter for context {}", name))?;
+    }
+    if let Some(name) = active
+        && !contexts.contains_key(name)
+    {
+        bail!(
+            "The active context {} is not defined under \"contexts\"",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// The active context's name and filter, if one is set.
+pub fn active(config_dir: &Path) -> anyhow::Result<Option<(String, Filter)>> {
+    let config = config::load(config_dir)?;
+    let Some(name) = config.context else {
+        return Ok(None);
+    };
+    let filter = config.contexts[&name].parse()?;
+    Ok(Some((name, filter)))
+}
+
+pub fn define(config_dir: &Path, name: &str, definition: &str) -> anyhow::Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Context names may only use letters, digits, - and _");
+    }
+    let expression = expression(definition)?;
+    config::update(config_dir, |root| {
+        let contexts = root
+            .entry("contexts")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(contexts) = contexts {
+            contexts.insert(name.to_owned(), Value::String(expression.clone()));
+        }
+    })?;
+    println!("Defined context {}: {}", name, expression);
+    Ok(())
+}
+
+pub fn remove(config_dir: &Path, name: &str) -> anyhow::Result<()> {
+    let config = config::load(config_dir)?;
+    if !config.contexts.contains_key(name) {
+        bail!("No context named {}", name);
+    }
+    let was_active = config.context.as_deref() == Some(name);
+    config::update(config_dir, |root| {
+        if let Some(Value::Object(contexts)) = root.get_mut("contexts") {
+            contexts.remove(name);
+        }
+        if was_active {
+            root.remove("context");
+        }
+    })?;
+    println!("Removed context {}.", name);
+    Ok(())
+}
+
+pub fn set(config_dir: &Path, name: &str) -> anyhow::Result<()> {
+    let Some(expression) = config::load(config_dir)?.contexts.remove(name) else {
+        bail!(
+            "No context named {} (define one with `context define`)",
+            name
+        );
+    };
+    config::update(config_dir, |root| {
+        root.insert("context".to_owned(), Value::String(name.to_owned()));
+    })?;
+    println!("Context {} is active: {}", name, expression);
+    Ok(())
+}
+
+pub fn clear(config_dir: &Path) -> anyhow::Result<()> {
+    if config::load(config_dir)?.context.is_none() {
+        println!("No context is active.");
+        return Ok(());
+    }
+    config::update(config_dir, |root| {
+        root.remove("context");
+    })?;
+    println!("Cleared the context; every task is shown again.");
+    Ok(())
+}
+
+/// Lists the definitions, marking the active one with `*`.
+pub fn print_contexts(config_dir: &Path) -> anyhow::Result<()> {
+    let config = config::load(config_dir)?;
+    if config.contexts.is_empty() {
+        println!("No contexts defined.");
+    }
+    for (name, expression) in &config.contexts {
+        let mark = if config.context.as_ref() == Some(name) {
+            '*'
+        } else {
+            ' '
+        };
+        println!("{} {}: {}", mark, name, expression);
+    }
+    Ok(())
+}
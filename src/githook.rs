@@ -0,0 +1,186 @@
+//! Git hooks tying commits to tasks. `post-commit` adds a task for every
+//! `Task: <description>` trailer in the message and completes each task the
+//! message mentions as `closes task#<id>`; `prepare-commit-msg` lists open
+//! tasks in the message template as a reminder of both.
+
+use crate::{
+    crdt::Clock,
+    task::{self, Task},
+};
+use anyhow::{Context, bail};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const HOOKS: [&str; 2] = ["prepare-commit-msg", "post-commit"];
+/// Marks hook scripts written by `install`, so they can be replaced or
+/// removed without touching hooks the user wrote.
+const MARKER: &str = "# Installed by cli_task_manager git-hook";
+/// How many open tasks the commit message template lists.
+const LISTED: usize = 10;
+
+fn git(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn hooks_dir() -> anyhow::Result<PathBuf> {
+    // Asking git honours core.hooksPath and linked worktrees.
+    Ok(PathBuf::from(
+        git(&["rev-parse", "--git-path", "hooks"])?.trim(),
+    ))
+}
+
+fn ours(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|s| s.contains(MARKER))
+}
+
+/// Writes both hooks into the current repository. Hooks of the same name
+/// that were not written here are left alone unless `force` is set.
+pub fn install(force: bool) -> anyhow::Result<()> {
+    let dir = hooks_dir()?;
+    for hook in HOOKS {
+        let path = dir.join(hook);
+        if path.exists() && !ours(&path) && !force {
+            bail!(
+                "{} already exists; use --force to replace it",
+                path.display()
+            );
+        }
+    }
+
+    let exe = std::env::current_exe().context("Failed to find the running executable")?;
+    let exe = format!("'{}'", exe.to_string_lossy().replace('\'', r"'\''"));
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for hook in HOOKS {
+        let path = dir.join(hook);
+        let script = format!(
+            "#!/bin/sh\n{}\nexec {} git-hook {} \"$@\"\n",
+            MARKER, exe, hook
+        );
+        fs::write(&path, script).with_context(|| format!("Failed to write {}", path.display()))?;
+        // Git for Windows runs hooks through its own sh whatever the mode.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                .with_context(|| format!("Failed to make {} executable", path.display()))?;
+        }
+        println!("Installed {}", path.display());
+    }
+    Ok(())
+}
+
+pub fn uninstall() -> anyhow::Result<()> {
+    let dir = hooks_dir()?;
+    for hook in HOOKS {
+        let path = dir.join(hook);
+        if ours(&path) {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            println!("Removed {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Appends open tasks to the message git is about to open in the editor.
+/// `source` is git's second hook argument; messages given with `-m`, and
+/// merges, squashes, and amends, are left as they are.
+pub fn prepare_message(tasks: &[Task], file: &Path, source: Option<&str>) -> anyhow::Result<()> {
+    if source.is_some_and(|s| s != "template") {
+        return Ok(());
+    }
+    let open: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| task::is_actionable(tasks, t))
+        .take(LISTED)
+        .collect();
+    if open.is_empty() {
+        return Ok(());
+    }
+
+    let mut text = String::from(
+        "\n# Open tasks. Write \"closes task#<id>\" to complete one, or add a\n\
+         # \"Task: <description>\" trailer to create one.\n",
+    );
+    for task in open {
+        text.push_str(&format!("#   {}: {}\n", task.id, task.description));
+    }
+    fs::OpenOptions::new()
+        .append(true)
+        .open(file)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .with_context(|| format!("Failed to update {}", file.display()))
+}
+
+/// Ids mentioned as `closes task#<id>` (also `close` or `closed`, in any
+/// case).
+fn closed_ids(message: &str) -> Vec<u32> {
+    let lower = message.to_lowercase();
+    let mut ids = Vec::new();
+    for (at, _) in lower.match_indices("task#") {
+        let verb = lower[..at].trim_end().rsplit(char::is_whitespace).next();
+        if !matches!(verb, Some("close" | "closes" | "closed")) {
+            continue;
+        }
+        let digits: String = lower[at + 5..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        if let Ok(id) = digits.parse() {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Applies the trailers and `closes` references of the commit just made.
+/// Returns whether any task changed. Unknown ids are only reported, since
+/// the commit has already happened.
+pub fn after_commit(tasks: &mut Vec<Task>, clock: &Clock) -> anyhow::Result<bool> {
+    let commit = git(&["rev-parse", "--short", "HEAD"])?;
+    let commit = commit.trim();
+    let message = git(&["log", "-1", "--format=%B"])?;
+    let trailers = git(&["log", "-1", "--format=%(trailers:key=Task,valueonly)"])?;
+
+    let mut changed = false;
+    for description in trailers.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let id = task::add_task(
+            tasks,
+            description.to_owned(),
+            task::NewTask::default(),
+            clock,
+        )?;
+        task::find_task_mut(tasks, id)?.log(&format!("created by commit {}", commit), clock);
+        println!("Added task {}: {}", id, description);
+        changed = true;
+    }
+
+    for id in closed_ids(&message) {
+        match tasks.iter().find(|t| t.id == id) {
+            None => eprintln!("Commit mentions task#{}, but there is no such task", id),
+            Some(task) if task.completed => {}
+            Some(_) => {
+                let note = format!("Closed by commit {}", commit);
+                task::mark_done(tasks, id, Some(note), clock)?;
+                println!("Completed task {}", id);
+                changed = true;
+            }
+        }
+    }
+    Ok(changed)
+}
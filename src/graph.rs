@@ -0,0 +1,97 @@
+//! Renders dependencies and subtasks as a graph description that Graphviz or
+//! a Markdown viewer with Mermaid support can draw.
+
+use crate::task::Task;
+use clap::ValueEnum;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    /// Graphviz, e.g. `graph | dot -Tsvg > plan.svg`
+    #[default]
+    Dot,
+    /// A Mermaid flowchart for a ```mermaid block
+    Mermaid,
+}
+
+enum Edge {
+    /// The first task must be done before the second.
+    Blocks,
+    /// The second task is a subtask of the first.
+    Contains,
+}
+
+/// Prints open tasks (every task with `all`) and the edges between them.
+/// Arrows point from what must happen first to what it unblocks, and from a
+/// parent to its subtasks.
+pub fn print_graph(tasks: &[Task], format: Format, all: bool) {
+    let shown: Vec<&Task> = tasks.iter().filter(|t| all || !t.completed).collect();
+    let node = |uuid: &Uuid| shown.iter().find(|t| t.uuid == *uuid).map(|t| t.id);
+
+    let mut edges = Vec::new();
+    for task in &shown {
+        for dependency in task.depends_on.iter().filter_map(node) {
+            edges.push((dependency, task.id, Edge::Blocks));
+        }
+        if let Some(parent) = task.parent.as_ref().and_then(node) {
+            edges.push((parent, task.id, Edge::Contains));
+        }
+    }
+
+    match format {
+        Format::Dot => {
+            println!("digraph tasks {{");
+            println!("  rankdir=LR;");
+            println!("  node [shape=box];");
+            for task in &shown {
+                let style = if task.completed {
+                    " style=filled fillcolor=lightgrey"
+                } else {
+                    ""
+                };
+                println!(
+                    "  t{} [label=\"{}\"{}];",
+                    task.id,
+                    dot_escape(&label(task)),
+                    style
+                );
+            }
+            for (from, to, edge) in &edges {
+                match edge {
+                    Edge::Blocks => println!("  t{} -> t{};", from, to),
+                    Edge::Contains => println!("  t{} -> t{} [style=dashed];", from, to),
+                }
+            }
+            println!("}}");
+        }
+        Format::Mermaid => {
+            println!("flowchart LR");
+            for task in &shown {
+                println!("  t{}[\"{}\"]", task.id, label(task).replace('"', "#quot;"));
+            }
+            for (from, to, edge) in &edges {
+                match edge {
+                    Edge::Blocks => println!("  t{} --> t{}", from, to),
+                    Edge::Contains => println!("  t{} -.-> t{}", from, to),
+                }
+            }
+            let done: Vec<String> = shown
+                .iter()
+                .filter(|t| t.completed)
+                .map(|t| format!("t{}", t.id))
+                .collect();
+            if !done.is_empty() {
+                println!("  classDef done fill:#ddd,color:#666");
+                println!("  class {} done", done.join(","));
+            }
+        }
+    }
+}
+
+fn label(task: &Task) -> String {
+    format!("{}: {}", task.id, task.description)
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
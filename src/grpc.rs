@@ -0,0 +1,660 @@
+//! `serve --grpc`: the `Tasks` service of `proto/tasks.proto`, for typed
+//! clients in other languages. Each call is answered by the same code as the
+//! HTTP route it mirrors (see `server`), so the bearer token, which goes in
+//! the `authorization` metadata, the tasks each user sees, and the masking
+//! are the same either way.
+
+use crate::{
+    access::Access,
+    crdt::{self, Stamp},
+    habit::Cadence,
+    scan,
+    server::{self, NewComment, Outcome, Refusal, ServeOptions},
+    sync::{ChangeSet, SealedPush},
+    task::{self, Task},
+};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::{net::TcpListener, path::PathBuf, sync::Arc};
+use tonic::{Code, Request, Response, Status};
+use uuid::Uuid;
+
+pub mod proto {
+    tonic::include_proto!("cli_task_manager.v1");
+}
+
+use proto::tasks_server::{Tasks, TasksServer};
+
+/// Answers gRPC calls on `listener` until the server stops.
+pub fn serve(
+    listener: TcpListener,
+    data_path: PathBuf,
+    options: Arc<ServeOptions>,
+) -> anyhow::Result<()> {
+    listener
+        .set_nonblocking(true)
+        .context("Failed to set up the gRPC listener")?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the gRPC runtime")?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::from_std(listener)
+            .context("Failed to set up the gRPC listener")?;
+        tonic::transport::Server::builder()
+            .add_service(TasksServer::new(Service { data_path, options }))
+            .serve_with_incoming(tonic::transport::server::TcpIncoming::from(listener))
+            .await
+            .context("gRPC server failed")
+    })
+}
+
+pub struct Service {
+    data_path: PathBuf,
+    options: Arc<ServeOptions>,
+}
+
+impl Service {
+    fn access<T>(&self, request: &Request<T>) -> Result<Access<'_>, Status> {
+        request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| self.options.authenticate(token))
+            .ok_or_else(|| Status::unauthenticated("Missing or invalid bearer token"))
+    }
+
+    fn sync_enabled(&self) -> Result<(), Status> {
+        if !self.options.sync {
+            return Err(Status::unimplemented(
+                "Sync is off; start the server with --sync",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Logs the call as `handle` logs HTTP requests, masking failures.
+    fn answer<T>(&self, call: &str, result: Result<T, Status>) -> Result<Response<T>, Status> {
+        let result = result.map_err(|status| {
+            let message = self.options.redact.mask(status.message()).into_owned();
+            Status::new(status.code(), message)
+        });
+        let code = result.as_ref().map_or_else(Status::code, |_| Code::Ok);
+        eprintln!("gRPC {} -> {:?}", call, code);
+        result.map(Response::new)
+    }
+}
+
+fn internal(err: anyhow::Error) -> Status {
+    Status::internal(format!("{:#}", err))
+}
+
+fn refused(refusal: Refusal) -> Status {
+    let code = match refusal.status {
+        400 => Code::InvalidArgument,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        409 => Code::Aborted,
+        _ => Code::Unknown,
+    };
+    Status::new(code, refusal.message)
+}
+
+fn settle<T>(outcome: Outcome<T>) -> Result<T, Status> {
+    outcome.map_err(internal)?.map_err(refused)
+}
+
+fn invalid(err: anyhow::Error) -> Status {
+    Status::invalid_argument(format!("{:#}", err))
+}
+
+#[tonic::async_trait]
+impl Tasks for Service {
+    async fn list_tasks(
+        &self,
+        request: Request<proto::ListTasksRequest>,
+    ) -> Result<Response<proto::ListTasksResponse>, Status> {
+        let result = (|| {
+            let access = self.access(&request)?;
+            let wanted = request.get_ref();
+            let page = (wanted.limit.is_some() || wanted.offset.is_some()).then(|| task::Page {
+                offset: wanted.offset.unwrap_or_default() as usize,
+                limit: wanted.limit.map(|limit| limit as usize),
+            });
+            let _turn = server::turn();
+            let tasks =
+                server::list(&self.data_path, &self.options, &access, page).map_err(internal)?;
+            Ok(proto::ListTasksResponse {
+                tasks: tasks.iter().map(to_proto).collect(),
+            })
+        })();
+        self.answer("ListTasks", result)
+    }
+
+    async fn list_comments(
+        &self,
+        request: Request<proto::ListCommentsRequest>,
+    ) -> Result<Response<proto::ListCommentsResponse>, Status> {
+        let result = (|| {
+            let access = self.access(&request)?;
+            let uuid = parse_uuid(&request.get_ref().task_uuid).map_err(invalid)?;
+            let _turn = server::turn();
+            let comments = settle(server::comments(
+                &self.data_path,
+                &access,
+                uuid,
+                &self.options.redact,
+            ))?;
+            Ok(proto::ListCommentsResponse {
+                comments: comments.iter().map(comment_to_proto).collect(),
+            })
+        })();
+        self.answer("ListComments", result)
+    }
+
+    async fn add_comment(
+        &self,
+        request: Request<proto::AddCommentRequest>,
+    ) -> Result<Response<proto::Comment>, Status> {
+        let result = (|| {
+            let access = self.access(&request)?;
+            let wanted = request.get_ref();
+            let uuid = parse_uuid(&wanted.task_uuid).map_err(invalid)?;
+            let new = NewComment {
+                text: wanted.text.clone(),
+                reply_to: optional(&wanted.reply_to)
+                    .map(parse_uuid)
+                    .transpose()
+                    .map_err(invalid)?,
+            };
+            let _turn = server::turn();
+            let comment = settle(server::add_comment(&self.data_path, &access, uuid, new))?;
+            Ok(comment_to_proto(&comment))
+        })();
+        self.answer("AddComment", result)
+    }
+
+    async fn get_feed(
+        &self,
+        request: Request<proto::GetFeedRequest>,
+    ) -> Result<Response<proto::Feed>, Status> {
+        let result = (|| {
+            let access = self.access(&request)?;
+            let completed = request.get_ref().completed;
+            let _turn = server::turn();
+            let atom = server::feed_for(&self.data_path, &self.options, &access, completed)
+                .map_err(internal)?;
+            Ok(proto::Feed { atom })
+        })();
+        self.answer("GetFeed", result)
+    }
+
+    async fn pull(
+        &self,
+        request: Request<proto::PullRequest>,
+    ) -> Result<Response<proto::Snapshot>, Status> {
+        let result = (|| {
+            self.sync_enabled()?;
+            let access = self.access(&request)?;
+            let _turn = server::turn();
+            let snapshot = server::snapshot(&self.data_path, &access).map_err(internal)?;
+            Ok(proto::Snapshot {
+                revision: snapshot.revision,
+                tasks: snapshot.tasks.iter().map(to_proto).collect(),
+                tombstones: tombstones_to_proto(&snapshot.tombstones),
+            })
+        })();
+        self.answer("Pull", result)
+    }
+
+    async fn push(
+        &self,
+        request: Request<proto::ChangeSet>,
+    ) -> Result<Response<proto::PushResponse>, Status> {
+        let result = (|| {
+            self.sync_enabled()?;
+            let access = self.access(&request)?;
+            let changes = changes_from_proto(request.get_ref()).map_err(invalid)?;
+            let _turn = server::turn();
+            let revision = settle(server::push(&self.data_path, &access, changes))?;
+            Ok(proto::PushResponse { revision })
+        })();
+        self.answer("Push", result)
+    }
+
+    async fn pull_sealed(
+        &self,
+        request: Request<proto::PullSealedRequest>,
+    ) -> Result<Response<proto::Sealed>, Status> {
+        let result = (|| {
+            self.sync_enabled()?;
+            let access = self.access(&request)?;
+            let _turn = server::turn();
+            let sealed = settle(server::sealed(&self.data_path, &access))?;
+            Ok(proto::Sealed {
+                revision: sealed.revision,
+                payload: sealed.payload.unwrap_or_default(),
+            })
+        })();
+        self.answer("PullSealed", result)
+    }
+
+    async fn push_sealed(
+        &self,
+        request: Request<proto::SealedPush>,
+    ) -> Result<Response<proto::PushResponse>, Status> {
+        let result = (|| {
+            self.sync_enabled()?;
+            let access = self.access(&request)?;
+            let push = SealedPush {
+                base_revision: request.get_ref().base_revision.clone(),
+                payload: request.get_ref().payload.clone(),
+            };
+            let _turn = server::turn();
+            let revision = settle(server::push_sealed(&self.data_path, &access, push))?;
+            Ok(proto::PushResponse { revision })
+        })();
+        self.answer("PushSealed", result)
+    }
+}
+
+/// Unset fields are empty strings in the messages.
+fn optional(text: &str) -> Option<&str> {
+    Some(text).filter(|text| !text.is_empty())
+}
+
+fn parse_uuid(text: &str) -> anyhow::Result<Uuid> {
+    text.parse()
+        .with_context(|| format!("{:?} is not a uuid", text))
+}
+
+fn parse_time(text: &str) -> anyhow::Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .map(|time| time.with_timezone(&Utc))
+        .with_context(|| format!("{:?} is not an RFC 3339 time", text))
+}
+
+fn parse_date(text: &str) -> anyhow::Result<NaiveDate> {
+    text.parse()
+        .with_context(|| format!("{:?} is not a YYYY-MM-DD date", text))
+}
+
+fn time_to_proto(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339()
+}
+
+fn stamp_to_proto(stamp: &Stamp) -> proto::Stamp {
+    proto::Stamp {
+        millis: stamp.0,
+        replica: stamp.1.to_string(),
+    }
+}
+
+fn stamp_from_proto(stamp: &proto::Stamp) -> anyhow::Result<Stamp> {
+    Ok(Stamp(stamp.millis, parse_uuid(&stamp.replica)?))
+}
+
+fn tombstones_to_proto(
+    tombstones: &crdt::Tombstones,
+) -> std::collections::BTreeMap<String, proto::Stamp> {
+    tombstones
+        .iter()
+        .map(|(uuid, stamp)| (uuid.to_string(), stamp_to_proto(stamp)))
+        .collect()
+}
+
+fn comment_to_proto(comment: &task::Comment) -> proto::Comment {
+    proto::Comment {
+        id: comment.id.to_string(),
+        author: comment.author.clone(),
+        at: time_to_proto(&comment.at),
+        text: comment.text.clone(),
+        reply_to: comment
+            .reply_to
+            .map(|uuid| uuid.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn comment_from_proto(comment: &proto::Comment) -> anyhow::Result<task::Comment> {
+    Ok(task::Comment {
+        id: parse_uuid(&comment.id)?,
+        author: comment.author.clone(),
+        at: parse_time(&comment.at)?,
+        text: comment.text.clone(),
+        reply_to: optional(&comment.reply_to).map(parse_uuid).transpose()?,
+    })
+}
+
+fn priority_to_proto(priority: Option<task::Priority>) -> proto::Priority {
+    match priority {
+        None => proto::Priority::Unset,
+        Some(task::Priority::Low) => proto::Priority::Low,
+        Some(task::Priority::Medium) => proto::Priority::Medium,
+        Some(task::Priority::High) => proto::Priority::High,
+        Some(task::Priority::Urgent) => proto::Priority::Urgent,
+    }
+}
+
+fn priority_from_proto(value: i32) -> anyhow::Result<Option<task::Priority>> {
+    Ok(
+        match proto::Priority::try_from(value)
+            .with_context(|| format!("Unknown priority {}", value))?
+        {
+            proto::Priority::Unset => None,
+            proto::Priority::Low => Some(task::Priority::Low),
+            proto::Priority::Medium => Some(task::Priority::Medium),
+            proto::Priority::High => Some(task::Priority::High),
+            proto::Priority::Urgent => Some(task::Priority::Urgent),
+        },
+    )
+}
+
+pub fn to_proto(task: &Task) -> proto::Task {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    let date = |value: Option<NaiveDate>| value.map(|d| d.to_string()).unwrap_or_default();
+    proto::Task {
+        id: task.id,
+        uuid: task.uuid.to_string(),
+        description: task.description.clone(),
+        completed: task.completed,
+        pinned: task.pinned,
+        inbox: task.inbox,
+        someday: task.someday,
+        completed_at: task
+            .completed_at
+            .as_ref()
+            .map(time_to_proto)
+            .unwrap_or_default(),
+        note: text(&task.note),
+        project: text(&task.project),
+        tags: task.tags.clone(),
+        milestone: text(&task.milestone),
+        assignee: text(&task.assignee),
+        priority: priority_to_proto(task.priority).into(),
+        due: date(task.due),
+        scheduled: date(task.scheduled),
+        estimate: task.estimate.unwrap_or_default(),
+        waiting_on: text(&task.waiting_on),
+        depends_on: task.depends_on.iter().map(Uuid::to_string).collect(),
+        order: task.order,
+        parent: task.parent.map(|uuid| uuid.to_string()).unwrap_or_default(),
+        link: text(&task.link),
+        attributes: task.attributes.clone(),
+        checklist: task
+            .checklist
+            .iter()
+            .map(|item| proto::ChecklistItem {
+                text: item.text.clone(),
+                done: item.done,
+            })
+            .collect(),
+        time_log: task
+            .time_log
+            .iter()
+            .map(|interval| proto::Interval {
+                start: time_to_proto(&interval.start),
+                end: interval.end.as_ref().map(time_to_proto).unwrap_or_default(),
+            })
+            .collect(),
+        comments: task.comments.iter().map(comment_to_proto).collect(),
+        history: task
+            .history
+            .iter()
+            .map(|entry| proto::HistoryEntry {
+                at: time_to_proto(&entry.at),
+                event: entry.event.clone(),
+            })
+            .collect(),
+        stamps: task
+            .stamps
+            .iter()
+            .map(|(field, stamp)| (field.clone(), stamp_to_proto(stamp)))
+            .collect(),
+        source: task.source.as_ref().map(|source| proto::Location {
+            file: source.file.clone(),
+            line: source.line as u32,
+        }),
+        habit: task.habit.map(String::from).unwrap_or_default(),
+        occurrences: task.occurrences.iter().map(time_to_proto).collect(),
+    }
+}
+
+pub fn from_proto(task: &proto::Task) -> anyhow::Result<Task> {
+    let text = |value: &str| optional(value).map(str::to_owned);
+    let date = |value: &str| optional(value).map(parse_date).transpose();
+    Ok(Task {
+        id: task.id,
+        uuid: parse_uuid(&task.uuid)?,
+        description: task.description.clone(),
+        completed: task.completed,
+        pinned: task.pinned,
+        inbox: task.inbox,
+        someday: task.someday,
+        completed_at: optional(&task.completed_at).map(parse_time).transpose()?,
+        note: text(&task.note),
+        project: text(&task.project),
+        tags: task.tags.clone(),
+        milestone: text(&task.milestone),
+        assignee: text(&task.assignee),
+        priority: priority_from_proto(task.priority)?,
+        due: date(&task.due)?,
+        scheduled: date(&task.scheduled)?,
+        estimate: Some(task.estimate).filter(|minutes| *minutes > 0),
+        waiting_on: text(&task.waiting_on),
+        depends_on: task
+            .depends_on
+            .iter()
+            .map(|uuid| parse_uuid(uuid))
+            .collect::<anyhow::Result<_>>()?,
+        order: task.order,
+        parent: optional(&task.parent).map(parse_uuid).transpose()?,
+        source: task.source.as_ref().map(|source| scan::Location {
+            file: source.file.clone(),
+            line: source.line as usize,
+        }),
+        link: text(&task.link),
+        attributes: task.attributes.clone(),
+        checklist: task
+            .checklist
+            .iter()
+            .map(|item| task::ChecklistItem {
+                text: item.text.clone(),
+                done: item.done,
+            })
+            .collect(),
+        habit: optional(&task.habit)
+            .map(|habit| Cadence::try_from(habit.to_owned()))
+            .transpose()?,
+        occurrences: task
+            .occurrences
+            .iter()
+            .map(|time| parse_time(time))
+            .collect::<anyhow::Result<_>>()?,
+        time_log: task
+            .time_log
+            .iter()
+            .map(|interval| {
+                Ok(task::Interval {
+                    start: parse_time(&interval.start)?,
+                    end: optional(&interval.end).map(parse_time).transpose()?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?,
+        comments: task
+            .comments
+            .iter()
+            .map(comment_from_proto)
+            .collect::<anyhow::Result<_>>()?,
+        history: task
+            .history
+            .iter()
+            .map(|entry| {
+                Ok(task::HistoryEntry {
+                    at: parse_time(&entry.at)?,
+                    event: entry.event.clone(),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?,
+        stamps: task
+            .stamps
+            .iter()
+            .map(|(field, stamp)| Ok((field.clone(), stamp_from_proto(stamp)?)))
+            .collect::<anyhow::Result<_>>()?,
+    })
+}
+
+fn changes_from_proto(changes: &proto::ChangeSet) -> anyhow::Result<ChangeSet> {
+    Ok(ChangeSet {
+        base_revision: changes.base_revision.clone(),
+        upserts: changes
+            .upserts
+            .iter()
+            .map(from_proto)
+            .collect::<anyhow::Result<_>>()?,
+        deletes: changes
+            .deletes
+            .iter()
+            .map(|uuid| parse_uuid(uuid))
+            .collect::<anyhow::Result<_>>()?,
+        tombstones: changes
+            .tombstones
+            .iter()
+            .map(|(uuid, stamp)| Ok((parse_uuid(uuid)?, stamp_from_proto(stamp)?)))
+            .collect::<anyhow::Result<_>>()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::tasks_client::TasksClient;
+    use serde_json::json;
+    use tonic::transport::Channel;
+
+    fn task(id: u32, project: &str, description: &str) -> Task {
+        serde_json::from_value(json!({
+            "id": id,
+            "uuid": format!("00000000-0000-4000-8000-{:012}", id),
+            "description": description,
+            "completed": false,
+            "project": project,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn tasks_survive_the_messages() {
+        let full: Task = serde_json::from_value(json!({
+            "id": 7,
+            "uuid": "00000000-0000-4000-8000-000000000007",
+            "description": "file taxes",
+            "completed": true,
+            "pinned": true,
+            "completed_at": "2026-04-01T09:30:00Z",
+            "note": "sent",
+            "project": "home",
+            "tags": ["finance"],
+            "priority": "urgent",
+            "due": "2026-04-15",
+            "estimate": 90,
+            "depends_on": ["00000000-0000-4000-8000-000000000001"],
+            "order": 0,
+            "parent": "00000000-0000-4000-8000-000000000002",
+            "source": { "file": "src/lib.rs", "line": 12 },
+            "attributes": { "customer": "acme" },
+            "checklist": [{ "text": "forms", "done": true }],
+            "habit": "3x/week",
+            "occurrences": ["2026-03-30T08:00:00Z"],
+            "time_log": [{ "start": "2026-03-30T08:00:00Z" }],
+            "comments": [{
+                "id": "00000000-0000-4000-8000-0000000000c1",
+                "author": "sam",
+                "at": "2026-03-30T08:05:00Z",
+                "text": "done?"
+            }],
+            "history": [{ "at": "2026-04-01T09:30:00Z", "event": "completed" }],
+            "stamps": { "description": [5, "00000000-0000-4000-8000-0000000000ff"] }
+        }))
+        .unwrap();
+        for task in [full, task(1, "work", "plain")] {
+            assert!(from_proto(&to_proto(&task)).unwrap() == task);
+        }
+        let mut bad = to_proto(&task(1, "work", "plain"));
+        bad.due = "friday".to_owned();
+        assert!(from_proto(&bad).is_err());
+    }
+
+    #[test]
+    fn calls_are_answered_as_over_http() {
+        let dir =
+            std::env::temp_dir().join(format!("cli_task_manager-grpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data_path = dir.join("tasks.json");
+        let tasks = [
+            task(1, "household", "buy milk"),
+            task(2, "work", "rotate key sk-abcdefghij"),
+        ];
+        task::save_tasks(&data_path, &tasks).unwrap();
+        let options = ServeOptions {
+            addr: String::new(),
+            grpc: None,
+            sync: false,
+            token: Some("owner-token".to_owned()),
+            users: serde_json::from_value(json!([
+                { "name": "sam", "token": "sam-token", "projects": { "household": "write" } }
+            ]))
+            .unwrap(),
+            redact: serde_json::from_value(json!(["sk-[a-z]+"])).unwrap(),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener, data_path, Arc::new(options)));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let channel = Channel::from_shared(format!("http://{}", addr))
+                .unwrap()
+                .connect()
+                .await
+                .unwrap();
+            let mut client = TasksClient::new(channel);
+            let call = |token: Option<&str>| {
+                let mut request = Request::new(proto::ListTasksRequest::default());
+                if let Some(token) = token {
+                    let value = format!("Bearer {}", token).parse().unwrap();
+                    request.metadata_mut().insert("authorization", value);
+                }
+                request
+            };
+
+            let err = client.list_tasks(call(None)).await.unwrap_err();
+            assert_eq!(err.code(), Code::Unauthenticated);
+
+            let sam = client.list_tasks(call(Some("sam-token"))).await.unwrap();
+            let seen: Vec<u32> = sam.get_ref().tasks.iter().map(|t| t.id).collect();
+            assert_eq!(seen, [1]);
+
+            let owner = client.list_tasks(call(Some("owner-token"))).await.unwrap();
+            let descriptions: Vec<&str> = owner
+                .get_ref()
+                .tasks
+                .iter()
+                .map(|t| t.description.as_str())
+                .collect();
+            assert_eq!(descriptions.len(), 2);
+            assert!(!descriptions.iter().any(|d| d.contains("sk-abc")));
+
+            let mut pull = Request::new(proto::PullRequest {});
+            pull.metadata_mut()
+                .insert("authorization", "Bearer owner-token".parse().unwrap());
+            let err = client.pull(pull).await.unwrap_err();
+            assert_eq!(err.code(), Code::Unimplemented);
+        });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Habits: tasks that are never finished, only done again. `done` on a habit
+//! records an occurrence, and the `habits` view compares recent weeks with
+//! the habit's cadence.
+
+use crate::{crdt::Clock, table::Table, task::Task};
+use anyhow::bail;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How often a habit should happen, e.g. `3x/week` or `daily`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cadence {
+    pub times: u32,
+    pub per: Period,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+}
+
+impl Cadence {
+    /// Occurrences wanted in a whole week.
+    pub fn weekly_target(self) -> u32 {
+        match self.per {
+            Period::Day => self.times * 7,
+            Period::Week => self.times,
+        }
+    }
+}
+
+impl fmt::Display for Cadence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let per = match self.per {
+            Period::Day => "day",
+            Period::Week => "week",
+        };
+        write!(f, "{}x/{}", self.times, per)
+    }
+}
+
+impl TryFrom<String> for Cadence {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> anyhow::Result<Self> {
+        parse_cadence(&text)
+    }
+}
+
+impl From<Cadence> for String {
+    fn from(cadence: Cadence) -> String {
+        cadence.to_string()
+    }
+}
+
+pub fn parse_cadence(input: &str) -> anyhow::Result<Cadence> {
+    let text = input.trim().to_ascii_lowercase();
+    let (times, per) = match text.as_str() {
+        "daily" => ("1", "day"),
+        "weekly" => ("1", "week"),
+        _ => match text.split_once('/') {
+            Some((times, per)) => (times.trim_end_matches('x'), per),
+            None => bail!("Invalid cadence '{}' (try 3x/week or daily)", input),
+        },
+    };
+    let per = match per {
+        "day" | "d" => Period::Day,
+        "week" | "wk" | "w" => Period::Week,
+        _ => bail!("Unknown period in '{}' (use day or week)", input),
+    };
+    match times.parse::<u32>() {
+        Ok(times) if times > 0 => Ok(Cadence { times, per }),
+        _ => bail!("Invalid cadence '{}' (try 3x/week or daily)", input),
+    }
+}
+
+/// Monday of the local week containing `date`.
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+}
+
+fn count_between(task: &Task, from: NaiveDate, to: NaiveDate) -> u32 {
+    task.occurrences
+        .iter()
+        .map(|at| at.with_timezone(&Local).date_naive())
+        .filter(|day| *day >= from && *day < to)
+        .count() as u32
+}
+
+/// Progress in the current period, e.g. `1/3 this week`.
+pub fn progress(task: &Task) -> Option<String> {
+    let cadence = task.habit?;
+    let today = Local::now().date_naive();
+    let (from, label) = match cadence.per {
+        Period::Day => (today, "today"),
+        Period::Week => (week_start(today), "this week"),
+    };
+    let done = count_between(task, from, today + Duration::days(1));
+    Some(format!("{}/{} {}", done, cadence.times, label))
+}
+
+pub fn record(task: &mut Task, clock: &Clock) -> anyhow::Result<()> {
+    if task.habit.is_none() {
+        bail!("Task {} is not a habit", task.id);
+    }
+    task.occurrences.push(Utc::now());
+    task.touch("occurrences", clock);
+    Ok(())
+}
+
+/// The week a habit started: when its description was first stamped, or its
+/// earliest occurrence if that is older. Weeks before it are not held
+/// against the habit.
+fn first_week(task: &Task) -> Option<NaiveDate> {
+    let created = task
+        .stamps
+        .get("description")
+        .and_then(|stamp| DateTime::<Utc>::from_timestamp_millis(stamp.0 as i64));
+    let first = created
+        .into_iter()
+        .chain(task.occurrences.iter().copied())
+        .min()?;
+    Some(week_start(first.with_timezone(&Local).date_naive()))
+}
+
+/// One row per habit with a column for each of the last `weeks` weeks (the
+/// current one last) and adherence over the finished weeks.
+pub fn print_habits(tasks: &[Task], weeks: u32) {
+    let habits: Vec<&Task> = tasks.iter().filter(|t| t.habit.is_some()).collect();
+    if habits.is_empty() {
+        println!("No habits (add one with `add --habit 3x/week`).");
+        return;
+    }
+
+    let this_week = week_start(Local::now().date_naive());
+    let starts: Vec<NaiveDate> = (0..weeks.max(1))
+        .rev()
+        .map(|i| this_week - Duration::weeks(i64::from(i)))
+        .collect();
+    let mut headers = vec!["Habit".to_owned(), "Cadence".to_owned()];
+    headers.extend(starts.iter().map(|s| s.format("%b %d").to_string()));
+    headers.push("Adherence".to_owned());
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    let numeric: Vec<usize> = (2..headers.len()).collect();
+    let mut table = Table::new(&headers).align_right(&numeric);
+
+    for task in habits {
+        let cadence = task.habit.expect("filtered to habits");
+        let target = cadence.weekly_target();
+        let mut row = vec![
+            format!("{}: {}", task.id, task.description),
+            cadence.to_string(),
+        ];
+        let first = first_week(task);
+        let (mut met, mut wanted) = (0, 0);
+        for start in &starts {
+            if first.is_some_and(|first| *start < first) {
+                row.push("-".to_owned());
+                continue;
+            }
+            let done = count_between(task, *start, *start + Duration::weeks(1));
+            row.push(format!("{}/{}", done, target));
+            if *start < this_week {
+                met += done.min(target);
+                wanted += target;
+            }
+        }
+        row.push(if wanted == 0 {
+            "-".to_owned()
+        } else {
+            format!("{:.0}%", f64::from(met) / f64::from(wanted) * 100.0)
+        });
+        table.push(row);
+    }
+    table.print();
+}
@@ -0,0 +1,232 @@
+//! `history`: what changed across every task, and when, oldest first and
+//! grouped by day, so a past day's work can be pieced back together.
+//!
+//! A tasks file stored as an event log (see `events`) holds every change.
+//! JSON and MessagePack files only keep when each field of a task last
+//! changed, from its stamps, beside the events in its own history, such as
+//! completions; older edits to the same field are gone.
+
+use crate::{checksum, crdt, events, redact, report, storage, table, task::Task};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Datelike, Days, Local, TimeZone, Utc, Weekday};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, fs, path::Path};
+use uuid::Uuid;
+
+/// Fields every change touches, which say nothing on their own.
+const BOOKKEEPING: &[&str] = &["stamps", "history"];
+
+struct Change {
+    at: DateTime<Utc>,
+    id: Option<u32>,
+    description: String,
+    what: String,
+}
+
+/// The start of `--since`: a span back from now (`7d`, `12h`, `2w`), a
+/// weekday (the latest one, today included), or a date as for `--due`.
+pub fn parse_since(input: &str) -> anyhow::Result<DateTime<Utc>> {
+    let lower = input.to_lowercase();
+    if let Some(unit) = lower.chars().last()
+        && let Ok(count) = lower[..lower.len() - unit.len_utf8()].parse::<i64>()
+    {
+        let span = match unit {
+            'h' => chrono::Duration::hours(count),
+            'd' => chrono::Duration::days(count),
+            'w' => chrono::Duration::weeks(count),
+            _ => bail!("Unknown unit in {:?} (use h, d or w, e.g. 7d)", input),
+        };
+        return Ok(Utc::now() - span);
+    }
+    if let Ok(weekday) = lower.parse::<Weekday>() {
+        let today = Local::now().date_naive();
+        let back =
+            (7 + today.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+        return Ok(report::start_of(today - Days::new(back.into())));
+    }
+    report::parse_date(input)
+        .map(report::start_of)
+        .with_context(|| {
+            format!(
+                "Invalid --since {:?} (use e.g. 7d, tuesday or a date)",
+                input
+            )
+        })
+}
+
+/// The end of `--until`: the close of the day given, as for `--due`.
+pub fn parse_until(input: &str) -> anyhow::Result<DateTime<Utc>> {
+    let day = report::parse_date(input)?;
+    Ok(report::start_of(day + Days::new(1)))
+}
+
+/// The changes in an event log, read from the file at `path`.
+fn from_log(path: &Path, redact: &redact::Rules) -> anyhow::Result<Vec<Change>> {
+    let data = fs::read(path)
+        .with_context(|| format!("Failed to read tasks file at {}", path.display()))?;
+    let (_, data) = checksum::split(&data);
+    let log = events::parse(data)
+        .with_context(|| format!("Failed to parse tasks file at {}", path.display()))?;
+    let mut states: HashMap<Uuid, Map<String, Value>> = HashMap::new();
+    let mut changes = Vec::new();
+    for event in &log.events {
+        let fields = states.entry(event.uuid).or_default();
+        events::apply(fields, &event.changes);
+        let what = match event.event {
+            events::Kind::Added => "added".to_owned(),
+            events::Kind::Completed => "completed".to_owned(),
+            events::Kind::Reopened => "reopened".to_owned(),
+            events::Kind::Removed => "removed".to_owned(),
+            events::Kind::Edited => {
+                let mut names: Vec<&str> = event
+                    .changes
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|name| !BOOKKEEPING.contains(name))
+                    .collect();
+                // A history entry says what happened better than the field.
+                let logged = event
+                    .changes
+                    .get("history")
+                    .and_then(Value::as_array)
+                    .and_then(|entries| entries.last())
+                    .and_then(|entry| entry.get("event"))
+                    .and_then(Value::as_str);
+                match (names.is_empty(), logged) {
+                    (true, Some(logged)) => logged.to_owned(),
+                    (true, None) => continue,
+                    (false, _) => {
+                        names.sort();
+                        format!("changed {}", names.join(", "))
+                    }
+                }
+            }
+        };
+        changes.push(Change {
+            at: event.at,
+            id: fields.get("id").and_then(Value::as_u64).map(|id| id as u32),
+            description: redact
+                .mask(
+                    fields
+                        .get("description")
+                        .and_then(Value::as_str)
+                        .unwrap_or(""),
+                )
+                .into_owned(),
+            what,
+        });
+        if event.event == events::Kind::Removed {
+            states.remove(&event.uuid);
+        }
+    }
+    Ok(changes)
+}
+
+fn at(millis: u64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis as i64)
+        .single()
+        .unwrap_or_default()
+}
+
+/// The changes the tasks themselves record: their history, when each field
+/// last changed, and tombstones for the ones removed.
+fn from_tasks(path: &Path, tasks: &[Task]) -> anyhow::Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    for task in tasks {
+        let change = |at, what| Change {
+            at,
+            id: Some(task.id),
+            description: task.description.clone(),
+            what,
+        };
+        for entry in &task.history {
+            changes.push(change(entry.at, entry.event.clone()));
+        }
+        // Fields stamped within a second of each other changed together;
+        // the history already tells of the changes it logged.
+        let mut stamped: Vec<(u64, &str)> = task
+            .stamps
+            .iter()
+            .filter(|(name, _)| !BOOKKEEPING.contains(&name.as_str()))
+            .map(|(name, stamp)| (stamp.0, name.as_str()))
+            .collect();
+        stamped.sort();
+        let mut groups: Vec<(u64, Vec<&str>)> = Vec::new();
+        for (millis, name) in stamped {
+            match groups.last_mut() {
+                Some((start, names)) if millis - *start < 1000 => names.push(name),
+                _ => groups.push((millis, vec![name])),
+            }
+        }
+        for (millis, mut names) in groups {
+            let when = at(millis);
+            let logged = task
+                .history
+                .iter()
+                .any(|e| (e.at - when).num_milliseconds().abs() < 1000);
+            if logged {
+                continue;
+            }
+            // Adding a task stamps both; nothing else does at once.
+            let what = if names.contains(&"description") && names.contains(&"completed") {
+                "added".to_owned()
+            } else {
+                names.sort();
+                format!("changed {}", names.join(", "))
+            };
+            changes.push(change(when, what));
+        }
+    }
+    for (uuid, stamp) in crdt::load_tombstones(path)? {
+        changes.push(Change {
+            at: at(stamp.0),
+            id: None,
+            description: format!("({})", uuid),
+            what: "removed".to_owned(),
+        });
+    }
+    Ok(changes)
+}
+
+/// Prints the changes made from `since` until `until`.
+pub fn print(
+    path: &Path,
+    tasks: &[Task],
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    redact: &redact::Rules,
+) -> anyhow::Result<()> {
+    let log = storage::detect(path) == storage::Format::Events;
+    let mut changes = if log {
+        from_log(path, redact)?
+    } else {
+        from_tasks(path, tasks)?
+    };
+    changes.retain(|c| since.is_none_or(|s| c.at >= s) && until.is_none_or(|u| c.at < u));
+    changes.sort_by_key(|c| c.at);
+    if changes.is_empty() {
+        println!("No changes in that time.");
+    }
+    let mut day = None;
+    for change in &changes {
+        let local = change.at.with_timezone(&Local);
+        if day != Some(local.date_naive()) {
+            day = Some(local.date_naive());
+            println!("{}", local.format("%a %Y-%m-%d"));
+        }
+        let id = change.id.map(|id| id.to_string()).unwrap_or_default();
+        println!(
+            "  {}  {:>4} {}: {}",
+            local.format("%H:%M"),
+            id,
+            table::truncate(&change.description, 50),
+            change.what
+        );
+    }
+    if !log {
+        println!(
+            "(Only each field's latest change is known for this file; `convert --format events` keeps every one from then on.)"
+        );
+    }
+    Ok(())
+}
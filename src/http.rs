@@ -0,0 +1,332 @@
+//! Just enough HTTP/1.1 for the sync server and client: one request per
+//! connection, `Content-Length` bodies. The server speaks no TLS; put it
+//! behind SSH or a reverse proxy when the network is not trusted. The client
+//! is `ureq`, which speaks TLS itself for `https://` URLs.
+//!
+//! A request must arrive whole within `TIMEOUT`, with at most `MAX_HEADERS`
+//! headers in `MAX_HEAD` bytes, so a slow or endless client cannot hold a
+//! connection open.
+
+use anyhow::{Context, bail};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+const MAX_BODY: usize = 64 * 1024 * 1024;
+const MAX_HEAD: u64 = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.header("Authorization")?.strip_prefix("Bearer ")
+    }
+
+    /// The value of query parameter `name`, or `""` if it has none. Values
+    /// are not percent-decoded.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.path.split_once('?')?;
+        query
+            .split('&')
+            .find_map(|pair| match pair.split_once('=') {
+                Some((key, value)) if key == name => Some(value),
+                None if pair == name => Some(""),
+                _ => None,
+            })
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// Where a remote server lives, parsed from `http://host[:port][/prefix]`
+/// or the same with `https://`.
+pub struct Endpoint {
+    host: String,
+    prefix: String,
+    tls: bool,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        let (rest, tls) = match (url.strip_prefix("http://"), url.strip_prefix("https://")) {
+            (Some(rest), _) => (rest, false),
+            (_, Some(rest)) => (rest, true),
+            _ => bail!(
+                "Unsupported URL {}: only http:// and https:// are supported",
+                url
+            ),
+        };
+        let (authority, prefix) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        if authority.is_empty() {
+            bail!("Missing host in URL {}", url);
+        }
+        let host = if authority.contains(':') {
+            authority.to_owned()
+        } else {
+            format!("{}:{}", authority, if tls { 443 } else { 80 })
+        };
+        Ok(Self {
+            host,
+            prefix: prefix.to_owned(),
+            tls,
+        })
+    }
+
+    /// Sends one request and reads the whole response.
+    pub fn send(
+        &self,
+        method: &str,
+        path: &str,
+        token: &str,
+        body: Option<&[u8]>,
+    ) -> anyhow::Result<Response> {
+        let url = format!(
+            "{}://{}{}{}",
+            if self.tls { "https" } else { "http" },
+            self.host,
+            self.prefix,
+            path
+        );
+        let request = ureq::http::Request::builder()
+            .method(method)
+            .uri(&url)
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", "application/json");
+        let response = match body {
+            Some(body) => agent(TIMEOUT).run(request.body(body)?),
+            None => agent(TIMEOUT).run(request.body(())?),
+        };
+        let mut response = response.with_context(|| format!("Failed to reach {}", self.host))?;
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(MAX_BODY as u64)
+            .read_to_vec()
+            .with_context(|| format!("Failed to read response from {}", self.host))?;
+        Ok(Response {
+            status: response.status().as_u16(),
+            body,
+        })
+    }
+}
+
+/// The client every outgoing request goes through, which reports error
+/// statuses as responses rather than failures and gives up on a request
+/// after `timeout`.
+pub fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_global(Some(timeout))
+        .build()
+        .new_agent()
+}
+
+/// A connection read until a fixed time, after which reads fail.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request took too long to arrive",
+            ));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        (&mut &*self.stream).read(buf)
+    }
+}
+
+pub fn read_request(stream: &TcpStream) -> anyhow::Result<Request> {
+    parse_request(Deadline {
+        stream,
+        until: Instant::now() + TIMEOUT,
+    })
+}
+
+fn parse_request(reader: impl Read) -> anyhow::Result<Request> {
+    let mut reader = BufReader::new(reader);
+    let mut head = (&mut reader).take(MAX_HEAD);
+
+    let line = read_line(&mut head).context("Failed to read request line")?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        bail!("Malformed request line: {}", line.trim());
+    };
+    let (method, path) = (method.to_owned(), path.to_owned());
+
+    let headers = read_headers(&mut head)?;
+    let body = read_body(&mut reader, &headers)?;
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+pub fn write_response(
+    mut stream: &TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// One line of the request head, which must end before `MAX_HEAD` runs out.
+fn read_line(head: &mut io::Take<impl BufRead>) -> anyhow::Result<String> {
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        if head.limit() == 0 {
+            bail!("Request head exceeds {} bytes", MAX_HEAD);
+        }
+        bail!("Connection closed mid-request");
+    }
+    Ok(line)
+}
+
+fn read_headers(head: &mut io::Take<impl BufRead>) -> anyhow::Result<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(head).context("Failed to read headers")?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            bail!("Request has more than {} headers", MAX_HEADERS);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    Ok(headers)
+}
+
+/// Without a `Content-Length`, a request has no body.
+fn read_body(reader: &mut impl Read, headers: &[(String, String)]) -> anyhow::Result<Vec<u8>> {
+    let length = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+        .map(|(_, v)| v.parse::<usize>())
+        .transpose()
+        .context("Invalid Content-Length header")?;
+
+    let mut body = Vec::new();
+    match length {
+        Some(len) if len > MAX_BODY => bail!("Body of {} bytes exceeds the limit", len),
+        Some(len) => {
+            body.resize(len, 0);
+            reader.read_exact(&mut body).context("Truncated body")?;
+        }
+        None => {}
+    }
+    Ok(body)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_default_the_port_by_scheme() {
+        let plain = Endpoint::parse("http://nas/tasks/").unwrap();
+        assert_eq!(
+            (plain.host.as_str(), plain.prefix.as_str(), plain.tls),
+            ("nas:80", "/tasks", false)
+        );
+        let tls = Endpoint::parse("https://example.com").unwrap();
+        assert_eq!(
+            (tls.host.as_str(), tls.prefix.as_str(), tls.tls),
+            ("example.com:443", "", true)
+        );
+        let port = Endpoint::parse("https://example.com:8443/v1").unwrap();
+        assert_eq!(
+            (port.host.as_str(), port.prefix.as_str()),
+            ("example.com:8443", "/v1")
+        );
+        assert!(Endpoint::parse("ftp://example.com").is_err());
+        assert!(Endpoint::parse("https:///sync").is_err());
+    }
+
+    #[test]
+    fn requests_parse_within_limits() {
+        let request =
+            parse_request(&b"PUT /sync?since=3 HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}"[..])
+                .unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.query("since")),
+            ("PUT", Some("3"))
+        );
+        assert_eq!(request.body, b"{}");
+
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X: y\r\n".repeat(MAX_HEADERS + 1)
+        );
+        let err = parse_request(many.as_bytes()).err().unwrap();
+        assert!(format!("{:#}", err).contains("more than"));
+
+        let long = format!(
+            "GET / HTTP/1.1\r\nX: {}\r\n\r\n",
+            "y".repeat(MAX_HEAD as usize)
+        );
+        let err = parse_request(long.as_bytes()).err().unwrap();
+        assert!(format!("{:#}", err).contains("exceeds"));
+
+        assert!(parse_request(&b"GET / HTTP/1.1\r\nX: y"[..]).is_err());
+    }
+}
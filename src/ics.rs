@@ -0,0 +1,211 @@
+//! The stored schedule as an iCalendar file of time blocks, for calendar
+//! apps. Each open task with a scheduled day and an estimate becomes one
+//! event per day it spans, laid end to end from the start of the working day.
+
+use crate::{plan, task::Task};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// iCalendar events (VEVENT), one per block of work
+    Ics,
+}
+
+/// A stretch of time set aside for a task.
+struct Block<'a> {
+    task: &'a Task,
+    start: NaiveDateTime,
+    minutes: u32,
+}
+
+/// Lays scheduled tasks out as blocks. Within a day tasks follow `plan`
+/// order; work that does not fit carries over to the next day with room.
+fn blocks<'a>(
+    tasks: &'a [Task],
+    capacity: &dyn Fn(NaiveDate) -> u32,
+    day_start: NaiveTime,
+) -> anyhow::Result<Vec<Block<'a>>> {
+    let mut scheduled: Vec<(NaiveDate, usize, &Task)> = plan::order(tasks)?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, s)| {
+            let task = s.task;
+            Some((task.scheduled?, i, task)).filter(|_| task.estimate.is_some_and(|e| e > 0))
+        })
+        .collect();
+    scheduled.sort_by_key(|(day, i, _)| (*day, *i));
+
+    let mut blocks = Vec::new();
+    let Some(&(mut day, _, _)) = scheduled.first() else {
+        return Ok(blocks);
+    };
+    let mut used = 0;
+    for (scheduled, _, task) in scheduled {
+        if scheduled > day {
+            day = scheduled;
+            used = 0;
+        }
+        let mut left = task.estimate.unwrap_or(0);
+        while left > 0 {
+            let room = capacity(day).saturating_sub(used);
+            if room == 0 {
+                day += Duration::days(1);
+                used = 0;
+                continue;
+            }
+            let take = left.min(room);
+            blocks.push(Block {
+                task,
+                start: day.and_time(day_start) + Duration::minutes(used.into()),
+                minutes: take,
+            });
+            used += take;
+            left -= take;
+        }
+    }
+    Ok(blocks)
+}
+
+/// Escapes a TEXT value. Line breaks of any kind become `\n`.
+fn escape(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Folds a content line at 75 octets with CRLF and a leading space, as the
+/// format requires, without splitting a character.
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+pub fn export(
+    tasks: &[Task],
+    capacity: &dyn Fn(NaiveDate) -> u32,
+    day_start: NaiveTime,
+) -> anyhow::Result<String> {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//cli_task_manager//schedule//EN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+    ];
+    let mut part = 0;
+    let mut previous = None;
+    for block in blocks(tasks, capacity, day_start)? {
+        part = if previous == Some(block.task.uuid) {
+            part + 1
+        } else {
+            1
+        };
+        previous = Some(block.task.uuid);
+        let end = block.start + Duration::minutes(block.minutes.into());
+        let mut description = format!("Task {}", block.task.id);
+        if let Some(project) = &block.task.project {
+            description.push_str(&format!(" in {}", project));
+        }
+        if let Some(due) = block.task.due {
+            description.push_str(&format!(", due {}", due));
+        }
+        lines.extend([
+            "BEGIN:VEVENT".to_owned(),
+            // Stable across exports, so re-importing updates the events.
+            format!("UID:{}-{}@cli_task_manager", block.task.uuid, part),
+            format!("DTSTAMP:{}", stamp),
+            // Floating local times: the blocks follow the calendar's zone.
+            format!("DTSTART:{}", block.start.format("%Y%m%dT%H%M%S")),
+            format!("DTEND:{}", end.format("%Y%m%dT%H%M%S")),
+            format!("SUMMARY:{}", escape(&block.task.description)),
+            format!("DESCRIPTION:{}", escape(&description)),
+            "TRANSP:OPAQUE".to_owned(),
+            "END:VEVENT".to_owned(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_owned());
+    Ok(lines.iter().map(|l| fold(l)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn unfold(text: &str) -> String {
+        text.replace("\r\n ", "")
+    }
+
+    #[test]
+    fn text_values_are_escaped() {
+        assert_eq!(escape("plain text"), "plain text");
+        assert_eq!(escape(r"a\b;c,d"), r"a\\b\;c\,d");
+        assert_eq!(escape("one\ntwo\r\nthree\rfour"), r"one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn long_lines_fold_at_75_octets() {
+        assert_eq!(fold("SUMMARY:short"), "SUMMARY:short\r\n");
+        let exact = "x".repeat(75);
+        assert_eq!(fold(&exact), format!("{}\r\n", exact));
+
+        let long = format!("SUMMARY:{}", "y".repeat(200));
+        let folded = fold(&long);
+        let lines: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        assert_eq!(unfold(&folded), format!("{}\r\n", long));
+    }
+
+    #[test]
+    fn folding_keeps_characters_whole() {
+        let line = format!("{}é日本", "x".repeat(74));
+        let folded = fold(&line);
+        assert_eq!(folded, format!("{}\r\n é日本\r\n", "x".repeat(74)));
+        let many = "日".repeat(60);
+        let folded = fold(&many);
+        assert!(folded.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(unfold(&folded), format!("{}\r\n", many));
+    }
+
+    #[test]
+    fn exported_events_escape_and_fold() {
+        let task: Task = serde_json::from_value(json!({
+            "id": 4,
+            "uuid": "00000000-0000-4000-8000-000000000004",
+            "description": format!("Plan offsite; venue, food\nand {}", "travel ".repeat(10)),
+            "completed": false,
+            "scheduled": "2026-10-14",
+            "estimate": 90,
+        }))
+        .unwrap();
+        let calendar = export(
+            std::slice::from_ref(&task),
+            &|_| 480,
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+        )
+        .unwrap();
+        assert!(calendar.split("\r\n").all(|line| line.len() <= 75));
+        let calendar = unfold(&calendar);
+        assert!(calendar.contains(&format!(
+            "SUMMARY:Plan offsite\\; venue\\, food\\nand {}\r\n",
+            "travel ".repeat(10)
+        )));
+        assert!(calendar.contains("DTSTART:20261014T090000\r\nDTEND:20261014T103000\r\n"));
+    }
+}
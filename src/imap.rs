@@ -0,0 +1,444 @@
+//! Turning unread email into tasks, over IMAP with the `imap` crate and
+//! `rustls` for `imaps://` folders.
+//!
+//! Each unread message becomes a task named after its subject, linked to the
+//! message by its `Message-ID` as a `mid:` URL. Fetching the headers marks
+//! the message read, and the link keeps a message that is marked unread
+//! again from being added twice.
+
+use crate::{
+    crdt::Clock,
+    task::{self, Task},
+};
+use anyhow::{Context, anyhow, bail};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::Path,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct Mailbox {
+    /// The folder to read, such as `imaps://mail.example.com/INBOX`.
+    pub url: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+/// A connection to the server, in the clear or over TLS.
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// Where a folder lives, parsed from `imaps://host[:port]/folder` or the
+/// same with `imap://`. The folder defaults to `INBOX`.
+#[derive(Debug, PartialEq)]
+struct Folder {
+    tls: bool,
+    host: String,
+    port: u16,
+    name: String,
+}
+
+impl Folder {
+    fn parse(url: &str) -> anyhow::Result<Self> {
+        let (rest, tls) = match (url.strip_prefix("imap://"), url.strip_prefix("imaps://")) {
+            (Some(rest), _) => (rest, false),
+            (_, Some(rest)) => (rest, true),
+            _ => bail!(
+                "Unsupported URL {}: only imap:// and imaps:// are supported",
+                url
+            ),
+        };
+        let (authority, name) = rest.split_once('/').unwrap_or((rest, ""));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("Invalid port in URL {}", url))?,
+            ),
+            None => (authority, if tls { 993 } else { 143 }),
+        };
+        if host.is_empty() {
+            bail!("Missing host in URL {}", url);
+        }
+        let name = percent_decode(name.trim_end_matches('/'))
+            .with_context(|| format!("Invalid folder in URL {}", url))?;
+        Ok(Self {
+            tls,
+            host: host.to_owned(),
+            port,
+            name: if name.is_empty() {
+                "INBOX".to_owned()
+            } else {
+                name
+            },
+        })
+    }
+
+    fn connect(&self) -> anyhow::Result<Box<dyn Stream>> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .with_context(|| format!("Failed to connect to {}:{}", self.host, self.port))?;
+        tcp.set_read_timeout(Some(TIMEOUT))?;
+        tcp.set_write_timeout(Some(TIMEOUT))?;
+        if !self.tls {
+            return Ok(Box::new(tcp));
+        }
+        let roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let name = rustls::pki_types::ServerName::try_from(self.host.clone())
+            .with_context(|| format!("Invalid host name {}", self.host))?;
+        let tls = rustls::ClientConnection::new(Arc::new(config), name)?;
+        Ok(Box::new(rustls::StreamOwned::new(tls, tcp)))
+    }
+}
+
+/// `%XX` escapes in a URL path decoded.
+fn percent_decode(text: &str) -> Option<String> {
+    let mut out = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            out.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// What a task is made from: a message's subject and `Message-ID`.
+#[derive(Debug, Default, PartialEq)]
+struct Message {
+    subject: Option<String>,
+    message_id: Option<String>,
+}
+
+impl Message {
+    fn parse(headers: &str) -> Self {
+        let mut message = Self::default();
+        for line in unfold(headers) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("subject") {
+                message.subject = Some(decode_words(value));
+            } else if name.eq_ignore_ascii_case("message-id") {
+                message.message_id = Some(value.trim_matches(['<', '>']).to_owned());
+            }
+        }
+        message
+    }
+}
+
+impl Mailbox {
+    /// The unread messages in the folder, oldest first, which fetching marks
+    /// read.
+    fn unseen(&self) -> anyhow::Result<Vec<Message>> {
+        let folder = Folder::parse(&self.url)?;
+        let Some(user) = &self.user else {
+            bail!("Reading {} needs a login name (--user)", self.url);
+        };
+        let Some(password) = &self.password else {
+            bail!(
+                "No password for {}: pass --password or store one with `auth set imap {}`",
+                user,
+                user
+            );
+        };
+        let mut client = imap::Client::new(folder.connect()?);
+        client
+            .read_greeting()
+            .with_context(|| format!("Failed to reach {}", folder.host))?;
+        let mut session = client.login(user, password).map_err(|(err, _)| {
+            anyhow!("Failed to log in to {} as {}: {}", folder.host, user, err)
+        })?;
+        session
+            .select(&folder.name)
+            .with_context(|| format!("Failed to open {}", self.url))?;
+        let mut uids: Vec<u32> = session
+            .uid_search("UNSEEN")
+            .with_context(|| format!("Failed to search {}", self.url))?
+            .into_iter()
+            .collect();
+        uids.sort_unstable();
+        let mut messages = Vec::new();
+        if !uids.is_empty() {
+            let set = uids
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            let fetched = session
+                .uid_fetch(&set, "BODY[HEADER]")
+                .with_context(|| format!("Failed to read {}", self.url))?;
+            let mut fetched: Vec<_> = fetched.iter().collect();
+            fetched.sort_by_key(|fetch| fetch.uid);
+            for fetch in fetched {
+                let headers = String::from_utf8_lossy(fetch.header().unwrap_or_default());
+                messages.push(Message::parse(&headers));
+            }
+        }
+        // The messages are in hand; a failed goodbye changes nothing.
+        let _ = session.logout();
+        Ok(messages)
+    }
+}
+
+/// Joins header lines continued on the next line with leading whitespace.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match lines.last_mut() {
+            Some(last) if line.starts_with([' ', '\t']) => {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
+
+/// Decodes RFC 2047 encoded words such as `=?UTF-8?B?...?=`. Words in
+/// charsets other than UTF-8 and ASCII are left as they are.
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..].split_once("?=").and_then(|(word, tail)| {
+            let mut parts = word.splitn(3, '?');
+            let (charset, encoding, text) = (parts.next()?, parts.next()?, parts.next()?);
+            if !charset.eq_ignore_ascii_case("utf-8") && !charset.eq_ignore_ascii_case("us-ascii") {
+                return None;
+            }
+            let bytes = match encoding {
+                "B" | "b" => base64(text)?,
+                "Q" | "q" => quoted(text)?,
+                _ => return None,
+            };
+            Some((String::from_utf8_lossy(&bytes).into_owned(), tail))
+        });
+        let Some((text, tail)) = decoded else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            after_word = false;
+            continue;
+        };
+        // Whitespace between two encoded words is not part of the text.
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            out.push_str(between);
+        }
+        out.push_str(&text);
+        rest = tail;
+        after_word = true;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn base64(text: &str) -> Option<Vec<u8>> {
+    let mut bits = 0u32;
+    let mut count = 0;
+    let mut out = Vec::new();
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn quoted(text: &str) -> Option<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'_' => out.push(b' '),
+            b'=' => {
+                let hex = text.get(i + 1..i + 3)?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    Some(out)
+}
+
+/// Adds a task for each unread message. Returns whether any were added.
+pub fn ingest(
+    tasks: &mut Vec<Task>,
+    mailbox: &Mailbox,
+    project: Option<&str>,
+    clock: &Clock,
+) -> anyhow::Result<bool> {
+    add_messages(tasks, mailbox.unseen()?, project, clock)
+}
+
+/// Adds a task for each of `messages` not already linked from one.
+fn add_messages(
+    tasks: &mut Vec<Task>,
+    messages: Vec<Message>,
+    project: Option<&str>,
+    clock: &Clock,
+) -> anyhow::Result<bool> {
+    let mut added = false;
+    for Message {
+        subject,
+        message_id,
+    } in messages
+    {
+        let link = message_id.map(|id| format!("mid:{}", id));
+        if link.is_some() && tasks.iter().any(|t| t.link == link) {
+            continue;
+        }
+        let description = subject
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "(no subject)".to_owned());
+        let details = task::NewTask {
+            project: project.map(str::to_owned),
+            ..Default::default()
+        };
+        let id = task::add_task(tasks, description.clone(), details, clock)?;
+        let task = task::find_task_mut(tasks, id)?;
+        if link.is_some() {
+            task.link = link;
+            task.touch("link", clock);
+        }
+        println!("Added {}: {}", id, description);
+        added = true;
+    }
+    Ok(added)
+}
+
+/// Ingests once, or every `interval` until interrupted. The tasks file is
+/// reloaded each time so edits made in between are kept.
+pub fn poll(
+    data_path: &Path,
+    mailbox: &Mailbox,
+    project: Option<&str>,
+    interval: Option<Duration>,
+) -> anyhow::Result<()> {
+    loop {
+        let mut tasks = task::load_tasks(data_path)?;
+        let clock = Clock::load(data_path, &tasks)?;
+        match ingest(&mut tasks, mailbox, project, &clock) {
+            Ok(true) => task::save_tasks(data_path, &tasks)?,
+            Ok(false) => {}
+            // A dropped connection should not end a long-running poll.
+            Err(err) if interval.is_some() => eprintln!("Error: {:#}", err),
+            Err(err) => return Err(err),
+        }
+        match interval {
+            Some(interval) => thread::sleep(interval),
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn folders_parse_from_urls() {
+        assert_eq!(
+            Folder::parse("imaps://mail.example.com").unwrap(),
+            Folder {
+                tls: true,
+                host: "mail.example.com".to_owned(),
+                port: 993,
+                name: "INBOX".to_owned(),
+            }
+        );
+        let folder = Folder::parse("imap://localhost:1143/Work%20Mail/").unwrap();
+        assert_eq!(
+            (folder.tls, folder.port, folder.name.as_str()),
+            (false, 1143, "Work Mail")
+        );
+        assert!(Folder::parse("https://mail.example.com").is_err());
+        assert!(Folder::parse("imaps:///INBOX").is_err());
+    }
+
+    #[test]
+    fn headers_give_subject_and_message_id() {
+        let headers = "Subject: =?UTF-8?B?UmVuZXc=?=\r\n =?UTF-8?Q?_passp=C3=B6rt?=\r\nMessage-ID: <42@example.com>\r\nFrom: a@example.com\r\n\r\n";
+        assert_eq!(
+            Message::parse(headers),
+            Message {
+                subject: Some("Renew passpört".to_owned()),
+                message_id: Some("42@example.com".to_owned()),
+            }
+        );
+        assert_eq!(
+            Message::parse("From: a@example.com\r\n"),
+            Message::default()
+        );
+    }
+
+    #[test]
+    fn messages_become_tasks_once() {
+        let dir =
+            std::env::temp_dir().join(format!("cli_task_manager-imap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let clock = Clock::load(&dir.join("tasks.json"), &[]).unwrap();
+        let message = |subject: Option<&str>, id: Option<&str>| Message {
+            subject: subject.map(str::to_owned),
+            message_id: id.map(str::to_owned),
+        };
+        let mut tasks = Vec::new();
+        let added = add_messages(
+            &mut tasks,
+            vec![
+                message(Some(" Pay invoice "), Some("1@example.com")),
+                message(Some(""), None),
+            ],
+            Some("mail"),
+            &clock,
+        )
+        .unwrap();
+        assert!(added);
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].description, "Pay invoice");
+        assert_eq!(tasks[0].link.as_deref(), Some("mid:1@example.com"));
+        assert_eq!(tasks[0].project.as_deref(), Some("mail"));
+        assert_eq!(tasks[1].description, "(no subject)");
+        assert_eq!(tasks[1].link, None);
+
+        let again = vec![message(Some("Pay invoice"), Some("1@example.com"))];
+        assert!(!add_messages(&mut tasks, again, None, &clock).unwrap());
+        assert_eq!(tasks.len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
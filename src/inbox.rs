@@ -0,0 +1,175 @@
+//! GTD-style capture and processing: `inbox` takes a thought down with no
+//! questions asked, and `clarify` later walks through each one to decide
+//! what it is.
+
+use crate::{
+    ai::Suggestion,
+    crdt::{self, Clock},
+    report,
+    task::{self, NewTask, Priority, Task},
+};
+use anyhow::Context;
+use clap::ValueEnum;
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+pub fn capture(tasks: &mut Vec<Task>, text: String, clock: &Clock) -> anyhow::Result<u32> {
+    let id = task::add_task(tasks, text, NewTask::default(), clock)?;
+    let task = task::find_task_mut(tasks, id)?;
+    task.inbox = true;
+    task.touch("inbox", clock);
+    Ok(id)
+}
+
+pub fn print_inbox(tasks: &[Task]) {
+    let items: Vec<&Task> = tasks.iter().filter(|t| t.inbox && !t.completed).collect();
+    if items.is_empty() {
+        println!("Inbox is empty.");
+        return;
+    }
+    for task in items {
+        println!("{}", task::format_line(tasks, task));
+    }
+    println!("(process these with `clarify`)");
+}
+
+/// Asks `question` and returns the trimmed answer, or `None` at end of input.
+pub fn ask(question: &str) -> anyhow::Result<Option<String>> {
+    print!("{}", question);
+    io::stdout().flush().context("Failed to flush prompt")?;
+    let mut answer = String::new();
+    let read = io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("Failed to read answer")?;
+    Ok((read > 0).then(|| answer.trim().to_owned()))
+}
+
+/// Asks until `parse` accepts the answer; a blank answer means `None`.
+fn ask_optional<T>(
+    question: &str,
+    parse: impl Fn(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<Option<T>> {
+    loop {
+        let Some(answer) = ask(question)? else {
+            return Ok(None);
+        };
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        match parse(&answer) {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => println!("{:#}", err),
+        }
+    }
+}
+
+/// Walks through unprocessed inbox items one at a time. Each is clarified
+/// into a proper task, deferred to someday/maybe, deleted, or skipped; one
+/// with a suggestion (from `ai`) can also take it as it is. Returns whether
+/// anything changed.
+pub fn clarify(
+    data_path: &Path,
+    tasks: &mut Vec<Task>,
+    clock: &Clock,
+    suggestions: &HashMap<u32, Suggestion>,
+) -> anyhow::Result<bool> {
+    let pending: Vec<u32> = tasks
+        .iter()
+        .filter(|t| t.inbox && !t.completed)
+        .map(|t| t.id)
+        .collect();
+    if pending.is_empty() {
+        println!("Inbox is empty.");
+        return Ok(false);
+    }
+
+    let mut changed = false;
+    for (i, id) in pending.iter().enumerate() {
+        let description = task::find_task_mut(tasks, *id)?.description.clone();
+        println!("[{}/{}] {}: {}", i + 1, pending.len(), id, description);
+        let suggestion = suggestions.get(id);
+        let (question, answers) = match suggestion {
+            Some(suggestion) => {
+                println!("  Suggested: {}", suggestion.describe());
+                (
+                    "  [a]ccept, [c]larify, [s]omeday, [d]elete, s[k]ip, [q]uit? ",
+                    &["a", "c", "s", "d", "k", "q"][..],
+                )
+            }
+            None => (
+                "  [c]larify, [s]omeday, [d]elete, s[k]ip, [q]uit? ",
+                &["c", "s", "d", "k", "q"][..],
+            ),
+        };
+        let choice = loop {
+            match ask(question)? {
+                None => break "q".to_owned(),
+                Some(answer) if answers.contains(&answer.as_str()) => {
+                    break answer;
+                }
+                Some(_) => println!("  Please answer {}.", answers.join(", ")),
+            }
+        };
+
+        match choice.as_str() {
+            "a" | "c" => {
+                let (project, priority, due) = match (choice.as_str(), suggestion) {
+                    ("a", Some(suggestion)) => (
+                        suggestion.project.clone(),
+                        suggestion.priority,
+                        suggestion.due,
+                    ),
+                    _ => (
+                        ask_optional("  Project (blank for none): ", |p| Ok(p.to_owned()))?,
+                        ask_optional(
+                            "  Priority (low/medium/high/urgent, blank for none): ",
+                            |p| {
+                                Priority::from_str(p, true)
+                                    .map_err(|_| anyhow::anyhow!("  Unknown priority '{}'", p))
+                            },
+                        )?,
+                        ask_optional(
+                            "  Due (e.g. 2026-11-03 or friday, blank for none): ",
+                            report::parse_date,
+                        )?,
+                    ),
+                };
+
+                let task = task::find_task_mut(tasks, *id)?;
+                task.inbox = false;
+                task.touch("inbox", clock);
+                if project.is_some() {
+                    task.project = project;
+                    task.touch("project", clock);
+                }
+                if priority.is_some() {
+                    task.priority = priority;
+                    task.touch("priority", clock);
+                }
+                if due.is_some() {
+                    task.due = due;
+                    task.touch("due", clock);
+                }
+            }
+            "s" => {
+                let task = task::find_task_mut(tasks, *id)?;
+                task.inbox = false;
+                task.someday = true;
+                task.touch("inbox", clock);
+                task.touch("someday", clock);
+            }
+            "d" => {
+                let uuid = task::remove_task(tasks, *id)?;
+                crdt::bury(data_path, uuid, clock.tick())?;
+            }
+            "k" => continue,
+            _ => break,
+        }
+        changed = true;
+    }
+    Ok(changed)
+}
@@ -0,0 +1,288 @@
+//! A sidecar index of where each task sits in a large tasks file, so `show`
+//! and `done` can read and rewrite the one task they touch instead of
+//! parsing the whole list.
+//!
+//! `save_tasks` writes the index beside `tasks.json` as `tasks.index.json`
+//! once the list reaches `MIN_TASKS`. It records the size and modification
+//! time of the file it describes, and is ignored as soon as the file no
+//! longer matches, such as after a hand edit; the commands then take the
+//! ordinary path and the next save brings the index up to date.
+
+use crate::{
+    checksum,
+    crdt::{Clock, Stamp},
+    journal, redact,
+    task::{self, Task},
+    undo,
+};
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use uuid::Uuid;
+
+/// Below this many tasks parsing everything is quick, and no index is kept.
+const MIN_TASKS: usize = 1000;
+
+/// What the fast paths need to know about a task without reading it.
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    id: u32,
+    uuid: Uuid,
+    /// Byte range of the task's object in the tasks file.
+    start: usize,
+    end: usize,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    completed: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<Uuid>,
+}
+
+impl Entry {
+    fn of(task: &Task) -> Self {
+        Self {
+            id: task.id,
+            uuid: task.uuid,
+            start: 0,
+            end: 0,
+            completed: task.completed,
+            depends_on: task.depends_on.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+    /// Size and modification time (in nanoseconds) of the indexed file.
+    len: u64,
+    modified: u128,
+    /// The newest stamp on any task, so a clock can start past it.
+    latest: Option<Stamp>,
+    entries: Vec<Entry>,
+}
+
+fn index_path(tasks_path: &Path) -> PathBuf {
+    tasks_path.with_extension("index.json")
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u128)> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((meta.len(), modified.as_nanos()))
+}
+
+/// Writes `entries`, whose byte ranges are already filled in.
+fn write_entries(
+    tasks_path: &Path,
+    entries: Vec<Entry>,
+    latest: Option<Stamp>,
+) -> anyhow::Result<()> {
+    let path = index_path(tasks_path);
+    if entries.len() < MIN_TASKS {
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        return Ok(());
+    }
+    let Some((len, modified)) = file_stamp(tasks_path) else {
+        return Ok(());
+    };
+    let index = Index {
+        len,
+        modified,
+        latest,
+        entries,
+    };
+    let data = serde_json::to_vec(&index).context("Failed to serialize the task index")?;
+    task::write_atomic(&path, &data)
+}
+
+/// Brings the index up to date with the tasks file just written, where
+/// `spans` are the byte ranges `save_tasks` put each task at.
+pub fn write(tasks_path: &Path, spans: &[(usize, usize)], tasks: &[Task]) -> anyhow::Result<()> {
+    let latest = tasks.iter().flat_map(|t| t.stamps.values()).max().copied();
+    let entries = tasks
+        .iter()
+        .zip(spans)
+        .map(|(task, &(start, end))| Entry {
+            start,
+            end,
+            ..Entry::of(task)
+        })
+        .collect();
+    write_entries(tasks_path, entries, latest)
+}
+
+/// The index, if there is one and it still describes the tasks file.
+fn load(tasks_path: &Path) -> Option<Index> {
+    // An unfinished save is replayed by the ordinary path first.
+    if journal::is_pending(tasks_path) {
+        return None;
+    }
+    let data = fs::read(index_path(tasks_path)).ok()?;
+    let index: Index = serde_json::from_slice(&data).ok()?;
+    let (len, modified) = file_stamp(tasks_path)?;
+    (index.len == len && index.modified == modified).then_some(index)
+}
+
+impl Index {
+    fn entry(&self, id: u32) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+}
+
+impl Entry {
+    /// Reads just the task at this entry, checking it is the one expected.
+    fn read(&self, tasks_path: &Path) -> anyhow::Result<Task> {
+        let mut file = fs::File::open(tasks_path)
+            .with_context(|| format!("Failed to open {}", tasks_path.display()))?;
+        let mut bytes = vec![0; self.end - self.start];
+        file.seek(SeekFrom::Start(self.start as u64))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .with_context(|| format!("Failed to read {}", tasks_path.display()))?;
+        let task: Task = serde_json::from_slice(&bytes)
+            .with_context(|| format!("The task index for {} is stale", tasks_path.display()))?;
+        if task.uuid != self.uuid {
+            bail!("The task index for {} is stale", tasks_path.display());
+        }
+        Ok(task)
+    }
+}
+
+/// `show` through the index. Returns whether it could be used.
+pub fn show(tasks_path: &Path, id: u32, redact: &redact::Rules) -> anyhow::Result<bool> {
+    let Some(index) = load(tasks_path) else {
+        return Ok(false);
+    };
+    let Some(entry) = index.entry(id) else {
+        bail!("No task with id {}", id);
+    };
+    let task = redact.masked(&entry.read(tasks_path)?);
+    task::print_task(&task, &|uuid| {
+        index.entries.iter().find(|e| e.uuid == uuid).map(|e| e.id)
+    });
+    Ok(true)
+}
+
+/// Completes task `id` through the index, splicing the changed task into
+/// the file: the rest is copied across around it, never parsed or held in
+/// memory whole. Returns the tasks this unblocked, or `None` when the index
+/// cannot be used or the task needs the ordinary path (habits, which record
+/// an occurrence instead). The caller takes the ordinary path itself when
+/// something that runs on every command, such as archiving, is set up.
+pub fn done(tasks_path: &Path, id: u32, note: Option<String>) -> anyhow::Result<Option<Vec<Task>>> {
+    let Some(index) = load(tasks_path) else {
+        return Ok(None);
+    };
+    let Some(position) = index.entries.iter().position(|e| e.id == id) else {
+        bail!("No task with id {}", id);
+    };
+    let entry = &index.entries[position];
+    let task = entry.read(tasks_path)?;
+    if task.habit.is_some() {
+        return Ok(None);
+    }
+    let mut old = fs::File::open(tasks_path)
+        .with_context(|| format!("Failed to open tasks file at {}", tasks_path.display()))?;
+    // The offsets count the first line, which a file from before it was
+    // written lacks; the ordinary path gives the file one.
+    if checksum::skip_header(&mut old)?.is_none() {
+        return Ok(None);
+    }
+
+    let clock = Clock::load_after(tasks_path, index.latest)?;
+    let before = [task.clone()];
+    let mut one = vec![task];
+    let finished = task::mark_done(&mut one, id, note, &clock)?;
+    let task = &one[0];
+
+    let object = serde_json::to_string_pretty(task).context("Failed to serialize the task")?;
+    // Indented to sit in the array as `save_tasks` would have written it.
+    let object = object.replace('\n', "\n  ");
+    journal::record(tasks_path, &one)?;
+    checksum::write_sealed(tasks_path, index.entries.len(), &mut |out| {
+        old.seek(SeekFrom::Start(checksum::HEADER_LEN as u64))?;
+        let head = entry.start - checksum::HEADER_LEN;
+        io::copy(&mut (&mut old).take(head as u64), out)?;
+        out.write_all(object.as_bytes())?;
+        old.seek(SeekFrom::Start(entry.end as u64))?;
+        io::copy(&mut old, out)?;
+        Ok(())
+    })?;
+    undo::record(tasks_path, &before, &one)?;
+
+    // Everything after the task moves by however much it grew or shrank.
+    let new_end = entry.start + object.len();
+    let old_end = entry.end;
+    let mut entries = index.entries;
+    entries[position].completed = true;
+    let moved = |at: usize| at - old_end + new_end;
+    entries[position].end = new_end;
+    for entry in &mut entries[position + 1..] {
+        entry.start = moved(entry.start);
+        entry.end = moved(entry.end);
+    }
+    // Open tasks that waited on this one and on nothing else still open.
+    let open = |uuid: &Uuid| entries.iter().any(|e| e.uuid == *uuid && !e.completed);
+    let unblocked = entries
+        .iter()
+        .filter(|e| !e.completed && e.depends_on.contains(&finished))
+        .filter(|e| !e.depends_on.iter().any(open))
+        .map(|e| e.read(tasks_path))
+        .collect::<anyhow::Result<Vec<Task>>>()?;
+    let latest = task.stamps.values().max().copied().max(index.latest);
+    journal::commit(tasks_path, latest)?;
+    write_entries(tasks_path, entries, latest)?;
+    Ok(Some(unblocked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn done_splices_the_task_into_the_file() {
+        let dir =
+            std::env::temp_dir().join(format!("cli_task_manager-index-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tasks.json");
+        let uuid = |id: u32| format!("00000000-0000-4000-8000-{:012}", id);
+        let tasks: Vec<Task> = (1..=MIN_TASKS as u32)
+            .map(|id| {
+                // Task 3 waits on 2, and task 4 on 2 and 1.
+                let depends_on = match id {
+                    3 => vec![uuid(2)],
+                    4 => vec![uuid(2), uuid(1)],
+                    _ => Vec::new(),
+                };
+                serde_json::from_value(json!({
+                    "id": id,
+                    "uuid": uuid(id),
+                    "description": format!("task {}", id),
+                    "completed": false,
+                    "depends_on": depends_on,
+                }))
+                .unwrap()
+            })
+            .collect();
+        task::save_tasks(&path, &tasks).unwrap();
+
+        let unblocked = done(&path, 2, Some("shipped".to_owned())).unwrap().unwrap();
+        assert_eq!(unblocked.iter().map(|t| t.id).collect::<Vec<_>>(), [3]);
+        let loaded = task::load_tasks(&path).unwrap();
+        assert_eq!(loaded.len(), tasks.len());
+        assert!(loaded[1].completed);
+        assert!(loaded.iter().filter(|t| t.completed).count() == 1);
+        assert!(loaded[2..] == tasks[2..]);
+        // The index was moved along, so it still finds tasks past the splice.
+        assert!(done(&path, 999, None).unwrap().is_some());
+        assert!(task::load_tasks(&path).unwrap()[998].completed);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
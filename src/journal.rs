@@ -0,0 +1,166 @@
+//! A write-ahead journal beside each tasks file, `tasks.journal.jsonl`, so
+//! a change survives a crash while the tasks file is being rewritten.
+//!
+//! Before a save replaces the tasks file, every task edited since the last
+//! save (every task with a stamp newer than the last one committed) is
+//! appended to the journal and synced. Once the new tasks file is in place
+//! the journal is cut back to a single line recording the newest stamp it
+//! holds. If the save never finished, the journal still has entries past
+//! that line, and the next load puts them back and finishes the save.
+//!
+//! Removals are not journaled: they leave a tombstone, which a replay
+//! applies as sync would. Neither are older edits brought in by a sync;
+//! syncing again brings them back.
+
+use crate::{
+    crdt::{self, Stamp},
+    task::{self, Task},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    /// The tasks file holds every edit up to this stamp.
+    Committed(Option<Stamp>),
+    /// A task as it is about to be saved.
+    Put(Box<Task>),
+}
+
+fn journal_path(tasks_path: &Path) -> PathBuf {
+    tasks_path.with_extension("journal.jsonl")
+}
+
+/// The records in the journal, leaving out a last line cut short by a
+/// crash while it was appended, which the save it belonged to never got
+/// past.
+fn records(tasks_path: &Path) -> anyhow::Result<Vec<Record>> {
+    let path = journal_path(tasks_path);
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    let lines: Vec<&str> = data.lines().filter(|l| !l.trim().is_empty()).collect();
+    let mut records = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(_) if i + 1 == lines.len() => break,
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to parse {} at line {}", path.display(), i + 1)
+                });
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Appends the tasks edited since the last commit, synced to disk before
+/// returning. With no journal yet, that is every task.
+pub fn record(tasks_path: &Path, tasks: &[Task]) -> anyhow::Result<()> {
+    let committed = records(tasks_path)?
+        .into_iter()
+        .rev()
+        .find_map(|r| match r {
+            Record::Committed(stamp) => Some(stamp),
+            Record::Put(_) => None,
+        });
+    let changed = tasks.iter().filter(|t| match committed {
+        Some(committed) => crdt::latest(&t.stamps) > committed,
+        None => true,
+    });
+
+    let mut data = String::new();
+    for task in changed {
+        let line = serde_json::to_string(&Record::Put(Box::new(task.clone())))
+            .context("Failed to serialize a journal entry")?;
+        data.push_str(&line);
+        data.push('\n');
+    }
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    let path = journal_path(tasks_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create data directory at {}", parent.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.write_all(data.as_bytes())
+        .and_then(|()| file.sync_data())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Marks everything up to `latest` as safely in the tasks file.
+pub fn commit(tasks_path: &Path, latest: Option<Stamp>) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(&Record::Committed(latest))
+        .context("Failed to serialize a journal entry")?;
+    line.push('\n');
+    task::write_atomic(&journal_path(tasks_path), line.as_bytes())
+}
+
+/// The tasks journaled by a save that has not been committed.
+fn pending(tasks_path: &Path) -> anyhow::Result<Vec<Task>> {
+    let mut pending = Vec::new();
+    for record in records(tasks_path)? {
+        match record {
+            Record::Committed(_) => pending.clear(),
+            Record::Put(task) => pending.push(*task),
+        }
+    }
+    Ok(pending)
+}
+
+/// Whether a save was left unfinished, and the next load will replay it.
+pub fn is_pending(tasks_path: &Path) -> bool {
+    pending(tasks_path).map_or(true, |p| !p.is_empty())
+}
+
+/// Applies the entries a save left behind without finishing to `tasks`, as
+/// just read from the tasks file. Returns how many tasks that changed.
+pub fn replay(tasks_path: &Path, tasks: &mut Vec<Task>) -> anyhow::Result<usize> {
+    let pending = pending(tasks_path)?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let mut replayed = Vec::new();
+    for task in pending {
+        let uuid = task.uuid;
+        match tasks.iter_mut().find(|t| t.uuid == task.uuid) {
+            // Already saved, or saved since with an even newer edit.
+            Some(current) if crdt::latest(&current.stamps) >= crdt::latest(&task.stamps) => {
+                continue;
+            }
+            Some(current) => *current = task,
+            None => tasks.push(task),
+        }
+        if !replayed.contains(&uuid) {
+            replayed.push(uuid);
+        }
+    }
+    // Tasks removed by the unfinished save, or after their last journaled
+    // edit, stay removed, as a sync would have it.
+    let tombstones = crdt::load_tombstones(tasks_path)?;
+    tasks.retain(|t| {
+        tombstones
+            .get(&t.uuid)
+            .is_none_or(|buried| crdt::latest(&t.stamps) > Some(*buried))
+    });
+    Ok(replayed.len())
+}
@@ -0,0 +1,177 @@
+//! Credentials kept in the operating system's keyring instead of flags,
+//! environment variables, or files: the Secret Service (GNOME Keyring,
+//! KWallet) on Linux and the BSDs, the Keychain on macOS, and the
+//! Credential Manager on Windows.
+//!
+//! `auth set` stores one; commands that need it fall back to the keyring
+//! when it is not given on the command line or in the environment.
+
+use anyhow::{Context, bail};
+use clap::ValueEnum;
+use keyring::{Entry, Error};
+use std::io::{self, BufRead, IsTerminal};
+
+const APPLICATION: &str = "cli_task_manager";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Service {
+    /// The token for `sync remote`, per server URL
+    Sync,
+    /// The token `serve` requires of clients
+    Serve,
+    /// The password for `ingest imap`, per login name
+    Imap,
+    /// The API key for the model set as `ai` in config.json
+    Ai,
+    /// The passphrase of the `sync_encryption` identity file
+    Encryption,
+}
+
+impl Service {
+    fn name(self) -> &'static str {
+        match self {
+            Service::Sync => "sync",
+            Service::Serve => "serve",
+            Service::Imap => "imap",
+            Service::Ai => "ai",
+            Service::Encryption => "encryption",
+        }
+    }
+
+    /// What the credential is stored under: the server URL, the login name,
+    /// or nothing for the rest, of which there is one per machine.
+    pub fn account(self, account: Option<String>) -> anyhow::Result<String> {
+        match (self, account) {
+            (Service::Sync, Some(url)) => Ok(url.trim_end_matches('/').to_owned()),
+            (Service::Imap, Some(user)) => Ok(user),
+            (Service::Serve | Service::Ai | Service::Encryption, None) => Ok("default".to_owned()),
+            (Service::Sync, None) => bail!("Name the server: `auth set sync <url>`"),
+            (Service::Imap, None) => bail!("Name the login: `auth set imap <user>`"),
+            (service, Some(_)) => bail!(
+                "`{}` has one secret per machine; drop the account",
+                service.name()
+            ),
+        }
+    }
+}
+
+fn entry(service: Service, account: &str) -> anyhow::Result<Entry> {
+    Entry::new(&format!("{}:{}", APPLICATION, service.name()), account)
+        .context("Failed to open the keyring")
+}
+
+/// The stored credential, or `None` if there is none or no keyring to ask.
+pub fn get(service: Service, account: &str) -> anyhow::Result<Option<String>> {
+    match entry(service, account)?.get_password() {
+        Ok(secret) if !secret.is_empty() => Ok(Some(secret)),
+        Ok(_) | Err(Error::NoEntry | Error::NoStorageAccess(_) | Error::PlatformFailure(_)) => {
+            Ok(None)
+        }
+        Err(err) => Err(err).context("Failed to read the keyring"),
+    }
+}
+
+/// Echo turned off on the terminal on stdin, until dropped.
+#[cfg(unix)]
+struct Hidden {
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl Hidden {
+    fn start() -> Option<Self> {
+        use std::os::fd::AsRawFd;
+        let fd = io::stdin().as_raw_fd();
+        // SAFETY: termios is plain data, filled in by tcgetattr before use,
+        // and the saved settings are put back on drop.
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return None;
+            }
+            let saved = termios;
+            termios.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(fd, libc::TCSANOW, &termios);
+            Some(Self { saved })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Hidden {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+        // SAFETY: restores the settings read in `start`.
+        unsafe {
+            libc::tcsetattr(io::stdin().as_raw_fd(), libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+/// Without termios the secret is echoed as it is typed.
+#[cfg(not(unix))]
+struct Hidden;
+
+#[cfg(not(unix))]
+impl Hidden {
+    fn start() -> Option<Self> {
+        None
+    }
+}
+
+/// Reads a secret from the terminal without echoing it, or one line from
+/// stdin when that is not a terminal.
+pub fn read_secret(prompt: &str) -> anyhow::Result<String> {
+    let stdin = io::stdin();
+    let terminal = stdin.is_terminal();
+    if terminal {
+        eprint!("{}", prompt);
+    }
+    let hidden = terminal.then(Hidden::start).flatten();
+    let mut line = String::new();
+    let read = stdin.lock().read_line(&mut line);
+    if hidden.is_some() {
+        drop(hidden);
+        // The Enter that ended the line was not echoed either.
+        eprintln!();
+    }
+    read.context("Failed to read the secret")?;
+    let secret = line.trim_end_matches(['\n', '\r']).to_owned();
+    if secret.is_empty() {
+        bail!("No secret given");
+    }
+    Ok(secret)
+}
+
+/// Stores a secret read from the terminal or stdin.
+pub fn set(service: Service, account: &str) -> anyhow::Result<()> {
+    let secret = read_secret(&format!("Secret for {} {}: ", service.name(), account))?;
+    entry(service, account)?
+        .set_password(&secret)
+        .context("Failed to store the secret")?;
+    println!(
+        "Stored the {} secret for {} in the keyring.",
+        service.name(),
+        account
+    );
+    Ok(())
+}
+
+pub fn remove(service: Service, account: &str) -> anyhow::Result<()> {
+    match entry(service, account)?.delete_credential() {
+        Ok(()) => {
+            println!(
+                "Removed the {} secret for {} from the keyring.",
+                service.name(),
+                account
+            );
+            Ok(())
+        }
+        Err(Error::NoEntry) => bail!(
+            "No {} secret for {} in the keyring",
+            service.name(),
+            account
+        ),
+        Err(err) => Err(err).context("Failed to remove the secret"),
+    }
+}
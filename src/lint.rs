@@ -0,0 +1,102 @@
+//! `lint`: nudges toward a healthy backlog by listing open tasks that break
+//! a hygiene rule. Each rule can be tuned or turned off with `null` under
+//! `"lint"` in config.json; these are the defaults:
+//!
+//! ```json
+//! {
+//!   "lint": {
+//!     "missing_project": true,
+//!     "overdue_days": 30,
+//!     "max_description": 100,
+//!     "estimate_from": "high"
+//!   }
+//! }
+//! ```
+//!
+//! Inbox items are left out, since `clarify` has not given them a project
+//! or estimate yet; so are someday tasks and habits.
+
+use crate::task::{self, Priority, Task};
+use chrono::Local;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Flag tasks that belong to no project.
+    missing_project: bool,
+    /// Flag tasks overdue by more than this many days.
+    overdue_days: Option<i64>,
+    /// Flag descriptions longer than this many characters.
+    max_description: Option<usize>,
+    /// Flag tasks at least this urgent that have no estimate.
+    estimate_from: Option<Priority>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            missing_project: true,
+            overdue_days: Some(30),
+            max_description: Some(100),
+            estimate_from: Some(Priority::High),
+        }
+    }
+}
+
+/// Prints the tasks breaking each rule.
+pub fn print_lint(tasks: &[Task], rules: &Rules) {
+    let today = Local::now().date_naive();
+    let open: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| !t.completed && !t.inbox && !t.someday && t.habit.is_none())
+        .collect();
+
+    let mut findings: Vec<(String, Vec<&Task>)> = Vec::new();
+    if rules.missing_project {
+        let found = open.iter().copied().filter(|t| t.project.is_none());
+        findings.push(("No project".to_owned(), found.collect()));
+    }
+    if let Some(days) = rules.overdue_days {
+        let found = open
+            .iter()
+            .copied()
+            .filter(|t| t.due.is_some_and(|due| (today - due).num_days() > days));
+        findings.push((
+            format!("Overdue by more than {} day(s)", days),
+            found.collect(),
+        ));
+    }
+    if let Some(max) = rules.max_description {
+        let found = open
+            .iter()
+            .copied()
+            .filter(|t| t.description.chars().count() > max);
+        findings.push((
+            format!("Description longer than {} characters", max),
+            found.collect(),
+        ));
+    }
+    if let Some(from) = rules.estimate_from {
+        let found = open
+            .iter()
+            .copied()
+            .filter(|t| t.priority >= Some(from) && t.estimate.is_none());
+        findings.push((
+            format!("No estimate at {} priority or above", from.name()),
+            found.collect(),
+        ));
+    }
+
+    let total: usize = findings.iter().map(|(_, found)| found.len()).sum();
+    if total == 0 {
+        println!("No problems found in {} open task(s).", open.len());
+        return;
+    }
+    for (rule, found) in findings.iter().filter(|(_, found)| !found.is_empty()) {
+        println!("{} ({}):", rule, found.len());
+        for task in found {
+            println!("  {}", task::format_line(tasks, task));
+        }
+    }
+}
@@ -1,56 +1,2152 @@
-use clap::{Parser, Subcommand};
-use directories::ProjectDirs;
-use std::path::PathBuf;
+use anyhow::Context;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::{self, BufRead, IsTerminal},
+    path::{Path, PathBuf},
+};
 
+mod access;
+mod age;
+mod aging;
+mod ai;
+mod archive;
+mod attribute;
+mod automation;
+mod autotag;
+mod checksum;
+mod compact;
+mod config;
+mod context;
+mod crdt;
+mod daemon;
+mod date;
+mod dedupe;
+mod deps;
+mod diff;
+mod doctor;
+mod duration;
+mod events;
+mod export;
+mod feed;
+mod filter;
+mod focus;
+mod githook;
+mod graph;
+mod grpc;
+mod habit;
+mod history;
+mod http;
+mod ics;
+mod imap;
+mod inbox;
+mod index;
+mod journal;
+mod keyring;
+mod lint;
+mod man;
+mod matrix;
+mod mcp;
+mod merge;
+mod milestone;
+mod notify;
+mod pager;
+mod paths;
+mod plan;
+mod profile;
+mod query;
+mod redact;
+mod repair;
+mod report;
+mod review;
+mod scan;
+mod schedule;
+mod script;
+mod sentence;
+mod server;
+mod shell;
+mod ssh;
+mod stats;
+mod status;
+mod storage;
+mod sync;
+mod table;
 mod task;
+mod template;
+mod undo;
+mod update;
+mod validate;
+mod view;
+mod watch;
 
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Cli {
+    /// Work on the task list of this context instead of the default one
+    #[arg(
+        long = "in",
+        global = true,
+        value_name = "CONTEXT",
+        env = "CLI_TASK_MANAGER_CONTEXT"
+    )]
+    in_context: Option<String>,
+    /// Keep tasks in .tasks.json next to the executable, not in user directories
+    #[arg(long, global = true)]
+    portable: bool,
+    /// Use your own task list even inside a project that has one
+    #[arg(long, global = true, conflicts_with = "portable")]
+    global: bool,
+    /// Use the settings and tasks of this profile (see `profile`)
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "portable",
+        env = "CLI_TASK_MANAGER_PROFILE"
+    )]
+    profile: Option<String>,
+    /// Print long output straight to the terminal instead of through $PAGER
+    #[arg(long, global = true)]
+    no_pager: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per run, so the size of `List` costs nothing worth boxing for.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Add a new task
-    Add { description: String },
+    Add {
+        description: String,
+        /// Project the task belongs to
+        #[arg(long)]
+        project: Option<String>,
+        /// Milestone the task counts towards
+        #[arg(long)]
+        milestone: Option<String>,
+        /// Expected effort, e.g. 2h, 45m, 1h30m
+        #[arg(long, value_parser = duration::parse_minutes)]
+        estimate: Option<u32>,
+        #[arg(long, value_enum)]
+        priority: Option<task::Priority>,
+        /// Due date, e.g. 2026-11-03, today, friday, next week or "in 3 days"
+        #[arg(long, value_parser = report::parse_date)]
+        due: Option<chrono::NaiveDate>,
+        /// Make this a habit repeated on a cadence, e.g. 3x/week or daily
+        #[arg(long, value_parser = habit::parse_cadence)]
+        habit: Option<habit::Cadence>,
+        /// Tag the task, e.g. --tag finance (repeatable)
+        #[arg(long, value_parser = task::parse_tag)]
+        tag: Vec<String>,
+        /// Refuse to add a task an open task already says nearly the same as
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Capture a thought for later processing (list the inbox without text)
+    Inbox { text: Option<String> },
+    /// Run a command written as a sentence, e.g. do remind me to call Ann tomorrow
+    Do {
+        /// Only show the command the sentence is read as
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(required = true, trailing_var_arg = true)]
+        sentence: Vec<String>,
+    },
+    /// Type commands one after another with the tasks kept loaded
+    Shell,
+    /// Process inbox items one by one into tasks
+    #[command(alias = "triage")]
+    Clarify {
+        /// Ask the model set as `ai` in config.json for suggestions to confirm
+        #[arg(long)]
+        ai: bool,
+    },
     /// List tasks (use --all to include completed)
     List {
-        #[arg(short, long)]
-        all: bool,
+        #[command(flatten)]
+        filters: FilterArgs,
+        /// Order of the listed tasks: keys among id, created, estimate,
+        /// priority, due, project, description, and manual, e.g.
+        /// priority,-due (a - reverses a key)
+        #[arg(long, default_value = "id", allow_hyphen_values = true)]
+        sort: task::Sort,
+        /// Show at most this many tasks
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Skip this many tasks first
+        #[arg(long, default_value_t = 0, conflicts_with = "page")]
+        offset: usize,
+        /// Show this page of --limit tasks, counting from 1
+        #[arg(long, requires = "limit", value_parser = clap::value_parser!(u64).range(1..))]
+        page: Option<u64>,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        /// Keep the list on screen, redrawing it whenever the tasks change
+        #[arg(long)]
+        watch: bool,
+        /// With --watch, also redraw at least this often, in seconds
+        #[arg(long, default_value_t = 60, requires = "watch")]
+        interval: u64,
+        /// List archived tasks instead
+        #[arg(long, conflicts_with = "watch")]
+        archived: bool,
+        /// With --archived, only the tasks completed in this month (YYYY-MM)
+        #[arg(long, value_parser = archive::parse_month, requires = "archived")]
+        month: Option<String>,
+    },
+    /// Move completed tasks into monthly archive files
+    Archive {
+        /// Only tasks completed more than this many days ago (default:
+        /// archive_after_days in config.json, else 90)
+        #[arg(long)]
+        older_than: Option<u32>,
+    },
+    /// Show the tasks you can work on right now
+    Next {
+        /// How many tasks to show
+        #[arg(short = 'n', long, default_value_t = 5)]
+        count: usize,
+        /// Order to pick them in, as for `list --sort`
+        #[arg(long, default_value = "priority", allow_hyphen_values = true)]
+        sort: task::Sort,
+        #[command(flatten)]
+        layout: LayoutArgs,
+    },
+    /// Show how well habits kept to their cadence in recent weeks
+    Habits {
+        /// Number of weeks to show, including this one
+        #[arg(long, default_value_t = 4)]
+        weeks: u32,
+    },
+    /// Pick a random task you could work on right now
+    Shuffle {
+        /// Only tasks in this project
+        #[arg(long)]
+        project: Option<String>,
+        /// Only tasks estimated at most this long, e.g. 30m
+        #[arg(long, value_parser = duration::parse_minutes)]
+        max_estimate: Option<u32>,
+    },
+    /// Mark a task as completed (for a habit, record that it was done)
+    Done {
+        id: u32,
+        /// How the task was resolved, shown by `show` and `report done`
+        #[arg(long)]
+        note: Option<String>,
+        /// Also send a desktop notification for tasks this unblocks
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Put a completed task back on the open list
+    Reopen { id: u32 },
+    /// Copy a task into a new open one, keeping its checklist unticked
+    Clone {
+        id: u32,
+        /// Due date for the copy (YYYY-MM-DD) instead of the original's
+        #[arg(long, value_parser = report::parse_date)]
+        due: Option<chrono::NaiveDate>,
+    },
+    /// Move a task to another project and/or another context's task list
+    #[command(group = clap::ArgGroup::new("destination").required(true).multiple(true))]
+    Move {
+        id: u32,
+        /// Project to move the task to (empty to take it out of its project)
+        #[arg(long, group = "destination")]
+        project: Option<String>,
+        /// Context whose task list the task should move to
+        #[arg(long, group = "destination")]
+        context: Option<String>,
+    },
+    /// Fold a duplicate task into another and remove the duplicate
+    Merge { id: u32, into: u32 },
+    /// Find open tasks that look like duplicates and offer to merge each pair
+    Dedupe,
+    /// Break a task into subtasks, read one per line unless --items is given
+    Split {
+        id: u32,
+        /// Comma-separated subtasks, e.g. "draft,review,send"
+        #[arg(long, value_delimiter = ',')]
+        items: Vec<String>,
+    },
+    /// Move a task one place earlier in the manual order (`list --sort manual`)
+    MoveUp { id: u32 },
+    /// Move a task one place later in the manual order
+    MoveDown { id: u32 },
+    /// Put a task at a position in the manual order, counting from 1
+    MoveTo { id: u32, position: usize },
+    /// Park a task in the someday/maybe bucket, out of `list` and `next`
+    Someday {
+        id: u32,
+        /// Bring the task back from the bucket
+        #[arg(long)]
+        off: bool,
+    },
+    /// Sort open tasks into an urgent/important grid
+    Matrix {
+        /// Tasks due within this many days count as urgent
+        #[arg(long, default_value_t = 2)]
+        urgent_days: i64,
+    },
+    /// Review overdue, waiting, blocked, and someday/maybe tasks, or set up
+    /// a review that recurs
+    Review {
+        #[command(subcommand)]
+        action: Option<ReviewAction>,
     },
-    /// Mark a task as completed
-    Done { id: u32 },
+    /// Keep a task at the top of `list` and `next`
+    Pin { id: u32 },
+    /// Stop keeping a task at the top
+    Unpin { id: u32 },
     /// Remove a task
     Remove { id: u32 },
+    /// Take back the last changes commands made, even in earlier runs
+    Undo {
+        /// How many commands to take back
+        #[arg(default_value_t = 1)]
+        count: usize,
+        /// List what can be undone and redone instead
+        #[arg(long, conflicts_with = "count")]
+        list: bool,
+    },
+    /// List what changed across every task, day by day
+    History {
+        /// How far back to start: a span such as 7d or 12h, a weekday
+        /// (the latest one), or a date
+        #[arg(long, value_parser = history::parse_since)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        /// Last day to include
+        #[arg(long, value_parser = history::parse_until)]
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    /// Show how the tasks differ from those in a backup, export or other
+    /// tasks file
+    Diff { file: PathBuf },
+    /// Make again the changes `undo` took back
+    Redo {
+        #[arg(default_value_t = 1)]
+        count: usize,
+    },
+    /// Assign a task to someone (omit the name to unassign)
+    Assign { id: u32, name: Option<String> },
+    /// Set a task's priority (omit the level to clear it)
+    Priority {
+        id: u32,
+        #[arg(value_enum)]
+        level: Option<task::Priority>,
+    },
+    /// Set a task's due date, as `add --due` takes it (omit to clear it)
+    Due {
+        id: u32,
+        #[arg(value_parser = report::parse_date)]
+        date: Option<chrono::NaiveDate>,
+    },
+    /// Set a task's effort estimate, e.g. 2h (omit to clear it)
+    Estimate {
+        id: u32,
+        #[arg(value_parser = duration::parse_minutes)]
+        duration: Option<u32>,
+    },
+    /// Change a task's description, tags, or user-defined attributes
+    /// (declared in config.json)
+    Edit {
+        id: u32,
+        #[arg(long)]
+        description: Option<String>,
+        /// Add a tag (repeatable)
+        #[arg(long, value_parser = task::parse_tag)]
+        tag: Vec<String>,
+        /// Remove a tag (repeatable)
+        #[arg(long, value_parser = task::parse_tag)]
+        untag: Vec<String>,
+        /// Set an attribute, e.g. customer=acme (repeatable)
+        #[arg(long, value_name = "NAME=VALUE", value_parser = attribute::parse_assignment)]
+        set: Vec<(String, String)>,
+        /// Remove an attribute (repeatable)
+        #[arg(long, value_name = "NAME")]
+        unset: Vec<String>,
+    },
+    /// List open tasks breaking the hygiene rules set under "lint" in
+    /// config.json
+    Lint,
+    /// Select tasks with SQL, e.g. "SELECT id, description FROM tasks WHERE
+    /// priority = 'high' ORDER BY due"
+    Query { sql: String },
+    /// Define and switch filters that narrow list, next, and report until
+    /// cleared
+    Context {
+        #[command(subcommand)]
+        action: ContextAction,
+    },
+    /// Save, remove, or list named sets of `list` arguments
+    View {
+        #[command(subcommand)]
+        action: ViewAction,
+    },
+    /// Create, delete, or list profiles: bundles of settings and tasks
+    /// chosen with --profile
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Read and change settings in config.json
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Suggest open tasks that fit in the given time, e.g. 1h
+    Fits {
+        #[arg(value_parser = duration::parse_minutes)]
+        duration: u32,
+    },
+    /// Mark a task as waiting on someone (omit the person to take it back)
+    Delegate { id: u32, person: Option<String> },
+    /// Make a task wait until another is done
+    Depend {
+        id: u32,
+        on: u32,
+        /// Drop the dependency instead of adding it
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Make a task a subtask of another (omit the parent to detach it)
+    Parent { id: u32, parent: Option<u32> },
+    /// Export dependencies and subtasks for Graphviz or Markdown
+    Graph {
+        #[arg(long, value_enum, default_value_t)]
+        format: graph::Format,
+        /// Include completed tasks
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// What is planned for today, against the day's working hours
+    Today {
+        #[command(flatten)]
+        layout: LayoutArgs,
+    },
+    /// List open tasks in an order that respects dependencies and due dates
+    Plan {
+        /// Show the chain of estimated work that decides when everything is
+        /// done, and how much the other tasks can slip
+        #[arg(long)]
+        critical_path: bool,
+        /// Only this milestone's tasks and what they depend on
+        #[arg(long, requires = "critical_path")]
+        milestone: Option<String>,
+    },
+    /// Suggest a day for each open task from its due date, estimate, and
+    /// dependencies, and store it as the task's scheduled day
+    Schedule {
+        /// Estimated work that fits in one day, instead of the working hours
+        /// in config.json
+        #[arg(long, value_parser = duration::parse_minutes)]
+        capacity: Option<u32>,
+        /// Print the schedule without saving it
+        #[arg(long)]
+        dry_run: bool,
+        /// Write the saved schedule as calendar events to standard output
+        /// instead of scheduling again
+        #[arg(long, value_enum, conflicts_with = "dry_run")]
+        export: Option<ics::Format>,
+        /// When the working day starts, for exported events
+        #[arg(long, value_parser = report::parse_time, default_value = "09:00", requires = "export")]
+        day_start: chrono::NaiveTime,
+    },
+    /// Write the task list as JSON or as an HTML page to standard output
+    Export {
+        #[arg(long, value_enum, default_value_t)]
+        format: export::Format,
+        #[command(flatten)]
+        filters: FilterArgs,
+        /// Order of the tasks, as for `list --sort`
+        #[arg(long, default_value = "id", allow_hyphen_values = true)]
+        sort: task::Sort,
+    },
+    /// Show a task's details and comments
+    Show { id: u32 },
+    /// Comment on a task as the current user
+    Comment {
+        id: u32,
+        text: String,
+        /// Reply to the comment with this number (as printed by `show`)
+        #[arg(long)]
+        reply: Option<usize>,
+    },
+    /// Start tracking time on a task (stops any other running timer)
+    Start { id: u32 },
+    /// Stop the running timer, or the one on the given task
+    Stop { id: Option<u32> },
+    /// Show how far along each project is
+    Projects {
+        /// Weigh tasks by their estimates instead of counting them
+        #[arg(long)]
+        weighted: bool,
+        /// Draw a progress bar per project
+        #[arg(long)]
+        bar: bool,
+    },
+    /// Manage milestones and see how each is doing
+    Milestone {
+        #[command(subcommand)]
+        action: MilestoneAction,
+    },
+    /// Completion trends over recent days
+    Stats {
+        /// Draw a chart of this measure
+        #[arg(long, value_enum, conflicts_with = "heatmap")]
+        chart: Option<stats::Chart>,
+        /// Show a calendar of completions over the past year
+        #[arg(long, conflicts_with = "score")]
+        heatmap: bool,
+        /// Score each period: weighted completions minus overdue days
+        /// (weights under "score" in config.json)
+        #[arg(long, conflicts_with = "chart")]
+        score: bool,
+        /// Period to total the score over
+        #[arg(long, value_enum, default_value_t = stats::Period::Week, requires = "score")]
+        by: stats::Period,
+        /// How many days back to look, including today
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+        days: u32,
+    },
+    /// Summaries across tasks
+    Report {
+        #[command(subcommand)]
+        kind: ReportKind,
+    },
+    /// Count open and overdue tasks, e.g. for a shell prompt
+    Status {
+        /// Print just overdue/open, like 3!/7
+        #[arg(long, conflicts_with_all = ["waybar", "tmux"])]
+        short: bool,
+        /// Print JSON for a Waybar custom module (text, tooltip, class)
+        #[arg(long, conflicts_with = "tmux")]
+        waybar: bool,
+        /// Print overdue/open with tmux color codes, for status-right
+        #[arg(long)]
+        tmux: bool,
+        /// Keep running and print a fresh line every this many seconds
+        #[arg(long)]
+        interval: Option<u64>,
+    },
+    /// Add tasks for TODO/FIXME comments and close those whose comment is gone
+    Scan {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+    /// Create tasks from messages arriving elsewhere
+    Ingest {
+        #[command(subcommand)]
+        source: IngestSource,
+    },
+    /// Keep sync tokens and passwords in the system keyring
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+    /// Create and complete tasks from commit messages in this git repository
+    GitHook {
+        #[command(subcommand)]
+        action: GitHookAction,
+    },
+    /// Show where tasks, settings, and caches are kept
+    Paths,
+    /// Write man pages for the program and each of its commands
+    GenerateMan {
+        /// Directory to write the pages to
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Replace this program with the latest prebuilt release, checking its
+    /// SHA-256 first; for installs that did not come through cargo
+    SelfUpdate {
+        /// Only report whether a newer release exists
+        #[arg(long)]
+        check: bool,
+    },
+    /// Check for what would stop commands from working, such as unwritable
+    /// directories, files that do not parse, or unreachable sync remotes,
+    /// and say how to fix it; exits 1 when a check failed
+    Doctor {
+        /// Also send a test notification
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Salvage readable tasks from a corrupted tasks file
+    Repair,
+    /// Check the tasks file for problems without changing it; exits 0 when
+    /// valid, 1 when problems were found, 2 when it cannot be read at all
+    Validate,
+    /// Rewrite the tasks file in another storage format, which later saves
+    /// keep
+    Convert {
+        #[arg(long, value_enum)]
+        format: storage::Format,
+    },
+    /// Drop old tombstones and history and repeated notes, and rewrite the
+    /// tasks file
+    Compact {
+        /// Keep tombstones and history from this many recent days; longer
+        /// than any replica goes without syncing
+        #[arg(long, default_value_t = 180)]
+        keep_days: u32,
+    },
+    /// Manage a task's checklist
+    Check {
+        #[command(subcommand)]
+        action: CheckAction,
+    },
+    /// Merge tasks with another copy and write the result to both
+    Sync {
+        /// Resolve conflicting edits in favour of one side instead of asking
+        #[arg(long, value_enum, global = true)]
+        prefer: Option<merge::Side>,
+        #[command(subcommand)]
+        target: SyncTarget,
+    },
+    /// Keep the tasks in memory for an instant `list`, and sync with the
+    /// remotes in config.json in the background, until interrupted
+    Daemon {
+        /// Seconds between syncs when nothing changes
+        #[arg(long, default_value_t = 300)]
+        interval: u64,
+        /// Seconds to wait after a change, so a burst of commands syncs once
+        #[arg(long, default_value_t = 2)]
+        debounce: u64,
+        /// Side that wins a conflict, since there is nobody to ask; needed
+        /// when there are remotes
+        #[arg(long, value_enum)]
+        prefer: Option<merge::Side>,
+    },
+    /// Serve tasks over HTTP for other machines
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:7373")]
+        addr: String,
+        /// Also offer the gRPC service of proto/tasks.proto on this address,
+        /// e.g. 127.0.0.1:7374
+        #[arg(long, value_name = "ADDR")]
+        grpc: Option<String>,
+        /// Accept pulls and pushes from `sync remote`
+        #[arg(long)]
+        sync: bool,
+        /// Bearer token clients must present for full access
+        #[arg(long, env = "CLI_TASK_MANAGER_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        /// JSON file of users with their own tokens and per-project permissions
+        #[arg(long)]
+        users: Option<PathBuf>,
+        /// Speak the Model Context Protocol on stdin and stdout instead, for assistants
+        #[arg(long, conflicts_with_all = ["addr", "grpc", "sync", "users"])]
+        mcp: bool,
+        /// With --mcp, offer only the tool that lists tasks
+        #[arg(long, requires = "mcp")]
+        read_only: bool,
+    },
+    /// List the Rhai scripts in the config directory that run as commands
+    Scripts,
+    /// Run a Rhai script from the scripts folder of the config directory
+    #[command(external_subcommand)]
+    Script(Vec<String>),
+}
+
+/// The options choosing which tasks `list` and `export` cover.
+#[derive(clap::Args)]
+struct FilterArgs {
+    /// Include completed tasks
+    #[arg(short, long)]
+    all: bool,
+    /// Only tasks assigned to you (CLI_TASK_MANAGER_USER, else $USER)
+    #[arg(long, conflicts_with = "assignee")]
+    mine: bool,
+    /// Only tasks assigned to this person
+    #[arg(long)]
+    assignee: Option<String>,
+    /// Only tasks in this project
+    #[arg(long)]
+    project: Option<String>,
+    /// Only tasks in this milestone
+    #[arg(long)]
+    milestone: Option<String>,
+    /// Only delegated tasks, grouped by who they are waiting on
+    #[arg(long)]
+    delegated: bool,
+    /// Only tasks estimated at most this long, e.g. 30m
+    #[arg(long, value_parser = duration::parse_minutes)]
+    max_estimate: Option<u32>,
+    /// Only tasks waiting on an unfinished dependency
+    #[arg(long)]
+    blocked: bool,
+    /// Only tasks parked with `someday`
+    #[arg(long)]
+    someday: bool,
+    /// Only tasks with this tag (repeatable; tasks must have them all)
+    #[arg(long, value_parser = task::parse_tag)]
+    tag: Vec<String>,
+    /// Only tasks with this attribute value, e.g. customer=acme (repeatable)
+    #[arg(long = "where", value_name = "NAME=VALUE", value_parser = attribute::parse_assignment)]
+    attributes: Vec<(String, String)>,
+    /// Only tasks matching this expression, e.g. "priority:high +finance"
+    #[arg(long, value_name = "EXPR", allow_hyphen_values = true)]
+    filter: Option<filter::Filter>,
+    /// Add the arguments saved as this view (see `view save`)
+    #[arg(long, value_name = "NAME")]
+    view: Option<String>,
+    /// Only the tasks this Rhai script from the config directory keeps
+    #[arg(long, value_name = "NAME")]
+    script: Option<String>,
+}
+
+impl FilterArgs {
+    /// The filter these options describe, less `--script`, which the caller
+    /// runs against the tasks it has.
+    fn list_filter(self, config_dir: &Path) -> anyhow::Result<task::ListFilter> {
+        let assignee = if self.mine {
+            Some(current_user()?)
+        } else {
+            self.assignee
+        };
+        Ok(task::ListFilter {
+            all: self.all,
+            assignee,
+            project: self.project,
+            milestone: self.milestone,
+            delegated: self.delegated,
+            max_estimate: self.max_estimate,
+            blocked: self.blocked,
+            someday: self.someday,
+            with_someday: false,
+            attributes: self.attributes,
+            tags: self.tag,
+            expression: self.filter,
+            context: active_context(config_dir)?,
+            only: None,
+        })
+    }
+}
+
+#[derive(clap::Args)]
+struct LayoutArgs {
+    /// Print each task through a template, e.g. "{id}\t{priority}\t{description}"
+    #[arg(long, conflicts_with = "columns")]
+    format: Option<template::Template>,
+    /// Table columns, e.g. id,due,description (default: columns in
+    /// config.json, else id,status,flags,priority,due,project,tags,description)
+    #[arg(long, value_delimiter = ',', value_parser = template::parse_field)]
+    columns: Vec<String>,
+}
+
+impl LayoutArgs {
+    fn layout(&self, config_dir: &Path) -> anyhow::Result<task::Layout<'_>> {
+        Ok(match &self.format {
+            Some(template) => task::Layout::Template(template),
+            None if !self.columns.is_empty() => task::Layout::Table(self.columns.clone()),
+            None => task::Layout::Table(config::load(config_dir)?.columns()),
+        })
+    }
+}
+
+impl Commands {
+    /// The view `list` or `export` was asked to use.
+    fn view(&self) -> Option<&str> {
+        match self {
+            Commands::List { filters, .. } | Commands::Export { filters, .. } => {
+                filters.view.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Read-only commands whose output can run past one screen.
+    fn pages(&self) -> bool {
+        matches!(
+            self,
+            Commands::Inbox { text: None }
+                | Commands::List { watch: false, .. }
+                | Commands::Next { .. }
+                | Commands::Habits { .. }
+                | Commands::Matrix { .. }
+                | Commands::Fits { .. }
+                | Commands::Graph { .. }
+                | Commands::Plan { .. }
+                | Commands::Today { .. }
+                | Commands::Show { .. }
+                | Commands::Projects { .. }
+                | Commands::Milestone {
+                    action: MilestoneAction::List
+                }
+                | Commands::Report { .. }
+                | Commands::Stats { .. }
+                | Commands::Lint
+                | Commands::Query { .. }
+                | Commands::Scripts
+                | Commands::History { .. }
+                | Commands::Diff { .. }
+        )
+    }
+
+    /// Whether the command only shows tasks or hands them out, never saving
+    /// them, so they can be masked for it.
+    fn displays(&self) -> bool {
+        self.pages()
+            || matches!(
+                self,
+                Commands::List { .. }
+                    | Commands::Export { .. }
+                    | Commands::Shuffle { .. }
+                    | Commands::Graph { .. }
+                    | Commands::Script(_)
+                    | Commands::Review { action: None }
+                    | Commands::Schedule {
+                        export: Some(_),
+                        ..
+                    }
+                    | Commands::GitHook {
+                        action: GitHookAction::PrepareCommitMsg { .. }
+                    }
+            )
+    }
+}
+
+#[derive(Subcommand)]
+enum IngestSource {
+    /// One task per unread email in an IMAP folder
+    Imap {
+        /// The folder to read, e.g. imaps://mail.example.com/INBOX
+        url: String,
+        /// Login name
+        #[arg(long, env = "CLI_TASK_MANAGER_IMAP_USER")]
+        user: Option<String>,
+        #[arg(long, env = "CLI_TASK_MANAGER_IMAP_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+        /// Project for the new tasks
+        #[arg(long)]
+        project: Option<String>,
+        /// Keep checking for new mail every this many seconds
+        #[arg(long)]
+        interval: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuthAction {
+    /// Store a secret, read from the terminal (or stdin when piped)
+    Set {
+        #[arg(value_enum)]
+        service: keyring::Service,
+        /// The server URL for `sync`, or the login name for `imap`
+        account: Option<String>,
+    },
+    /// Delete a stored secret
+    Remove {
+        #[arg(value_enum)]
+        service: keyring::Service,
+        account: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum GitHookAction {
+    /// Install the prepare-commit-msg and post-commit hooks
+    Install {
+        /// Replace existing hooks of the same name
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove the hooks installed by `install`
+    Uninstall,
+    #[command(hide = true)]
+    PrepareCommitMsg {
+        file: PathBuf,
+        source: Option<String>,
+        commit: Option<String>,
+    },
+    #[command(hide = true)]
+    PostCommit,
+}
+
+#[derive(Subcommand)]
+enum CheckAction {
+    /// Add a step to a task's checklist
+    Add { id: u32, text: String },
+    /// Tick off checklist step number `n` (as printed by `show`)
+    Done { id: u32, n: usize },
+}
+
+#[derive(Subcommand)]
+enum ContextAction {
+    /// Define a context, e.g. `context define work '--filter project:work'`
+    Define {
+        name: String,
+        #[arg(allow_hyphen_values = true)]
+        filter: String,
+    },
+    /// Forget a context
+    Remove { name: String },
+    /// Narrow list, next, and report to a context from now on
+    Set { name: String },
+    /// Show every task again
+    Clear,
+    /// Show the contexts, marking the active one with *
+    List,
+}
+
+#[derive(Subcommand)]
+enum ViewAction {
+    /// Save list arguments under a name, e.g.
+    /// `view save urgent '--filter "priority:high status:open" --sort due'`
+    Save {
+        name: String,
+        #[arg(allow_hyphen_values = true)]
+        args: String,
+    },
+    /// Forget a view
+    Remove { name: String },
+    /// Show the saved views
+    List,
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// Start a profile with no tasks
+    Create {
+        name: String,
+        /// Start it with a copy of the default profile's config.json
+        #[arg(long)]
+        copy_config: bool,
+    },
+    /// Delete a profile with its settings and tasks
+    Delete {
+        name: String,
+        /// Do not ask first
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the profiles, marking the one in use with *
+    List,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a setting, e.g. `config get working_hours.fri`
+    Get { key: String },
+    /// Change a setting, e.g. `config set archive_after_days 30`; the value
+    /// is read as JSON when it is JSON and as text otherwise
+    Set {
+        key: String,
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+    },
+    /// Open config.json in $VISUAL or $EDITOR, saving it only if it loads
+    Edit,
+}
+
+#[derive(Subcommand)]
+enum MilestoneAction {
+    /// Create a milestone due on the given date
+    Add {
+        name: String,
+        /// Target date (YYYY-MM-DD)
+        #[arg(long, value_parser = report::parse_date)]
+        due: chrono::NaiveDate,
+    },
+    /// Delete a milestone that no longer has tasks
+    Remove { name: String },
+    /// Show each milestone's open tasks against days remaining
+    List,
+    /// Put a task in a milestone (omit the name to take it out)
+    Set { id: u32, name: Option<String> },
+}
+
+#[derive(Subcommand)]
+enum ReviewAction {
+    /// Add a task for a review every week on a day, or every day,
+    /// e.g. `review every "Weekly review" friday --at 16:00`
+    Every {
+        name: String,
+        /// daily, or a day of the week
+        #[arg(value_parser = review::parse_every)]
+        on: String,
+        #[arg(long, value_parser = report::parse_time, default_value = "09:00")]
+        at: chrono::NaiveTime,
+    },
+    /// Show recurring reviews and when each comes around next
+    List,
+    /// Stop a recurring review
+    Stop { name: String },
+}
+
+#[derive(Subcommand)]
+enum ReportKind {
+    /// Last week's completions, this week's plan, and overdue tasks, for printing
+    Weekly {
+        #[arg(long, value_enum, default_value_t)]
+        format: report::WeeklyFormat,
+    },
+    /// Compare estimates with tracked time, per task and overall
+    Accuracy {
+        /// Include open tasks, not just completed ones
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Task counts, open/done split, and open estimates per tag, project, ...
+    GroupBy {
+        #[arg(value_enum)]
+        by: report::GroupKey,
+    },
+    /// Completed tasks with their completion dates and resolution notes
+    Done {
+        /// First day to include (YYYY-MM-DD)
+        #[arg(long, value_parser = report::parse_date)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day to include (YYYY-MM-DD)
+        #[arg(long, value_parser = report::parse_date)]
+        to: Option<chrono::NaiveDate>,
+    },
+    /// Tracked time per project and task, e.g. for invoicing
+    Time {
+        /// First day to include (YYYY-MM-DD)
+        #[arg(long, value_parser = report::parse_date)]
+        from: Option<chrono::NaiveDate>,
+        /// Last day to include (YYYY-MM-DD)
+        #[arg(long, value_parser = report::parse_date)]
+        to: Option<chrono::NaiveDate>,
+        /// Only tasks in this project
+        #[arg(long)]
+        project: Option<String>,
+        /// Hourly rate used to price each line
+        #[arg(long)]
+        rate: Option<f64>,
+        #[arg(long, value_enum, default_value_t)]
+        format: report::Format,
+    },
+}
+
+#[derive(Subcommand)]
+enum SyncTarget {
+    /// Sync with a tasks file at another path (e.g. a shared folder)
+    File { path: PathBuf },
+    /// Sync with a tasks file on another machine, given as user@host:path
+    Ssh { target: String },
+    /// Sync with a `serve --sync` server, e.g. http://host:7373 or https://host
+    Remote {
+        url: String,
+        /// Bearer token configured on the server (default: from `auth set sync`)
+        #[arg(long, env = "CLI_TASK_MANAGER_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Show how the syncs of a running `daemon` went
+    Status,
 }
 
 fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    let data_path = get_data_path()?;
-    let mut tasks = task::load_tasks(&data_path)?;
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    run(Cli::parse_from(&argv), argv, None)
+}
+
+/// Runs one command; in the `shell`, `session` keeps the tasks loaded from
+/// one to the next.
+fn run(
+    mut cli: Cli,
+    mut argv: Vec<OsString>,
+    mut session: Option<&mut shell::Session>,
+) -> anyhow::Result<()> {
+    if let Commands::Profile { action } = &cli.command {
+        // Managed from the usual directories, whichever profile is in use.
+        let base = paths::Dirs::locate(false, true, None)?;
+        return match action {
+            ProfileAction::Create { name, copy_config } => {
+                profile::create(&base, name, *copy_config)
+            }
+            ProfileAction::Delete { name, force } => profile::delete(&base, name, *force),
+            ProfileAction::List => profile::print_profiles(&base, cli.profile.as_deref()),
+        };
+    }
+    let dirs = paths::Dirs::locate(cli.portable, cli.global, cli.profile.as_deref())?;
+    if let Some(name) = cli.command.view() {
+        let args = view::lookup(&dirs.config, name)?;
+        argv = view::expand(argv, name, args);
+        cli = Cli::parse_from(&argv);
+        if cli.command.view().is_some() {
+            anyhow::bail!("A view cannot use another view");
+        }
+    }
+    dirs.migrate_legacy()?;
+    let default_path = dirs.tasks.clone();
+    let data_path = match &cli.in_context {
+        Some(name) => context::tasks_path(&default_path, name)?,
+        None => default_path.clone(),
+    };
+    if let Commands::Do { dry_run, sentence } = &cli.command {
+        let tasks = task::load_tasks(&data_path)?;
+        let args = sentence::translate(&sentence.join(" "), &tasks)?;
+        let shown: Vec<String> = args
+            .iter()
+            .map(|arg| match arg.contains(char::is_whitespace) {
+                true => format!("{:?}", arg),
+                false => arg.clone(),
+            })
+            .collect();
+        eprintln!("> {}", shown.join(" "));
+        if *dry_run {
+            return Ok(());
+        }
+        let at = argv
+            .iter()
+            .position(|arg| arg == "do")
+            .context("`do` missing from the command line")?;
+        argv.truncate(at);
+        argv.extend(args.into_iter().map(Into::into));
+        cli = Cli::parse_from(&argv);
+    }
+    if let Commands::Shell = cli.command {
+        if session.is_some() {
+            anyhow::bail!("Already in the shell");
+        }
+        return shell(&cli, &dirs);
+    }
+
+    match cli.command {
+        Commands::Status {
+            short,
+            waybar,
+            tmux,
+            interval,
+        } => {
+            let style = match (short, waybar, tmux) {
+                (true, _, _) => status::Style::Short,
+                (_, true, _) => status::Style::Waybar,
+                (_, _, true) => status::Style::Tmux,
+                _ => status::Style::Summary,
+            };
+            let interval = interval.map(|secs| std::time::Duration::from_secs(secs.max(1)));
+            return status::print(&data_path, style, interval);
+        }
+        Commands::Context { action } => {
+            return match action {
+                ContextAction::Define { name, filter } => {
+                    focus::define(&dirs.config, &name, &filter)
+                }
+                ContextAction::Remove { name } => focus::remove(&dirs.config, &name),
+                ContextAction::Set { name } => focus::set(&dirs.config, &name),
+                ContextAction::Clear => focus::clear(&dirs.config),
+                ContextAction::List => focus::print_contexts(&dirs.config),
+            };
+        }
+        Commands::View { action } => {
+            return match action {
+                ViewAction::Save { name, args } => {
+                    let words = view::split(&args)?;
+                    let argv = ["cli_task_manager", "list"].into_iter().map(String::from);
+                    match Cli::try_parse_from(argv.chain(words)) {
+                        Ok(cli) if cli.command.view().is_none() => {
+                            view::save(&dirs.config, &name, &args)
+                        }
+                        Ok(_) => anyhow::bail!("A view cannot use another view"),
+                        Err(err) => anyhow::bail!(
+                            "Invalid list arguments for view {}:\n{}",
+                            name,
+                            err.render().to_string().trim_end()
+                        ),
+                    }
+                }
+                ViewAction::Remove { name } => view::remove(&dirs.config, &name),
+                ViewAction::List => view::print_views(&dirs.config),
+            };
+        }
+        Commands::Config { action } => {
+            return match action {
+                ConfigAction::Get { key } => config::get(&dirs.config, &key),
+                ConfigAction::Set { key, value } => config::set(&dirs.config, &key, &value),
+                ConfigAction::Edit => config::edit(&dirs.config),
+            };
+        }
+        Commands::GitHook {
+            action: GitHookAction::Install { force },
+        } => return githook::install(force),
+        Commands::GitHook {
+            action: GitHookAction::Uninstall,
+        } => return githook::uninstall(),
+        Commands::Auth { action } => {
+            return match action {
+                AuthAction::Set { service, account } => {
+                    keyring::set(service, &service.account(account)?)
+                }
+                AuthAction::Remove { service, account } => {
+                    keyring::remove(service, &service.account(account)?)
+                }
+            };
+        }
+        Commands::Ingest {
+            source:
+                IngestSource::Imap {
+                    url,
+                    user,
+                    password,
+                    project,
+                    interval,
+                },
+        } => {
+            let password = match (&user, password) {
+                (Some(user), None) => keyring::get(keyring::Service::Imap, user)?,
+                (_, password) => password,
+            };
+            let mailbox = imap::Mailbox {
+                url,
+                user,
+                password,
+            };
+            let interval = interval.map(|secs| std::time::Duration::from_secs(secs.max(1)));
+            return imap::poll(&data_path, &mailbox, project.as_deref(), interval);
+        }
+        Commands::Paths => {
+            println!("Tasks:  {}", data_path.display());
+            println!("Config: {}", dirs.config.display());
+            println!("Cache:  {}", dirs.cache.display());
+            return Ok(());
+        }
+        Commands::GenerateMan { dir } => {
+            let written = man::generate(Cli::command(), &dir)?;
+            println!("Wrote {} man pages to {}.", written, dir.display());
+            return Ok(());
+        }
+        Commands::SelfUpdate { check } => return update::run(check),
+        Commands::Compact { keep_days } => return compact::compact(&data_path, keep_days),
+        Commands::Daemon {
+            interval,
+            debounce,
+            prefer,
+        } => {
+            let interval = std::time::Duration::from_secs(interval.max(1));
+            let debounce = std::time::Duration::from_secs(debounce);
+            let config_dir = dirs.config.clone();
+            let render = move |args: Vec<String>, tasks: &[task::Task]| {
+                let cli = Cli::try_parse_from(args)?;
+                let Commands::List {
+                    filters,
+                    sort,
+                    limit,
+                    offset,
+                    page,
+                    layout,
+                    ..
+                } = cli.command
+                else {
+                    anyhow::bail!("The daemon only answers `list`");
+                };
+                let script = filters.script.clone();
+                let filter = filters.list_filter(&config_dir)?;
+                let page = list_page(limit, offset, page);
+                let mut tasks = tasks.to_vec();
+                print_list(
+                    &config_dir,
+                    &mut tasks,
+                    filter,
+                    script.as_deref(),
+                    &sort,
+                    page,
+                    &layout,
+                )
+            };
+            return daemon::run(
+                &data_path,
+                &dirs.config,
+                interval,
+                debounce,
+                prefer,
+                Box::new(render),
+            );
+        }
+        Commands::Sync {
+            target: SyncTarget::Status,
+            ..
+        } => return daemon::print_status(&data_path),
+        Commands::Doctor { notify } => {
+            let code = doctor::run(&dirs, &data_path, notify);
+            if session.is_some() {
+                return Ok(());
+            }
+            std::process::exit(code)
+        }
+        Commands::Validate => {
+            let code = validate::run(&data_path);
+            if session.is_some() {
+                return Ok(());
+            }
+            std::process::exit(code)
+        }
+        Commands::Convert { format } => return storage::convert(&data_path, format),
+        Commands::Repair => {
+            let report = repair::repair_tasks(&data_path)?;
+            if report.is_none()
+                && let Some(count) = checksum::accept(&data_path)?
+            {
+                println!(
+                    "{} no longer matched its checksum; accepted it as it is, with {} task(s).",
+                    data_path.display(),
+                    count
+                );
+                return Ok(());
+            }
+            repair::print_report(&data_path, report.as_ref());
+            return Ok(());
+        }
+        Commands::Serve {
+            addr,
+            grpc,
+            sync,
+            token,
+            users,
+            mcp,
+            read_only,
+        } => {
+            if mcp {
+                let options = mcp::McpOptions {
+                    config_dir: &dirs.config,
+                    read_only,
+                    redact: config::load(&dirs.config)?.redact,
+                };
+                return mcp::serve(&data_path, &options);
+            }
+            let users = match users {
+                Some(path) => access::load_users(&path)?,
+                None => Vec::new(),
+            };
+            let token = match token {
+                Some(token) => Some(token),
+                None => keyring::get(keyring::Service::Serve, "default")?,
+            };
+            let options = server::ServeOptions {
+                addr,
+                grpc,
+                sync,
+                token,
+                users,
+                redact: config::load(&dirs.config)?.redact,
+            };
+            return server::serve(&data_path, options);
+        }
+        _ => {}
+    }
+
+    let _pager = if !cli.no_pager && cli.command.pages() {
+        pager::start(config::load(&dirs.config)?.pager.as_deref())
+    } else {
+        None
+    };
+    // A running daemon has the tasks in memory already.
+    if let Commands::List {
+        watch: false,
+        archived: false,
+        ..
+    } = cli.command
+        && session.is_none()
+    {
+        let args = argv.iter().map(|a| a.to_string_lossy().into_owned());
+        if daemon::list(&data_path, args.collect())? {
+            return Ok(());
+        }
+    }
+    let redaction = config::load(&dirs.config)?.redact;
+    let automations = config::load(&dirs.config)?.automations;
+    // Commands touching one task skip parsing the whole list when it is
+    // large enough to be indexed, unless automations, archiving, or a review
+    // that has come around need to see it.
+    let whole_list = !automations.is_empty()
+        || config::load(&dirs.config)?.archive_after_days.is_some()
+        || review::pending(&data_path)?;
+    match &cli.command {
+        _ if session.is_some() => {}
+        Commands::Show { id } if !whole_list && index::show(&data_path, *id, &redaction)? => {
+            return Ok(());
+        }
+        Commands::Done { id, note, notify } if !whole_list => {
+            if let Some(unblocked) = index::done(&data_path, *id, note.clone())? {
+                let unblocked: Vec<&task::Task> = unblocked.iter().collect();
+                report_unblocked(&[], &unblocked, *notify, &redaction);
+                return Ok(());
+            }
+        }
+        _ => {}
+    }
+
+    let mut tasks = match session.as_deref_mut() {
+        Some(session) => session.tasks(&data_path)?,
+        None => task::load_tasks(&data_path)?,
+    };
+    if review::trigger(&data_path, &mut tasks)? {
+        task::save_tasks(&data_path, &tasks)?;
+    }
+    if let Some(days) = config::load(&dirs.config)?.archive_after_days
+        && archive::rotate(&data_path, &mut tasks, days)? > 0
+    {
+        task::save_tasks(&data_path, &tasks)?;
+    }
+    if !automations.is_empty() {
+        let clock = crdt::Clock::load(&data_path, &tasks)?;
+        if automation::check_overdue(&automations, &mut tasks, &redaction, &clock)? {
+            task::save_tasks(&data_path, &tasks)?;
+        }
+    }
+    let displays = cli.command.displays();
+    if displays {
+        redaction.apply(&mut tasks);
+    }
+    // Edits synced in from elsewhere already set off automations there.
+    let before =
+        (!automations.is_empty() && !displays && !matches!(cli.command, Commands::Sync { .. }))
+            .then(|| automation::Snapshot::of(&tasks));
+    let undoable =
+        !displays && !matches!(cli.command, Commands::Undo { .. } | Commands::Redo { .. });
+    let snapshot = undoable.then(|| tasks.clone());
 
     match cli.command {
-        Commands::Add { description } => {
-            task::add_task(&mut tasks, description)?;
+        Commands::Add {
+            description,
+            project,
+            milestone,
+            estimate,
+            priority,
+            due,
+            habit,
+            tag,
+            strict,
+        } => {
+            dedupe::check_new(&tasks, &description, strict)?;
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let milestone = match milestone {
+                Some(name) => Some(milestone::resolve(
+                    &milestone::load_milestones(&data_path)?,
+                    &name,
+                )?),
+                None => None,
+            };
+            let details = task::NewTask {
+                project,
+                milestone,
+                priority,
+                due,
+                estimate,
+                habit,
+                tags: tag,
+            };
+            let id = task::add_task(&mut tasks, description, details, &clock)?;
+            autotag::classify(
+                &config::load(&dirs.config)?.auto_tag,
+                &mut tasks,
+                id,
+                &clock,
+            )?;
+            task::save_tasks(&data_path, &tasks)?;
+            if let Some(due) = due {
+                let hours = config::load(&dirs.config)?.working_hours;
+                schedule::warn_overcommit(&tasks, due, &hours);
+            }
+        }
+        Commands::Inbox { text } => match text {
+            Some(text) => {
+                let clock = crdt::Clock::load(&data_path, &tasks)?;
+                inbox::capture(&mut tasks, text, &clock)?;
+                task::save_tasks(&data_path, &tasks)?;
+            }
+            None => inbox::print_inbox(&tasks),
+        },
+        Commands::Scan { path } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            if scan::scan(&mut tasks, &path, &clock)? {
+                task::save_tasks(&data_path, &tasks)?;
+            }
+        }
+        Commands::GitHook { action } => match action {
+            GitHookAction::PrepareCommitMsg { file, source, .. } => {
+                githook::prepare_message(&tasks, &file, source.as_deref())?
+            }
+            GitHookAction::PostCommit => {
+                let clock = crdt::Clock::load(&data_path, &tasks)?;
+                if githook::after_commit(&mut tasks, &clock)? {
+                    task::save_tasks(&data_path, &tasks)?;
+                }
+            }
+            GitHookAction::Install { .. } | GitHookAction::Uninstall => {
+                unreachable!("handled before loading tasks")
+            }
+        },
+        Commands::Export {
+            format,
+            filters,
+            sort,
+        } => {
+            let script = filters.script.clone();
+            let mut filter = filters.list_filter(&dirs.config)?;
+            // A backup should hold the someday tasks, too.
+            filter.with_someday = true;
+            if let Some(name) = &script {
+                filter.only = Some(script::filter(&dirs.config, &tasks, name)?);
+            }
+            print!("{}", export::export(&tasks, format, &filter, &sort)?);
+        }
+        Commands::Stats {
+            chart,
+            heatmap,
+            score,
+            by,
+            days,
+        } => match chart {
+            Some(chart) => stats::chart(&tasks, chart, days),
+            None if heatmap => stats::heatmap(&tasks),
+            None if score => stats::score(&tasks, &config::load(&dirs.config)?.score, by, days),
+            None => stats::summary(&tasks, days),
+        },
+        Commands::Clarify { ai } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let suggestions = if ai {
+                let settings = config::load(&dirs.config)?.ai.context(
+                    "No model to ask: set \"ai\": { \"url\": ..., \"model\": ... } in config.json",
+                )?;
+                let pending: Vec<u32> = tasks
+                    .iter()
+                    .filter(|t| t.inbox && !t.completed)
+                    .map(|t| t.id)
+                    .collect();
+                if pending.is_empty() {
+                    HashMap::new()
+                } else {
+                    ai::suggest(&settings, &tasks, &pending, &redaction)?
+                }
+            } else {
+                HashMap::new()
+            };
+            if inbox::clarify(&data_path, &mut tasks, &clock, &suggestions)? {
+                task::save_tasks(&data_path, &tasks)?;
+            }
+        }
+        Commands::List {
+            filters,
+            sort,
+            limit,
+            offset,
+            page,
+            layout,
+            watch,
+            interval,
+            archived,
+            month,
+        } => {
+            if archived {
+                tasks = archive::load_archived(&data_path, month.as_deref())?;
+                redaction.apply(&mut tasks);
+            }
+            let script = filters.script.clone();
+            let mut filter = filters.list_filter(&dirs.config)?;
+            filter.all |= archived;
+            let page = list_page(limit, offset, page);
+            if watch {
+                let interval = std::time::Duration::from_secs(interval.max(1));
+                return watch::run(&data_path, interval, "list", || {
+                    let mut tasks = task::load_tasks(&data_path)?;
+                    redaction.apply(&mut tasks);
+                    aging::apply(&config::load(&dirs.config)?.aging, &mut tasks);
+                    let mut filter = filter.clone();
+                    if let Some(name) = &script {
+                        filter.only = Some(script::filter(&dirs.config, &tasks, name)?);
+                    }
+                    let layout = layout.layout(&dirs.config)?;
+                    task::list_tasks(&tasks, &filter, &sort, page, &layout);
+                    Ok(())
+                });
+            }
+            print_list(
+                &dirs.config,
+                &mut tasks,
+                filter,
+                script.as_deref(),
+                &sort,
+                page,
+                &layout,
+            )?;
+        }
+        Commands::Next {
+            count,
+            sort,
+            layout,
+        } => {
+            aging::apply(&config::load(&dirs.config)?.aging, &mut tasks);
+            let context = active_context(&dirs.config)?;
+            let layout = layout.layout(&dirs.config)?;
+            task::print_next(&tasks, count, &sort, &layout, context.as_ref());
+        }
+        Commands::Shuffle {
+            project,
+            max_estimate,
+        } => task::print_random(&tasks, project.as_deref(), max_estimate),
+        Commands::Habits { weeks } => habit::print_habits(&tasks, weeks),
+        Commands::Done { id, note, notify } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let task = task::find_task_mut(&mut tasks, id)?;
+            if task.habit.is_some() {
+                if note.is_some() {
+                    anyhow::bail!("Habits are never closed, so they take no --note");
+                }
+                habit::record(task, &clock)?;
+                println!(
+                    "Recorded {}: {}.",
+                    redaction.mask(&task.description),
+                    habit::progress(task).unwrap_or_default()
+                );
+                task::save_tasks(&data_path, &tasks)?;
+            } else {
+                let finished = task::mark_done(&mut tasks, id, note, &clock)?;
+                task::save_tasks(&data_path, &tasks)?;
+
+                let unblocked = deps::unblocked_by(&tasks, finished);
+                report_unblocked(&tasks, &unblocked, notify, &redaction);
+            }
+        }
+        Commands::Reopen { id } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::reopen_task(&mut tasks, id, &clock)?;
             task::save_tasks(&data_path, &tasks)?;
         }
-        Commands::List { all } => task::list_tasks(&tasks, all),
-        Commands::Done { id } => {
-            task::mark_done(&mut tasks, id)?;
+        Commands::Clone { id, due } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let new_id = task::clone_task(&mut tasks, id, due, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+            println!("Cloned task {} as task {}.", id, new_id);
+        }
+        Commands::Move {
+            id,
+            project,
+            context: to_context,
+        } => {
+            if let Some(project) = project {
+                let clock = crdt::Clock::load(&data_path, &tasks)?;
+                task::set_project(&mut tasks, id, Some(project), &clock)?;
+            }
+            match to_context {
+                Some(name) => {
+                    let target = context::tasks_path(&default_path, &name)?;
+                    let new_id = context::move_task(&data_path, &mut tasks, id, &target, &name)?;
+                    println!("Moved task {} to context {} as task {}.", id, name, new_id);
+                }
+                None => task::save_tasks(&data_path, &tasks)?,
+            }
+        }
+        Commands::Merge { id, into } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let uuid = task::merge_tasks(&mut tasks, id, into, &clock)?;
+            crdt::bury(&data_path, uuid, clock.tick())?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Scripts => script::print_scripts(&dirs.config)?,
+        Commands::Script(words) => script::command(&dirs.config, &tasks, &words)?,
+        Commands::Dedupe => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            if dedupe::dedupe(&data_path, &mut tasks, &clock)? {
+                task::save_tasks(&data_path, &tasks)?;
+            }
+        }
+        Commands::Split { id, items } => {
+            let items = if items.is_empty() {
+                read_items()?
+            } else {
+                items
+            };
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let ids = task::split_task(&mut tasks, id, &items, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+            let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+            println!("Split task {} into tasks {}.", id, ids.join(", "));
+        }
+        Commands::MoveUp { id } => reorder(&data_path, &mut tasks, id, task::Placement::Up)?,
+        Commands::MoveDown { id } => reorder(&data_path, &mut tasks, id, task::Placement::Down)?,
+        Commands::MoveTo { id, position } => {
+            reorder(&data_path, &mut tasks, id, task::Placement::At(position))?
+        }
+        Commands::Someday { id, off } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::set_someday(&mut tasks, id, !off, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Review { action } => match action {
+            None => review::print_review(&tasks),
+            Some(ReviewAction::Every { name, on, at }) => {
+                let mut reviews = review::load_recurring(&data_path)?;
+                review::add_recurring(&mut reviews, &name, on, at)?;
+                review::save_recurring(&data_path, &reviews)?;
+                review::print_recurring(&reviews);
+            }
+            Some(ReviewAction::List) => {
+                review::print_recurring(&review::load_recurring(&data_path)?)
+            }
+            Some(ReviewAction::Stop { name }) => {
+                let mut reviews = review::load_recurring(&data_path)?;
+                review::remove_recurring(&mut reviews, &name)?;
+                review::save_recurring(&data_path, &reviews)?;
+            }
+        },
+        Commands::Matrix { urgent_days } => matrix::print_matrix(&tasks, urgent_days),
+        Commands::Pin { id } | Commands::Unpin { id } => {
+            let pinned = matches!(cli.command, Commands::Pin { .. });
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::set_pinned(&mut tasks, id, pinned, &clock)?;
             task::save_tasks(&data_path, &tasks)?;
         }
         Commands::Remove { id } => {
-            task::remove_task(&mut tasks, id)?;
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let uuid = task::remove_task(&mut tasks, id)?;
+            crdt::bury(&data_path, uuid, clock.tick())?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Undo { list: true, .. } => undo::print_history(&data_path)?,
+        Commands::Undo { count, .. } => undo::undo(&data_path, &mut tasks, count)?,
+        Commands::Redo { count } => undo::redo(&data_path, &mut tasks, count)?,
+        Commands::Diff { file } => diff::print(&tasks, &file, &redaction)?,
+        Commands::History { since, until } => {
+            history::print(&data_path, &tasks, since, until, &redaction)?
+        }
+        Commands::Assign { id, name } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::assign_task(&mut tasks, id, name, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Priority { id, level } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::set_priority(&mut tasks, id, level, &clock)?;
             task::save_tasks(&data_path, &tasks)?;
         }
+        Commands::Due { id, date } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::set_due(&mut tasks, id, date, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Estimate { id, duration } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::estimate_task(&mut tasks, id, duration, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Edit {
+            id,
+            description,
+            tag,
+            untag,
+            set,
+            unset,
+        } => {
+            if description.is_none()
+                && tag.is_empty()
+                && untag.is_empty()
+                && set.is_empty()
+                && unset.is_empty()
+            {
+                anyhow::bail!(
+                    "Nothing to change; pass --description, --tag, --untag, --set or --unset"
+                );
+            }
+            let config = config::load(&dirs.config)?;
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let task = task::find_task_mut(&mut tasks, id)?;
+            task::retag(task, &tag, &untag, &clock);
+            if !set.is_empty() || !unset.is_empty() {
+                attribute::edit(task, &config.attributes, &set, &unset, &clock)?;
+            }
+            if let Some(description) = description {
+                task::set_description(task, &description, &clock)?;
+                autotag::classify(&config.auto_tag, &mut tasks, id, &clock)?;
+            }
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Query { sql } => query::run(&tasks, &sql)?,
+        Commands::Lint => lint::print_lint(&tasks, &config::load(&dirs.config)?.lint),
+        Commands::Fits { duration } => task::print_fits(&tasks, duration),
+        Commands::Delegate { id, person } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::delegate_task(&mut tasks, id, person, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Depend { id, on, remove } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            if remove {
+                deps::remove_dependency(&mut tasks, id, on, &clock)?;
+            } else {
+                deps::add_dependency(&mut tasks, id, on, &clock)?;
+            }
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Parent { id, parent } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            deps::set_parent(&mut tasks, id, parent, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Graph { format, all } => graph::print_graph(&tasks, format, all),
+        Commands::Archive { older_than } => {
+            let days = match older_than {
+                Some(days) => days,
+                None => config::load(&dirs.config)?.archive_after_days.unwrap_or(90),
+            };
+            let moved = archive::rotate(&data_path, &mut tasks, days)?;
+            if moved > 0 {
+                task::save_tasks(&data_path, &tasks)?;
+            }
+            println!(
+                "Archived {} task(s) completed more than {} day(s) ago.",
+                moved, days
+            );
+        }
+        Commands::Today { layout } => schedule::print_today(
+            &tasks,
+            &config::load(&dirs.config)?.working_hours,
+            &layout.layout(&dirs.config)?,
+        ),
+        Commands::Schedule {
+            capacity,
+            dry_run,
+            export,
+            day_start,
+        } => {
+            let hours = config::load(&dirs.config)?.working_hours;
+            let capacity = |day| capacity.map_or_else(|| hours.minutes(day), |c| c.max(1));
+            if let Some(ics::Format::Ics) = export {
+                print!("{}", ics::export(&tasks, &capacity, day_start)?);
+                return Ok(());
+            }
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            if schedule::run(&mut tasks, &capacity, dry_run, &clock)? {
+                task::save_tasks(&data_path, &tasks)?;
+            }
+        }
+        Commands::Plan {
+            critical_path: false,
+            ..
+        } => plan::print_plan(&tasks)?,
+        Commands::Plan {
+            critical_path: true,
+            milestone,
+        } => {
+            let milestone = milestone
+                .map(|n| milestone::resolve(&milestone::load_milestones(&data_path)?, &n))
+                .transpose()?;
+            plan::print_critical_path(&tasks, milestone.as_deref())?
+        }
+        Commands::Show { id } => task::show_task(&tasks, id)?,
+        Commands::Comment { id, text, reply } => {
+            let author = current_user()?;
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            let task = task::find_task_mut(&mut tasks, id)?;
+            let reply_to = reply
+                .map(|number| task::comment_by_number(task, number))
+                .transpose()?;
+            task::add_comment(task, &author, &text, reply_to, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Start { id } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            if let Some(stopped) = task::start_task(&mut tasks, id, &clock)? {
+                println!("Stopped task {}.", stopped);
+            }
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Stop { id } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            task::stop_task(&mut tasks, id, &clock)?;
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Projects { weighted, bar } => report::projects(&tasks, weighted, bar),
+        Commands::Milestone { action } => {
+            let mut milestones = milestone::load_milestones(&data_path)?;
+            match action {
+                MilestoneAction::Add { name, due } => {
+                    milestone::add_milestone(&mut milestones, &name, due)?;
+                    milestone::save_milestones(&data_path, &milestones)?;
+                }
+                MilestoneAction::Remove { name } => {
+                    milestone::remove_milestone(&mut milestones, &tasks, &name)?;
+                    milestone::save_milestones(&data_path, &milestones)?;
+                }
+                MilestoneAction::List => milestone::print_milestones(&milestones, &tasks),
+                MilestoneAction::Set { id, name } => {
+                    let name = name
+                        .map(|n| milestone::resolve(&milestones, &n))
+                        .transpose()?;
+                    let clock = crdt::Clock::load(&data_path, &tasks)?;
+                    task::set_milestone(&mut tasks, id, name, &clock)?;
+                    task::save_tasks(&data_path, &tasks)?;
+                }
+            }
+        }
+        Commands::Report { kind } => {
+            if let Some(context) = active_context(&dirs.config)? {
+                let all = tasks.clone();
+                tasks.retain(|t| context.matches(&all, t));
+            }
+            match kind {
+                ReportKind::Weekly { format } => print!("{}", report::weekly(&tasks, format)),
+                ReportKind::Accuracy { all } => report::accuracy(&tasks, all),
+                ReportKind::Done { from, to } => report::done(&tasks, from, to),
+                ReportKind::GroupBy { by } => report::group_by(&tasks, by),
+                ReportKind::Time {
+                    from,
+                    to,
+                    project,
+                    rate,
+                    format,
+                } => {
+                    let query = report::TimeQuery {
+                        from,
+                        to,
+                        project,
+                        rate,
+                        format,
+                    };
+                    report::time(&tasks, &query);
+                }
+            }
+        }
+        Commands::Check { action } => {
+            let clock = crdt::Clock::load(&data_path, &tasks)?;
+            match action {
+                CheckAction::Add { id, text } => {
+                    task::add_check_item(task::find_task_mut(&mut tasks, id)?, &text, &clock)?
+                }
+                CheckAction::Done { id, n } => {
+                    task::complete_check_item(task::find_task_mut(&mut tasks, id)?, n, &clock)?
+                }
+            }
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Sync { prefer, target } => {
+            let config = config::load(&dirs.config)?;
+            let encryption = config.sync_encryption.as_ref();
+            match target {
+                SyncTarget::File { path } => {
+                    sync::sync_file(&data_path, &mut tasks, &path, prefer, encryption)?
+                }
+                SyncTarget::Ssh { target } => {
+                    sync::sync_ssh(&data_path, &mut tasks, &target, prefer, encryption)?
+                }
+                SyncTarget::Remote { url, token } => {
+                    let account = keyring::Service::Sync.account(Some(url.clone()))?;
+                    let token = match token {
+                        Some(token) => token,
+                        None => {
+                            keyring::get(keyring::Service::Sync, &account)?.with_context(|| {
+                                format!(
+                                    "No token for {}; pass --token or run `auth set sync {}`",
+                                    url, account
+                                )
+                            })?
+                        }
+                    };
+                    sync::sync_remote(&data_path, &mut tasks, &url, &token, prefer, encryption)?
+                }
+                SyncTarget::Status => unreachable!("handled before loading tasks"),
+            }
+            task::save_tasks(&data_path, &tasks)?;
+        }
+        Commands::Status { .. }
+        | Commands::Ingest { .. }
+        | Commands::Paths
+        | Commands::GenerateMan { .. }
+        | Commands::SelfUpdate { .. }
+        | Commands::Auth { .. }
+        | Commands::Doctor { .. }
+        | Commands::Repair
+        | Commands::Validate
+        | Commands::Convert { .. }
+        | Commands::Compact { .. }
+        | Commands::Daemon { .. }
+        | Commands::Serve { .. }
+        | Commands::Do { .. }
+        | Commands::Shell
+        | Commands::Context { .. }
+        | Commands::View { .. }
+        | Commands::Config { .. }
+        | Commands::Profile { .. } => {
+            unreachable!("handled before loading tasks")
+        }
+    }
+    if let Some(before) = before {
+        let clock = crdt::Clock::load(&data_path, &tasks)?;
+        if automation::after_command(&automations, &before, &mut tasks, &redaction, &clock)? {
+            task::save_tasks(&data_path, &tasks)?;
+        }
+    }
+    if let Some(snapshot) = snapshot {
+        match &session {
+            Some(session) => undo::record_as(&data_path, session.line.clone(), &snapshot, &tasks)?,
+            None => undo::record(&data_path, &snapshot, &tasks)?,
+        }
+    }
+    if let Some(session) = session
+        && !displays
+    {
+        session.keep(&data_path, &tasks);
+    }
+    Ok(())
+}
+
+/// `shell`: runs each line typed as a command, with the global options the
+/// shell was started with unless the line gives its own.
+fn shell(cli: &Cli, dirs: &paths::Dirs) -> anyhow::Result<()> {
+    let mut globals = vec![vec!["--no-pager".to_owned()]];
+    if let Some(name) = &cli.in_context {
+        globals.push(vec!["--in".to_owned(), name.clone()]);
+    }
+    if cli.portable {
+        globals.push(vec!["--portable".to_owned()]);
+    }
+    if cli.global {
+        globals.push(vec!["--global".to_owned()]);
+    }
+    if let Some(name) = &cli.profile {
+        globals.push(vec!["--profile".to_owned(), name.clone()]);
+    }
+    let completions = shell::Completions(
+        Cli::command()
+            .get_subcommands()
+            .filter(|command| !command.is_hide_set())
+            .map(|command| {
+                let options = command
+                    .get_arguments()
+                    .filter_map(|arg| arg.get_long())
+                    .map(|long| format!("--{}", long))
+                    .collect();
+                (command.get_name().to_owned(), options)
+            })
+            .collect(),
+    );
+    let mut session = shell::Session::default();
+    shell::run(
+        &dirs.cache.join("shell_history"),
+        completions,
+        |line, words| {
+            let mut argv: Vec<OsString> = vec![env!("CARGO_PKG_NAME").into()];
+            for global in &globals {
+                let given = words
+                    .iter()
+                    .any(|w| *w == global[0] || w.starts_with(&format!("{}=", global[0])));
+                if !given {
+                    argv.extend(global.iter().map(OsString::from));
+                }
+            }
+            argv.extend(words.into_iter().map(OsString::from));
+            match Cli::try_parse_from(&argv) {
+                Ok(cli) => {
+                    session.line = line.to_owned();
+                    run(cli, argv, Some(&mut session))
+                }
+                // Help and usage mistakes alike are only printed.
+                Err(err) => err.print().context("Failed to print help"),
+            }
+        },
+    )
+}
+
+fn reorder(
+    data_path: &Path,
+    tasks: &mut [task::Task],
+    id: u32,
+    placement: task::Placement,
+) -> anyhow::Result<()> {
+    let clock = crdt::Clock::load(data_path, tasks)?;
+    let position = task::reorder_task(tasks, id, placement, &clock)?;
+    task::save_tasks(data_path, tasks)?;
+    println!("Task {} is now at position {}.", id, position);
+    Ok(())
+}
+
+/// Reads subtasks for `split`, one per line, until an empty line or EOF.
+fn read_items() -> anyhow::Result<Vec<String>> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        println!("Enter subtasks one per line; finish with an empty line.");
+    }
+    let mut items = Vec::new();
+    for line in stdin.lock().lines() {
+        let line = line.context("Failed to read subtasks")?;
+        if line.trim().is_empty() {
+            break;
+        }
+        items.push(line);
+    }
+    Ok(items)
+}
+
+/// Lists the tasks completing one made actionable, and with `notify` sends
+/// them as a desktop notification too.
+fn report_unblocked(
+    tasks: &[task::Task],
+    unblocked: &[&task::Task],
+    notify: bool,
+    redaction: &redact::Rules,
+) {
+    if unblocked.is_empty() {
+        return;
+    }
+    let unblocked: Vec<task::Task> = unblocked.iter().map(|t| redaction.masked(t)).collect();
+    println!("Now actionable:");
+    for task in &unblocked {
+        println!("  {}", task::format_line(tasks, task));
+    }
+    if notify {
+        let body: Vec<String> = unblocked
+            .iter()
+            .map(|t| format!("{}: {}", t.id, t.description))
+            .collect();
+        if let Err(err) = notify::send("Tasks unblocked", &body.join("\n")) {
+            eprintln!("Warning: {:#}", err);
+        }
+    }
+}
+
+/// Who "me" is for `--mine`; an explicit setting wins over the login name,
+/// since a shared list may use nicknames rather than account names.
+fn current_user() -> anyhow::Result<String> {
+    ["CLI_TASK_MANAGER_USER", "USER", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .ok_or_else(|| {
+            anyhow::anyhow!("Unable to determine current user; set CLI_TASK_MANAGER_USER")
+        })
+}
+
+/// The page `--limit`, `--offset` and `--page` pick.
+fn list_page(limit: Option<usize>, offset: usize, page: Option<u64>) -> task::Page {
+    task::Page {
+        offset: match (page, limit) {
+            (Some(page), Some(limit)) => (page as usize - 1).saturating_mul(limit),
+            _ => offset,
+        },
+        limit,
+    }
+}
+
+/// Prints `list` over `tasks`, which are masked, here or in the daemon.
+#[allow(clippy::too_many_arguments)]
+fn print_list(
+    config_dir: &Path,
+    tasks: &mut [task::Task],
+    mut filter: task::ListFilter,
+    script: Option<&str>,
+    sort: &task::Sort,
+    page: task::Page,
+    layout: &LayoutArgs,
+) -> anyhow::Result<()> {
+    aging::apply(&config::load(config_dir)?.aging, tasks);
+    if let Some(name) = script {
+        filter.only = Some(script::filter(config_dir, tasks, name)?);
     }
+    task::list_tasks(tasks, &filter, sort, page, &layout.layout(config_dir)?);
     Ok(())
 }
 
-fn get_data_path() -> anyhow::Result<PathBuf> {
-    let proj_dirs = ProjectDirs::from("ian", "mwirigi", "cli_task_manager")
-        .ok_or_else(|| anyhow::anyhow!("Unable to determine data directory"))?;
-    Ok(proj_dirs.data_local_dir().join("tasks.json"))
+/// The filter of the active context, noting on stderr that it hides tasks.
+fn active_context(config_dir: &Path) -> anyhow::Result<Option<filter::Filter>> {
+    let Some((name, filter)) = focus::active(config_dir)? else {
+        return Ok(None);
+    };
+    eprintln!(
+        "(context {} is active; `context clear` shows every task)",
+        name
+    );
+    Ok(Some(filter))
 }
@@ -0,0 +1,36 @@
+//! `generate-man`: man pages in roff for the program and each of its
+//! commands, written by `clap_mangen` from the same definitions as `--help`,
+//! for packagers to install under `man1`. Nested commands get pages of their
+//! own, named like `cli_task_manager-sync-remote.1`.
+
+use anyhow::Context;
+use clap::Command;
+use clap_mangen::Man;
+use std::{fs, path::Path};
+
+/// Writes a page for `command` and every command under it into `dir`,
+/// returning how many were written.
+pub fn generate(command: Command, dir: &Path) -> anyhow::Result<usize> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    // Building spreads the global options to every command and names each
+    // one after its path, which the pages take their titles from.
+    let mut command = command.disable_help_subcommand(true);
+    command.build();
+    write_pages(&command, dir)
+}
+
+fn write_pages(command: &Command, dir: &Path) -> anyhow::Result<usize> {
+    let man = Man::new(command.clone()).source(concat!(
+        env!("CARGO_PKG_NAME"),
+        " ",
+        env!("CARGO_PKG_VERSION")
+    ));
+    let file = dir.join(man.get_filename());
+    man.generate_to(dir)
+        .with_context(|| format!("Failed to write {}", file.display()))?;
+    let mut written = 1;
+    for sub in command.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        written += write_pages(sub, dir)?;
+    }
+    Ok(written)
+}
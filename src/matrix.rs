@@ -0,0 +1,69 @@
+//! The Eisenhower matrix: open tasks sorted into a 2x2 grid by whether they
+//! are urgent (due soon, or marked urgent) and important (high priority).
+
+use crate::task::{Priority, Task};
+use chrono::Local;
+
+pub fn print_matrix(tasks: &[Task], urgent_days: i64) {
+    let today = Local::now().date_naive();
+    let mut cells: [Vec<&Task>; 4] = Default::default();
+    for task in tasks.iter().filter(|t| !t.completed && !t.someday) {
+        let urgent = task.priority == Some(Priority::Urgent)
+            || task
+                .due
+                .is_some_and(|due| (due - today).num_days() <= urgent_days);
+        let important = task.priority >= Some(Priority::High);
+        let cell = match (urgent, important) {
+            (true, true) => 0,
+            (false, true) => 1,
+            (true, false) => 2,
+            (false, false) => 3,
+        };
+        cells[cell].push(task);
+    }
+
+    let columns = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse::<usize>().ok())
+        .unwrap_or(80);
+    let width = (columns.saturating_sub(7) / 2).max(20);
+    let rule = format!("+{}+{}+", "-".repeat(width + 2), "-".repeat(width + 2));
+    let titles = [
+        "DO: urgent and important",
+        "PLAN: important, not urgent",
+        "DELEGATE: urgent, not important",
+        "DROP: neither",
+    ];
+
+    println!("{}", rule);
+    for row in [0, 2] {
+        let (left, right) = (&cells[row], &cells[row + 1]);
+        print_row(titles[row], titles[row + 1], width);
+        for i in 0..left.len().max(right.len()).max(1) {
+            print_row(
+                &left.get(i).map(|t| entry(t)).unwrap_or_default(),
+                &right.get(i).map(|t| entry(t)).unwrap_or_default(),
+                width,
+            );
+        }
+        println!("{}", rule);
+    }
+}
+
+fn entry(task: &Task) -> String {
+    format!("{}: {}", task.id, task.description)
+}
+
+fn print_row(left: &str, right: &str, width: usize) {
+    println!("| {} | {} |", fit(left, width), fit(right, width));
+}
+
+/// Pads or truncates `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return format!("{:<width$}", text);
+    }
+    let mut cut: String = text.chars().take(width - 1).collect();
+    cut.push('…');
+    cut
+}
@@ -0,0 +1,294 @@
+//! `serve --mcp`: the Model Context Protocol over stdin and stdout, so an
+//! assistant that speaks it can read and manage the tasks through a few
+//! tools: `list_tasks`, `add_task`, and `complete_task`. Messages are
+//! JSON-RPC 2.0, one per line.
+//!
+//! There is no tool to remove or rewrite tasks, and `--read-only` leaves
+//! only `list_tasks`. What the tools hand back is masked by the redaction
+//! rules like everything else shown, and each change they make can be taken
+//! back with `undo`.
+
+use crate::{
+    autotag, config, crdt, dedupe, habit, redact, report,
+    task::{self, Priority, Task},
+    undo,
+};
+use anyhow::{Context, bail};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+pub struct McpOptions<'a> {
+    pub config_dir: &'a Path,
+    pub read_only: bool,
+    pub redact: redact::Rules,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListArgs {
+    #[serde(default)]
+    completed: bool,
+    project: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddArgs {
+    description: String,
+    project: Option<String>,
+    priority: Option<Priority>,
+    due: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    allow_duplicate: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CompleteArgs {
+    id: u32,
+    note: Option<String>,
+}
+
+/// Answers requests from stdin until it closes.
+pub fn serve(data_path: &Path, options: &McpOptions) -> anyhow::Result<()> {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line.context("Failed to read from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(reply) = answer(data_path, options, &line) else {
+            continue;
+        };
+        serde_json::to_writer(&mut stdout, &reply).context("Failed to write a reply")?;
+        stdout
+            .write_all(b"\n")
+            .and_then(|()| stdout.flush())
+            .context("Failed to write a reply")?;
+    }
+    Ok(())
+}
+
+/// The reply to one message; notifications have none.
+fn answer(data_path: &Path, options: &McpOptions, line: &str) -> Option<Value> {
+    let message: Message = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(err) => return Some(error(Value::Null, -32700, &format!("Parse error: {}", err))),
+    };
+    let id = message.id?;
+    let result = match message.method.as_str() {
+        "initialize" => json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        }),
+        "ping" => json!({}),
+        "tools/list" => json!({ "tools": tools(options.read_only) }),
+        "tools/call" => {
+            let name = message.params.get("name").and_then(Value::as_str);
+            let arguments = message
+                .params
+                .get("arguments")
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+            let Some(name) = name else {
+                return Some(error(id, -32602, "tools/call needs a tool name"));
+            };
+            // Failures go back to the assistant as the tool's result, so it
+            // can see what went wrong and try again.
+            match call(data_path, options, name, arguments) {
+                Ok(text) => json!({ "content": [{ "type": "text", "text": text }] }),
+                Err(err) => {
+                    let text = options.redact.mask(&format!("{:#}", err)).into_owned();
+                    json!({ "content": [{ "type": "text", "text": text }], "isError": true })
+                }
+            }
+        }
+        method => return Some(error(id, -32601, &format!("Unknown method {}", method))),
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn tools(read_only: bool) -> Vec<Value> {
+    let mut tools = vec![json!({
+        "name": "list_tasks",
+        "description": "List the tasks, one per line with its id. Open tasks only unless completed is set.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "completed": { "type": "boolean", "description": "Include completed tasks" },
+                "project": { "type": "string", "description": "Only tasks in this project" },
+            },
+        },
+    })];
+    if read_only {
+        return tools;
+    }
+    tools.push(json!({
+        "name": "add_task",
+        "description": "Add a task and return its id. Refuses one that repeats an open task unless allow_duplicate is set.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "description": { "type": "string" },
+                "project": { "type": "string" },
+                "priority": { "type": "string", "enum": ["low", "medium", "high", "urgent"] },
+                "due": { "type": "string", "description": "A date such as 2026-11-01, today, tomorrow or friday" },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "allow_duplicate": { "type": "boolean" },
+            },
+            "required": ["description"],
+        },
+    }));
+    tools.push(json!({
+        "name": "complete_task",
+        "description": "Mark the task with this id done, or record an occurrence of a habit.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "note": { "type": "string", "description": "How the task was resolved" },
+            },
+            "required": ["id"],
+        },
+    }));
+    tools
+}
+
+fn call(
+    data_path: &Path,
+    options: &McpOptions,
+    name: &str,
+    arguments: Value,
+) -> anyhow::Result<String> {
+    let mut tasks = task::load_tasks(data_path)?;
+    let before = tasks.clone();
+    let text = match name {
+        "list_tasks" => return Ok(list(&tasks, parse(name, arguments)?, &options.redact)),
+        "add_task" | "complete_task" if options.read_only => {
+            bail!("The task list is read-only here")
+        }
+        "add_task" => add(data_path, &mut tasks, parse(name, arguments)?, options)?,
+        "complete_task" => complete(data_path, &mut tasks, parse(name, arguments)?, options)?,
+        _ => bail!("Unknown tool {}", name),
+    };
+    task::save_tasks(data_path, &tasks)?;
+    undo::record_as(data_path, format!("mcp {}", name), &before, &tasks)?;
+    Ok(text)
+}
+
+fn parse<T: DeserializeOwned>(name: &str, arguments: Value) -> anyhow::Result<T> {
+    serde_json::from_value(arguments).with_context(|| format!("Bad arguments to {}", name))
+}
+
+fn list(tasks: &[Task], args: ListArgs, redaction: &redact::Rules) -> String {
+    let mut shown: Vec<Task> = tasks
+        .iter()
+        .filter(|t| args.completed || !t.completed)
+        .filter(|t| {
+            args.project
+                .as_deref()
+                .is_none_or(|p| t.project.as_deref() == Some(p))
+        })
+        .cloned()
+        .collect();
+    if shown.is_empty() {
+        return "No tasks.".to_owned();
+    }
+    redaction.apply(&mut shown);
+    let lines: Vec<String> = shown
+        .iter()
+        .map(|task| task::format_line(tasks, task))
+        .collect();
+    lines.join("\n")
+}
+
+fn add(
+    data_path: &Path,
+    tasks: &mut Vec<Task>,
+    args: AddArgs,
+    options: &McpOptions,
+) -> anyhow::Result<String> {
+    if !args.allow_duplicate
+        && let Some(open) = dedupe::matching(tasks, &args.description).first()
+    {
+        bail!(
+            "Task {} already says this: {} (set allow_duplicate to add it anyway)",
+            open.id,
+            options.redact.mask(&open.description)
+        );
+    }
+    let tags = args
+        .tags
+        .iter()
+        .map(|tag| task::parse_tag(tag).map_err(anyhow::Error::msg))
+        .collect::<anyhow::Result<_>>()?;
+    let details = task::NewTask {
+        project: args.project,
+        priority: args.priority,
+        due: args.due.as_deref().map(report::parse_date).transpose()?,
+        tags,
+        ..Default::default()
+    };
+    let clock = crdt::Clock::load(data_path, tasks)?;
+    let id = task::add_task(tasks, args.description, details, &clock)?;
+    let task = task::find_task_mut(tasks, id)?;
+    let changes = autotag::apply(&config::load(options.config_dir)?.auto_tag, task, &clock);
+    if changes.is_empty() {
+        Ok(format!("Added task {}.", id))
+    } else {
+        Ok(format!("Added task {} ({}).", id, changes.join(", ")))
+    }
+}
+
+fn complete(
+    data_path: &Path,
+    tasks: &mut [Task],
+    args: CompleteArgs,
+    options: &McpOptions,
+) -> anyhow::Result<String> {
+    let clock = crdt::Clock::load(data_path, tasks)?;
+    let task = task::find_task_mut(tasks, args.id)?;
+    let description = options.redact.mask(&task.description).into_owned();
+    if task.habit.is_some() {
+        if args.note.is_some() {
+            bail!("Habits are never closed, so they take no note");
+        }
+        habit::record(task, &clock)?;
+        return Ok(format!(
+            "Recorded {}: {}.",
+            description,
+            habit::progress(task).unwrap_or_default()
+        ));
+    }
+    if task.completed {
+        bail!("Task {} is already completed", args.id);
+    }
+    task::mark_done(tasks, args.id, args.note, &clock)?;
+    Ok(format!("Completed task {}: {}.", args.id, description))
+}
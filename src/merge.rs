@@ -0,0 +1,678 @@
+use crate::{
+    crdt::{self, Stamp, Tombstones},
+    task::{Comment, HistoryEntry, Interval, Task},
+};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, BufRead, IsTerminal, Write},
+};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+/// Both sides changed the same thing in different ways since the base.
+pub struct Conflict {
+    pub uuid: Uuid,
+    pub label: String,
+    pub field: String,
+    pub local: String,
+    pub remote: String,
+}
+
+pub struct MergeOutcome {
+    pub tasks: Vec<Task>,
+    /// Tombstones still in force, i.e. for tasks that stayed deleted.
+    pub tombstones: Tombstones,
+    pub conflicts: usize,
+}
+
+type Fields = Map<String, Value>;
+
+/// Merges two descendants of `base`, task by task (keyed by UUID) and field
+/// by field. Fields stamped on both sides are last-writer-wins registers, so
+/// the later edit wins the same way on every machine. Unstamped fields (from
+/// files written before stamps existed) fall back to the base: a side that
+/// left a field untouched yields to the side that changed it, and only when
+/// both changed it differently is `resolve` asked to pick a winner.
+///
+/// `tombstones` should hold the removals known to either side; a removal wins
+/// over every edit stamped before it.
+pub fn three_way(
+    base: &[Task],
+    local: &[Task],
+    remote: &[Task],
+    tombstones: &Tombstones,
+    resolve: &mut dyn FnMut(&Conflict) -> anyhow::Result<Side>,
+) -> anyhow::Result<MergeOutcome> {
+    let base = index(base)?;
+    let local_fields = index(local)?;
+    let remote_fields = index(remote)?;
+
+    let mut order: Vec<Uuid> = local.iter().map(|t| t.uuid).collect();
+    order.extend(
+        remote
+            .iter()
+            .map(|t| t.uuid)
+            .filter(|u| !local_fields.contains_key(u)),
+    );
+
+    let mut merged = Vec::new();
+    let mut conflicts = 0;
+    for uuid in order {
+        let b = base.get(&uuid);
+        let tomb = tombstones.get(&uuid);
+        let fields = match (local_fields.get(&uuid), remote_fields.get(&uuid)) {
+            (Some(l), Some(r)) => Some(merge_fields(uuid, b, l, r, resolve, &mut conflicts)?),
+            (Some(l), None) => {
+                keep_or_drop(uuid, b, l, Side::Local, tomb, resolve, &mut conflicts)?
+            }
+            (None, Some(r)) => {
+                keep_or_drop(uuid, b, r, Side::Remote, tomb, resolve, &mut conflicts)?
+            }
+            (None, None) => None,
+        };
+        if let Some(fields) = fields.filter(|f| outlives(f, tomb)) {
+            merged.push(fields);
+        }
+    }
+
+    let local_ids: HashMap<Uuid, u32> = local.iter().map(|t| (t.uuid, t.id)).collect();
+    let tasks = assign_ids(merged, &local_ids)?;
+    let live: HashSet<Uuid> = tasks.iter().map(|t| t.uuid).collect();
+    let tombstones = tombstones
+        .iter()
+        .filter(|(uuid, _)| !live.contains(uuid))
+        .map(|(uuid, stamp)| (*uuid, *stamp))
+        .collect();
+    Ok(MergeOutcome {
+        tasks,
+        tombstones,
+        conflicts,
+    })
+}
+
+/// Builds a resolver that applies `prefer` when given and otherwise asks on
+/// the terminal, refusing to guess when there is nobody to ask.
+pub fn resolver(prefer: Option<Side>) -> impl FnMut(&Conflict) -> anyhow::Result<Side> {
+    move |conflict| {
+        if let Some(side) = prefer {
+            return Ok(side);
+        }
+        if !io::stdin().is_terminal() {
+            bail!(
+                "Conflict on task {} ({}); rerun with --prefer local|remote",
+                conflict.label,
+                conflict.field
+            );
+        }
+        prompt(conflict)
+    }
+}
+
+fn prompt(conflict: &Conflict) -> anyhow::Result<Side> {
+    println!(
+        "Conflict on task {} [{}], field '{}':",
+        conflict.label, conflict.uuid, conflict.field
+    );
+    println!("  [l]ocal:  {}", conflict.local);
+    println!("  [r]emote: {}", conflict.remote);
+
+    let stdin = io::stdin();
+    loop {
+        print!("Keep which? [l/r] ");
+        io::stdout().flush().context("Failed to flush prompt")?;
+        let mut answer = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut answer)
+            .context("Failed to read answer")?
+            == 0
+        {
+            bail!("No answer given for conflict on task {}", conflict.label);
+        }
+        match answer.trim() {
+            "l" | "local" => return Ok(Side::Local),
+            "r" | "remote" => return Ok(Side::Remote),
+            _ => println!("Please answer 'l' or 'r'."),
+        }
+    }
+}
+
+fn index(tasks: &[Task]) -> anyhow::Result<HashMap<Uuid, Fields>> {
+    let mut map = HashMap::new();
+    for task in tasks {
+        let Value::Object(fields) = serde_json::to_value(task).context("Failed to encode task")?
+        else {
+            unreachable!("tasks serialize as JSON objects");
+        };
+        if map.insert(task.uuid, fields).is_some() {
+            bail!("Duplicate task uuid {}", task.uuid);
+        }
+    }
+    Ok(map)
+}
+
+fn merge_fields(
+    uuid: Uuid,
+    base: Option<&Fields>,
+    local: &Fields,
+    remote: &Fields,
+    resolve: &mut dyn FnMut(&Conflict) -> anyhow::Result<Side>,
+    conflicts: &mut usize,
+) -> anyhow::Result<Fields> {
+    let mut keys: Vec<&String> = local.keys().collect();
+    keys.extend(remote.keys().filter(|k| !local.contains_key(*k)));
+
+    let local_stamps = stamps(local);
+    let remote_stamps = stamps(remote);
+    let mut merged_stamps = BTreeMap::new();
+    let mut merged = Fields::new();
+    for key in keys {
+        match key.as_str() {
+            "id" => {
+                merged.insert(key.clone(), local[key].clone());
+                continue;
+            }
+            "stamps" => continue,
+            "comments" => {
+                merged.insert(
+                    key.clone(),
+                    union_comments(local.get(key), remote.get(key))?,
+                );
+                continue;
+            }
+            "time_log" => {
+                merged.insert(key.clone(), union_time(local.get(key), remote.get(key))?);
+                continue;
+            }
+            "history" => {
+                merged.insert(key.clone(), union_history(local.get(key), remote.get(key))?);
+                continue;
+            }
+            "occurrences" => {
+                merged.insert(
+                    key.clone(),
+                    union_occurrences(local.get(key), remote.get(key))?,
+                );
+                continue;
+            }
+            _ => {}
+        }
+        let l = local.get(key);
+        let r = remote.get(key);
+        let b = base.and_then(|b| b.get(key));
+        let ls = local_stamps.get(key);
+        let rs = remote_stamps.get(key);
+        let side = match (ls, rs) {
+            (Some(ls), Some(rs)) if ls != rs => {
+                if ls > rs {
+                    Side::Local
+                } else {
+                    Side::Remote
+                }
+            }
+            _ if l == r || r == b => Side::Local,
+            _ if l == b => Side::Remote,
+            _ => {
+                *conflicts += 1;
+                resolve(&Conflict {
+                    uuid,
+                    label: label(local),
+                    field: key.clone(),
+                    local: show(l),
+                    remote: show(r),
+                })?
+            }
+        };
+        let (value, stamp) = match side {
+            _ if l == r => (l, ls.max(rs)),
+            Side::Local => (l, ls),
+            Side::Remote => (r, rs),
+        };
+        if let Some(value) = value {
+            merged.insert(key.clone(), value.clone());
+        }
+        if let Some(stamp) = stamp {
+            merged_stamps.insert(key.clone(), *stamp);
+        }
+    }
+    if !merged_stamps.is_empty() {
+        merged.insert(
+            "stamps".to_owned(),
+            serde_json::to_value(merged_stamps).context("Failed to encode stamps")?,
+        );
+    }
+    Ok(merged)
+}
+
+/// Handles a task present on only one side. With a tombstone the stamps
+/// decide (see [`outlives`]); without one, a new task is kept, an untouched
+/// one was deleted by the other side, and an edited one is a conflict.
+fn keep_or_drop(
+    uuid: Uuid,
+    base: Option<&Fields>,
+    fields: &Fields,
+    side: Side,
+    tomb: Option<&Stamp>,
+    resolve: &mut dyn FnMut(&Conflict) -> anyhow::Result<Side>,
+    conflicts: &mut usize,
+) -> anyhow::Result<Option<Fields>> {
+    let Some(base) = base.filter(|_| tomb.is_none()) else {
+        return Ok(Some(fields.clone()));
+    };
+    if same_content(base, fields) {
+        return Ok(None);
+    }
+
+    *conflicts += 1;
+    let (local, remote) = match side {
+        Side::Local => ("edited".to_owned(), "deleted".to_owned()),
+        Side::Remote => ("deleted".to_owned(), "edited".to_owned()),
+    };
+    let conflict = Conflict {
+        uuid,
+        label: label(fields),
+        field: "(task)".to_owned(),
+        local,
+        remote,
+    };
+    Ok((resolve(&conflict)? == side).then(|| fields.clone()))
+}
+
+/// Comments only ever get added, so both sides' threads are kept whole
+/// rather than one side's list replacing the other's.
+fn union_comments(local: Option<&Value>, remote: Option<&Value>) -> anyhow::Result<Value> {
+    let mut comments: Vec<Comment> = Vec::new();
+    for side in [local, remote].into_iter().flatten() {
+        let side: Vec<Comment> =
+            serde_json::from_value(side.clone()).context("Failed to decode comments")?;
+        for comment in side {
+            if !comments.iter().any(|c| c.id == comment.id) {
+                comments.push(comment);
+            }
+        }
+    }
+    comments.sort_by_key(|c| (c.at, c.id));
+    serde_json::to_value(comments).context("Failed to encode comments")
+}
+
+/// Events logged on either machine all happened, so both logs are kept.
+fn union_history(local: Option<&Value>, remote: Option<&Value>) -> anyhow::Result<Value> {
+    let mut history: Vec<HistoryEntry> = Vec::new();
+    for side in [local, remote].into_iter().flatten() {
+        let side: Vec<HistoryEntry> =
+            serde_json::from_value(side.clone()).context("Failed to decode task history")?;
+        for entry in side {
+            if !history.contains(&entry) {
+                history.push(entry);
+            }
+        }
+    }
+    history.sort_by_key(|e| e.at);
+    serde_json::to_value(history).context("Failed to encode task history")
+}
+
+/// A habit done on either machine counts.
+fn union_occurrences(local: Option<&Value>, remote: Option<&Value>) -> anyhow::Result<Value> {
+    let mut occurrences: Vec<DateTime<Utc>> = Vec::new();
+    for side in [local, remote].into_iter().flatten() {
+        let side: Vec<DateTime<Utc>> =
+            serde_json::from_value(side.clone()).context("Failed to decode habit log")?;
+        occurrences.extend(side);
+    }
+    occurrences.sort();
+    occurrences.dedup();
+    serde_json::to_value(occurrences).context("Failed to encode habit log")
+}
+
+/// Time tracked on either machine is kept. Intervals are identified by their
+/// start; if one side has since stopped a timer, the stopped copy wins.
+fn union_time(local: Option<&Value>, remote: Option<&Value>) -> anyhow::Result<Value> {
+    let mut intervals: Vec<Interval> = Vec::new();
+    for side in [local, remote].into_iter().flatten() {
+        let side: Vec<Interval> =
+            serde_json::from_value(side.clone()).context("Failed to decode time log")?;
+        for interval in side {
+            match intervals.iter_mut().find(|i| i.start == interval.start) {
+                Some(existing) => {
+                    if existing.end.is_none() {
+                        existing.end = interval.end;
+                    }
+                }
+                None => intervals.push(interval),
+            }
+        }
+    }
+    intervals.sort_by_key(|i| i.start);
+    serde_json::to_value(intervals).context("Failed to encode time log")
+}
+
+/// A removed task comes back only if it was edited after the removal.
+fn outlives(fields: &Fields, tomb: Option<&Stamp>) -> bool {
+    match tomb {
+        None => true,
+        Some(tomb) => crdt::latest(&stamps(fields)).is_some_and(|s| s > *tomb),
+    }
+}
+
+fn stamps(fields: &Fields) -> BTreeMap<String, Stamp> {
+    fields
+        .get("stamps")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Numeric ids are per-file conveniences, so they are ignored when deciding
+/// whether a task changed.
+fn same_content(a: &Fields, b: &Fields) -> bool {
+    let content = |f: &Fields| {
+        f.iter()
+            .filter(|(k, _)| k.as_str() != "id")
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>()
+    };
+    content(a) == content(b)
+}
+
+/// Local tasks keep their ids; tasks arriving from the remote keep theirs
+/// unless that id is already taken here, in which case they get a fresh one.
+fn assign_ids(merged: Vec<Fields>, local_ids: &HashMap<Uuid, u32>) -> anyhow::Result<Vec<Task>> {
+    let mut tasks = merged
+        .into_iter()
+        .map(|fields| serde_json::from_value::<Task>(Value::Object(fields)))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to decode merged task")?;
+
+    let mut used: HashSet<u32> = tasks
+        .iter()
+        .filter_map(|t| local_ids.get(&t.uuid).copied())
+        .collect();
+    let mut next_id = tasks.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+    for task in tasks.iter_mut() {
+        match local_ids.get(&task.uuid) {
+            Some(&id) => task.id = id,
+            None if used.insert(task.id) => {}
+            None => {
+                task.id = next_id;
+                used.insert(next_id);
+                next_id += 1;
+            }
+        }
+    }
+    Ok(tasks)
+}
+
+fn label(fields: &Fields) -> String {
+    match fields.get("description") {
+        Some(Value::String(s)) => format!("\"{}\"", s),
+        _ => "(untitled)".to_owned(),
+    }
+}
+
+fn show(value: Option<&Value>) -> String {
+    match value {
+        None => "(unset)".to_owned(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const A: &str = "00000000-0000-4000-8000-00000000000a";
+    const B: &str = "00000000-0000-4000-8000-00000000000b";
+    const C: &str = "00000000-0000-4000-8000-00000000000c";
+    const REPLICA: &str = "00000000-0000-4000-8000-0000000000ff";
+
+    fn task(id: u32, uuid: &str, description: &str, extra: Value) -> Task {
+        let mut value = json!({
+            "id": id,
+            "uuid": uuid,
+            "description": description,
+            "completed": false,
+        });
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(value).expect("a valid task")
+    }
+
+    fn stamp(time: u64) -> Stamp {
+        Stamp(time, REPLICA.parse().unwrap())
+    }
+
+    fn uuid(text: &str) -> Uuid {
+        text.parse().unwrap()
+    }
+
+    /// Merges, failing the test if any conflict needs resolving.
+    fn merge(
+        base: &[Task],
+        local: &[Task],
+        remote: &[Task],
+        tombstones: &Tombstones,
+    ) -> MergeOutcome {
+        three_way(base, local, remote, tombstones, &mut |c| {
+            panic!("unexpected conflict on {}", c.field)
+        })
+        .unwrap()
+    }
+
+    fn find(outcome: &MergeOutcome, uuid: &str) -> Task {
+        let uuid = self::uuid(uuid);
+        outcome
+            .tasks
+            .iter()
+            .find(|t| t.uuid == uuid)
+            .cloned()
+            .expect("the task is in the merge")
+    }
+
+    #[test]
+    fn edits_to_different_fields_both_land() {
+        let base = [task(1, A, "Report", json!({}))];
+        let local = [task(1, A, "Write the report", json!({}))];
+        let remote = [task(1, A, "Report", json!({ "priority": "high" }))];
+        let outcome = merge(&base, &local, &remote, &Tombstones::new());
+        assert_eq!(outcome.conflicts, 0);
+        let merged = find(&outcome, A);
+        assert_eq!(merged.description, "Write the report");
+        assert!(merged.priority == Some(crate::task::Priority::High));
+    }
+
+    #[test]
+    fn the_later_stamp_wins() {
+        let base = [task(1, A, "Report", json!({}))];
+        let local = [task(
+            1,
+            A,
+            "Local",
+            json!({ "stamps": { "description": [5, REPLICA] } }),
+        )];
+        let remote = [task(
+            1,
+            A,
+            "Remote",
+            json!({ "stamps": { "description": [7, REPLICA] } }),
+        )];
+        for (l, r) in [(&local, &remote), (&remote, &local)] {
+            let outcome = merge(&base, l, r, &Tombstones::new());
+            let merged = find(&outcome, A);
+            assert_eq!(merged.description, "Remote");
+            assert!(merged.stamps["description"] == stamp(7));
+        }
+    }
+
+    #[test]
+    fn unstamped_edits_on_both_sides_are_resolved() {
+        let base = [task(1, A, "Report", json!({}))];
+        let local = [task(1, A, "Local", json!({}))];
+        let remote = [task(1, A, "Remote", json!({}))];
+        for (side, expected) in [(Side::Local, "Local"), (Side::Remote, "Remote")] {
+            let mut asked = Vec::new();
+            let outcome = three_way(&base, &local, &remote, &Tombstones::new(), &mut |c| {
+                asked.push((c.field.clone(), c.local.clone(), c.remote.clone()));
+                Ok(side)
+            })
+            .unwrap();
+            assert_eq!(outcome.conflicts, 1);
+            assert_eq!(
+                asked,
+                [(
+                    "description".to_owned(),
+                    "Local".to_owned(),
+                    "Remote".to_owned()
+                )]
+            );
+            assert_eq!(find(&outcome, A).description, expected);
+        }
+    }
+
+    #[test]
+    fn a_failed_resolution_fails_the_merge() {
+        let base = [task(1, A, "Report", json!({}))];
+        let local = [task(1, A, "Local", json!({}))];
+        let remote = [task(1, A, "Remote", json!({}))];
+        let result = three_way(&base, &local, &remote, &Tombstones::new(), &mut |_| {
+            bail!("no answer")
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn untouched_tasks_deleted_on_one_side_stay_deleted() {
+        let base = [
+            task(1, A, "Report", json!({})),
+            task(2, B, "Milk", json!({})),
+        ];
+        let local = [task(1, A, "Report", json!({}))];
+        let outcome = merge(&base, &local, &base, &Tombstones::new());
+        assert_eq!(outcome.tasks.len(), 1);
+        assert_eq!(outcome.tasks[0].uuid, uuid(A));
+    }
+
+    #[test]
+    fn an_edit_against_a_deletion_is_a_conflict() {
+        let base = [task(1, A, "Report", json!({}))];
+        let remote = [task(1, A, "Write the report", json!({}))];
+        let mut fields = Vec::new();
+        let outcome = three_way(&base, &[], &remote, &Tombstones::new(), &mut |c| {
+            fields.push((c.field.clone(), c.local.clone()));
+            Ok(Side::Remote)
+        })
+        .unwrap();
+        assert_eq!(fields, [("(task)".to_owned(), "deleted".to_owned())]);
+        assert_eq!(find(&outcome, A).description, "Write the report");
+    }
+
+    #[test]
+    fn tombstones_beat_earlier_edits_only() {
+        let base = [
+            task(1, A, "Report", json!({})),
+            task(2, B, "Milk", json!({})),
+        ];
+        let remote = [
+            task(
+                1,
+                A,
+                "Report",
+                json!({ "stamps": { "description": [3, REPLICA] } }),
+            ),
+            task(
+                2,
+                B,
+                "Oat milk",
+                json!({ "stamps": { "description": [9, REPLICA] } }),
+            ),
+        ];
+        let tombstones = Tombstones::from([(uuid(A), stamp(5)), (uuid(B), stamp(5))]);
+        let outcome = merge(&base, &[], &remote, &tombstones);
+        assert_eq!(outcome.tasks.len(), 1);
+        assert_eq!(outcome.tasks[0].description, "Oat milk");
+        // B came back, so only A's removal is still in force.
+        assert!(outcome.tombstones == Tombstones::from([(uuid(A), stamp(5))]));
+    }
+
+    #[test]
+    fn logs_are_unions_of_both_sides() {
+        let comment =
+            |id: &str, at: &str| json!({ "id": id, "author": "sam", "at": at, "text": id });
+        let base = [task(1, A, "Report", json!({}))];
+        let local = [task(
+            1,
+            A,
+            "Report",
+            json!({
+                "comments": [comment(B, "2025-01-02T00:00:00Z")],
+                "history": [{ "at": "2025-01-01T00:00:00Z", "event": "reopened" }],
+                "occurrences": ["2025-01-01T00:00:00Z", "2025-01-03T00:00:00Z"],
+                "time_log": [{ "start": "2025-01-01T09:00:00Z" }],
+            }),
+        )];
+        let remote = [task(
+            1,
+            A,
+            "Report",
+            json!({
+                "comments": [comment(C, "2025-01-01T00:00:00Z"), comment(B, "2025-01-02T00:00:00Z")],
+                "history": [
+                    { "at": "2025-01-01T00:00:00Z", "event": "reopened" },
+                    { "at": "2025-01-04T00:00:00Z", "event": "completed" },
+                ],
+                "occurrences": ["2025-01-02T00:00:00Z", "2025-01-03T00:00:00Z"],
+                "time_log": [
+                    { "start": "2025-01-01T09:00:00Z", "end": "2025-01-01T10:00:00Z" },
+                    { "start": "2025-01-01T08:00:00Z", "end": "2025-01-01T08:30:00Z" },
+                ],
+            }),
+        )];
+        let merged = find(&merge(&base, &local, &remote, &Tombstones::new()), A);
+        let comments: Vec<Uuid> = merged.comments.iter().map(|c| c.id).collect();
+        assert_eq!(comments, [uuid(C), uuid(B)]);
+        let events: Vec<&str> = merged.history.iter().map(|e| e.event.as_str()).collect();
+        assert_eq!(events, ["reopened", "completed"]);
+        assert_eq!(merged.occurrences.len(), 3);
+        assert_eq!(merged.time_log.len(), 2);
+        assert!(merged.time_log.iter().all(|i| i.end.is_some()));
+        assert_eq!(merged.tracked_minutes(), 90);
+    }
+
+    #[test]
+    fn remote_tasks_get_fresh_ids_when_theirs_are_taken() {
+        let local = [
+            task(1, A, "Report", json!({})),
+            task(2, B, "Milk", json!({})),
+        ];
+        let remote = [
+            task(1, A, "Report", json!({})),
+            task(2, C, "Call", json!({})),
+        ];
+        let outcome = merge(&[], &local, &remote, &Tombstones::new());
+        let ids: Vec<(u32, Uuid)> = outcome.tasks.iter().map(|t| (t.id, t.uuid)).collect();
+        assert_eq!(ids, [(1, uuid(A)), (2, uuid(B)), (3, uuid(C))]);
+    }
+
+    #[test]
+    fn duplicate_uuids_are_refused() {
+        let local = [
+            task(1, A, "Report", json!({})),
+            task(2, A, "Again", json!({})),
+        ];
+        let result = three_way(&[], &local, &[], &Tombstones::new(), &mut |_| {
+            Ok(Side::Local)
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Named milestones with target dates that tasks count towards.
+//!
+//! The milestones themselves live beside the tasks file in `milestones.json`;
+//! each task only records the name of the milestone it belongs to.
+
+use crate::{
+    table::Table,
+    task::{self, Task},
+};
+use anyhow::{Context, bail};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct Milestone {
+    pub name: String,
+    pub due: NaiveDate,
+}
+
+fn milestones_path(tasks_path: &Path) -> PathBuf {
+    tasks_path.with_file_name("milestones.json")
+}
+
+pub fn load_milestones(tasks_path: &Path) -> anyhow::Result<Vec<Milestone>> {
+    let path = milestones_path(tasks_path);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read milestones at {}", path.display()))?;
+    if data.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse milestones at {}", path.display()))
+}
+
+pub fn save_milestones(tasks_path: &Path, milestones: &[Milestone]) -> anyhow::Result<()> {
+    let data =
+        serde_json::to_string_pretty(milestones).context("Failed to serialize milestones")?;
+    task::write_atomic(&milestones_path(tasks_path), data.as_bytes())
+}
+
+/// Looks a milestone up by name, ignoring case, and returns its stored name.
+pub fn resolve(milestones: &[Milestone], name: &str) -> anyhow::Result<String> {
+    match milestones
+        .iter()
+        .find(|m| m.name.eq_ignore_ascii_case(name.trim()))
+    {
+        Some(milestone) => Ok(milestone.name.clone()),
+        None => bail!(
+            "No milestone named {} (create it with `milestone add`)",
+            name
+        ),
+    }
+}
+
+pub fn add_milestone(
+    milestones: &mut Vec<Milestone>,
+    name: &str,
+    due: NaiveDate,
+) -> anyhow::Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("Milestone name cannot be empty");
+    }
+    if resolve(milestones, name).is_ok() {
+        bail!("A milestone named {} already exists", name);
+    }
+    milestones.push(Milestone {
+        name: name.to_owned(),
+        due,
+    });
+    milestones.sort_by_key(|m| m.due);
+    Ok(())
+}
+
+/// Removes a milestone that no task belongs to any more.
+pub fn remove_milestone(
+    milestones: &mut Vec<Milestone>,
+    tasks: &[Task],
+    name: &str,
+) -> anyhow::Result<()> {
+    let name = resolve(milestones, name)?;
+    let members = tasks
+        .iter()
+        .filter(|t| t.milestone.as_deref() == Some(&name))
+        .count();
+    if members > 0 {
+        bail!(
+            "{} task(s) still belong to {}; move them with `milestone set` first",
+            members,
+            name
+        );
+    }
+    milestones.retain(|m| m.name != name);
+    Ok(())
+}
+
+/// One line per milestone: how much is left against how long is left. A
+/// milestone is at risk when more tasks remain open than days until it is due.
+pub fn print_milestones(milestones: &[Milestone], tasks: &[Task]) {
+    if milestones.is_empty() {
+        println!("No milestones (add one with `milestone add`).");
+        return;
+    }
+
+    let today = Local::now().date_naive();
+    let mut table = Table::new(&["Milestone", "Due", "Open", "Remaining", "Health"]);
+    for milestone in milestones {
+        let members: Vec<&Task> = tasks
+            .iter()
+            .filter(|t| t.milestone.as_deref() == Some(&milestone.name))
+            .collect();
+        let open = members.iter().filter(|t| !t.completed).count();
+        let days = (milestone.due - today).num_days();
+
+        let remaining = match days {
+            0 => "due today".to_owned(),
+            d if d < 0 => format!("{} day(s) overdue", -d),
+            d => format!("{} day(s) left", d),
+        };
+        let health = if members.is_empty() {
+            "no tasks"
+        } else if open == 0 {
+            "done"
+        } else if days < 0 {
+            "overdue"
+        } else if open as i64 > days.max(1) {
+            "at risk"
+        } else {
+            "on track"
+        };
+        table.push(vec![
+            milestone.name.clone(),
+            milestone.due.to_string(),
+            format!("{}/{}", open, members.len()),
+            remaining,
+            health.to_owned(),
+        ]);
+    }
+    table.print();
+}
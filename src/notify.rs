@@ -0,0 +1,44 @@
+//! Desktop notifications through whatever the platform ships with:
+//! `notify-send` on Linux and the BSDs, `osascript` on macOS.
+
+use anyhow::{Context, bail};
+use std::process::Command;
+
+/// The program notifications go through on this platform.
+pub fn program() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "osascript"
+    } else {
+        "notify-send"
+    }
+}
+
+pub fn send(title: &str, body: &str) -> anyhow::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(title)
+        );
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg(title).arg(body);
+        command
+    };
+
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {} for a notification", program))?;
+    if !status.success() {
+        bail!("{} failed ({})", program, status);
+    }
+    Ok(())
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
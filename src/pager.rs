@@ -0,0 +1,85 @@
+//! Paging long output the way git does: when stdout is a terminal, it is
+//! handed to the `pager` of config.json (which `$CLI_TASK_MANAGER_PAGER`
+//! overrides, as for any setting) or `$PAGER` (default `less`). `less` gets
+//! `LESS=FRX` unless set, so output that fits on one screen is printed as
+//! if no pager ran. Setting the pager to an empty string or `cat` turns
+//! paging off. Paging redirects the descriptors of this process, so it only
+//! happens on Unix; elsewhere output goes straight to the terminal.
+
+#[cfg(unix)]
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    os::fd::AsRawFd,
+    process::{Child, Command, Stdio},
+};
+
+/// While alive, stdout goes to the pager; dropping it waits for the pager.
+#[cfg(unix)]
+pub struct Pager {
+    child: Child,
+    stdout: libc::c_int,
+}
+
+/// Starts the pager if stdout is a terminal and one is configured, by
+/// `configured` or else `$PAGER`. A pager that fails to start is not an
+/// error; output just goes to the terminal.
+#[cfg(unix)]
+pub fn start(configured: Option<&str>) -> Option<Pager> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let pager = configured
+        .map(str::to_owned)
+        .or_else(|| env::var("PAGER").ok())
+        .unwrap_or_else(|| "less".to_owned());
+    let pager = pager.trim();
+    if pager.is_empty() || pager == "cat" {
+        return None;
+    }
+
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(pager).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let mut child = command.spawn().ok()?;
+    let input = child.stdin.take()?;
+
+    // SAFETY: plain descriptor juggling on fds this process owns; the pipe
+    // stays open through fd 1 after `input` is dropped.
+    let stdout = unsafe {
+        let saved = libc::dup(libc::STDOUT_FILENO);
+        libc::dup2(input.as_raw_fd(), libc::STDOUT_FILENO);
+        // Quitting the pager early should end the program quietly rather
+        // than panic on the next write to the closed pipe.
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        saved
+    };
+    Some(Pager { child, stdout })
+}
+
+#[cfg(unix)]
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        // SAFETY: restores the descriptor saved in `start`, which closes the
+        // pager's input so it sees end of file.
+        unsafe {
+            libc::dup2(self.stdout, libc::STDOUT_FILENO);
+            libc::close(self.stdout);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(not(unix))]
+pub struct Pager;
+
+#[cfg(not(unix))]
+pub fn start(_configured: Option<&str>) -> Option<Pager> {
+    None
+}
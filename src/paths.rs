@@ -0,0 +1,263 @@
+//! Where the task list, settings, and caches live. `XDG_DATA_HOME`,
+//! `XDG_CONFIG_HOME`, and `XDG_CACHE_HOME` are honored on every platform
+//! when set to an absolute path; otherwise each falls back to the platform's
+//! usual location (`~/.local/share`, `~/Library/Application Support`,
+//! `%LOCALAPPDATA%`, and so on).
+//!
+//! In portable mode everything lives in one directory instead, with the
+//! tasks in `.tasks.json`, so the binary and its data can travel together
+//! on a USB stick. It is chosen by `--portable` (the executable's directory)
+//! or by a `.tasks.json` found in the working directory or next to the
+//! executable.
+//!
+//! Inside a code repository, a `.tasks/` directory or a `tasks.toml` in the
+//! working directory or any parent gives the project a task list of its
+//! own, kept in `.tasks/tasks.json` beside it. `tasks.toml` may move that
+//! file with a line such as `file = "docs/tasks.json"`. `--global` ignores
+//! portable and project lists and works on the usual one.
+//!
+//! A profile, chosen by `--profile`, has data, config, and cache
+//! directories of its own under `profiles/<name>/` in the usual ones; see
+//! `profile`. Like `--global`, it ignores portable and project lists.
+
+use anyhow::{Context, anyhow, bail};
+use directories::ProjectDirs;
+use serde::Deserialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const APP: &str = "cli_task_manager";
+const PORTABLE_FILE: &str = ".tasks.json";
+
+pub struct Dirs {
+    /// The default context's tasks file.
+    pub tasks: PathBuf,
+    /// The task list and its sidecar files.
+    pub data: PathBuf,
+    /// Settings the user edits by hand.
+    pub config: PathBuf,
+    /// Anything that can be rebuilt from the data and deleted at will.
+    pub cache: PathBuf,
+    /// Where data lived before XDG variables were honored everywhere; the
+    /// same as `data` when there is nothing to migrate.
+    legacy_data: PathBuf,
+}
+
+fn xdg(var: &str) -> Option<PathBuf> {
+    // The spec says relative values are invalid and must be ignored.
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .map(|p| p.join(APP))
+}
+
+fn exe_dir() -> anyhow::Result<PathBuf> {
+    let exe = env::current_exe().context("Failed to find the running executable")?;
+    exe.parent()
+        .map(Path::to_owned)
+        .ok_or_else(|| anyhow!("{} has no parent directory", exe.display()))
+}
+
+/// The tasks file of the project enclosing `start`, if any.
+fn project_tasks(start: &Path) -> anyhow::Result<Option<PathBuf>> {
+    for dir in start.ancestors() {
+        let manifest = dir.join("tasks.toml");
+        if manifest.is_file() {
+            let file = manifest_file(&manifest)?;
+            return Ok(Some(
+                dir.join(file.as_deref().unwrap_or(".tasks/tasks.json")),
+            ));
+        }
+        if dir.join(".tasks").is_dir() {
+            return Ok(Some(dir.join(".tasks").join("tasks.json")));
+        }
+    }
+    Ok(None)
+}
+
+/// What a `tasks.toml` may say.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    /// Where the project's tasks file is, relative to the manifest.
+    file: Option<String>,
+}
+
+/// Reads the optional `file` key from a `tasks.toml`.
+fn manifest_file(path: &Path) -> anyhow::Result<Option<String>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_manifest(&text).with_context(|| format!("Invalid {}", path.display()))
+}
+
+fn parse_manifest(text: &str) -> anyhow::Result<Option<String>> {
+    let manifest: Manifest = toml::from_str(text)?;
+    if manifest.file.as_deref() == Some("") {
+        bail!("file must name a path");
+    }
+    Ok(manifest.file)
+}
+
+impl Dirs {
+    pub fn locate(portable: bool, global: bool, profile: Option<&str>) -> anyhow::Result<Self> {
+        if portable {
+            return Ok(Self::portable(exe_dir()?));
+        }
+        let project = ProjectDirs::from("ian", "mwirigi", APP)
+            .ok_or_else(|| anyhow!("Unable to determine data directory"))?;
+        let data = xdg("XDG_DATA_HOME").unwrap_or_else(|| project.data_local_dir().to_owned());
+        let mut dirs = Self {
+            tasks: data.join("tasks.json"),
+            data,
+            config: xdg("XDG_CONFIG_HOME").unwrap_or_else(|| project.config_dir().to_owned()),
+            cache: xdg("XDG_CACHE_HOME").unwrap_or_else(|| project.cache_dir().to_owned()),
+            legacy_data: project.data_local_dir().to_owned(),
+        };
+        if let Some(name) = profile.filter(|name| *name != crate::profile::DEFAULT) {
+            let dirs = dirs.profile(name)?;
+            if !dirs.config.is_dir() {
+                bail!(
+                    "No profile named {}; create it with `profile create {}`",
+                    name,
+                    name
+                );
+            }
+            return Ok(dirs);
+        }
+        if global {
+            return Ok(dirs);
+        }
+
+        let cwd = env::current_dir().context("Failed to read the working directory")?;
+        let found = [Ok(cwd.clone()), exe_dir()]
+            .into_iter()
+            .flatten()
+            .find(|dir| dir.join(PORTABLE_FILE).is_file());
+        if let Some(dir) = found {
+            return Ok(Self::portable(dir));
+        }
+        // A project list keeps the user's settings and cache; only the
+        // tasks and their sidecar files are local.
+        if let Some(tasks) = project_tasks(&cwd)? {
+            dirs.data = tasks.parent().map(Path::to_owned).unwrap_or_default();
+            dirs.legacy_data = dirs.data.clone();
+            dirs.tasks = tasks;
+        }
+        Ok(dirs)
+    }
+
+    /// The directories of profile `name`, given the usual ones.
+    pub fn profile(&self, name: &str) -> anyhow::Result<Self> {
+        crate::profile::check_name(name)?;
+        let data = self.data.join("profiles").join(name);
+        Ok(Self {
+            tasks: data.join("tasks.json"),
+            config: self.config.join("profiles").join(name),
+            cache: self.cache.join("profiles").join(name),
+            legacy_data: data.clone(),
+            data,
+        })
+    }
+
+    fn portable(dir: PathBuf) -> Self {
+        Self {
+            tasks: dir.join(PORTABLE_FILE),
+            config: dir.clone(),
+            cache: dir.join(".cache"),
+            legacy_data: dir.clone(),
+            data: dir,
+        }
+    }
+
+    /// Moves an existing data directory from the old location the first
+    /// time the new one is used, so setting `XDG_DATA_HOME` on macOS or
+    /// Windows does not appear to lose every task.
+    pub fn migrate_legacy(&self) -> anyhow::Result<()> {
+        if self.data == self.legacy_data
+            || self.tasks.exists()
+            || !self.legacy_data.join("tasks.json").exists()
+        {
+            return Ok(());
+        }
+        if let Some(parent) = self.data.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        let moved = is_empty_dir(&self.data) && {
+            let _ = fs::remove_dir(&self.data);
+            fs::rename(&self.legacy_data, &self.data).is_ok()
+        };
+        if !moved {
+            // Across file systems, or into a directory that already has other
+            // files: copy instead, and leave the old copy for the user to
+            // delete once they are happy.
+            copy_dir(&self.legacy_data, &self.data)?;
+        }
+        eprintln!(
+            "{} tasks from {} to {}.",
+            if moved { "Moved" } else { "Copied" },
+            self.legacy_data.display(),
+            self.data.display()
+        );
+        Ok(())
+    }
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    match fs::read_dir(path) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => !path.exists(),
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    let entries =
+        fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", from.display()))?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if !target.exists() {
+            fs::copy(entry.path(), &target).with_context(|| {
+                format!(
+                    "Failed to copy {} to {}",
+                    entry.path().display(),
+                    target.display()
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests_name_the_tasks_file() {
+        assert_eq!(parse_manifest("").unwrap(), None);
+        assert_eq!(
+            parse_manifest("# where tasks live\nfile = \"docs/tasks.json\" # shared\n").unwrap(),
+            Some("docs/tasks.json".to_owned())
+        );
+        assert_eq!(
+            parse_manifest("file = 'C:\\tasks.json'").unwrap(),
+            Some("C:\\tasks.json".to_owned())
+        );
+    }
+
+    #[test]
+    fn manifests_reject_what_they_do_not_hold() {
+        assert!(parse_manifest("file = \"\"").is_err());
+        assert!(parse_manifest("file = 3").is_err());
+        assert!(parse_manifest("files = \"tasks.json\"").is_err());
+        assert!(parse_manifest("file = \"a\"\nfile = \"b\"").is_err());
+        assert!(parse_manifest("file = \"unterminated").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! An order to work through open tasks in: every task comes after the tasks
+//! it depends on, and among tasks that are free to start, the most pressing
+//! goes first.
+
+use crate::{
+    duration,
+    table::Table,
+    task::{self, Task},
+};
+use anyhow::bail;
+use chrono::NaiveDate;
+use std::{cmp::Reverse, collections::BTreeSet};
+use uuid::Uuid;
+
+/// A task in the plan, with the date it has to be done by: its own due date,
+/// or an earlier one of a task waiting on it.
+pub struct Step<'a> {
+    pub task: &'a Task,
+    pub deadline: Option<NaiveDate>,
+}
+
+/// For each open task, the positions in `open` of its open dependencies.
+/// Dependencies on finished or removed tasks no longer constrain anything.
+fn dependencies(open: &[&Task]) -> Vec<Vec<usize>> {
+    open.iter()
+        .map(|task| {
+            task.depends_on
+                .iter()
+                .filter_map(|uuid| open.iter().position(|t| t.uuid == *uuid))
+                .collect()
+        })
+        .collect()
+}
+
+/// Fails with the tasks in a dependency cycle among those `sorted` left out.
+/// Adding a dependency refuses to close a cycle, but merging edits from two
+/// replicas can still produce one.
+fn check_cycles(open: &[&Task], deps: &[Vec<usize>], sorted: &[usize]) -> anyhow::Result<()> {
+    let stuck: Vec<usize> = (0..open.len()).filter(|i| !sorted.contains(i)).collect();
+    let Some(&start) = stuck.first() else {
+        return Ok(());
+    };
+    // Every stuck task waits on another stuck task, so following those
+    // dependencies from any of them must come back around.
+    let mut path = vec![start];
+    loop {
+        let current = *path.last().expect("path starts non-empty");
+        let next = *deps[current]
+            .iter()
+            .find(|d| stuck.contains(d))
+            .expect("a stuck task waits on a stuck task");
+        if let Some(at) = path.iter().position(|&i| i == next) {
+            let mut cycle: Vec<String> =
+                path[at..].iter().map(|&i| open[i].id.to_string()).collect();
+            cycle.push(open[next].id.to_string());
+            bail!(
+                "Dependency cycle: {} (each waits on the next; break it with `depend --remove`)",
+                cycle.join(" -> ")
+            );
+        }
+        path.push(next);
+    }
+}
+
+/// Orders the open tasks. Fails if dependencies form a cycle.
+pub fn order(tasks: &[Task]) -> anyhow::Result<Vec<Step<'_>>> {
+    let open: Vec<&Task> = tasks.iter().filter(|t| !t.completed).collect();
+    let deps = dependencies(&open);
+    let mut dependents = vec![Vec::new(); open.len()];
+    for (i, on) in deps.iter().enumerate() {
+        for &d in on {
+            dependents[d].push(i);
+        }
+    }
+
+    // Any topological order, to find cycles and to pass deadlines back.
+    let mut waiting: Vec<usize> = deps.iter().map(Vec::len).collect();
+    let mut sorted: Vec<usize> = (0..open.len()).filter(|&i| waiting[i] == 0).collect();
+    let mut next = 0;
+    while let Some(&i) = sorted.get(next) {
+        next += 1;
+        for &d in &dependents[i] {
+            waiting[d] -= 1;
+            if waiting[d] == 0 {
+                sorted.push(d);
+            }
+        }
+    }
+    check_cycles(&open, &deps, &sorted)?;
+
+    // A task is due no later than anything that waits on it.
+    let mut deadlines: Vec<Option<NaiveDate>> = open.iter().map(|t| t.due).collect();
+    for &i in sorted.iter().rev() {
+        for &d in &dependents[i] {
+            if let Some(due) = deadlines[d] {
+                deadlines[i] = Some(deadlines[i].map_or(due, |own| own.min(due)));
+            }
+        }
+    }
+
+    // The same walk again, now taking the most pressing ready task each time.
+    let key = |i: usize| {
+        let task = open[i];
+        (
+            task.someday,
+            deadlines[i].is_none(),
+            deadlines[i],
+            Reverse(task.priority),
+            task.id,
+            i,
+        )
+    };
+    let mut waiting: Vec<usize> = deps.iter().map(Vec::len).collect();
+    let mut ready: BTreeSet<_> = (0..open.len())
+        .filter(|&i| waiting[i] == 0)
+        .map(key)
+        .collect();
+    let mut plan = Vec::with_capacity(open.len());
+    while let Some(first) = ready.pop_first() {
+        let i = first.5;
+        plan.push(Step {
+            task: open[i],
+            deadline: deadlines[i],
+        });
+        for &d in &dependents[i] {
+            waiting[d] -= 1;
+            if waiting[d] == 0 {
+                ready.insert(key(d));
+            }
+        }
+    }
+    Ok(plan)
+}
+
+pub fn print_plan(tasks: &[Task]) -> anyhow::Result<()> {
+    let plan = order(tasks)?;
+    if plan.is_empty() {
+        println!("No open tasks.");
+        return Ok(());
+    }
+    let width = plan.len().to_string().len();
+    for (n, step) in plan.iter().enumerate() {
+        let mut line = task::format_line(tasks, step.task);
+        if let Some(deadline) = step
+            .deadline
+            .filter(|d| step.task.due.is_none_or(|due| due > *d))
+        {
+            line.push_str(&format!(" (needed by {})", deadline));
+        }
+        println!("{:>w$}. {}", n + 1, line, w = width);
+    }
+    Ok(())
+}
+
+/// Open tasks towards `milestone` and everything they still wait on.
+fn towards<'a>(plan: Vec<Step<'a>>, milestone: &str) -> Vec<Step<'a>> {
+    let mut wanted: Vec<Uuid> = plan
+        .iter()
+        .filter(|s| s.task.milestone.as_deref() == Some(milestone))
+        .map(|s| s.task.uuid)
+        .collect();
+    // Dependencies come earlier in the plan, so one pass from the end
+    // collects them all.
+    for step in plan.iter().rev() {
+        if wanted.contains(&step.task.uuid) {
+            wanted.extend(step.task.depends_on.iter().copied());
+        }
+    }
+    plan.into_iter()
+        .filter(|s| wanted.contains(&s.task.uuid))
+        .collect()
+}
+
+/// Prints the chain of tasks whose estimates add up to the longest run of
+/// work, which no amount of parallel effort can shorten, and how long every
+/// other task can slip without delaying the end. Limited to `milestone` and
+/// what it depends on when given.
+pub fn print_critical_path(tasks: &[Task], milestone: Option<&str>) -> anyhow::Result<()> {
+    let mut plan = order(tasks)?;
+    if let Some(milestone) = milestone {
+        plan = towards(plan, milestone);
+    }
+    if plan.is_empty() {
+        println!("No open tasks.");
+        return Ok(());
+    }
+
+    let length = |i: usize| plan[i].task.estimate.unwrap_or(0);
+    let deps: Vec<Vec<usize>> = plan
+        .iter()
+        .map(|s| {
+            s.task
+                .depends_on
+                .iter()
+                .filter_map(|uuid| plan.iter().position(|p| p.task.uuid == *uuid))
+                .collect()
+        })
+        .collect();
+
+    // Earliest finish, in minutes of work, walking the plan forwards.
+    let mut finish = vec![0; plan.len()];
+    for i in 0..plan.len() {
+        let start = deps[i].iter().map(|&d| finish[d]).max().unwrap_or(0);
+        finish[i] = start + length(i);
+    }
+    let end = finish.iter().copied().max().unwrap_or(0);
+    // Latest finish that still meets `end`, walking backwards.
+    let mut latest = vec![end; plan.len()];
+    for i in (0..plan.len()).rev() {
+        for &d in &deps[i] {
+            latest[d] = latest[d].min(latest[i] - length(i));
+        }
+    }
+
+    // Trace the chain back from the task that finishes last.
+    let mut chain = Vec::new();
+    let mut current = (0..plan.len()).max_by_key(|&i| (finish[i], Reverse(i)));
+    while let Some(i) = current {
+        chain.push(i);
+        current = deps[i]
+            .iter()
+            .copied()
+            .find(|&d| finish[d] == finish[i] - length(i));
+    }
+    chain.reverse();
+
+    println!(
+        "Critical path: {} of work across {} task(s): {}",
+        duration::format_minutes(end),
+        chain.len(),
+        chain
+            .iter()
+            .map(|&i| plan[i].task.id.to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    );
+    let mut table =
+        Table::new(&["ID", "Task", "Estimate", "Starts after", "Slack"]).align_right(&[0, 2, 3, 4]);
+    for i in 0..plan.len() {
+        let task = plan[i].task;
+        let slack = if chain.contains(&i) {
+            "critical".to_owned()
+        } else {
+            duration::format_minutes(latest[i] - finish[i])
+        };
+        table.push(vec![
+            task.id.to_string(),
+            task.description.clone(),
+            task.estimate
+                .map(duration::format_minutes)
+                .unwrap_or_else(|| "-".to_owned()),
+            duration::format_minutes(finish[i] - length(i)),
+            slack,
+        ]);
+    }
+    table.print();
+
+    let unestimated = plan.iter().filter(|s| s.task.estimate.is_none()).count();
+    if unestimated > 0 {
+        println!(
+            "{} task(s) without an estimate count as no work; set one with `estimate`.",
+            unestimated
+        );
+    }
+    Ok(())
+}
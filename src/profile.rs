@@ -0,0 +1,133 @@
+//! Profiles: named bundles of settings and tasks, such as `work` and `home`,
+//! each with its own config.json, task list, and caches (see `paths`).
+//! `--profile work`, or `CLI_TASK_MANAGER_PROFILE=work`, runs a command in
+//! one. Contexts (`--in`) are task lists within whichever profile is in use.
+//! Without a profile, the usual directories are the `default` one.
+
+use crate::{inbox, paths::Dirs, task};
+use anyhow::{Context, bail};
+use std::{fs, io::IsTerminal, path::Path};
+
+pub const DEFAULT: &str = "default";
+
+pub fn check_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Profile names may only use letters, digits, - and _");
+    }
+    Ok(())
+}
+
+/// The profiles that exist besides `default`, by name.
+fn names(base: &Dirs) -> anyhow::Result<Vec<String>> {
+    let dir = base.config.join("profiles");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+        if entry.path().is_dir()
+            && let Some(name) = entry.file_name().to_str()
+            && check_name(name).is_ok()
+        {
+            names.push(name.to_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn open_count(tasks_path: &Path) -> String {
+    match task::read_tasks(tasks_path) {
+        Ok((tasks, _)) => format!(
+            "{} open task(s)",
+            tasks.iter().filter(|t| !t.completed).count()
+        ),
+        Err(_) => "unreadable tasks".to_owned(),
+    }
+}
+
+/// `profile list`: every profile, marking the one in use with `*`.
+pub fn print_profiles(base: &Dirs, active: Option<&str>) -> anyhow::Result<()> {
+    let active = active.unwrap_or(DEFAULT);
+    let mark = |name: &str| if name == active { '*' } else { ' ' };
+    println!(
+        "{} {:<12} {}",
+        mark(DEFAULT),
+        DEFAULT,
+        open_count(&base.tasks)
+    );
+    for name in names(base)? {
+        let dirs = base.profile(&name)?;
+        println!("{} {:<12} {}", mark(&name), name, open_count(&dirs.tasks));
+    }
+    Ok(())
+}
+
+/// `profile create`: makes the profile's directories, starting it with a
+/// copy of the usual config.json when `copy_config`.
+pub fn create(base: &Dirs, name: &str, copy_config: bool) -> anyhow::Result<()> {
+    if name == DEFAULT {
+        bail!("The default profile always exists");
+    }
+    let dirs = base.profile(name)?;
+    if dirs.config.exists() {
+        bail!("Profile {} already exists", name);
+    }
+    for dir in [&dirs.config, &dirs.data] {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let config = base.config.join("config.json");
+    if copy_config && config.exists() {
+        fs::copy(&config, dirs.config.join("config.json"))
+            .with_context(|| format!("Failed to copy {}", config.display()))?;
+    }
+    println!(
+        "Created profile {}; use it with `--profile {}`.",
+        name, name
+    );
+    Ok(())
+}
+
+/// `profile delete`: removes the profile with its settings and tasks,
+/// after asking, or without asking when `force`.
+pub fn delete(base: &Dirs, name: &str, force: bool) -> anyhow::Result<()> {
+    if name == DEFAULT {
+        bail!("The default profile cannot be deleted");
+    }
+    let dirs = base.profile(name)?;
+    if !dirs.config.exists() {
+        bail!("No profile named {}", name);
+    }
+    if !force {
+        if !std::io::stdin().is_terminal() {
+            bail!(
+                "Deleting profile {} loses its tasks; pass --force to go ahead",
+                name
+            );
+        }
+        let question = format!(
+            "Delete profile {} with its settings and {}? [y/N] ",
+            name,
+            open_count(&dirs.tasks)
+        );
+        if !inbox::ask(&question)?.is_some_and(|answer| answer.eq_ignore_ascii_case("y")) {
+            println!("Kept profile {}.", name);
+            return Ok(());
+        }
+    }
+    for dir in [&dirs.data, &dirs.config, &dirs.cache] {
+        if dir.exists() {
+            fs::remove_dir_all(dir)
+                .with_context(|| format!("Failed to remove {}", dir.display()))?;
+        }
+    }
+    println!("Deleted profile {}.", name);
+    Ok(())
+}
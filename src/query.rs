@@ -0,0 +1,605 @@
+//! `query`: a small SQL dialect over the task list, for questions the list
+//! filters cannot ask:
+//!
+//! ```text
+//! SELECT id, description FROM tasks
+//!   WHERE priority = 'high' AND due < '2025-02-01' ORDER BY due LIMIT 10
+//! ```
+//!
+//! The columns are the `--format` fields (`*` for the default table). A
+//! condition compares a field with `=`, `!=`, `<`, `<=`, `>`, `>=`, or
+//! `LIKE` (`%` for any text, `_` for one character), or tests it with
+//! `IS [NOT] NULL`, and conditions combine with `AND`, `OR`, `NOT`, and
+//! parentheses. Values are read the way the field is: priorities rank by
+//! urgency, `due` takes the dates `--due` does, and `estimate` and `tracked`
+//! take durations such as `30m`. Text compares ignoring case. Completed
+//! tasks are included; `WHERE status = 'open'` leaves them out.
+
+use crate::{
+    duration, report,
+    task::{self, Priority, Task},
+    template,
+};
+use anyhow::{Context, bail};
+use clap::ValueEnum;
+use std::cmp::Ordering;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &["<=", ">=", "!=", "<>", "=", "<", ">", "(", ")", ",", "*"];
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if let Some(quoted) = rest.strip_prefix('\'') {
+            // '' inside a string is a quote.
+            let mut text = String::new();
+            let mut chars = quoted.char_indices().peekable();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '\'')) if chars.peek().is_none_or(|(_, c)| *c != '\'') => {
+                        break i + 1;
+                    }
+                    Some((_, '\'')) => {
+                        chars.next();
+                        text.push('\'');
+                    }
+                    Some((_, c)) => text.push(c),
+                    None => bail!("Unclosed string '{}", text),
+                }
+            };
+            tokens.push(Token::Text(text));
+            rest = &quoted[end..];
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+')))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                bail!("Unexpected {:?} in query", rest.chars().next().unwrap());
+            }
+            tokens.push(Token::Word(rest[..end].to_owned()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Like,
+    NotLike,
+}
+
+enum Condition {
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Compare(String, Comparison, Value),
+    IsNull(String, bool),
+}
+
+/// A field's value, typed so that it orders the way the field should.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+struct Key {
+    field: String,
+    descending: bool,
+}
+
+struct Query {
+    columns: Vec<String>,
+    condition: Option<Condition>,
+    order: Vec<Key>,
+    limit: Option<usize>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn describe(&self) -> String {
+        match self.peek() {
+            Some(Token::Word(word)) => format!("{:?}", word),
+            Some(Token::Text(text)) => format!("'{}'", text),
+            Some(Token::Symbol(symbol)) => format!("{:?}", symbol),
+            None => "the end".to_owned(),
+        }
+    }
+
+    /// Consumes keyword `word` if it is next.
+    fn keyword(&mut self, word: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(word));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, word: &str) -> anyhow::Result<()> {
+        if !self.keyword(word) {
+            bail!("Expected {} but found {}", word, self.describe());
+        }
+        Ok(())
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn field(&mut self) -> anyhow::Result<String> {
+        match self.peek().cloned() {
+            Some(Token::Word(word)) => {
+                self.position += 1;
+                template::parse_field(&word.to_lowercase())
+            }
+            _ => bail!("Expected a field but found {}", self.describe()),
+        }
+    }
+
+    fn literal(&mut self) -> anyhow::Result<String> {
+        match self.peek().cloned() {
+            Some(Token::Text(text) | Token::Word(text)) => {
+                self.position += 1;
+                Ok(text)
+            }
+            _ => bail!("Expected a value but found {}", self.describe()),
+        }
+    }
+
+    fn query(&mut self) -> anyhow::Result<Query> {
+        self.expect_keyword("select")?;
+        let columns = if self.symbol("*") {
+            task::DEFAULT_COLUMNS
+                .iter()
+                .map(|c| c.to_string())
+                .collect()
+        } else {
+            let mut columns = vec![self.field()?];
+            while self.symbol(",") {
+                columns.push(self.field()?);
+            }
+            columns
+        };
+        self.expect_keyword("from")?;
+        if !self.keyword("tasks") {
+            bail!("Expected tasks but found {}", self.describe());
+        }
+        let condition = if self.keyword("where") {
+            Some(self.or()?)
+        } else {
+            None
+        };
+        let mut order = Vec::new();
+        if self.keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let field = self.field()?;
+                let descending = self.keyword("desc");
+                if !descending {
+                    self.keyword("asc");
+                }
+                order.push(Key { field, descending });
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.keyword("limit") {
+            let text = self.literal()?;
+            Some(
+                text.parse()
+                    .with_context(|| format!("LIMIT {} is not a count", text))?,
+            )
+        } else {
+            None
+        };
+        if self.peek().is_some() {
+            bail!("Unexpected {} at the end of the query", self.describe());
+        }
+        Ok(Query {
+            columns,
+            condition,
+            order,
+            limit,
+        })
+    }
+
+    fn or(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.and()?;
+        while self.keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.not()?;
+        while self.keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> anyhow::Result<Condition> {
+        if self.keyword("not") {
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        if self.symbol("(") {
+            let condition = self.or()?;
+            if !self.symbol(")") {
+                bail!("Expected ) but found {}", self.describe());
+            }
+            return Ok(condition);
+        }
+        let field = self.field()?;
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(Condition::IsNull(field, !negated));
+        }
+        let comparison = if self.keyword("like") {
+            Comparison::Like
+        } else if self.keyword("not") {
+            self.expect_keyword("like")?;
+            Comparison::NotLike
+        } else {
+            let comparison = match self.peek() {
+                Some(Token::Symbol("=")) => Comparison::Equal,
+                Some(Token::Symbol("!=" | "<>")) => Comparison::NotEqual,
+                Some(Token::Symbol("<")) => Comparison::Less,
+                Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+                Some(Token::Symbol(">")) => Comparison::Greater,
+                Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+                _ => bail!(
+                    "Expected a comparison after {} but found {}",
+                    field,
+                    self.describe()
+                ),
+            };
+            self.position += 1;
+            comparison
+        };
+        let literal = self.literal()?;
+        let value = match comparison {
+            Comparison::Like | Comparison::NotLike => Value::Text(literal.to_lowercase()),
+            _ => parse_value(&field, &literal)?,
+        };
+        Ok(Condition::Compare(field, comparison, value))
+    }
+}
+
+fn rank(priority: Priority) -> f64 {
+    Priority::value_variants()
+        .iter()
+        .position(|p| *p == priority)
+        .unwrap_or_default() as f64
+}
+
+/// `literal` read the way values of `field` are.
+fn parse_value(field: &str, literal: &str) -> anyhow::Result<Value> {
+    Ok(match field {
+        "id" => Value::Number(
+            literal
+                .parse()
+                .with_context(|| format!("{} is not an id", literal))?,
+        ),
+        "priority" => Value::Number(rank(
+            Priority::from_str(literal, true)
+                .map_err(|_| anyhow::anyhow!("{} is not a priority", literal))?,
+        )),
+        "due" => Value::Text(report::parse_date(literal)?.to_string()),
+        "estimate" | "tracked" => Value::Number(duration::parse_minutes(literal)?.into()),
+        _ => Value::Text(literal.to_lowercase()),
+    })
+}
+
+/// The value of `field` for `task`, or `None` when it has none.
+fn value(tasks: &[Task], task: &Task, field: &str) -> Option<Value> {
+    match field {
+        "id" => Some(Value::Number(task.id.into())),
+        "priority" => task.priority.map(|p| Value::Number(rank(p))),
+        "estimate" => task.estimate.map(|e| Value::Number(e.into())),
+        "tracked" if task.time_log.is_empty() => None,
+        "tracked" => Some(Value::Number(task.tracked_minutes().into())),
+        _ => {
+            let text = template::field(tasks, task, field);
+            (!text.is_empty()).then(|| Value::Text(text.to_lowercase()))
+        }
+    }
+}
+
+/// Orders two values, numerically when both texts are numbers, so that
+/// attributes such as `attr.points` compare sensibly.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Text(a), Value::Text(b)) => match (a.parse::<f64>(), b.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(a.cmp(b)),
+        },
+        _ => a.partial_cmp(b),
+    }
+}
+
+/// Whether `text` matches a LIKE `pattern`.
+fn like(text: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('%', rest)) => (0..=text.len()).any(|skip| like(&text[skip..], rest)),
+        Some(('_', rest)) => !text.is_empty() && like(&text[1..], rest),
+        Some((c, rest)) => text.first() == Some(c) && like(&text[1..], rest),
+    }
+}
+
+impl Condition {
+    fn holds(&self, tasks: &[Task], task: &Task) -> bool {
+        match self {
+            Condition::And(a, b) => a.holds(tasks, task) && b.holds(tasks, task),
+            Condition::Or(a, b) => a.holds(tasks, task) || b.holds(tasks, task),
+            Condition::Not(condition) => !condition.holds(tasks, task),
+            Condition::IsNull(field, null) => value(tasks, task, field).is_none() == *null,
+            Condition::Compare(field, comparison, wanted) => {
+                // As in SQL, a missing value passes no comparison.
+                let Some(actual) = value(tasks, task, field) else {
+                    return false;
+                };
+                let ordering = compare(&actual, wanted);
+                match comparison {
+                    Comparison::Equal => ordering == Some(Ordering::Equal),
+                    Comparison::NotEqual => ordering != Some(Ordering::Equal),
+                    Comparison::Less => ordering == Some(Ordering::Less),
+                    Comparison::LessOrEqual => ordering.is_some_and(Ordering::is_le),
+                    Comparison::Greater => ordering == Some(Ordering::Greater),
+                    Comparison::GreaterOrEqual => ordering.is_some_and(Ordering::is_ge),
+                    Comparison::Like | Comparison::NotLike => {
+                        let (Value::Text(text), Value::Text(pattern)) = (&actual, wanted) else {
+                            return false;
+                        };
+                        let text: Vec<char> = text.chars().collect();
+                        let pattern: Vec<char> = pattern.chars().collect();
+                        like(&text, &pattern) == matches!(comparison, Comparison::Like)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The columns `input` asks for and the tasks it selects, in order.
+fn select<'a>(tasks: &'a [Task], input: &str) -> anyhow::Result<(Vec<String>, Vec<&'a Task>)> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+    };
+    let query = parser.query().context("Invalid query")?;
+    let mut rows: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| query.condition.as_ref().is_none_or(|c| c.holds(tasks, t)))
+        .collect();
+    rows.sort_by(|a, b| {
+        for key in &query.order {
+            // Missing values come last either way, as in the list sorts.
+            let ordering = match (value(tasks, a, &key.field), value(tasks, b, &key.field)) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(x), Some(y)) => {
+                    let ordering = compare(&x, &y).unwrap_or(Ordering::Equal);
+                    if key.descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                }
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+        a.id.cmp(&b.id)
+    });
+    if let Some(limit) = query.limit {
+        rows.truncate(limit);
+    }
+    Ok((query.columns, rows))
+}
+
+/// Runs `input` over `tasks` and prints the rows it selects as a table.
+pub fn run(tasks: &[Task], input: &str) -> anyhow::Result<()> {
+    let (columns, rows) = select(tasks, input)?;
+    if rows.is_empty() {
+        println!("No matching tasks.");
+        return Ok(());
+    }
+    task::print_table(tasks, &rows, &columns);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value as Json, json};
+
+    fn task(value: Json) -> Task {
+        serde_json::from_value(value).expect("a valid task")
+    }
+
+    fn sample() -> Vec<Task> {
+        vec![
+            task(json!({
+                "id": 1,
+                "description": "Write the report",
+                "completed": false,
+                "project": "Work",
+                "priority": "high",
+                "due": "2025-01-20",
+                "estimate": 90,
+                "attributes": { "points": "10" }
+            })),
+            task(json!({
+                "id": 2,
+                "description": "Buy milk",
+                "completed": true,
+                "priority": "low",
+                "estimate": 15,
+                "attributes": { "points": "9" }
+            })),
+            task(json!({
+                "id": 3,
+                "description": "Call O'Brien",
+                "completed": false,
+                "project": "work",
+                "priority": "urgent",
+                "due": "2025-02-10"
+            })),
+        ]
+    }
+
+    fn ids(input: &str) -> Vec<u32> {
+        let tasks = sample();
+        let (_, rows) = select(&tasks, input).unwrap();
+        rows.iter().map(|t| t.id).collect()
+    }
+
+    fn error(input: &str) -> String {
+        let tasks = sample();
+        format!("{:#}", select(&tasks, input).err().expect("an error"))
+    }
+
+    #[test]
+    fn tokenizes_symbols_words_and_strings() {
+        assert_eq!(
+            tokenize("a<='it''s' OR b<>1").unwrap(),
+            vec![
+                Token::Word("a".to_owned()),
+                Token::Symbol("<="),
+                Token::Text("it's".to_owned()),
+                Token::Word("OR".to_owned()),
+                Token::Word("b".to_owned()),
+                Token::Symbol("<>"),
+                Token::Word("1".to_owned()),
+            ]
+        );
+        assert!(tokenize("a = 'open").is_err());
+        assert!(tokenize("a ; b").is_err());
+    }
+
+    #[test]
+    fn like_patterns() {
+        let like = |text: &str, pattern: &str| {
+            let text: Vec<char> = text.chars().collect();
+            let pattern: Vec<char> = pattern.chars().collect();
+            like(&text, &pattern)
+        };
+        assert!(like("report", "%port"));
+        assert!(like("report", "r_p%"));
+        assert!(like("", "%"));
+        assert!(!like("report", "r_ort"));
+        assert!(!like("report", "port"));
+        assert!(like("日本語", "_本_"));
+    }
+
+    #[test]
+    fn selects_columns() {
+        let tasks = sample();
+        let (columns, _) = select(&tasks, "select id, DESCRIPTION from tasks").unwrap();
+        assert_eq!(columns, ["id", "description"]);
+        let (columns, _) = select(&tasks, "SELECT * FROM tasks").unwrap();
+        assert_eq!(columns, task::DEFAULT_COLUMNS);
+    }
+
+    #[test]
+    fn conditions_compare_fields_the_way_they_are_read() {
+        assert_eq!(ids("SELECT id FROM tasks WHERE priority >= 'high'"), [1, 3]);
+        assert_eq!(ids("SELECT id FROM tasks WHERE due < '2025-02-01'"), [1]);
+        assert_eq!(ids("SELECT id FROM tasks WHERE estimate > 1h"), [1]);
+        assert_eq!(ids("SELECT id FROM tasks WHERE project = 'WORK'"), [1, 3]);
+        assert_eq!(
+            ids("SELECT id FROM tasks WHERE description = 'call o''brien'"),
+            [3]
+        );
+        assert_eq!(ids("SELECT id FROM tasks WHERE attr.points > 9"), [1]);
+    }
+
+    #[test]
+    fn missing_values_pass_no_comparison() {
+        assert_eq!(ids("SELECT id FROM tasks WHERE due != '2025-01-20'"), [3]);
+        assert_eq!(ids("SELECT id FROM tasks WHERE due IS NULL"), [2]);
+        assert_eq!(ids("SELECT id FROM tasks WHERE due IS NOT NULL"), [1, 3]);
+    }
+
+    #[test]
+    fn conditions_combine() {
+        assert_eq!(
+            ids("SELECT id FROM tasks WHERE status = 'open' AND NOT description LIKE 'call%'"),
+            [1]
+        );
+        assert_eq!(
+            ids(
+                "SELECT id FROM tasks WHERE priority = low OR (project = work AND due > '2025-02-01')"
+            ),
+            [2, 3]
+        );
+        assert_eq!(
+            ids("SELECT id FROM tasks WHERE description NOT LIKE '%r%'"),
+            [2]
+        );
+    }
+
+    #[test]
+    fn orders_and_limits() {
+        assert_eq!(
+            ids("SELECT id FROM tasks ORDER BY priority DESC"),
+            [3, 1, 2]
+        );
+        assert_eq!(ids("SELECT id FROM tasks ORDER BY due"), [1, 3, 2]);
+        assert_eq!(ids("SELECT id FROM tasks ORDER BY due DESC"), [3, 1, 2]);
+        assert_eq!(
+            ids("SELECT id FROM tasks ORDER BY project, id DESC"),
+            [3, 1, 2]
+        );
+        assert_eq!(ids("SELECT id FROM tasks ORDER BY estimate LIMIT 1"), [2]);
+    }
+
+    #[test]
+    fn reports_what_it_expected() {
+        assert!(error("SELECT , id FROM tasks").contains("Expected a field"));
+        assert!(error("SELECT id FROM notes").contains("Expected tasks but found \"notes\""));
+        assert!(error("SELECT id FROM tasks WHERE id").contains("Expected a comparison after id"));
+        assert!(
+            error("SELECT id FROM tasks WHERE (id = 1").contains("Expected ) but found the end")
+        );
+        assert!(error("SELECT id FROM tasks LIMIT some").contains("is not a count"));
+        assert!(error("SELECT id FROM tasks WHERE id = x").contains("x is not an id"));
+        assert!(error("SELECT id FROM tasks id").contains("at the end of the query"));
+    }
+}
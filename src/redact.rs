@@ -0,0 +1,120 @@
+//! Masking of secrets pasted into task text by mistake, such as API keys or
+//! card numbers, wherever tasks leave this program for a reader: the screen,
+//! exports, commit message templates, notifications, and what `serve` hands
+//! out. Set in config.json as patterns in the syntax of the `regex` crate:
+//!
+//! ```json
+//! {
+//!   "redact": ["sk-[A-Za-z0-9]{20,}", "\\d{4}([ -]?\\d{4}){3}"]
+//! }
+//! ```
+//!
+//! The tasks file itself, and sync, keep the text as it was typed, so
+//! editing a task or removing the secret by hand is still possible.
+
+use crate::task::Task;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+
+const MASK: &str = "[redacted]";
+
+#[derive(Default, Deserialize)]
+#[serde(transparent)]
+pub struct Rules(#[serde(deserialize_with = "compile")] Vec<Regex>);
+
+/// Patterns are compiled as config.json is read, so a bad one is reported
+/// with the file.
+fn compile<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|err| {
+                serde::de::Error::custom(format!("bad pattern {:?}: {}", pattern, err))
+            })
+        })
+        .collect()
+}
+
+impl Rules {
+    /// `text` with every match of every pattern replaced by the mask.
+    pub fn mask<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for regex in &self.0 {
+            if let Cow::Owned(masked) = regex.replace_all(&text, MASK) {
+                text = Cow::Owned(masked);
+            }
+        }
+        text
+    }
+
+    fn mask_in_place(&self, text: &mut String) {
+        if let Cow::Owned(masked) = self.mask(text) {
+            *text = masked;
+        }
+    }
+
+    /// Masks every free-text field of `tasks`, which must not be saved
+    /// afterwards.
+    pub fn apply(&self, tasks: &mut [Task]) {
+        if self.0.is_empty() {
+            return;
+        }
+        for task in tasks {
+            self.mask_in_place(&mut task.description);
+            for text in [&mut task.note, &mut task.link, &mut task.waiting_on]
+                .into_iter()
+                .flatten()
+            {
+                self.mask_in_place(text);
+            }
+            for value in task.attributes.values_mut() {
+                self.mask_in_place(value);
+            }
+            for comment in &mut task.comments {
+                self.mask_in_place(&mut comment.text);
+            }
+            for item in &mut task.checklist {
+                self.mask_in_place(&mut item.text);
+            }
+        }
+    }
+
+    /// A masked copy of `task`.
+    pub fn masked(&self, task: &Task) -> Task {
+        let mut task = task.clone();
+        self.apply(std::slice::from_mut(&mut task));
+        task
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(patterns: &[&str]) -> Rules {
+        serde_json::from_value(serde_json::json!(patterns)).unwrap()
+    }
+
+    #[test]
+    fn masks_every_match() {
+        let rules = rules(&[r"\bsk-\w+", r"\d{4}([ -]?\d{4}){3}"]);
+        assert_eq!(
+            rules.mask("key sk-abc1 and ask-x, card 4111 1111 1111 1111."),
+            "key [redacted] and ask-x, card [redacted]."
+        );
+        assert!(matches!(rules.mask("nothing here"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn nested_repeats_do_not_hang() {
+        let text = format!("{}c", "a".repeat(64));
+        assert_eq!(rules(&["(a+)+b"]).mask(&text), text);
+    }
+
+    #[test]
+    fn bad_patterns_are_refused_on_load() {
+        let err = serde_json::from_value::<Rules>(serde_json::json!(["(a"])).err();
+        assert!(err.is_some_and(|err| err.to_string().contains("bad pattern \"(a\"")));
+    }
+}
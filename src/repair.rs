@@ -0,0 +1,212 @@
+use crate::{
+    checksum, storage,
+    task::{self, Task},
+};
+use anyhow::Context;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A chunk of the broken file that could not be turned back into a task.
+pub struct Dropped {
+    pub offset: usize,
+    pub snippet: String,
+}
+
+pub struct RepairReport {
+    pub recovered: usize,
+    pub renumbered: Vec<(u32, u32)>,
+    pub dropped: Vec<Dropped>,
+    pub quarantine: PathBuf,
+}
+
+/// Salvages every parseable task object from a corrupted tasks file.
+///
+/// Returns `Ok(None)` when the file already parses and nothing needs fixing.
+/// Otherwise the original is copied aside untouched before the recovered tasks
+/// are written back through the normal atomic save path.
+pub fn repair_tasks(path: &Path) -> anyhow::Result<Option<RepairReport>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    if storage::detect(path) != storage::Format::Json {
+        return match task::read_tasks(path) {
+            Ok(_) => Ok(None),
+            Err(err) => Err(err.context("`repair` can only salvage tasks stored as JSON")),
+        };
+    }
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read tasks file at {}", path.display()))?;
+    let (_, body) = checksum::split(data.as_bytes());
+    let body = &data[data.len() - body.len()..];
+    if body.trim().is_empty() || serde_json::from_str::<Vec<Task>>(body).is_ok() {
+        return Ok(None);
+    }
+
+    let (mut tasks, dropped) = salvage(body);
+    let renumbered = renumber_duplicates(&mut tasks);
+
+    let quarantine = quarantine_path(path);
+    fs::copy(path, &quarantine).with_context(|| {
+        format!(
+            "Failed to copy broken tasks file to {}",
+            quarantine.display()
+        )
+    })?;
+    task::save_tasks(path, &tasks)?;
+
+    Ok(Some(RepairReport {
+        recovered: tasks.len(),
+        renumbered,
+        dropped,
+        quarantine,
+    }))
+}
+
+pub fn print_report(path: &Path, report: Option<&RepairReport>) {
+    let Some(report) = report else {
+        println!(
+            "Tasks file at {} is valid; nothing to repair.",
+            path.display()
+        );
+        return;
+    };
+
+    println!(
+        "Recovered {} task(s) into {}",
+        report.recovered,
+        path.display()
+    );
+    for (old, new) in &report.renumbered {
+        println!("  duplicate id {} renumbered to {}", old, new);
+    }
+    if report.dropped.is_empty() {
+        println!("Nothing was dropped.");
+    } else {
+        println!("Dropped {} unreadable fragment(s):", report.dropped.len());
+        for fragment in &report.dropped {
+            println!("  at byte {}: {}", fragment.offset, fragment.snippet);
+        }
+    }
+    println!("Broken original kept at {}", report.quarantine.display());
+}
+
+/// Walks the raw text looking for brace-balanced `{...}` objects, ignoring
+/// braces inside JSON strings, and keeps those that deserialize as a `Task`.
+fn salvage(data: &str) -> (Vec<Task>, Vec<Dropped>) {
+    let bytes = data.as_bytes();
+    let mut tasks = Vec::new();
+    let mut dropped = Vec::new();
+    let mut pos = 0;
+
+    while let Some(rel) = data[pos..].find('{') {
+        let start = pos + rel;
+        match object_end(bytes, start) {
+            Some(end) => match serde_json::from_str::<Task>(&data[start..end]) {
+                Ok(task) => {
+                    tasks.push(task);
+                    pos = end;
+                }
+                Err(_) => {
+                    // The object may have swallowed a neighbour (e.g. a lost
+                    // closing brace), so resume at the next opening brace.
+                    let next = data[start + 1..]
+                        .find('{')
+                        .map_or(end, |r| (start + 1 + r).min(end));
+                    dropped.push(fragment(data, start, next));
+                    pos = next;
+                }
+            },
+            None => {
+                dropped.push(fragment(data, start, data.len()));
+                break;
+            }
+        }
+    }
+
+    (tasks, dropped)
+}
+
+/// Returns the byte index just past the brace closing the object at `start`.
+fn object_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, &b) in bytes.iter().enumerate().skip(start) {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn fragment(data: &str, start: usize, end: usize) -> Dropped {
+    const MAX_SNIPPET: usize = 60;
+
+    let text: String = data[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let snippet = if text.chars().count() > MAX_SNIPPET {
+        let cut: String = text.chars().take(MAX_SNIPPET).collect();
+        format!("{}...", cut)
+    } else {
+        text
+    };
+    Dropped {
+        offset: start,
+        snippet,
+    }
+}
+
+/// Salvaged objects can repeat an id (e.g. after a bad hand edit); later
+/// copies get fresh ids so `done`/`remove` stay unambiguous.
+fn renumber_duplicates(tasks: &mut [Task]) -> Vec<(u32, u32)> {
+    let mut next_id = tasks.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+    let mut seen = HashSet::new();
+    let mut renumbered = Vec::new();
+
+    for task in tasks.iter_mut() {
+        if !seen.insert(task.id) {
+            renumbered.push((task.id, next_id));
+            task.id = next_id;
+            seen.insert(next_id);
+            next_id += 1;
+        }
+    }
+    renumbered
+}
+
+fn quarantine_path(path: &Path) -> PathBuf {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "tasks.json".to_owned());
+    path.with_file_name(format!("{}.corrupt-{}", name, stamp))
+}
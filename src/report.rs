@@ -0,0 +1,578 @@
+//! Summaries computed across many tasks, as opposed to `list`, which shows
+//! tasks one per line.
+
+use crate::{
+    date, duration, export,
+    table::Table,
+    task::{Priority, Task},
+};
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    Text,
+    Csv,
+}
+
+/// What `report time` should cover and how to price it.
+pub struct TimeQuery {
+    /// First day included, in local time.
+    pub from: Option<NaiveDate>,
+    /// Last day included, in local time.
+    pub to: Option<NaiveDate>,
+    pub project: Option<String>,
+    /// Hourly rate; amounts are left out without one.
+    pub rate: Option<f64>,
+    pub format: Format,
+}
+
+pub fn parse_date(input: &str) -> anyhow::Result<NaiveDate> {
+    date::parse(input)
+}
+
+pub fn parse_time(input: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(input, "%H:%M")
+        .with_context(|| format!("Invalid time '{}' (use HH:MM)", input))
+}
+
+/// Local midnight at the start of `date`.
+pub fn start_of(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight exists");
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+/// Per-project completion, by task count or, with `weighted`, by estimated
+/// effort. Unestimated tasks weigh as much as the project's average estimate
+/// so they neither vanish from nor dominate the figure.
+pub fn projects(tasks: &[Task], weighted: bool, bar: bool) {
+    let mut projects: BTreeMap<Option<&str>, Vec<&Task>> = BTreeMap::new();
+    for task in tasks {
+        projects
+            .entry(task.project.as_deref())
+            .or_default()
+            .push(task);
+    }
+    if projects.is_empty() {
+        println!("No tasks found.");
+        return;
+    }
+
+    let mut headers = vec!["Project", "Done", "Progress"];
+    if bar {
+        headers.push("");
+    }
+    let mut table = Table::new(&headers).align_right(&[1, 2]);
+    for (project, tasks) in &projects {
+        let done = tasks.iter().filter(|t| t.completed).count();
+        let fraction = if weighted {
+            let estimates: Vec<u32> = tasks.iter().filter_map(|t| t.estimate).collect();
+            let fallback = if estimates.is_empty() {
+                1.0
+            } else {
+                f64::from(estimates.iter().sum::<u32>()) / estimates.len() as f64
+            };
+            let weight = |t: &&Task| t.estimate.map_or(fallback, f64::from);
+            let total: f64 = tasks.iter().map(weight).sum();
+            let finished = tasks
+                .iter()
+                .filter(|t| t.completed)
+                .fold(0.0, |sum, t| sum + weight(t));
+            finished / total
+        } else {
+            done as f64 / tasks.len() as f64
+        };
+
+        let mut row = vec![
+            project.unwrap_or("(no project)").to_owned(),
+            format!("{}/{}", done, tasks.len()),
+            format!("{:.0}%", fraction * 100.0),
+        ];
+        if bar {
+            row.push(progress_bar(fraction, 20));
+        }
+        table.push(row);
+    }
+    table.print();
+}
+
+/// What `report group-by` groups tasks by.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GroupKey {
+    /// Each tag; a task with several tags counts in each
+    Tag,
+    Project,
+    Assignee,
+    Milestone,
+    Priority,
+}
+
+/// Counts per group, split into open and done, with the estimated work
+/// still open, for an overview of where the workload sits.
+pub fn group_by(tasks: &[Task], key: GroupKey) {
+    let mut groups: BTreeMap<Option<String>, Vec<&Task>> = BTreeMap::new();
+    for task in tasks.iter().filter(|t| t.habit.is_none()) {
+        let names: Vec<Option<String>> = match key {
+            GroupKey::Tag if task.tags.is_empty() => vec![None],
+            GroupKey::Tag => task.tags.iter().cloned().map(Some).collect(),
+            GroupKey::Project => vec![task.project.clone()],
+            GroupKey::Assignee => vec![task.assignee.clone()],
+            GroupKey::Milestone => vec![task.milestone.clone()],
+            GroupKey::Priority => vec![task.priority.map(|p| p.name().to_owned())],
+        };
+        for name in names {
+            groups.entry(name).or_default().push(task);
+        }
+    }
+    if groups.is_empty() {
+        println!("No tasks found.");
+        return;
+    }
+
+    let header = match key {
+        GroupKey::Tag => "Tag",
+        GroupKey::Project => "Project",
+        GroupKey::Assignee => "Assignee",
+        GroupKey::Milestone => "Milestone",
+        GroupKey::Priority => "Priority",
+    };
+    let mut table = Table::new(&[
+        header,
+        "Tasks",
+        "Open",
+        "Done",
+        "Open estimate",
+        "Unestimated",
+    ])
+    .align_right(&[1, 2, 3, 4, 5]);
+    // Groups without a value go last, as in the other reports.
+    let (unset, mut named): (Vec<_>, Vec<_>) =
+        groups.into_iter().partition(|(name, _)| name.is_none());
+    if let GroupKey::Priority = key {
+        named.sort_by_key(|(name, _)| {
+            std::cmp::Reverse(
+                name.as_deref()
+                    .and_then(|n| Priority::from_str(n, true).ok()),
+            )
+        });
+    }
+    for (name, tasks) in named.into_iter().chain(unset) {
+        let open: Vec<&&Task> = tasks.iter().filter(|t| !t.completed).collect();
+        let estimate: u32 = open.iter().filter_map(|t| t.estimate).sum();
+        let unestimated = open.iter().filter(|t| t.estimate.is_none()).count();
+        table.push(vec![
+            name.unwrap_or_else(|| format!("(no {})", header.to_lowercase())),
+            tasks.len().to_string(),
+            open.len().to_string(),
+            (tasks.len() - open.len()).to_string(),
+            if estimate > 0 {
+                duration::format_minutes(estimate)
+            } else {
+                String::new()
+            },
+            unestimated.to_string(),
+        ]);
+    }
+    table.print();
+}
+
+fn progress_bar(fraction: f64, width: usize) -> String {
+    let filled = (fraction * width as f64).round() as usize;
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+/// Completed tasks with when and how they were resolved, newest first.
+/// Tasks completed before completion times were recorded are listed last.
+pub fn done(tasks: &[Task], from: Option<NaiveDate>, to: Option<NaiveDate>) {
+    let from = from.map(start_of);
+    let to = to.and_then(|d| d.succ_opt()).map(start_of);
+    let mut finished: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| t.completed)
+        .filter(|t| match t.completed_at {
+            Some(at) => from.is_none_or(|f| at >= f) && to.is_none_or(|t| at < t),
+            None => from.is_none() && to.is_none(),
+        })
+        .collect();
+    finished.sort_by_key(|t| std::cmp::Reverse(t.completed_at));
+    if finished.is_empty() {
+        println!("No tasks completed in that period.");
+        return;
+    }
+
+    for task in finished {
+        let when = match task.completed_at {
+            Some(at) => at.with_timezone(&Local).format("%Y-%m-%d").to_string(),
+            None => "unknown".to_owned(),
+        };
+        println!("{:<10}  {:>4}: {}", when, task.id, task.description);
+        if let Some(note) = &task.note {
+            println!("{:<10}        {}", "", note);
+        }
+    }
+}
+
+/// Compares each task's estimate with the time actually tracked on it.
+/// Only completed tasks count unless `all` is set, since an open task's
+/// tracked time is not final yet.
+pub fn accuracy(tasks: &[Task], all: bool) {
+    let rows: Vec<(&Task, u32, u32)> = tasks
+        .iter()
+        .filter(|t| all || t.completed)
+        .filter_map(|t| {
+            let tracked = t.tracked_minutes();
+            t.estimate
+                .filter(|_| tracked > 0)
+                .map(|estimate| (t, estimate, tracked))
+        })
+        .collect();
+    if rows.is_empty() {
+        println!("No tasks have both an estimate and tracked time.");
+        return;
+    }
+
+    let mut table =
+        Table::new(&["ID", "Estimate", "Actual", "Difference", "Task"]).align_right(&[0, 1, 2, 3]);
+    let (mut over, mut under, mut exact) = (0, 0, 0);
+    let mut error = 0.0;
+    for (task, estimate, tracked) in &rows {
+        match tracked.cmp(estimate) {
+            std::cmp::Ordering::Greater => over += 1,
+            std::cmp::Ordering::Less => under += 1,
+            std::cmp::Ordering::Equal => exact += 1,
+        }
+        error += (f64::from(*tracked) - f64::from(*estimate)).abs() / f64::from(*estimate);
+        table.push(vec![
+            task.id.to_string(),
+            duration::format_minutes(*estimate),
+            duration::format_minutes(*tracked),
+            difference(*estimate, *tracked),
+            task.description.clone(),
+        ]);
+    }
+    table.print();
+
+    let estimated: u32 = rows.iter().map(|r| r.1).sum();
+    let tracked: u32 = rows.iter().map(|r| r.2).sum();
+    println!();
+    println!(
+        "Total: estimated {}, tracked {}, {}",
+        duration::format_minutes(estimated),
+        duration::format_minutes(tracked),
+        difference(estimated, tracked)
+    );
+    println!(
+        "{} over estimate, {} under, {} on the dot; estimates were off by {:.0}% on average",
+        over,
+        under,
+        exact,
+        error / rows.len() as f64 * 100.0
+    );
+}
+
+/// How far the actual time strayed from the estimate, e.g. `+30m (+50%)`.
+fn difference(estimate: u32, actual: u32) -> String {
+    let sign = if actual >= estimate { '+' } else { '-' };
+    let delta = actual.abs_diff(estimate);
+    let percent = f64::from(delta) / f64::from(estimate) * 100.0;
+    if delta == 0 {
+        return "0m (0%)".to_owned();
+    }
+    format!(
+        "{}{} ({}{:.0}%)",
+        sign,
+        duration::format_minutes(delta),
+        sign,
+        percent
+    )
+}
+
+/// Sums tracked time per project and task within the query's dates, ready to
+/// paste into an invoice.
+pub fn time(tasks: &[Task], query: &TimeQuery) {
+    let from = query.from.map(start_of);
+    let to = query.to.and_then(|d| d.succ_opt()).map(start_of);
+
+    let mut projects: BTreeMap<Option<&str>, Vec<(&Task, u32)>> = BTreeMap::new();
+    for task in tasks {
+        if query.project.is_some() && task.project != query.project {
+            continue;
+        }
+        let minutes: u32 = task
+            .time_log
+            .iter()
+            .map(|i| i.minutes_within(from, to))
+            .sum();
+        if minutes > 0 {
+            projects
+                .entry(task.project.as_deref())
+                .or_default()
+                .push((task, minutes));
+        }
+    }
+
+    match query.format {
+        Format::Text => print_time_text(&projects, query),
+        Format::Csv => print_time_csv(&projects, query.rate),
+    }
+}
+
+type ByProject<'a> = BTreeMap<Option<&'a str>, Vec<(&'a Task, u32)>>;
+
+fn print_time_text(projects: &ByProject, query: &TimeQuery) {
+    if projects.is_empty() {
+        println!("No time tracked in that period.");
+        return;
+    }
+
+    let mut heading = String::from("Time tracked");
+    if let Some(from) = query.from {
+        heading.push_str(&format!(" from {}", from));
+    }
+    if let Some(to) = query.to {
+        heading.push_str(&format!(" to {}", to));
+    }
+    if let Some(rate) = query.rate {
+        heading.push_str(&format!(" at {:.2}/h", rate));
+    }
+    println!("{}", heading);
+
+    let line = |label: &str, minutes: u32| {
+        let mut text = format!("{:<40} {:>8}", label, duration::format_minutes(minutes));
+        if let Some(rate) = query.rate {
+            text.push_str(&format!(" {:>10.2}", amount(minutes, rate)));
+        }
+        text
+    };
+    let mut total = 0;
+    for (project, rows) in projects {
+        println!();
+        println!("{}", project.unwrap_or("(no project)"));
+        for (task, minutes) in rows {
+            let label = format!("  {:>3}  {}", task.id, task.description);
+            println!("{}", line(&label, *minutes));
+        }
+        let subtotal: u32 = rows.iter().map(|r| r.1).sum();
+        if projects.len() > 1 {
+            println!("{}", line("  Subtotal", subtotal));
+        }
+        total += subtotal;
+    }
+    println!();
+    println!("{}", line("Total", total));
+}
+
+fn print_time_csv(projects: &ByProject, rate: Option<f64>) {
+    let mut header = String::from("project,id,description,minutes,hours");
+    if rate.is_some() {
+        header.push_str(",amount");
+    }
+    println!("{}", header);
+    for (project, rows) in projects {
+        for (task, minutes) in rows {
+            let mut row = format!(
+                "{},{},{},{},{:.2}",
+                export::csv_field(project.unwrap_or_default()),
+                task.id,
+                export::csv_field(&task.description),
+                minutes,
+                f64::from(*minutes) / 60.0
+            );
+            if let Some(rate) = rate {
+                row.push_str(&format!(",{:.2}", amount(*minutes, rate)));
+            }
+            println!("{}", row);
+        }
+    }
+}
+
+fn amount(minutes: u32, rate: f64) -> f64 {
+    f64::from(minutes) / 60.0 * rate
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum WeeklyFormat {
+    /// Markdown, for pasting into notes or converting with pandoc
+    #[default]
+    Md,
+    /// A standalone page laid out for printing or saving as PDF
+    Html,
+}
+
+struct Section<'a> {
+    title: &'static str,
+    tasks: Vec<&'a Task>,
+}
+
+/// Last week's completions against this week's plan, for printing. Weeks
+/// start on Monday; "planned" means open tasks due later this week.
+pub fn weekly(tasks: &[Task], format: WeeklyFormat) -> String {
+    let today = Local::now().date_naive();
+    let monday = today - chrono::Duration::days(today.weekday().num_days_from_monday().into());
+    let last_monday = monday - chrono::Duration::days(7);
+    let sunday = monday + chrono::Duration::days(6);
+    let (from, to) = (start_of(last_monday), start_of(monday));
+
+    let completed: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| t.completed_at.is_some_and(|at| at >= from && at < to) && t.completed)
+        .collect();
+    let open = || tasks.iter().filter(|t| !t.completed && !t.someday);
+    let planned: Vec<&Task> = open()
+        .filter(|t| t.due.is_some_and(|d| d >= today && d <= sunday))
+        .collect();
+    let overdue: Vec<&Task> = open()
+        .filter(|t| t.due.is_some_and(|d| d < today))
+        .collect();
+
+    // (completed, planned, overdue, open) per project.
+    let all_open: Vec<&Task> = open().collect();
+    let mut projects: BTreeMap<&str, [usize; 4]> = BTreeMap::new();
+    for (column, list) in [&completed, &planned, &overdue, &all_open]
+        .iter()
+        .enumerate()
+    {
+        for task in list.iter() {
+            projects.entry(project_name(task)).or_default()[column] += 1;
+        }
+    }
+
+    let title = format!(
+        "Week of {} (last week: {} to {})",
+        monday,
+        last_monday,
+        monday - chrono::Duration::days(1)
+    );
+    let sections = [
+        Section {
+            title: "Completed last week",
+            tasks: completed,
+        },
+        Section {
+            title: "Planned this week",
+            tasks: planned,
+        },
+        Section {
+            title: "Overdue",
+            tasks: overdue,
+        },
+    ];
+    match format {
+        WeeklyFormat::Md => weekly_markdown(&title, &sections, &projects),
+        WeeklyFormat::Html => weekly_html(&title, &sections, &projects),
+    }
+}
+
+fn project_name(task: &Task) -> &str {
+    task.project.as_deref().unwrap_or("(no project)")
+}
+
+fn weekly_item(task: &Task) -> String {
+    let mut extras: Vec<String> = task.project.iter().cloned().collect();
+    if let Some(due) = task.due.filter(|_| !task.completed) {
+        extras.push(format!("due {}", due));
+    }
+    if let Some(at) = task.completed_at.filter(|_| task.completed) {
+        extras.push(format!("done {}", at.with_timezone(&Local).format("%a %d")));
+    }
+    if extras.is_empty() {
+        format!("#{} {}", task.id, task.description)
+    } else {
+        format!("#{} {} ({})", task.id, task.description, extras.join(", "))
+    }
+}
+
+const SUMMARY_HEADERS: [&str; 5] = ["Project", "Completed", "Planned", "Overdue", "Open"];
+
+fn weekly_markdown(
+    title: &str,
+    sections: &[Section],
+    projects: &BTreeMap<&str, [usize; 4]>,
+) -> String {
+    let mut out = format!("# {}\n", title);
+    for section in sections {
+        out.push_str(&format!(
+            "\n## {} ({})\n\n",
+            section.title,
+            section.tasks.len()
+        ));
+        if section.tasks.is_empty() {
+            out.push_str("None.\n");
+        }
+        for task in &section.tasks {
+            let mark = if task.completed { "x" } else { " " };
+            out.push_str(&format!("- [{}] {}\n", mark, weekly_item(task)));
+        }
+    }
+    out.push_str("\n## By project\n\n");
+    out.push_str(&format!("| {} |\n", SUMMARY_HEADERS.join(" | ")));
+    out.push_str("|---|--:|--:|--:|--:|\n");
+    for (name, counts) in projects {
+        let counts: Vec<String> = counts.iter().map(|c| c.to_string()).collect();
+        out.push_str(&format!("| {} | {} |\n", name, counts.join(" | ")));
+    }
+    out
+}
+
+const WEEKLY_STYLE: &str = "
+@page { size: A4; margin: 18mm; }
+body { font: 11pt/1.4 Georgia, serif; color: #000; max-width: 48em; margin: 2em auto; }
+h1 { font-size: 16pt; border-bottom: 2px solid #000; padding-bottom: 4pt; }
+h2 { font-size: 12.5pt; margin: 16pt 0 4pt; break-after: avoid; }
+ul { margin: 0; padding-left: 1.2em; }
+li { break-inside: avoid; }
+.none { color: #666; font-style: italic; }
+table { border-collapse: collapse; width: 100%; break-inside: avoid; }
+th, td { border: 1px solid #999; padding: 2pt 6pt; }
+td.n { text-align: right; }
+@media print { body { margin: 0; max-width: none; } }
+";
+
+fn weekly_html(title: &str, sections: &[Section], projects: &BTreeMap<&str, [usize; 4]>) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{0}</title>\n<style>{1}</style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        export::escape(title),
+        WEEKLY_STYLE
+    );
+    for section in sections {
+        out.push_str(&format!(
+            "<h2>{} ({})</h2>\n",
+            section.title,
+            section.tasks.len()
+        ));
+        if section.tasks.is_empty() {
+            out.push_str("<p class=\"none\">None.</p>\n");
+            continue;
+        }
+        out.push_str("<ul>\n");
+        for task in &section.tasks {
+            out.push_str(&format!(
+                "<li>{}</li>\n",
+                export::escape(&weekly_item(task))
+            ));
+        }
+        out.push_str("</ul>\n");
+    }
+    out.push_str("<h2>By project</h2>\n<table>\n<tr>");
+    for header in SUMMARY_HEADERS {
+        out.push_str(&format!("<th>{}</th>", header));
+    }
+    out.push_str("</tr>\n");
+    for (name, counts) in projects {
+        out.push_str(&format!("<tr><td>{}</td>", export::escape(name)));
+        for count in counts {
+            out.push_str(&format!("<td class=\"n\">{}</td>", count));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
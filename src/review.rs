@@ -0,0 +1,283 @@
+//! The periodic review: everything that needs a decision rather than work,
+//! gathered in one place.
+//!
+//! Reviews can recur, such as a weekly review every Friday at 16:00. They
+//! live beside the tasks file in `reviews.json`. Once one comes around, the
+//! next command run adds a task for it and sends a desktop notification.
+
+use crate::{
+    crdt::Clock,
+    deps, notify,
+    task::{self, Task},
+};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub fn print_review(tasks: &[Task]) {
+    let today = Local::now().date_naive();
+    let open: Vec<&Task> = tasks.iter().filter(|t| !t.completed).collect();
+    let sections: [(&str, Vec<&Task>); 4] = [
+        (
+            "Overdue",
+            open.iter()
+                .copied()
+                .filter(|t| t.due.is_some_and(|d| d < today))
+                .collect(),
+        ),
+        (
+            "Waiting on others",
+            open.iter()
+                .copied()
+                .filter(|t| t.waiting_on.is_some())
+                .collect(),
+        ),
+        (
+            "Blocked",
+            open.iter()
+                .copied()
+                .filter(|t| deps::is_blocked(tasks, t))
+                .collect(),
+        ),
+        (
+            "Someday/maybe",
+            open.iter().copied().filter(|t| t.someday).collect(),
+        ),
+    ];
+
+    let mut shown = false;
+    for (title, section) in sections {
+        if section.is_empty() {
+            continue;
+        }
+        if shown {
+            println!();
+        }
+        println!("{} ({}):", title, section.len());
+        for task in section {
+            println!("  {}", task::format_line(tasks, task));
+        }
+        shown = true;
+    }
+    if !shown {
+        println!("Nothing to review.");
+    }
+    for task in open.iter().filter(|t| review_name(t).is_some()) {
+        println!();
+        println!(
+            "Finish {} with `done {}`.",
+            task.description.to_lowercase(),
+            task.id
+        );
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Recurring {
+    pub name: String,
+    /// `daily`, or the day of the week it falls on, such as `friday`.
+    pub every: String,
+    pub at: NaiveTime,
+    /// When a task was last added for it, or when it was set up.
+    pub last: DateTime<Utc>,
+}
+
+/// Tasks added for a recurring review link back to it by name.
+const LINK: &str = "review:";
+
+fn review_name(task: &Task) -> Option<&str> {
+    task.link.as_deref()?.strip_prefix(LINK)
+}
+
+fn reviews_path(tasks_path: &Path) -> PathBuf {
+    tasks_path.with_file_name("reviews.json")
+}
+
+pub fn load_recurring(tasks_path: &Path) -> anyhow::Result<Vec<Recurring>> {
+    let path = reviews_path(tasks_path);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read reviews at {}", path.display()))?;
+    serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse reviews at {}", path.display()))
+}
+
+pub fn save_recurring(tasks_path: &Path, reviews: &[Recurring]) -> anyhow::Result<()> {
+    let data = serde_json::to_string_pretty(reviews).context("Failed to serialize reviews")?;
+    task::write_atomic(&reviews_path(tasks_path), data.as_bytes())
+}
+
+/// Accepts `daily` or a day of the week, full or abbreviated.
+pub fn parse_every(input: &str) -> anyhow::Result<String> {
+    let input = input.trim().to_lowercase();
+    if input == "daily" {
+        return Ok(input);
+    }
+    match input.parse::<Weekday>() {
+        Ok(day) => Ok(weekday_name(day).to_owned()),
+        Err(_) => bail!(
+            "Invalid day '{}' (use daily or a day of the week, e.g. friday)",
+            input
+        ),
+    }
+}
+
+fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+fn falls_on(review: &Recurring, day: NaiveDate) -> bool {
+    review.every == "daily" || review.every == weekday_name(day.weekday())
+}
+
+fn local(day: NaiveDate, at: NaiveTime) -> DateTime<Utc> {
+    let time = day.and_time(at);
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| time.and_utc())
+}
+
+/// The most recent time the review came around, up to `now`.
+fn latest(review: &Recurring, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let today = now.with_timezone(&Local).date_naive();
+    (0..8)
+        .map(|back| today - chrono::Duration::days(back))
+        .filter(|d| falls_on(review, *d))
+        .map(|d| local(d, review.at))
+        .find(|at| *at <= now)
+}
+
+/// The next time the review comes around after `now`.
+fn upcoming(review: &Recurring, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let today = now.with_timezone(&Local).date_naive();
+    (0..8)
+        .map(|ahead| today + chrono::Duration::days(ahead))
+        .filter(|d| falls_on(review, *d))
+        .map(|d| local(d, review.at))
+        .find(|at| *at > now)
+}
+
+pub fn add_recurring(
+    reviews: &mut Vec<Recurring>,
+    name: &str,
+    every: String,
+    at: NaiveTime,
+) -> anyhow::Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("Review name cannot be empty");
+    }
+    if reviews.iter().any(|r| r.name.eq_ignore_ascii_case(name)) {
+        bail!("A review named {} already exists", name);
+    }
+    reviews.push(Recurring {
+        name: name.to_owned(),
+        every,
+        at,
+        last: Utc::now(),
+    });
+    Ok(())
+}
+
+pub fn remove_recurring(reviews: &mut Vec<Recurring>, name: &str) -> anyhow::Result<()> {
+    let before = reviews.len();
+    reviews.retain(|r| !r.name.eq_ignore_ascii_case(name.trim()));
+    if reviews.len() == before {
+        bail!("No review named {}", name);
+    }
+    Ok(())
+}
+
+pub fn print_recurring(reviews: &[Recurring]) {
+    if reviews.is_empty() {
+        println!("No recurring reviews (add one with `review every`).");
+        return;
+    }
+    let now = Utc::now();
+    for review in reviews {
+        let next = upcoming(review, now)
+            .map(|at| {
+                at.with_timezone(&Local)
+                    .format(" (next %a %Y-%m-%d %H:%M)")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        println!(
+            "{}: {} at {}{}",
+            review.name,
+            review.every,
+            review.at.format("%H:%M"),
+            next
+        );
+    }
+}
+
+/// Whether a recurring review has come around since it last did, so that
+/// `trigger` has something to do.
+pub fn pending(tasks_path: &Path) -> anyhow::Result<bool> {
+    let now = Utc::now();
+    Ok(load_recurring(tasks_path)?
+        .iter()
+        .any(|review| latest(review, now).is_some_and(|at| at > review.last)))
+}
+
+/// Adds a task for each recurring review that has come around since it last
+/// did, unless one from before is still open, and sends a notification.
+/// Returns whether any task was added.
+pub fn trigger(tasks_path: &Path, tasks: &mut Vec<Task>) -> anyhow::Result<bool> {
+    let mut reviews = load_recurring(tasks_path)?;
+    let now = Utc::now();
+    let due: Vec<usize> = (0..reviews.len())
+        .filter(|&i| latest(&reviews[i], now).is_some_and(|at| at > reviews[i].last))
+        .collect();
+    if due.is_empty() {
+        return Ok(false);
+    }
+
+    let clock = Clock::load(tasks_path, tasks)?;
+    let mut added = false;
+    for i in due {
+        let review = &mut reviews[i];
+        review.last = now;
+        let open = tasks
+            .iter()
+            .any(|t| !t.completed && review_name(t) == Some(review.name.as_str()));
+        if open {
+            continue;
+        }
+        let details = task::NewTask {
+            due: Some(now.with_timezone(&Local).date_naive()),
+            ..Default::default()
+        };
+        let id = task::add_task(tasks, review.name.clone(), details, &clock)?;
+        let task = task::find_task_mut(tasks, id)?;
+        task.link = Some(format!("{}{}", LINK, review.name));
+        task.touch("link", &clock);
+        eprintln!(
+            "Time for {}: added task {}. Run `review` to go through it.",
+            review.name.to_lowercase(),
+            id
+        );
+        // The task is the record; a missing notifier should not stop anything.
+        let _ = notify::send(&review.name, "Run `review` to go through it.");
+        added = true;
+    }
+    save_recurring(tasks_path, &reviews)?;
+    Ok(added)
+}
@@ -0,0 +1,206 @@
+//! Tasks mirrored from `TODO` and `FIXME` comments in source code. Each
+//! comment becomes a task that remembers where it was found; scanning again
+//! follows the comment as lines move, and completes the task once the
+//! comment is gone.
+//!
+//! A comment is recognised by its text and file, so editing the text reads
+//! as the old comment being removed and a new one added. Files are recorded
+//! relative to the working directory, so scan from the same place (usually
+//! the repository root) each time.
+
+use crate::{
+    crdt::Clock,
+    task::{self, Task},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+};
+
+const MARKERS: [&str; 2] = ["TODO", "FIXME"];
+const COMMENT_STARTS: [&str; 6] = ["//", "#", "/*", "--", ";", "<!--"];
+/// Build output and dependencies, not code anyone writes TODOs in.
+const SKIPPED_DIRS: [&str; 2] = ["target", "node_modules"];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+struct Found {
+    location: Location,
+    description: String,
+}
+
+/// Drops `.` components so `./src/a.rs` and `src/a.rs` compare equal.
+fn clean(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+fn walk(dir: &Path, found: &mut Vec<Found>, files: &mut usize) -> anyhow::Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_ref()) {
+                walk(&path, found, files)?;
+            }
+            continue;
+        }
+        // Binary and other non-UTF-8 files have no comments to find.
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        *files += 1;
+        let file = clean(&path).to_string_lossy().into_owned();
+        for (n, line) in text.lines().enumerate() {
+            if let Some(description) = comment(line) {
+                found.push(Found {
+                    location: Location {
+                        file: file.clone(),
+                        line: n + 1,
+                    },
+                    description,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The task description for a `TODO`/`FIXME` comment on this line, such
+/// as `FIXME: handle empty input` for `// FIXME(sam): handle empty input`.
+fn comment(line: &str) -> Option<String> {
+    for marker in MARKERS {
+        for (at, _) in line.match_indices(marker) {
+            let before = &line[..at];
+            let after = &line[at + marker.len()..];
+            // Only the marker as its own word, which also skips mentions in
+            // prose such as `TODO` in backticks.
+            let starts =
+                before.is_empty() || before.ends_with([' ', '\t', '/', '*', '#', '-', ';', '!']);
+            let ends = after.is_empty() || after.starts_with([':', '(', ' ', '\t']);
+            if !starts || !ends {
+                continue;
+            }
+            let in_comment =
+                COMMENT_STARTS.iter().any(|s| before.contains(s)) || before.trim().starts_with('*');
+            if !in_comment {
+                continue;
+            }
+
+            let mut text = after;
+            if text.starts_with('(') {
+                text = text.split_once(')').map_or("", |(_, rest)| rest);
+            }
+            let text = text
+                .trim_start_matches([':', '-', ' ', '\t'])
+                .trim_end()
+                .trim_end_matches("*/")
+                .trim_end_matches("-->")
+                .trim();
+            return Some(if text.is_empty() {
+                marker.to_owned()
+            } else {
+                format!("{}: {}", marker, text)
+            });
+        }
+    }
+    None
+}
+
+/// Brings the tasks in line with the comments under `root`, printing what
+/// changed. Returns whether anything did.
+pub fn scan(tasks: &mut Vec<Task>, root: &Path, clock: &Clock) -> anyhow::Result<bool> {
+    let mut found = Vec::new();
+    let mut files = 0;
+    walk(root, &mut found, &mut files)?;
+    let root = clean(root);
+
+    let (mut added, mut moved, mut closed) = (0, 0, 0);
+    let mut changed = false;
+    let mut claimed: Vec<u32> = Vec::new();
+    for item in found {
+        // Prefer an open task. One closed by hand while its comment is still
+        // there stays closed instead of coming back on every scan.
+        let existing = tasks
+            .iter()
+            .filter(|t| !claimed.contains(&t.id) && t.description == item.description)
+            .filter(|t| {
+                t.source
+                    .as_ref()
+                    .is_some_and(|s| s.file == item.location.file)
+            })
+            .min_by_key(|t| t.completed)
+            .map(|t| t.id);
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = task::add_task(
+                    tasks,
+                    item.description.clone(),
+                    task::NewTask::default(),
+                    clock,
+                )?;
+                println!("Added {}: {} ({})", id, item.description, item.location);
+                added += 1;
+                id
+            }
+        };
+        claimed.push(id);
+
+        let task = task::find_task_mut(tasks, id)?;
+        if task.source.as_ref() != Some(&item.location) {
+            if task.source.is_some() && !task.completed {
+                moved += 1;
+            }
+            task.source = Some(item.location);
+            task.touch("source", clock);
+            changed = true;
+        }
+    }
+
+    let gone: Vec<u32> = tasks
+        .iter()
+        .filter(|t| !t.completed && !claimed.contains(&t.id))
+        .filter(|t| {
+            t.source
+                .as_ref()
+                .is_some_and(|s| Path::new(&s.file).starts_with(&root))
+        })
+        .map(|t| t.id)
+        .collect();
+    for id in gone {
+        let task = task::find_task_mut(tasks, id)?;
+        let note = format!(
+            "Comment removed from {}",
+            task.source.as_ref().expect("filtered on source")
+        );
+        println!("Closed {}: {}", id, task.description);
+        task::mark_done(tasks, id, Some(note), clock)?;
+        closed += 1;
+    }
+
+    println!(
+        "Scanned {} file(s): {} added, {} moved, {} closed.",
+        files, added, moved, closed
+    );
+    Ok(changed || closed > 0)
+}
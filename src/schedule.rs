@@ -0,0 +1,225 @@
+//! Suggesting a day to work on each open task. Tasks are taken in `plan`
+//! order and packed into days from today onwards, so that no day holds more
+//! estimated work than the daily capacity and nothing lands before the
+//! tasks it depends on.
+//!
+//! A task is planned for the day it is scheduled for, or else the day it is
+//! due; `today` and `add` warn when a day holds more than its working hours.
+
+use crate::{
+    config::WorkingHours,
+    crdt::Clock,
+    duration, plan,
+    task::{self, Layout, Task},
+};
+use chrono::{Duration, Local, NaiveDate};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Where one task landed.
+pub struct Slot {
+    pub uuid: Uuid,
+    pub day: NaiveDate,
+    /// The last day the task's work reaches; later than `day` only for tasks
+    /// estimated at more than a day's capacity.
+    pub until: NaiveDate,
+}
+
+pub struct Schedule {
+    pub slots: Vec<Slot>,
+    /// Minutes of estimated work planned for each day.
+    pub load: BTreeMap<NaiveDate, u32>,
+}
+
+/// Works out a day for every open task that is not parked for someday.
+/// Tasks without an estimate take up no capacity. Days with no capacity are
+/// skipped.
+pub fn schedule(
+    tasks: &[Task],
+    capacity: &dyn Fn(NaiveDate) -> u32,
+    from: NaiveDate,
+) -> anyhow::Result<Schedule> {
+    let mut used: BTreeMap<NaiveDate, u32> = BTreeMap::new();
+    let mut slots: Vec<Slot> = Vec::new();
+    for step in plan::order(tasks)? {
+        let task = step.task;
+        if task.someday {
+            continue;
+        }
+        // Work starts no earlier than the day the last dependency finishes.
+        let mut day = slots
+            .iter()
+            .filter(|s| task.depends_on.contains(&s.uuid))
+            .map(|s| s.until)
+            .max()
+            .unwrap_or(from)
+            .max(from);
+        let mut left = task.estimate.unwrap_or(0);
+        loop {
+            let room = capacity(day);
+            let free = room.saturating_sub(used.get(&day).copied().unwrap_or(0));
+            // Work longer than a day starts on an empty day and runs on.
+            if room > 0 && (left <= free || free == room) {
+                break;
+            }
+            day += Duration::days(1);
+        }
+        let start = day;
+        loop {
+            let today = used.entry(day).or_default();
+            let take = left.min(capacity(day).saturating_sub(*today));
+            *today += take;
+            left -= take;
+            if left == 0 {
+                break;
+            }
+            day += Duration::days(1);
+        }
+        slots.push(Slot {
+            uuid: task.uuid,
+            day: start,
+            until: day,
+        });
+    }
+    Ok(Schedule { slots, load: used })
+}
+
+/// Schedules the open tasks, prints the result day by day, and unless
+/// `dry_run` stores each task's day in its `scheduled` field. Returns
+/// whether any task changed.
+pub fn run(
+    tasks: &mut [Task],
+    capacity: &dyn Fn(NaiveDate) -> u32,
+    dry_run: bool,
+    clock: &Clock,
+) -> anyhow::Result<bool> {
+    let today = Local::now().date_naive();
+    let Schedule { slots, load } = schedule(tasks, capacity, today)?;
+    if slots.is_empty() {
+        println!("No open tasks to schedule.");
+        return Ok(false);
+    }
+
+    let by_uuid = |uuid: Uuid| tasks.iter().find(|t| t.uuid == uuid).expect("scheduled");
+    let mut days: BTreeMap<NaiveDate, Vec<&Slot>> = BTreeMap::new();
+    for slot in &slots {
+        for day in slot.day.iter_days().take_while(|d| *d <= slot.until) {
+            days.entry(day).or_default().push(slot);
+        }
+    }
+    for (day, slots) in &days {
+        println!("{}", day.format("%a %Y-%m-%d"));
+        for slot in slots {
+            let task = by_uuid(slot.uuid);
+            let mut line = format!("  {}: {}", task.id, task.description);
+            if slot.day < *day {
+                line.push_str(" (continued)");
+                println!("{}", line);
+                continue;
+            }
+            if let Some(estimate) = task.estimate {
+                line.push_str(&format!(" ({})", duration::format_minutes(estimate)));
+            }
+            if slot.until > slot.day {
+                line.push_str(&format!(" through {}", slot.until));
+            }
+            println!("{}", line);
+        }
+        if let Some(&work) = load.get(day).filter(|w| **w > 0) {
+            println!(
+                "  {} of {} planned",
+                duration::format_minutes(work),
+                duration::format_minutes(capacity(*day))
+            );
+        }
+    }
+    for slot in &slots {
+        let task = by_uuid(slot.uuid);
+        if let Some(due) = task.due.filter(|due| slot.until > *due) {
+            eprintln!(
+                "Warning: task {} runs until {}, after it is due on {}",
+                task.id, slot.until, due
+            );
+        }
+    }
+
+    if dry_run {
+        return Ok(false);
+    }
+    let mut changed = 0;
+    for slot in &slots {
+        let task = tasks
+            .iter_mut()
+            .find(|t| t.uuid == slot.uuid)
+            .expect("scheduled");
+        if task.scheduled != Some(slot.day) {
+            task.scheduled = Some(slot.day);
+            task.touch("scheduled", clock);
+            changed += 1;
+        }
+    }
+    println!("Scheduled {} task(s), {} changed.", slots.len(), changed);
+    Ok(changed > 0)
+}
+
+/// The day a task is expected to be worked on: the day it is scheduled for,
+/// or else the day it is due.
+fn planned_day(task: &Task) -> Option<NaiveDate> {
+    task.scheduled.or(task.due)
+}
+
+/// Open tasks planned for `day`. For today, anything planned for an earlier
+/// day and still open is carried over.
+pub fn planned_for(tasks: &[Task], day: NaiveDate) -> Vec<&Task> {
+    let today = Local::now().date_naive();
+    tasks
+        .iter()
+        .filter(|t| !t.completed && !t.someday)
+        .filter(|t| planned_day(t).is_some_and(|d| d == day || (day == today && d < today)))
+        .collect()
+}
+
+/// Warns when the estimates of the tasks planned for `day` add up to more
+/// than fits in it.
+pub fn warn_overcommit(tasks: &[Task], day: NaiveDate, hours: &WorkingHours) {
+    let planned: u32 = planned_for(tasks, day)
+        .iter()
+        .filter_map(|t| t.estimate)
+        .sum();
+    let room = hours.minutes(day);
+    if planned > room {
+        eprintln!(
+            "Warning: {} of work is planned for {}, over the {} of working hours",
+            duration::format_minutes(planned),
+            day,
+            duration::format_minutes(room)
+        );
+    }
+}
+
+/// Lists what is planned for today and how it compares with the working
+/// hours.
+pub fn print_today(tasks: &[Task], hours: &WorkingHours, layout: &Layout) {
+    let today = Local::now().date_naive();
+    let planned = planned_for(tasks, today);
+    match layout {
+        Layout::Template(template) => {
+            for task in &planned {
+                println!("{}", template.render(tasks, task));
+            }
+            return;
+        }
+        _ if planned.is_empty() => {
+            println!("Nothing planned for today.");
+            return;
+        }
+        Layout::Table(columns) => task::print_table(tasks, &planned, columns),
+    }
+    let work: u32 = planned.iter().filter_map(|t| t.estimate).sum();
+    println!(
+        "Planned: {} of {}",
+        duration::format_minutes(work),
+        duration::format_minutes(hours.minutes(today))
+    );
+    warn_overcommit(tasks, today, hours);
+}
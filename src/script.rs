@@ -0,0 +1,243 @@
+//! User scripts in the `scripts` folder of the config directory, which
+//! extend the CLI without rebuilding it. Scripts are written in Rhai
+//! (<https://rhai.rs>) and run inside this program, so nothing else needs
+//! installing. A script named `weekly-invoices.rhai` becomes
+//! `cli_task_manager weekly-invoices [args...]`, and can also narrow
+//! `list` with `list --script <name>`.
+//!
+//! A script sees:
+//!
+//! - `tasks`: every task, as a map of its JSON fields, with secrets masked
+//!   as for any other output
+//! - `ARGS`: the words after the command's name
+//! - `filter(expr)`: the tasks a `--filter` expression matches, e.g.
+//!   `filter("+finance status:open")`
+//! - `print(text)`: a line of output, which makes a script a report format
+//!
+//! A `list --script` filter defines `fn keep(task)` instead, returning
+//! whether `list` shows the task. Scripts only read tasks; changes go
+//! through the commands.
+
+use crate::{filter::Filter, task::Task};
+use anyhow::{Context, anyhow, bail};
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Scope};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+const EXTENSION: &str = "rhai";
+
+fn scripts_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("scripts")
+}
+
+/// The script called `name`, with or without its extension.
+fn find(config_dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let dir = scripts_dir(config_dir);
+    if !name.contains(['/', '\\']) {
+        let stem = name.strip_suffix(".rhai").unwrap_or(name);
+        let path = dir.join(format!("{}.{}", stem, EXTENSION));
+        if path.is_file() {
+            return Ok(path);
+        }
+    }
+    bail!(
+        "Unknown command {} (and no script of that name in {})",
+        name,
+        dir.display()
+    );
+}
+
+fn to_dynamic(task: &Task) -> Result<Dynamic, Box<EvalAltResult>> {
+    rhai::serde::to_dynamic(task)
+}
+
+/// The script at `path` compiled, with an engine that offers `filter` over
+/// `tasks`, and `tasks` and `ARGS` in scope.
+fn load(
+    path: &Path,
+    tasks: &[Task],
+    args: &[String],
+) -> anyhow::Result<(Engine, AST, Scope<'static>)> {
+    let source =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut engine = Engine::new();
+    let all = Rc::new(tasks.to_vec());
+    engine.register_fn(
+        "filter",
+        move |expr: &str| -> Result<Array, Box<EvalAltResult>> {
+            let filter: Filter = expr
+                .parse()
+                .map_err(|err| format!("Bad filter {:?}: {}", expr, err))?;
+            all.iter()
+                .filter(|task| filter.matches(&all, task))
+                .map(to_dynamic)
+                .collect()
+        },
+    );
+    let ast = engine
+        .compile(&source)
+        .map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+    let shown: Array = tasks
+        .iter()
+        .map(to_dynamic)
+        .collect::<Result<_, _>>()
+        .map_err(|err| anyhow!("Failed to pass tasks to {}: {}", path.display(), err))?;
+    let mut scope = Scope::new();
+    scope.push_constant("tasks", shown);
+    scope.push_constant(
+        "ARGS",
+        args.iter().cloned().map(Dynamic::from).collect::<Array>(),
+    );
+    Ok((engine, ast, scope))
+}
+
+fn failed(path: &Path, err: Box<EvalAltResult>) -> anyhow::Error {
+    anyhow!("{} failed: {}", path.display(), err)
+}
+
+/// Runs the script a custom command names, as `[name, args...]`.
+pub fn command(config_dir: &Path, tasks: &[Task], words: &[String]) -> anyhow::Result<()> {
+    let Some((name, args)) = words.split_first() else {
+        bail!("No command given");
+    };
+    let path = find(config_dir, name)?;
+    let (engine, ast, mut scope) = load(&path, tasks, args)?;
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|err| failed(&path, err))
+}
+
+/// The ids of the tasks a filter script keeps out of `tasks`.
+pub fn filter(config_dir: &Path, tasks: &[Task], name: &str) -> anyhow::Result<HashSet<u32>> {
+    let path = find(config_dir, name)?;
+    let (engine, ast, mut scope) = load(&path, tasks, &[])?;
+    if !ast
+        .iter_functions()
+        .any(|f| f.name == "keep" && f.params.len() == 1)
+    {
+        bail!("{} defines no keep(task) function", path.display());
+    }
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|err| failed(&path, err))?;
+    let mut kept = HashSet::new();
+    for task in tasks {
+        let keep = engine
+            .call_fn_with_options::<bool>(
+                CallFnOptions::new().eval_ast(false),
+                &mut scope,
+                &ast,
+                "keep",
+                (to_dynamic(task).map_err(|err| failed(&path, err))?,),
+            )
+            .map_err(|err| failed(&path, err))?;
+        if keep {
+            kept.insert(task.id);
+        }
+    }
+    Ok(kept)
+}
+
+/// Prints the scripts available as commands.
+pub fn print_scripts(config_dir: &Path) -> anyhow::Result<()> {
+    let dir = scripts_dir(config_dir);
+    let mut names: Vec<String> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|x| x == EXTENSION))
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .collect(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", dir.display()));
+        }
+    };
+    if names.is_empty() {
+        println!("No scripts in {}.", dir.display());
+        return Ok(());
+    }
+    names.sort();
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(id: u32, description: &str, priority: Option<&str>) -> Task {
+        serde_json::from_value(json!({
+            "id": id,
+            "uuid": format!("00000000-0000-4000-8000-{:012}", id),
+            "description": description,
+            "completed": false,
+            "priority": priority,
+        }))
+        .unwrap()
+    }
+
+    /// A config directory holding `scripts/<name>.rhai` with `source`.
+    fn config_dir(test: &str, name: &str, source: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cli_task_manager-script-{}-{}",
+            test,
+            std::process::id()
+        ));
+        fs::create_dir_all(scripts_dir(&dir)).unwrap();
+        fs::write(scripts_dir(&dir).join(format!("{}.rhai", name)), source).unwrap();
+        dir
+    }
+
+    #[test]
+    fn keep_picks_tasks_for_list() {
+        let dir = config_dir(
+            "keep",
+            "urgent",
+            r#"fn keep(task) { task.priority == "high" || task.description.contains("call") }"#,
+        );
+        let tasks = [
+            task(1, "write report", Some("high")),
+            task(2, "call bank", None),
+            task(3, "water plants", Some("low")),
+        ];
+        let kept = filter(&dir, &tasks, "urgent").unwrap();
+        assert_eq!(kept, HashSet::from([1, 2]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn commands_see_args_and_filters() {
+        let dir = config_dir(
+            "command",
+            "check",
+            r#"
+                if ARGS != ["a", "b"] { throw "args"; }
+                if tasks.len() != 2 { throw "tasks"; }
+                let high = filter("priority:high");
+                if high.len() != 1 || high[0].id != 1 { throw "filter"; }
+            "#,
+        );
+        let tasks = [task(1, "one", Some("high")), task(2, "two", None)];
+        let words = ["check.rhai", "a", "b"].map(String::from);
+        command(&dir, &tasks, &words).unwrap();
+        assert!(command(&dir, &tasks[..1], &words).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filters_need_keep() {
+        let dir = config_dir("nokeep", "plain", "print(1);");
+        let err = filter(&dir, &[], "plain").unwrap_err();
+        assert!(err.to_string().contains("keep(task)"));
+        assert!(filter(&dir, &[], "missing").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
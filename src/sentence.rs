@@ -0,0 +1,491 @@
+//! `do`: a command written as a plain sentence, read into the `add`, `done`,
+//! or `list` it means, which then runs as if typed:
+//!
+//! ```text
+//! do "remind me to renew passport next month, high priority"
+//!   -> add --due 2026-11-14 --priority high -- "renew passport"
+//! do "finished the plumber call"     -> done 12
+//! do "what's due this week for project home" -> list --filter "+WEEK project:home"
+//! ```
+//!
+//! Dates are any that `--due` takes (see `date`), such as `friday` or `in 3
+//! days`, each with an optional `on`, `by`, or `due` before it.
+//! Priorities are `high priority`, `priority low`, `urgent`, or `asap`;
+//! projects `project home`, `for project home`, or `project:home`; tags
+//! `+name` or `#name`; estimates `~30m` or `takes 2h`. A sentence that does
+//! not start by asking for a list or saying something is done adds a task.
+
+use crate::{
+    date, duration,
+    task::{self, Priority, Task},
+};
+use anyhow::bail;
+use chrono::{Local, NaiveDate};
+use clap::ValueEnum;
+
+/// Openings that make the sentence an `add`, dropped from the description.
+const ADD: &[&[&str]] = &[
+    &["remind", "me", "to"],
+    &["remember", "to"],
+    &["add", "a", "task", "to"],
+    &["add", "a", "task"],
+    &["add", "task"],
+    &["add"],
+    &["new", "task"],
+    &["i", "need", "to"],
+    &["i", "have", "to"],
+    &["i", "must"],
+    &["need", "to"],
+    &["todo"],
+];
+
+const DONE: &[&[&str]] = &[
+    &["i", "have", "finished"],
+    &["i", "finished"],
+    &["i", "did"],
+    &["i", "completed"],
+    &["mark"],
+    &["tick", "off"],
+    &["finished"],
+    &["finish"],
+    &["completed"],
+    &["complete"],
+    &["done"],
+];
+
+/// Endings of `mark ... done`.
+const DONE_ENDINGS: &[&[&str]] = &[
+    &["as", "done"],
+    &["as", "complete"],
+    &["as", "completed"],
+    &["done"],
+    &["complete"],
+    &["completed"],
+];
+
+const LIST: &[&[&str]] = &[
+    &["what", "is"],
+    &["what's"],
+    &["whats"],
+    &["what"],
+    &["which"],
+    &["show", "me"],
+    &["show"],
+    &["list"],
+];
+
+/// Words a question for a list is padded with, which filter nothing.
+const FILLER: &[&str] = &[
+    "a", "all", "am", "any", "are", "do", "due", "for", "have", "i", "in", "is", "me", "my", "of",
+    "on", "open", "task", "tasks", "the", "there", "to", "todo", "todos",
+];
+
+/// Words before a date that only say it is one.
+const DATE_PREFIXES: &[&str] = &["on", "by", "due", "before", "until"];
+
+struct Words {
+    original: Vec<String>,
+    /// Lowercased, without the punctuation around them.
+    lower: Vec<String>,
+    used: Vec<bool>,
+}
+
+impl Words {
+    fn new(text: &str) -> Self {
+        let original: Vec<String> = text.split_whitespace().map(str::to_owned).collect();
+        let lower = original
+            .iter()
+            .map(|w| {
+                w.trim_matches(|c: char| matches!(c, ',' | '.' | ';' | ':' | '!' | '?' | '"'))
+                    .to_lowercase()
+            })
+            .collect();
+        let used = vec![false; original.len()];
+        Self {
+            original,
+            lower,
+            used,
+        }
+    }
+
+    fn matches_at(&self, at: usize, phrase: &[&str]) -> bool {
+        at + phrase.len() <= self.lower.len()
+            && phrase
+                .iter()
+                .enumerate()
+                .all(|(i, word)| !self.used[at + i] && self.lower[at + i] == *word)
+    }
+
+    /// Takes the first of `phrases` the sentence starts with.
+    fn take_opening(&mut self, phrases: &[&[&str]]) -> bool {
+        let start = self.used.iter().position(|used| !used).unwrap_or(0);
+        self.take_phrase_at(start, phrases)
+    }
+
+    /// Takes the first of `phrases` the sentence ends with.
+    fn take_ending(&mut self, phrases: &[&[&str]]) -> bool {
+        let end = self
+            .used
+            .iter()
+            .rposition(|used| !used)
+            .map_or(0, |i| i + 1);
+        phrases.iter().any(|phrase| {
+            end >= phrase.len() && {
+                let at = end - phrase.len();
+                let matched = self.matches_at(at, phrase);
+                if matched {
+                    self.take(at, phrase.len());
+                }
+                matched
+            }
+        })
+    }
+
+    fn take_phrase_at(&mut self, at: usize, phrases: &[&[&str]]) -> bool {
+        for phrase in phrases {
+            if self.matches_at(at, phrase) {
+                self.take(at, phrase.len());
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Takes the first place `phrase` appears, returning where it was.
+    fn take_phrase(&mut self, phrase: &[&str]) -> Option<usize> {
+        let at = (0..self.lower.len()).find(|&at| self.matches_at(at, phrase))?;
+        self.take(at, phrase.len());
+        Some(at)
+    }
+
+    fn take(&mut self, at: usize, len: usize) {
+        for used in &mut self.used[at..at + len] {
+            *used = true;
+        }
+    }
+
+    /// Takes the word before `at` if it is one of `words`.
+    fn take_before(&mut self, at: usize, words: &[&str]) {
+        if at > 0 && !self.used[at - 1] && words.contains(&self.lower[at - 1].as_str()) {
+            self.used[at - 1] = true;
+        }
+    }
+
+    /// The words `f` picks out, taking each.
+    fn take_each<T>(&mut self, mut f: impl FnMut(&str) -> Option<T>) -> Vec<T> {
+        let mut found = Vec::new();
+        for at in 0..self.lower.len() {
+            if !self.used[at]
+                && let Some(value) = f(&self.lower[at])
+            {
+                self.used[at] = true;
+                found.push(value);
+            }
+        }
+        found
+    }
+
+    fn take_date(&mut self, today: NaiveDate) -> Option<NaiveDate> {
+        for at in 0..self.lower.len() {
+            if self.used[at] {
+                continue;
+            }
+            let end = self.used[at..]
+                .iter()
+                .position(|used| *used)
+                .map_or(self.lower.len(), |i| at + i);
+            if let Some((date, len)) = date::at(&self.lower[at..end], today) {
+                self.take(at, len);
+                self.take_before(at, DATE_PREFIXES);
+                return Some(date);
+            }
+        }
+        None
+    }
+
+    fn take_priority(&mut self) -> Option<Priority> {
+        for name in ["low", "medium", "high", "urgent"] {
+            let priority = Priority::from_str(name, true).ok()?;
+            if self.take_phrase(&[name, "priority"]).is_some()
+                || self.take_phrase(&["priority", name]).is_some()
+                || self.take_phrase(&[&format!("priority:{}", name)]).is_some()
+            {
+                return Some(priority);
+            }
+        }
+        for (word, priority) in [
+            ("urgent", Priority::Urgent),
+            ("urgently", Priority::Urgent),
+            ("asap", Priority::Urgent),
+            ("important", Priority::High),
+        ] {
+            if self.take_phrase(&[word]).is_some() {
+                return Some(priority);
+            }
+        }
+        None
+    }
+
+    fn take_project(&mut self) -> Option<String> {
+        for at in 0..self.lower.len() {
+            if self.used[at] {
+                continue;
+            }
+            if let Some(name) = self.lower[at].strip_prefix("project:")
+                && !name.is_empty()
+            {
+                let name = name.to_owned();
+                self.take(at, 1);
+                return Some(name);
+            }
+            if self.lower[at] == "project" && at + 1 < self.lower.len() && !self.used[at + 1] {
+                let name = self.lower[at + 1].clone();
+                self.take(at, 2);
+                self.take_before(at, &["for", "in", "to", "under"]);
+                return Some(name);
+            }
+        }
+        None
+    }
+
+    fn take_estimate(&mut self) -> Option<u32> {
+        for at in 0..self.lower.len() {
+            if self.used[at] {
+                continue;
+            }
+            if let Some(text) = self.lower[at].strip_prefix('~')
+                && let Ok(minutes) = duration::parse_minutes(text)
+            {
+                self.take(at, 1);
+                return Some(minutes);
+            }
+            if ["takes", "about"].contains(&self.lower[at].as_str())
+                && at + 1 < self.lower.len()
+                && !self.used[at + 1]
+                && let Ok(minutes) = duration::parse_minutes(&self.lower[at + 1])
+            {
+                self.take(at, 2);
+                return Some(minutes);
+            }
+        }
+        None
+    }
+
+    /// What is left, as typed, with the punctuation that led into a part
+    /// taken away trimmed.
+    fn rest(&self) -> String {
+        let kept: Vec<usize> = (0..self.original.len())
+            .filter(|&i| !self.used[i])
+            .collect();
+        let words: Vec<&str> = kept
+            .iter()
+            .enumerate()
+            .map(|(n, &i)| {
+                let word = self.original[i].as_str();
+                let next_taken = kept.get(n + 1).is_none_or(|&next| next != i + 1);
+                if next_taken {
+                    word.trim_end_matches([',', ';', ':', '.', '!'])
+                } else {
+                    word
+                }
+            })
+            .filter(|word| !word.is_empty())
+            .collect();
+        words.join(" ")
+    }
+
+    fn rest_lower(&self) -> Vec<&str> {
+        (0..self.lower.len())
+            .filter(|&i| !self.used[i] && !self.lower[i].is_empty())
+            .map(|i| self.lower[i].as_str())
+            .collect()
+    }
+}
+
+fn tag(word: &str) -> Option<String> {
+    let name = word.strip_prefix('+').or_else(|| word.strip_prefix('#'))?;
+    task::parse_tag(name).ok()
+}
+
+/// The arguments `text` stands for, such as `["add", "--due", ...]`.
+pub fn translate(text: &str, tasks: &[Task]) -> anyhow::Result<Vec<String>> {
+    let mut words = Words::new(text);
+    if words.original.is_empty() {
+        bail!("Say what to do, e.g. do \"remind me to call Ann tomorrow\"");
+    }
+    let today = Local::now().date_naive();
+    if words.take_opening(LIST) {
+        return Ok(list(words, today));
+    }
+    if words.take_opening(DONE) {
+        words.take_ending(DONE_ENDINGS);
+        return done(words, tasks);
+    }
+    words.take_opening(ADD);
+
+    let mut args = vec!["add".to_owned()];
+    if let Some(due) = words.take_date(today) {
+        args.extend(["--due".to_owned(), due.to_string()]);
+    }
+    if let Some(priority) = words.take_priority() {
+        args.extend(["--priority".to_owned(), priority.name().to_owned()]);
+    }
+    if let Some(project) = words.take_project() {
+        args.extend(["--project".to_owned(), project]);
+    }
+    if let Some(minutes) = words.take_estimate() {
+        args.extend(["--estimate".to_owned(), format!("{}m", minutes)]);
+    }
+    for tag in words.take_each(tag) {
+        args.extend(["--tag".to_owned(), tag]);
+    }
+    let description = words.rest();
+    let description = description.strip_prefix("to ").unwrap_or(&description);
+    if description.is_empty() {
+        bail!("Found nothing to add in {:?}", text);
+    }
+    args.extend(["--".to_owned(), description.to_owned()]);
+    Ok(args)
+}
+
+/// `done` for the task named by id, or by the one open task whose
+/// description has every word left as a whole word.
+fn done(words: Words, tasks: &[Task]) -> anyhow::Result<Vec<String>> {
+    let rest: Vec<&str> = words
+        .rest_lower()
+        .into_iter()
+        .filter(|w| !["the", "task", "my", "a", "an", "with"].contains(w))
+        .collect();
+    if let [id] = rest.as_slice()
+        && let Ok(id) = id.trim_start_matches('#').parse::<u32>()
+    {
+        return Ok(vec!["done".to_owned(), id.to_string()]);
+    }
+    let wanted = rest.join(" ");
+    if wanted.is_empty() {
+        bail!("Say which task is done, by its id or its description");
+    }
+    // Split alike, so `follow-up` finds "Follow-up with Ann".
+    let split = |text: &str| -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let needed = split(&wanted);
+    let found: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| {
+            let said = split(&t.description);
+            !t.completed && needed.iter().all(|w| said.contains(w))
+        })
+        .collect();
+    match found.as_slice() {
+        [task] => Ok(vec!["done".to_owned(), task.id.to_string()]),
+        [] => bail!("No open task says {:?}", wanted),
+        several => {
+            let lines: Vec<String> = several
+                .iter()
+                .map(|t| format!("  {}", task::format_line(tasks, t)))
+                .collect();
+            bail!(
+                "Several open tasks say {:?}; give the id:\n{}",
+                wanted,
+                lines.join("\n")
+            )
+        }
+    }
+}
+
+fn list(mut words: Words, today: NaiveDate) -> Vec<String> {
+    let mut terms = Vec::new();
+    for (phrase, term) in [
+        (&["overdue"][..], "+OVERDUE"),
+        (&["today"], "+TODAY"),
+        (&["tomorrow"], "+TOMORROW"),
+        (&["this", "week"], "+WEEK"),
+        (&["this", "month"], "+MONTH"),
+        (&["waiting"], "+WAITING"),
+        (&["blocked"], "+BLOCKED"),
+        (&["someday"], "+SOMEDAY"),
+        (&["inbox"], "+INBOX"),
+        (&["done"], "status:done"),
+        (&["completed"], "status:done"),
+        (&["finished"], "status:done"),
+    ] {
+        if words.take_phrase(phrase).is_some() {
+            terms.push(term.to_owned());
+        }
+    }
+    if let Some(due) = words.take_date(today) {
+        terms.push(format!("due:{}", due));
+    }
+    if let Some(priority) = words.take_priority() {
+        terms.push(format!("priority:{}", priority.name()));
+    }
+    if let Some(project) = words.take_project() {
+        terms.push(format!("project:{}", project));
+    }
+    for tag in words.take_each(tag) {
+        terms.push(format!("+{}", tag));
+    }
+    terms.extend(
+        words
+            .rest_lower()
+            .into_iter()
+            .filter(|w| !FILLER.contains(w))
+            .map(str::to_owned),
+    );
+    let mut args = vec!["list".to_owned()];
+    if !terms.is_empty() {
+        args.extend(["--filter".to_owned(), terms.join(" ")]);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tasks() -> Vec<Task> {
+        [
+            (1, "build", false),
+            (2, "Follow-up with Ann", false),
+            (3, "Buy bread", true),
+        ]
+        .into_iter()
+        .map(|(id, description, completed)| {
+            serde_json::from_value(json!({
+                "id": id,
+                "description": description,
+                "completed": completed,
+            }))
+            .unwrap()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn done_matches_whole_words() {
+        let tasks = tasks();
+        assert_eq!(translate("finished build", &tasks).unwrap(), ["done", "1"]);
+        assert_eq!(translate("done follow-up", &tasks).unwrap(), ["done", "2"]);
+        assert_eq!(
+            translate("finished the ann follow up", &tasks).unwrap(),
+            ["done", "2"]
+        );
+        assert!(translate("finish b", &tasks).is_err());
+        assert!(translate("done bread", &tasks).is_err());
+        assert_eq!(translate("done #3", &tasks).unwrap(), ["done", "3"]);
+    }
+
+    #[test]
+    fn add_takes_dates_the_way_due_does() {
+        let args = translate("remind me to call Ann by friday, high priority", &[]).unwrap();
+        assert_eq!(args[0], "add");
+        assert_eq!(args[1], "--due");
+        assert_eq!(args[2], date::parse("friday").unwrap().to_string());
+        assert_eq!(args[3..], ["--priority", "high", "--", "call Ann"]);
+    }
+}
@@ -0,0 +1,480 @@
+use crate::{
+    access::{Access, User},
+    crdt, feed, grpc,
+    http::{self, Request},
+    redact,
+    sync::{self, ChangeSet, Sealed, SealedPush, Snapshot},
+    task::{self, Comment, Task},
+};
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fs,
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+};
+use uuid::Uuid;
+
+const FEED: &str = "/feed.atom";
+
+pub struct ServeOptions {
+    pub addr: String,
+    /// Where to offer the gRPC service too, if anywhere; see `grpc`.
+    pub grpc: Option<String>,
+    pub sync: bool,
+    pub token: Option<String>,
+    pub users: Vec<User>,
+    pub redact: redact::Rules,
+}
+
+impl ServeOptions {
+    pub fn authenticate(&self, token: &str) -> Option<Access<'_>> {
+        if self.token.as_deref().is_some_and(|t| same_token(token, t)) {
+            return Some(Access::Full);
+        }
+        self.users
+            .iter()
+            .find(|u| same_token(token, &u.token))
+            .map(Access::Scoped)
+    }
+}
+
+/// A request turned down, with the HTTP status that says why.
+pub struct Refusal {
+    pub status: u16,
+    pub message: String,
+}
+
+/// What an operation shared by HTTP and gRPC answers: a failure of the
+/// server, else the result or why the request was turned down.
+pub type Outcome<T> = anyhow::Result<Result<T, Refusal>>;
+
+fn refuse<T>(status: u16, message: impl Into<String>) -> Outcome<T> {
+    Ok(Err(Refusal {
+        status,
+        message: message.into(),
+    }))
+}
+
+/// Held while a request is answered, over HTTP or gRPC, so writes never
+/// interleave.
+static TURN: Mutex<()> = Mutex::new(());
+
+pub fn turn() -> MutexGuard<'static, ()> {
+    TURN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Serves the local tasks file over HTTP, and over gRPC too when
+/// `options.grpc` says where. Each connection is read on a thread of its
+/// own, and requests are answered one at a time under `turn`. The file is re-read
+/// per request, so edits made with the CLI on this machine are picked up
+/// without a restart.
+pub fn serve(data_path: &Path, options: ServeOptions) -> anyhow::Result<()> {
+    if options.token.as_deref().is_none_or(str::is_empty) && options.users.is_empty() {
+        bail!(
+            "Refusing to serve without a token (pass --token, set CLI_TASK_MANAGER_TOKEN, or give --users)"
+        );
+    }
+
+    // Tasks from older files get a fresh uuid on every load until saved once;
+    // pin them now so snapshot revisions stay stable between requests.
+    if data_path.exists() {
+        let tasks = task::load_tasks(data_path)?;
+        task::save_tasks(data_path, &tasks)?;
+    }
+
+    let listener = TcpListener::bind(&options.addr)
+        .with_context(|| format!("Failed to listen on {}", options.addr))?;
+    println!(
+        "Serving {} on http://{}{}",
+        data_path.display(),
+        listener.local_addr()?,
+        if options.sync { " (sync enabled)" } else { "" }
+    );
+    let options = Arc::new(options);
+    if let Some(addr) = &options.grpc {
+        let grpc =
+            TcpListener::bind(addr).with_context(|| format!("Failed to listen on {}", addr))?;
+        println!("Serving gRPC on {}", grpc.local_addr()?);
+        let (data_path, options) = (data_path.to_owned(), options.clone());
+        thread::spawn(move || {
+            if let Err(err) = grpc::serve(grpc, data_path, options) {
+                eprintln!("gRPC server stopped: {:#}", err);
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let (data_path, options) = (data_path.to_owned(), options.clone());
+                thread::spawn(move || {
+                    if let Err(err) = handle(&data_path, &options, &stream) {
+                        let message = format!("Request failed: {:#}", err);
+                        eprintln!("{}", options.redact.mask(&message));
+                    }
+                });
+            }
+            Err(err) => eprintln!("Failed to accept connection: {}", err),
+        }
+    }
+    Ok(())
+}
+
+fn handle(data_path: &Path, options: &ServeOptions, stream: &TcpStream) -> anyhow::Result<()> {
+    let request = match http::read_request(stream) {
+        Ok(request) => request,
+        Err(err) => return respond_error(stream, 400, &format!("{:#}", err)),
+    };
+
+    let _turn = turn();
+    let (status, body) = match route(data_path, options, &request) {
+        Ok(reply) => reply,
+        Err(err) => (500, error_body(&format!("{:#}", err))),
+    };
+    eprintln!("{} {} -> {}", request.method, request.path, status);
+    let content_type = if status == 200 && request.path.starts_with(FEED) {
+        "application/atom+xml"
+    } else {
+        "application/json"
+    };
+    http::write_response(stream, status, content_type, &body)
+}
+
+fn route(
+    data_path: &Path,
+    options: &ServeOptions,
+    request: &Request,
+) -> anyhow::Result<(u16, Vec<u8>)> {
+    let path = request.path.split('?').next().unwrap_or_default();
+    // Feed readers rarely send custom headers, so the feed also accepts the
+    // token as `?token=`.
+    let token = match request.bearer_token() {
+        None if path == FEED => request.query("token"),
+        token => token,
+    };
+    let Some(access) = token.and_then(|t| options.authenticate(t)) else {
+        return Ok((401, error_body("Missing or invalid bearer token")));
+    };
+
+    match (request.method.as_str(), path) {
+        ("GET", FEED) => {
+            let completed = request.query("completed").is_some();
+            let atom = feed_for(data_path, options, &access, completed)?;
+            Ok((200, atom.into_bytes()))
+        }
+        ("GET", "/tasks") => {
+            let page = match page(request) {
+                Ok(page) => page,
+                Err(message) => return Ok((400, error_body(&message))),
+            };
+            reply(200, Ok(list(data_path, options, &access, page)?))
+        }
+        ("GET", "/sync") if options.sync => reply(200, Ok(snapshot(data_path, &access)?)),
+        ("PUT", "/sync") if options.sync => match serde_json::from_slice(&request.body) {
+            Ok(changes) => reply(200, revision(push(data_path, &access, changes)?)),
+            Err(err) => Ok((400, error_body(&format!("Malformed change set: {}", err)))),
+        },
+        (_, "/sync/sealed") if options.sync && !matches!(access, Access::Full) => {
+            Ok((403, error_body(SEALED_NEEDS_FULL)))
+        }
+        ("GET", "/sync/sealed") if options.sync => reply(200, sealed(data_path, &access)?),
+        ("PUT", "/sync/sealed") if options.sync => match serde_json::from_slice(&request.body) {
+            Ok(push) => reply(200, revision(push_sealed(data_path, &access, push)?)),
+            Err(err) => Ok((400, error_body(&format!("Malformed sealed push: {}", err)))),
+        },
+        (method, _) if path.starts_with("/tasks/") => comment_route(
+            data_path,
+            &access,
+            method,
+            path,
+            &request.body,
+            &options.redact,
+        ),
+        (_, "/tasks") => Ok((405, error_body("Method not allowed"))),
+        (_, "/sync" | "/sync/sealed") if options.sync => {
+            Ok((405, error_body("Method not allowed")))
+        }
+        _ => Ok((404, error_body("Not found"))),
+    }
+}
+
+/// The Atom feed of the tasks `access` may read, masked.
+pub fn feed_for(
+    data_path: &Path,
+    options: &ServeOptions,
+    access: &Access,
+    completed: bool,
+) -> anyhow::Result<String> {
+    let mut tasks = visible(task::load_tasks(data_path)?, access);
+    let clock = crdt::Clock::load(data_path, &tasks)?;
+    options.redact.apply(&mut tasks);
+    Ok(feed::atom(&tasks, clock.replica(), completed))
+}
+
+/// The tasks `access` may read, masked, in id order when paged.
+pub fn list(
+    data_path: &Path,
+    options: &ServeOptions,
+    access: &Access,
+    page: Option<task::Page>,
+) -> anyhow::Result<Vec<Task>> {
+    let mut tasks = visible(task::load_tasks(data_path)?, access);
+    if let Some(page) = page {
+        tasks.sort_by_key(|t| t.id);
+        page.apply(&mut tasks);
+    }
+    options.redact.apply(&mut tasks);
+    Ok(tasks)
+}
+
+pub fn snapshot(data_path: &Path, access: &Access) -> anyhow::Result<Snapshot> {
+    let tasks = visible(task::load_tasks(data_path)?, access);
+    let tombstones = crdt::load_tombstones(data_path)?;
+    Ok(Snapshot {
+        revision: sync::revision(&tasks, &tombstones)?,
+        tasks,
+        tombstones,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct NewComment {
+    pub text: String,
+    #[serde(default)]
+    pub reply_to: Option<Uuid>,
+}
+
+/// `GET` / `POST /tasks/<uuid>/comments`: read a task's comment threads, or
+/// add to them as the authenticated user.
+fn comment_route(
+    data_path: &Path,
+    access: &Access,
+    method: &str,
+    path: &str,
+    body: &[u8],
+    redact: &redact::Rules,
+) -> anyhow::Result<(u16, Vec<u8>)> {
+    let Some(uuid) = path
+        .strip_prefix("/tasks/")
+        .and_then(|rest| rest.strip_suffix("/comments"))
+        .and_then(|uuid| uuid.parse::<Uuid>().ok())
+    else {
+        return Ok((404, error_body("Not found")));
+    };
+    match method {
+        "GET" => reply(200, comments(data_path, access, uuid, redact)?),
+        "POST" => match serde_json::from_slice(body) {
+            Ok(new) => reply(201, add_comment(data_path, access, uuid, new)?),
+            Err(err) => Ok((400, error_body(&format!("Malformed comment: {}", err)))),
+        },
+        _ => Ok((405, error_body("Method not allowed"))),
+    }
+}
+
+/// The comments on task `uuid`, masked.
+pub fn comments(
+    data_path: &Path,
+    access: &Access,
+    uuid: Uuid,
+    redact: &redact::Rules,
+) -> Outcome<Vec<Comment>> {
+    let tasks = task::load_tasks(data_path)?;
+    match tasks
+        .iter()
+        .find(|t| t.uuid == uuid)
+        .filter(|t| access.can_read(t))
+    {
+        Some(task) => Ok(Ok(redact.masked(task).comments)),
+        None => refuse(404, "No such task"),
+    }
+}
+
+/// Adds `new` to the comments on task `uuid` as the authenticated user.
+pub fn add_comment(
+    data_path: &Path,
+    access: &Access,
+    uuid: Uuid,
+    new: NewComment,
+) -> Outcome<Comment> {
+    let mut tasks = task::load_tasks(data_path)?;
+    let clock = crdt::Clock::load(data_path, &tasks)?;
+    let Some(task) = tasks
+        .iter_mut()
+        .find(|t| t.uuid == uuid)
+        .filter(|t| access.can_read(t))
+    else {
+        return refuse(404, "No such task");
+    };
+    if !access.can_write(task) {
+        return refuse(
+            403,
+            format!("{} may not comment on this task", access.name()),
+        );
+    }
+    let comment = match task::add_comment(task, access.name(), &new.text, new.reply_to, &clock) {
+        Ok(comment) => comment,
+        Err(err) => return refuse(400, format!("{:#}", err)),
+    };
+    task::save_tasks(data_path, &tasks)?;
+    Ok(Ok(comment))
+}
+
+/// Each user sees, and syncs against, only the projects they may read; the
+/// revision is computed over that view so edits elsewhere never force them
+/// to re-pull.
+fn visible(tasks: Vec<Task>, access: &Access) -> Vec<Task> {
+    tasks.into_iter().filter(|t| access.can_read(t)).collect()
+}
+
+const CHANGED: &str = "Tasks changed since your pull; pull and merge again";
+
+/// Applies `changes` pushed from a pull by `access`, giving the revision
+/// after.
+pub fn push(data_path: &Path, access: &Access, changes: ChangeSet) -> Outcome<String> {
+    let mut tasks = task::load_tasks(data_path)?;
+    let mut tombstones = crdt::load_tombstones(data_path)?;
+    let view: Vec<Task> = tasks
+        .iter()
+        .filter(|t| access.can_read(t))
+        .cloned()
+        .collect();
+    if changes.base_revision != sync::revision(&view, &tombstones)? {
+        return refuse(409, CHANGED);
+    }
+    if let Some(message) = forbidden_change(&tasks, &changes, access) {
+        return refuse(403, message);
+    }
+
+    changes.apply(&mut tasks, &mut tombstones);
+    task::save_tasks(data_path, &tasks)?;
+    crdt::save_tombstones(data_path, &tombstones)?;
+
+    let view: Vec<Task> = tasks.into_iter().filter(|t| access.can_read(t)).collect();
+    Ok(Ok(sync::revision(&view, &tombstones)?))
+}
+
+/// Where `serve --sync` keeps the payload encrypted clients push.
+fn sealed_path(data_path: &Path) -> PathBuf {
+    data_path.with_file_name("sync-sealed.age")
+}
+
+fn read_sealed(data_path: &Path) -> anyhow::Result<Option<String>> {
+    let path = sealed_path(data_path);
+    match fs::read_to_string(&path) {
+        Ok(payload) => Ok(Some(payload)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Encrypted payloads cannot be filtered by project.
+const SEALED_NEEDS_FULL: &str = "Encrypted sync needs full access";
+
+pub fn sealed(data_path: &Path, access: &Access) -> Outcome<Sealed> {
+    if !matches!(access, Access::Full) {
+        return refuse(403, SEALED_NEEDS_FULL);
+    }
+    let payload = read_sealed(data_path)?;
+    Ok(Ok(Sealed {
+        revision: sync::sealed_revision(payload.as_deref()),
+        payload,
+    }))
+}
+
+/// Replaces the sealed payload with `push`, giving the revision after.
+pub fn push_sealed(data_path: &Path, access: &Access, push: SealedPush) -> Outcome<String> {
+    if !matches!(access, Access::Full) {
+        return refuse(403, SEALED_NEEDS_FULL);
+    }
+    let current = read_sealed(data_path)?;
+    if push.base_revision != sync::sealed_revision(current.as_deref()) {
+        return refuse(409, CHANGED);
+    }
+    task::write_atomic(&sealed_path(data_path), push.payload.as_bytes())?;
+    Ok(Ok(sync::sealed_revision(Some(&push.payload))))
+}
+
+/// A change must be writable both where the task is now and where it is
+/// going, so nobody can move a task out of (or into) a project they may only
+/// read.
+fn forbidden_change(tasks: &[Task], changes: &ChangeSet, access: &Access) -> Option<String> {
+    let existing = |uuid| tasks.iter().find(|t| t.uuid == uuid);
+    for task in &changes.upserts {
+        if !access.can_write(task) || existing(task.uuid).is_some_and(|t| !access.can_write(t)) {
+            return Some(format!(
+                "{} may not change task \"{}\" in {}",
+                access.name(),
+                task.description,
+                task.project.as_deref().unwrap_or("(no project)")
+            ));
+        }
+    }
+    for uuid in &changes.deletes {
+        if let Some(task) = existing(*uuid).filter(|t| !access.can_write(t)) {
+            return Some(format!(
+                "{} may not remove task \"{}\"",
+                access.name(),
+                task.description
+            ));
+        }
+    }
+    None
+}
+
+fn respond_error(stream: &TcpStream, status: u16, message: &str) -> anyhow::Result<()> {
+    http::write_response(stream, status, "application/json", &error_body(message))
+}
+
+/// The `?limit=` and `?offset=` of a request, if it asks for a page.
+fn page(request: &Request) -> Result<Option<task::Page>, String> {
+    let number = |name: &str| {
+        request
+            .query(name)
+            .map(|value| {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format!("{} must be a whole number", name))
+            })
+            .transpose()
+    };
+    let (offset, limit) = (number("offset")?, number("limit")?);
+    if offset.is_none() && limit.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(task::Page {
+        offset: offset.unwrap_or_default(),
+        limit,
+    }))
+}
+
+/// `outcome` as a response, with `status` when it succeeded.
+fn reply<T: Serialize>(status: u16, outcome: Result<T, Refusal>) -> anyhow::Result<(u16, Vec<u8>)> {
+    match outcome {
+        Ok(value) => Ok((status, serde_json::to_vec(&value)?)),
+        Err(refusal) => Ok((refusal.status, error_body(&refusal.message))),
+    }
+}
+
+/// What the sync pushes answer with.
+fn revision(outcome: Result<String, Refusal>) -> Result<serde_json::Value, Refusal> {
+    outcome.map(|revision| json!({ "revision": revision }))
+}
+
+fn error_body(message: &str) -> Vec<u8> {
+    json!({ "error": message }).to_string().into_bytes()
+}
+
+/// Compares without stopping at the first differing byte, so response timing
+/// does not reveal how much of a guessed token was right.
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
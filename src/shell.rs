@@ -0,0 +1,201 @@
+//! `shell`: commands typed one after another at a `tasks>` prompt, in one
+//! process that keeps the task list loaded between them. The file is read
+//! again only when something else changed it, so a triage session of many
+//! small edits does not pay for starting up and parsing the list each time.
+//!
+//! At a terminal the line is edited with `rustyline`, with Tab completing
+//! command names and their options and Up and Down walking back through
+//! earlier lines, which are kept in `shell_history` in the cache directory.
+//! Output is not paged. `exit`, `quit`, or Ctrl-D leaves.
+
+use crate::{task, task::Task, watch};
+use anyhow::Context;
+use rustyline::{
+    CompletionType, Config, Editor, Helper, Highlighter, Hinter, Validator, completion::Completer,
+    error::ReadlineError, history::DefaultHistory,
+};
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const PROMPT: &str = "tasks> ";
+const HISTORY: usize = 1000;
+
+/// The task list as the shell last saw it.
+#[derive(Default)]
+pub struct Session {
+    loaded: Option<Loaded>,
+    /// The line being run, as its undo step is labelled.
+    pub line: String,
+}
+
+struct Loaded {
+    path: PathBuf,
+    signature: Option<(SystemTime, u64)>,
+    tasks: Vec<Task>,
+}
+
+impl Session {
+    /// The tasks at `path`, read again only if the file changed since.
+    pub fn tasks(&mut self, path: &Path) -> anyhow::Result<Vec<Task>> {
+        let signature = watch::signature(path);
+        if let Some(loaded) = &self.loaded
+            && loaded.path == path
+            && loaded.signature.is_some()
+            && loaded.signature == signature
+        {
+            return Ok(loaded.tasks.clone());
+        }
+        let tasks = task::load_tasks(path)?;
+        self.keep(path, &tasks);
+        Ok(tasks)
+    }
+
+    /// Keeps `tasks` as what `path` now holds, once a command saved them.
+    pub fn keep(&mut self, path: &Path, tasks: &[Task]) {
+        self.loaded = Some(Loaded {
+            path: path.to_owned(),
+            signature: watch::signature(path),
+            tasks: tasks.to_vec(),
+        });
+    }
+}
+
+/// The names Tab completes: each command with its long options.
+#[derive(Helper, Highlighter, Hinter, Validator)]
+pub struct Completions(pub Vec<(String, Vec<String>)>);
+
+impl Completions {
+    /// The candidates for the word being typed, given the words before it.
+    fn candidates(&self, before: &[&str], word: &str) -> Vec<String> {
+        let names: Vec<&String> = match before.first() {
+            None => self.0.iter().map(|(name, _)| name).collect(),
+            Some(command) if word.starts_with('-') => self
+                .0
+                .iter()
+                .filter(|(name, _)| name == command)
+                .flat_map(|(_, options)| options)
+                .collect(),
+            Some(_) => Vec::new(),
+        };
+        names
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Completer for Completions {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(' ').map_or(0, |i| i + 1);
+        let earlier: Vec<&str> = before[..start].split_whitespace().collect();
+        let mut candidates = self.candidates(&earlier, &before[start..]);
+        if let [only] = candidates.as_mut_slice() {
+            only.push(' ');
+        }
+        Ok((start, candidates))
+    }
+}
+
+fn editor(
+    history_path: &Path,
+    completions: Completions,
+) -> anyhow::Result<Editor<Completions, DefaultHistory>> {
+    let config = Config::builder()
+        .max_history_size(HISTORY)?
+        .history_ignore_dups(true)?
+        .completion_type(CompletionType::List)
+        // Alt-key sequences arrive at once; a lone Esc should not wait.
+        .keyseq_timeout(Some(50))
+        .build();
+    let mut editor = Editor::with_config(config).context("Failed to set up line editing")?;
+    editor.set_helper(Some(completions));
+    if history_path.exists() {
+        editor
+            .load_history(history_path)
+            .with_context(|| format!("Failed to read {}", history_path.display()))?;
+    }
+    Ok(editor)
+}
+
+/// Reads lines until `exit` or end of input and hands each, with its words,
+/// to `execute`; its errors are printed, not fatal.
+pub fn run(
+    history_path: &Path,
+    completions: Completions,
+    mut execute: impl FnMut(&str, Vec<String>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let terminal = io::stdin().is_terminal() && io::stdout().is_terminal();
+    let mut editor = match terminal {
+        true => Some(editor(history_path, completions)?),
+        false => None,
+    };
+    if terminal {
+        println!(
+            "Type a command such as `list` or `add \"Call Ann\"`; `help` lists them, `exit` leaves."
+        );
+    }
+    loop {
+        let line = match &mut editor {
+            Some(editor) => match editor.readline(PROMPT) {
+                Ok(line) => Some(line),
+                // Ctrl-C drops the line.
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => None,
+                Err(err) => return Err(err).context("Failed to read a command"),
+            },
+            None => {
+                let mut line = String::new();
+                let read = io::stdin()
+                    .lock()
+                    .read_line(&mut line)
+                    .context("Failed to read a command")?;
+                (read > 0).then_some(line)
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(editor) = &mut editor {
+            editor.add_history_entry(line)?;
+            if let Some(dir) = history_path.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            editor
+                .save_history(history_path)
+                .with_context(|| format!("Failed to write {}", history_path.display()))?;
+        }
+        if ["exit", "quit"].contains(&line) {
+            break;
+        }
+        let words = match crate::view::split(line) {
+            Ok(words) => words,
+            Err(err) => {
+                eprintln!("Error: {:#}", err);
+                continue;
+            }
+        };
+        if let Err(err) = execute(line, words) {
+            eprintln!("Error: {:#}", err);
+        }
+        io::stdout().flush().context("Failed to flush output")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Remote file access through the system `ssh` client, so keys, agents, and
+//! `~/.ssh/config` aliases work exactly as they do in the shell.
+
+use anyhow::{Context, bail};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// A `user@host:path` target as accepted by scp.
+pub struct Target {
+    pub host: String,
+    pub path: String,
+}
+
+impl Target {
+    pub fn parse(target: &str) -> anyhow::Result<Self> {
+        let Some((host, path)) = target.split_once(':') else {
+            bail!("Expected user@host:path, got {}", target);
+        };
+        if host.is_empty() || path.is_empty() {
+            bail!("Expected user@host:path, got {}", target);
+        }
+        // The remote shell starts in the home directory, and `~` would not
+        // expand inside the quotes added below.
+        let path = path.strip_prefix("~/").unwrap_or(path);
+        Ok(Self {
+            host: host.to_owned(),
+            path: path.to_owned(),
+        })
+    }
+
+    /// Reads a remote file, or returns `None` if it does not exist yet.
+    pub fn read(&self, path: &str) -> anyhow::Result<Option<String>> {
+        let quoted = quote(path);
+        let script = format!("if [ -e {0} ]; then printf found; cat {0}; fi", quoted);
+        let output = Command::new("ssh")
+            .arg(&self.host)
+            .arg(script)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .context("Failed to run ssh")?;
+        if !output.status.success() {
+            bail!(
+                "ssh {} failed reading {} ({})",
+                self.host,
+                path,
+                output.status
+            );
+        }
+
+        let text = String::from_utf8(output.stdout)
+            .with_context(|| format!("{}:{} is not valid UTF-8", self.host, path))?;
+        Ok(text.strip_prefix("found").map(str::to_owned))
+    }
+
+    /// Replaces a remote file by writing a sibling temp file and renaming it,
+    /// so an interrupted upload never leaves a half-written file behind.
+    pub fn write(&self, path: &str, data: &[u8]) -> anyhow::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        let parent = match path.rfind('/') {
+            Some(0) => "/",
+            Some(i) => &path[..i],
+            None => ".",
+        };
+        let script = format!(
+            "mkdir -p {} && cat > {} && mv -f {} {}",
+            quote(parent),
+            quote(&tmp),
+            quote(&tmp),
+            quote(path)
+        );
+        let mut child = Command::new("ssh")
+            .arg(&self.host)
+            .arg(script)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("Failed to run ssh")?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(data)
+            .with_context(|| format!("Failed to upload {} to {}", path, self.host))?;
+        let status = child.wait().context("Failed to wait for ssh")?;
+        if !status.success() {
+            bail!("ssh {} failed writing {} ({})", self.host, path, status);
+        }
+        Ok(())
+    }
+}
+
+/// Single-quotes `s` for a POSIX shell.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
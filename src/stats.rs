@@ -0,0 +1,322 @@
+//! Trends over time drawn in the terminal with Unicode block characters:
+//! bar charts of recent days and a calendar heatmap of the past year, and a
+//! productivity score per day or week.
+
+use crate::{
+    table::{self, Table},
+    task::{Priority, Task},
+};
+use chrono::{Datelike, Local, NaiveDate};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Chart {
+    /// Tasks completed per day
+    Completions,
+}
+
+/// Eighth-height steps, from empty to a full cell.
+const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Rows a bar chart is tall.
+const HEIGHT: usize = 8;
+
+/// Completions per local day, for tasks that recorded when they were done.
+fn completions_by_day(tasks: &[Task]) -> BTreeMap<NaiveDate, usize> {
+    let mut days = BTreeMap::new();
+    for at in tasks
+        .iter()
+        .filter(|t| t.completed)
+        .filter_map(|t| t.completed_at)
+    {
+        *days
+            .entry(at.with_timezone(&Local).date_naive())
+            .or_default() += 1;
+    }
+    days
+}
+
+pub fn summary(tasks: &[Task], days: u32) {
+    let today = Local::now().date_naive();
+    let since = today - chrono::Duration::days(i64::from(days) - 1);
+    let done: usize = completions_by_day(tasks)
+        .range(since..)
+        .map(|(_, n)| n)
+        .sum();
+    let open = tasks.iter().filter(|t| !t.completed).count();
+    println!(
+        "{} open, {} completed in the last {} day(s) ({:.1} a day)",
+        open,
+        done,
+        days,
+        done as f64 / f64::from(days)
+    );
+}
+
+/// A bar chart of the last `days` days ending today. When there are more
+/// days than fit across the terminal, neighbouring days share a column.
+pub fn chart(tasks: &[Task], chart: Chart, days: u32) {
+    let per_day = match chart {
+        Chart::Completions => completions_by_day(tasks),
+    };
+    let today = Local::now().date_naive();
+    let first = today - chrono::Duration::days(i64::from(days) - 1);
+    let counts: Vec<usize> = first
+        .iter_days()
+        .take(days as usize)
+        .map(|d| per_day.get(&d).copied().unwrap_or(0))
+        .collect();
+
+    // The total bounds any column's label, whatever the grouping.
+    let label_width = counts.iter().sum::<usize>().to_string().len();
+    let room = table::terminal_width()
+        .map(|w| w.saturating_sub(label_width + 2).max(1))
+        .unwrap_or(usize::MAX);
+    let per_column = counts.len().div_ceil(room).max(1);
+    let columns: Vec<usize> = counts.chunks(per_column).map(|c| c.iter().sum()).collect();
+    let top = columns.iter().copied().max().unwrap_or(0);
+
+    println!(
+        "Completions per {}, {} to {} ({} total)",
+        if per_column == 1 {
+            "day".to_owned()
+        } else {
+            format!("{} days", per_column)
+        },
+        first,
+        today,
+        counts.iter().sum::<usize>()
+    );
+    let width = top.to_string().len();
+    if top == 0 {
+        println!("Nothing completed in that period.");
+        return;
+    }
+    for row in (0..HEIGHT).rev() {
+        let label = match row {
+            r if r == HEIGHT - 1 => top.to_string(),
+            0 => "0".to_owned(),
+            _ => String::new(),
+        };
+        let line: String = columns
+            .iter()
+            .map(|&n| {
+                // Eighths of a cell this bar reaches into the row.
+                let eighths = n * HEIGHT * 8 / top;
+                BLOCKS[eighths.saturating_sub(row * 8).min(8)]
+            })
+            .collect();
+        println!("{:>w$} │{}", label, line.trim_end(), w = width);
+    }
+    println!("{:>w$} └{}", "", "─".repeat(columns.len()), w = width);
+    let start = first.format("%b %d").to_string();
+    let end = today.format("%b %d").to_string();
+    let gap = columns.len().saturating_sub(start.len() + end.len()).max(1);
+    println!("{:>w$}  {}{}{}", "", start, " ".repeat(gap), end, w = width);
+}
+
+/// Shades for no completions and then each quarter of the busiest day.
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+const WEEKS: i64 = 53;
+
+/// A calendar of the past year, one column per week and one row per
+/// weekday, shaded by how many tasks were completed that day.
+pub fn heatmap(tasks: &[Task]) {
+    let per_day = completions_by_day(tasks);
+    let today = Local::now().date_naive();
+    let this_monday = today - chrono::Duration::days(today.weekday().num_days_from_monday().into());
+    let first = this_monday - chrono::Duration::weeks(WEEKS - 1);
+    let busiest = per_day.range(first..).map(|(_, n)| *n).max().unwrap_or(0);
+    let total: usize = per_day.range(first..).map(|(_, n)| n).sum();
+
+    let mondays: Vec<NaiveDate> = (0..WEEKS)
+        .map(|w| first + chrono::Duration::weeks(w))
+        .collect();
+    // Month names over the first week that starts in that month.
+    let mut months = String::new();
+    for (i, monday) in mondays.iter().enumerate() {
+        let starts_month = i == 0 || monday.month() != mondays[i - 1].month();
+        if starts_month && (i == 0 || months.chars().count() < i) {
+            months.push_str(&" ".repeat(i - months.chars().count()));
+            months.push_str(&monday.format("%b").to_string());
+        }
+    }
+    println!("{} task(s) completed since {}", total, first);
+    println!("    {}", months.trim_end());
+
+    for weekday in 0..7 {
+        let label = match weekday {
+            0 => "Mon",
+            2 => "Wed",
+            4 => "Fri",
+            _ => "",
+        };
+        let row: String = mondays
+            .iter()
+            .map(|monday| {
+                let day = *monday + chrono::Duration::days(weekday);
+                if day > today {
+                    return ' ';
+                }
+                match per_day.get(&day).copied().unwrap_or(0) {
+                    0 => SHADES[0],
+                    n => SHADES[(n * 4).div_ceil(busiest).clamp(1, 4)],
+                }
+            })
+            .collect();
+        println!("{:<3} {}", label, row.trim_end());
+    }
+    println!(
+        "    Less {} More",
+        SHADES
+            .iter()
+            .map(char::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Period {
+    Day,
+    Week,
+}
+
+/// Weights for `stats --score`, set as `score` in config.json; every field
+/// is optional.
+///
+/// ```json
+/// { "score": { "base": 1, "per_hour": 0.5, "overdue": 0.25,
+///   "priority": { "low": 1, "medium": 1.5, "high": 2, "urgent": 3 } } }
+/// ```
+///
+/// A completed task scores `base` times its priority's weight, plus
+/// `per_hour` for each estimated hour. Each day a task spends overdue costs
+/// `overdue`.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    base: f64,
+    per_hour: f64,
+    overdue: f64,
+    priority: PriorityWeights,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct PriorityWeights {
+    none: f64,
+    low: f64,
+    medium: f64,
+    high: f64,
+    urgent: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            base: 1.0,
+            per_hour: 0.5,
+            overdue: 0.25,
+            priority: PriorityWeights::default(),
+        }
+    }
+}
+
+impl Default for PriorityWeights {
+    fn default() -> Self {
+        Self {
+            none: 1.0,
+            low: 1.0,
+            medium: 1.5,
+            high: 2.0,
+            urgent: 3.0,
+        }
+    }
+}
+
+impl Weights {
+    fn completion(&self, task: &Task) -> f64 {
+        let priority = match task.priority {
+            None => self.priority.none,
+            Some(Priority::Low) => self.priority.low,
+            Some(Priority::Medium) => self.priority.medium,
+            Some(Priority::High) => self.priority.high,
+            Some(Priority::Urgent) => self.priority.urgent,
+        };
+        let hours = f64::from(task.estimate.unwrap_or(0)) / 60.0;
+        self.base * priority + self.per_hour * hours
+    }
+}
+
+/// Whether `task` was open past its due date at the end of `day`.
+fn overdue_on(task: &Task, day: NaiveDate) -> bool {
+    let Some(due) = task.due else { return false };
+    let finished = match task.completed_at.filter(|_| task.completed) {
+        Some(at) => at.with_timezone(&Local).date_naive() <= day,
+        // Completed before completion times were recorded: no way to tell.
+        None if task.completed => true,
+        None => false,
+    };
+    due < day && !finished
+}
+
+/// The score per day or week over the last `days` days, oldest first.
+pub fn score(tasks: &[Task], weights: &Weights, by: Period, days: u32) {
+    let today = Local::now().date_naive();
+    let first = today - chrono::Duration::days(i64::from(days) - 1);
+
+    // (period start, done, overdue task-days, score)
+    let mut periods: Vec<(NaiveDate, usize, usize, f64)> = Vec::new();
+    for day in first.iter_days().take(days as usize) {
+        let start = match by {
+            Period::Day => day,
+            Period::Week => {
+                day - chrono::Duration::days(day.weekday().num_days_from_monday().into())
+            }
+        };
+        if periods.last().is_none_or(|p| p.0 != start) {
+            periods.push((start, 0, 0, 0.0));
+        }
+        let period = periods.last_mut().expect("just pushed");
+        for task in tasks {
+            let done_today = task.completed
+                && task
+                    .completed_at
+                    .is_some_and(|at| at.with_timezone(&Local).date_naive() == day);
+            if done_today {
+                period.1 += 1;
+                period.3 += weights.completion(task);
+            }
+            if overdue_on(task, day) {
+                period.2 += 1;
+                period.3 -= weights.overdue;
+            }
+        }
+    }
+
+    let best = periods.iter().map(|p| p.3).fold(0.0, f64::max);
+    let mut table = Table::new(&["Period", "Done", "Overdue", "Score", ""]).align_right(&[1, 2, 3]);
+    for (start, done, overdue, score) in &periods {
+        let label = match by {
+            Period::Day => start.format("%a %Y-%m-%d").to_string(),
+            Period::Week => format!("week of {}", start),
+        };
+        let bar = if best > 0.0 && *score > 0.0 {
+            BLOCKS[8]
+                .to_string()
+                .repeat((score / best * 20.0).round().max(1.0) as usize)
+        } else {
+            String::new()
+        };
+        table.push(vec![
+            label,
+            done.to_string(),
+            overdue.to_string(),
+            format!("{:.2}", score),
+            bar,
+        ]);
+    }
+    table.print();
+}
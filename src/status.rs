@@ -0,0 +1,222 @@
+//! A one-glance summary for shell prompts and status bars. Prompts run this
+//! on every command, so it reads only the few fields it needs and skips the
+//! rest of each task instead of loading the full task list.
+//!
+//! With `--interval` it keeps running and prints a fresh line each time,
+//! which tmux (`#()` in `status-right`) and Waybar (a custom module without
+//! its own `interval`) both turn into a live display without re-spawning
+//! the command.
+
+use crate::{checksum, events, storage};
+use anyhow::Context;
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, de::IgnoredAny};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+#[derive(Deserialize)]
+struct Brief {
+    id: u32,
+    description: String,
+    completed: bool,
+    #[serde(default)]
+    someday: bool,
+    #[serde(default)]
+    inbox: bool,
+    /// Only whether there is one: habits recur, so they are never open work.
+    #[serde(default)]
+    habit: Option<IgnoredAny>,
+    #[serde(default)]
+    due: Option<NaiveDate>,
+}
+
+struct Counts {
+    open: usize,
+    overdue: usize,
+    due_today: Vec<String>,
+}
+
+pub enum Style {
+    Summary,
+    /// `3!/7` (overdue/open), just `7` when nothing is overdue, and nothing
+    /// at all when no tasks are open, so an idle prompt stays clean.
+    Short,
+    /// A Waybar custom module with `"return-type": "json"`.
+    Waybar,
+    /// Like `Short`, colored with tmux `#[...]` style codes.
+    Tmux,
+}
+
+fn count(path: &Path) -> anyhow::Result<Counts> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to read tasks file at {}", path.display()));
+        }
+    };
+    let (_, data) = checksum::split(&data);
+    let tasks: Vec<Brief> = if data.iter().all(u8::is_ascii_whitespace) {
+        Vec::new()
+    } else if data.first().copied().is_some_and(storage::is_msgpack) {
+        storage::decode(data)
+            .with_context(|| format!("Failed to parse tasks file at {}", path.display()))?
+    } else if data.first().copied().is_some_and(events::is_log) {
+        events::values(data)
+            .and_then(|(values, _)| {
+                values
+                    .into_iter()
+                    .map(|value| serde_json::from_value(value).map_err(Into::into))
+                    .collect()
+            })
+            .with_context(|| format!("Failed to parse tasks file at {}", path.display()))?
+    } else {
+        serde_json::from_slice(data)
+            .with_context(|| format!("Failed to parse tasks file at {}", path.display()))?
+    };
+
+    let today = Local::now().date_naive();
+    let mut counts = Counts {
+        open: 0,
+        overdue: 0,
+        due_today: Vec::new(),
+    };
+    // Unprocessed inbox items and habits are not tasks to get through, so
+    // they would only inflate the prompt.
+    for task in tasks
+        .iter()
+        .filter(|t| !t.completed && !t.someday && !t.inbox && t.habit.is_none())
+    {
+        counts.open += 1;
+        match task.due {
+            Some(due) if due < today => counts.overdue += 1,
+            Some(due) if due == today => counts
+                .due_today
+                .push(format!("{}: {}", task.id, task.description)),
+            _ => {}
+        }
+    }
+    Ok(counts)
+}
+
+fn short(counts: &Counts) -> String {
+    match counts {
+        Counts { open: 0, .. } => String::new(),
+        Counts {
+            open, overdue: 0, ..
+        } => open.to_string(),
+        Counts { open, overdue, .. } => format!("{}!/{}", overdue, open),
+    }
+}
+
+fn tmux(counts: &Counts) -> String {
+    let text = short(counts);
+    if counts.overdue > 0 {
+        let (overdue, rest) = text.split_once('/').unwrap_or((&text, ""));
+        format!("#[fg=red,bold]{}#[default]/{}", overdue, rest)
+    } else if !counts.due_today.is_empty() {
+        format!("#[fg=yellow]{}#[default]", text)
+    } else {
+        text
+    }
+}
+
+fn waybar(counts: &Counts) -> String {
+    let tooltip = if counts.due_today.is_empty() {
+        "Nothing due today".to_owned()
+    } else {
+        format!("Due today:\n{}", counts.due_today.join("\n"))
+    };
+    let class = if counts.overdue > 0 {
+        "overdue"
+    } else if !counts.due_today.is_empty() {
+        "due-today"
+    } else if counts.open > 0 {
+        "open"
+    } else {
+        "idle"
+    };
+    serde_json::json!({
+        "text": short(counts),
+        "tooltip": tooltip,
+        "class": class,
+        "alt": class,
+    })
+    .to_string()
+}
+
+fn render(path: &Path, style: &Style) -> anyhow::Result<String> {
+    let counts = count(path)?;
+    Ok(match style {
+        Style::Summary => format!(
+            "{} open, {} overdue, {} due today",
+            counts.open,
+            counts.overdue,
+            counts.due_today.len()
+        ),
+        Style::Short => short(&counts),
+        Style::Waybar => waybar(&counts),
+        Style::Tmux => tmux(&counts),
+    })
+}
+
+/// Prints the status once, or every `interval` until whatever reads the
+/// output goes away.
+pub fn print(path: &Path, style: Style, interval: Option<Duration>) -> anyhow::Result<()> {
+    let Some(interval) = interval else {
+        let line = render(path, &style)?;
+        if !line.is_empty() {
+            println!("{}", line);
+        }
+        return Ok(());
+    };
+
+    let mut stdout = io::stdout();
+    loop {
+        // A tasks file caught mid-write by another tool fails to parse;
+        // keep the last line up and try again next time.
+        if let Ok(line) = render(path, &style)
+            && writeln!(stdout, "{}", line)
+                .and_then(|_| stdout.flush())
+                .is_err()
+        {
+            return Ok(());
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inbox_items_and_habits_are_not_open() {
+        let path = std::env::temp_dir().join(format!(
+            "cli_task_manager-status-{}.json",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            r#"[
+                {"id": 1, "description": "pay rent", "completed": false, "due": "2000-01-01"},
+                {"id": 2, "description": "call", "completed": false},
+                {"id": 3, "description": "idea", "completed": false, "inbox": true},
+                {"id": 4, "description": "run", "completed": false, "habit": "3x/week"},
+                {"id": 5, "description": "maybe", "completed": false, "someday": true},
+                {"id": 6, "description": "done", "completed": true}
+            ]"#,
+        )
+        .unwrap();
+        let counts = count(&path).unwrap();
+        assert_eq!((counts.open, counts.overdue), (2, 1));
+        assert_eq!(short(&counts), "1!/2");
+        fs::remove_file(path).unwrap();
+    }
+}
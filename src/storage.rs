@@ -0,0 +1,182 @@
+//! The format a tasks file is stored in. JSON is the default and the only
+//! one meant for hand edits; MessagePack is smaller and quicker to read for
+//! large lists. An event log keeps every change rather than just the
+//! latest state (see `events`). A file's format is recognised from its
+//! first byte after the checksum line, and every save keeps the format the
+//! file already has, so `convert` is the only way to change it.
+//!
+//! There is no bincode option: it is not self-describing, so tasks written
+//! without their empty optional fields would not read back.
+
+use crate::{
+    checksum, events,
+    task::{self, Task},
+};
+use anyhow::{Context, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{fs, io::Read, path::Path};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Pretty-printed JSON, readable and editable by hand
+    Json,
+    /// MessagePack, compact but binary
+    Msgpack,
+    /// An append-only log of every change, one JSON event per line
+    Events,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Msgpack => "MessagePack",
+            Format::Events => "an event log",
+        }
+    }
+}
+
+/// Whether `byte` can open a MessagePack array, which JSON never starts with.
+pub fn is_msgpack(byte: u8) -> bool {
+    matches!(byte, 0x90..=0x9f | 0xdc | 0xdd)
+}
+
+/// The format of the file at `path`; JSON for a missing or empty one.
+pub fn detect(path: &Path) -> Format {
+    let mut first = [0];
+    let read = fs::File::open(path).and_then(|mut f| {
+        checksum::skip_header(&mut f)?;
+        f.read_exact(&mut first)
+    });
+    match read {
+        Ok(()) if is_msgpack(first[0]) => Format::Msgpack,
+        Ok(()) if events::is_log(first[0]) => Format::Events,
+        _ => Format::Json,
+    }
+}
+
+/// Encodes `tasks` as a MessagePack array of maps keyed like the JSON, with
+/// dates and ids as the same strings.
+pub fn encode(tasks: &[Task]) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut serializer = rmp_serde::Serializer::new(&mut out)
+        .with_struct_map()
+        .with_human_readable();
+    tasks
+        .serialize(&mut serializer)
+        .context("Failed to serialize tasks")?;
+    Ok(out)
+}
+
+/// Decodes a MessagePack array written by `encode` into `T`s, which may
+/// read only some of each task's fields.
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> anyhow::Result<Vec<T>> {
+    let mut rest = data;
+    let items =
+        Vec::<T>::deserialize(&mut rmp_serde::Deserializer::new(&mut rest).with_human_readable())?;
+    if !rest.is_empty() {
+        bail!(
+            "unexpected data after the list at byte {}",
+            data.len() - rest.len()
+        );
+    }
+    Ok(items)
+}
+
+/// Rewrites the tasks file at `data_path` in `format`.
+pub fn convert(data_path: &Path, format: Format) -> anyhow::Result<()> {
+    let from = detect(data_path);
+    let tasks = task::load_tasks(data_path)?;
+    task::save_tasks_as(data_path, &tasks, format)?;
+    if from == format {
+        println!(
+            "{} is already stored as {}; rewrote {} task(s).",
+            data_path.display(),
+            format.name(),
+            tasks.len()
+        );
+    } else {
+        println!(
+            "Converted {} task(s) in {} from {} to {}.",
+            tasks.len(),
+            data_path.display(),
+            from.name(),
+            format.name()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn task(value: Value) -> Task {
+        serde_json::from_value(value).expect("a valid task")
+    }
+
+    fn sample() -> Vec<Task> {
+        vec![
+            task(json!({
+                "id": 1,
+                "uuid": "cc581c8d-f080-459e-b3a4-4d1f2b7e9a10",
+                "description": "Grüße an Zoë ✓ — 日本語",
+                "completed": true,
+                "completed_at": "2026-10-14T13:54:59.054396281Z",
+                "note": null,
+                "tags": ["ü", "work"],
+                "estimate": u32::MAX,
+                "attributes": { "customer": "ACME" },
+                "stamps": {
+                    "completed": [u64::MAX, "f1976a8d-6d43-4ccd-8a62-2a1c0c7352ea"]
+                }
+            })),
+            task(json!({
+                "id": 2,
+                "uuid": "0b1e5ae4-3c52-4a39-8f59-9d8b6a0d1e22",
+                "description": "",
+                "completed": false,
+                "due": "2026-01-31"
+            })),
+        ]
+    }
+
+    #[test]
+    fn tasks_round_trip() {
+        let tasks = sample();
+        let data = encode(&tasks).unwrap();
+        assert!(is_msgpack(data[0]));
+        let read: Vec<Task> = decode(&data).unwrap();
+        assert!(read == tasks);
+    }
+
+    #[test]
+    fn decodes_the_fields_json_has() {
+        let tasks = sample();
+        let values: Vec<Value> = decode(&encode(&tasks).unwrap()).unwrap();
+        assert_eq!(Value::Array(values), serde_json::to_value(&tasks).unwrap());
+    }
+
+    #[test]
+    fn decodes_negative_and_wide_numbers() {
+        let value = json!([{ "small": -1, "negative": i64::MIN, "large": u64::MAX, "none": null }]);
+        let data = rmp_serde::to_vec_named(&value).unwrap();
+        let read: Vec<Value> = decode(&data).unwrap();
+        assert_eq!(Value::Array(read), value);
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let mut data = encode(&sample()).unwrap();
+        data.push(0xc0);
+        assert!(decode::<Value>(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_a_cut_off_list() {
+        let data = encode(&sample()).unwrap();
+        assert!(decode::<Value>(&data[..data.len() / 2]).is_err());
+    }
+}
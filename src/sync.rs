@@ -0,0 +1,496 @@
+use crate::{
+    age::Encryption,
+    checksum,
+    crdt::{self, Tombstones},
+    http::Endpoint,
+    keyring,
+    merge::{self, MergeOutcome, Side},
+    ssh,
+    task::{self, Task},
+};
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+const MAX_PUSH_ATTEMPTS: usize = 3;
+
+/// A place `daemon` syncs with, listed under `"remotes"` in config.json as
+/// `{ "file": path }`, `{ "ssh": "user@host:path" }`, or
+/// `{ "url": "http://host:7373" }` (or `https://`) with its token from
+/// `auth set sync`.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Remote {
+    File(PathBuf),
+    Ssh(String),
+    Url(String),
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Remote::File(path) => write!(f, "file:{}", path.display()),
+            Remote::Ssh(target) => write!(f, "ssh:{}", target),
+            Remote::Url(url) => f.write_str(url),
+        }
+    }
+}
+
+/// Syncs with a configured remote the way the matching `sync` subcommand
+/// would.
+pub fn sync_with(
+    data_path: &Path,
+    tasks: &mut Vec<Task>,
+    remote: &Remote,
+    prefer: Option<Side>,
+    encryption: Option<&Encryption>,
+) -> anyhow::Result<()> {
+    match remote {
+        Remote::File(path) => sync_file(data_path, tasks, path, prefer, encryption),
+        Remote::Ssh(target) => sync_ssh(data_path, tasks, target, prefer, encryption),
+        Remote::Url(url) => {
+            let account = keyring::Service::Sync.account(Some(url.clone()))?;
+            let token = keyring::get(keyring::Service::Sync, &account)?
+                .with_context(|| format!("No token for {}; run `auth set sync {}`", url, url))?;
+            sync_remote(data_path, tasks, url, &token, prefer, encryption)
+        }
+    }
+}
+
+/// What `GET /sync` returns: the server's tasks and a revision naming them.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub revision: String,
+    pub tasks: Vec<Task>,
+    #[serde(default)]
+    pub tombstones: Tombstones,
+}
+
+/// What `PUT /sync` accepts: only the tasks and tombstones that differ from
+/// the pulled snapshot. The server refuses it if its tasks moved past
+/// `base_revision`.
+#[derive(Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub base_revision: String,
+    pub upserts: Vec<Task>,
+    pub deletes: Vec<Uuid>,
+    #[serde(default)]
+    pub tombstones: Tombstones,
+}
+
+impl ChangeSet {
+    pub fn between(old: &Snapshot, merged: &MergeOutcome) -> Self {
+        let old_by_uuid: HashMap<Uuid, &Task> = old.tasks.iter().map(|t| (t.uuid, t)).collect();
+        let kept: HashSet<Uuid> = merged.tasks.iter().map(|t| t.uuid).collect();
+        Self {
+            base_revision: old.revision.clone(),
+            upserts: merged
+                .tasks
+                .iter()
+                .filter(|t| old_by_uuid.get(&t.uuid).copied() != Some(*t))
+                .cloned()
+                .collect(),
+            deletes: old
+                .tasks
+                .iter()
+                .map(|t| t.uuid)
+                .filter(|u| !kept.contains(u))
+                .collect(),
+            tombstones: merged
+                .tombstones
+                .iter()
+                .filter(|(uuid, stamp)| old.tombstones.get(uuid) != Some(stamp))
+                .map(|(uuid, stamp)| (*uuid, *stamp))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upserts.is_empty() && self.deletes.is_empty() && self.tombstones.is_empty()
+    }
+
+    pub fn apply(self, tasks: &mut Vec<Task>, tombstones: &mut Tombstones) {
+        let deletes: HashSet<Uuid> = self.deletes.into_iter().collect();
+        tasks.retain(|t| !deletes.contains(&t.uuid));
+        for task in self.upserts {
+            match tasks.iter_mut().find(|t| t.uuid == task.uuid) {
+                Some(existing) => *existing = task,
+                None => tasks.push(task),
+            }
+        }
+        *tombstones = crdt::union(tombstones, &self.tombstones);
+        let live: HashSet<Uuid> = tasks.iter().map(|t| t.uuid).collect();
+        tombstones.retain(|uuid, _| !live.contains(uuid));
+    }
+}
+
+/// What `GET /sync/sealed` returns: the encrypted payload last pushed, if
+/// any, and a revision naming it.
+#[derive(Serialize, Deserialize)]
+pub struct Sealed {
+    pub revision: String,
+    pub payload: Option<String>,
+}
+
+/// What `PUT /sync/sealed` accepts. The server cannot look inside, so it
+/// only checks that nobody pushed since `base_revision`.
+#[derive(Serialize, Deserialize)]
+pub struct SealedPush {
+    pub base_revision: String,
+    pub payload: String,
+}
+
+/// The revision of a sealed payload, or of none.
+pub fn sealed_revision(payload: Option<&str>) -> String {
+    let Some(payload) = payload else {
+        return String::new();
+    };
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// A cheap fingerprint of a task list; any edit, including one made with the
+/// CLI directly on the server machine, yields a different revision.
+pub fn revision(tasks: &[Task], tombstones: &Tombstones) -> anyhow::Result<String> {
+    let data = serde_json::to_string(&(tasks, tombstones)).context("Failed to serialize tasks")?;
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Merges `tasks` with the copy in another tasks file (a shared folder, a USB
+/// stick...), then writes the result to both sides.
+///
+/// The state agreed on at the previous sync is kept as the merge base, so a
+/// change made on only one side is never mistaken for a conflict.
+pub fn sync_file(
+    data_path: &Path,
+    tasks: &mut Vec<Task>,
+    remote_path: &Path,
+    prefer: Option<Side>,
+    encryption: Option<&Encryption>,
+) -> anyhow::Result<()> {
+    let remote_key = format!("file:{}", absolute(remote_path).display());
+    if let Some(encryption) = encryption {
+        let from = remote_path.display().to_string();
+        let (remote, remote_tombstones) = match fs::read_to_string(remote_path) {
+            Ok(sealed) => encryption.open(&sealed, &from)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                (Vec::new(), Tombstones::new())
+            }
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {}", from)),
+        };
+        let merged = merge_with(
+            data_path,
+            &remote_key,
+            tasks,
+            &remote,
+            &remote_tombstones,
+            prefer,
+        )?;
+        if revision(&remote, &remote_tombstones)? != revision(&merged.tasks, &merged.tombstones)?
+            || !remote_path.exists()
+        {
+            let sealed = encryption.seal(&merged.tasks, &merged.tombstones)?;
+            task::write_atomic(remote_path, sealed.as_bytes())?;
+        }
+        return finish(data_path, &remote_key, tasks, merged);
+    }
+    let remote = task::load_tasks(remote_path)?;
+    let remote_tombstones = crdt::load_tombstones(remote_path)?;
+
+    let merged = merge_with(
+        data_path,
+        &remote_key,
+        tasks,
+        &remote,
+        &remote_tombstones,
+        prefer,
+    )?;
+    task::save_tasks(remote_path, &merged.tasks)?;
+    crdt::save_tombstones(remote_path, &merged.tombstones)?;
+    finish(data_path, &remote_key, tasks, merged)
+}
+
+/// Like [`sync_file`], but for a tasks file on another machine reached with
+/// `ssh user@host`; the tombstones sidecar travels along with it.
+pub fn sync_ssh(
+    data_path: &Path,
+    tasks: &mut Vec<Task>,
+    target: &str,
+    prefer: Option<Side>,
+    encryption: Option<&Encryption>,
+) -> anyhow::Result<()> {
+    let remote = ssh::Target::parse(target)?;
+    let remote_key = format!("ssh:{}", target);
+    if let Some(encryption) = encryption {
+        let existing = remote.read(&remote.path)?;
+        let (remote_tasks, remote_tombstones) = match &existing {
+            Some(sealed) => encryption.open(sealed, target)?,
+            None => (Vec::new(), Tombstones::new()),
+        };
+        let merged = merge_with(
+            data_path,
+            &remote_key,
+            tasks,
+            &remote_tasks,
+            &remote_tombstones,
+            prefer,
+        )?;
+        if existing.is_none()
+            || revision(&remote_tasks, &remote_tombstones)?
+                != revision(&merged.tasks, &merged.tombstones)?
+        {
+            let sealed = encryption.seal(&merged.tasks, &merged.tombstones)?;
+            remote.write(&remote.path, sealed.as_bytes())?;
+        }
+        return finish(data_path, &remote_key, tasks, merged);
+    }
+    let tombstones_path = crdt::tombstone_path(Path::new(&remote.path))
+        .to_string_lossy()
+        .into_owned();
+
+    let remote_tasks: Vec<Task> = match remote.read(&remote.path)? {
+        Some(data) if !data.trim().is_empty() => {
+            let (seal, body) = checksum::split(data.as_bytes());
+            let tasks: Vec<Task> = serde_json::from_slice(body)
+                .with_context(|| format!("Failed to parse tasks file at {}", target))?;
+            checksum::check(
+                target,
+                seal.as_ref(),
+                Some(checksum::Sum::of(body)),
+                tasks.len(),
+            )?;
+            tasks
+        }
+        _ => Vec::new(),
+    };
+    let remote_tombstones: Tombstones = match remote.read(&tombstones_path)? {
+        Some(data) if !data.trim().is_empty() => serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse tombstones for {}", target))?,
+        _ => Tombstones::new(),
+    };
+
+    let merged = merge_with(
+        data_path,
+        &remote_key,
+        tasks,
+        &remote_tasks,
+        &remote_tombstones,
+        prefer,
+    )?;
+    let data = serde_json::to_string_pretty(&merged.tasks).context("Failed to serialize tasks")?;
+    let data = checksum::sealed(data.as_bytes(), merged.tasks.len());
+    remote.write(&remote.path, &data)?;
+    if !merged.tombstones.is_empty() || !remote_tombstones.is_empty() {
+        let data = serde_json::to_string_pretty(&merged.tombstones)
+            .context("Failed to serialize tombstones")?;
+        remote.write(&tombstones_path, data.as_bytes())?;
+    }
+    finish(data_path, &remote_key, tasks, merged)
+}
+
+/// Pulls from a `serve --sync` server, merges, and pushes back the changes.
+/// If someone else pushed in between, the pull and merge are simply redone.
+pub fn sync_remote(
+    data_path: &Path,
+    tasks: &mut Vec<Task>,
+    url: &str,
+    token: &str,
+    prefer: Option<Side>,
+    encryption: Option<&Encryption>,
+) -> anyhow::Result<()> {
+    let endpoint = Endpoint::parse(url)?;
+    if let Some(encryption) = encryption {
+        return sync_sealed(data_path, tasks, &endpoint, url, token, prefer, encryption);
+    }
+    let remote_key = format!("remote:{}", url.trim_end_matches('/'));
+
+    for _ in 0..MAX_PUSH_ATTEMPTS {
+        let response = endpoint.send("GET", "/sync", token, None)?;
+        check_status(url, response.status, &response.body)?;
+        let snapshot: Snapshot = serde_json::from_slice(&response.body)
+            .with_context(|| format!("Malformed sync snapshot from {}", url))?;
+
+        let merged = merge_with(
+            data_path,
+            &remote_key,
+            tasks,
+            &snapshot.tasks,
+            &snapshot.tombstones,
+            prefer,
+        )?;
+        let changes = ChangeSet::between(&snapshot, &merged);
+        if changes.is_empty() {
+            return finish(data_path, &remote_key, tasks, merged);
+        }
+
+        let body = serde_json::to_vec(&changes).context("Failed to serialize change set")?;
+        let response = endpoint.send("PUT", "/sync", token, Some(&body))?;
+        if response.status == 409 {
+            println!("Remote changed during sync; retrying.");
+            continue;
+        }
+        check_status(url, response.status, &response.body)?;
+        return finish(data_path, &remote_key, tasks, merged);
+    }
+    bail!(
+        "Remote {} kept changing; gave up after {} attempts",
+        url,
+        MAX_PUSH_ATTEMPTS
+    )
+}
+
+/// `sync_remote` through the server's sealed store, which it keeps for
+/// clients without being able to read it.
+fn sync_sealed(
+    data_path: &Path,
+    tasks: &mut Vec<Task>,
+    endpoint: &Endpoint,
+    url: &str,
+    token: &str,
+    prefer: Option<Side>,
+    encryption: &Encryption,
+) -> anyhow::Result<()> {
+    let remote_key = format!("remote:{}", url.trim_end_matches('/'));
+    for _ in 0..MAX_PUSH_ATTEMPTS {
+        let response = endpoint.send("GET", "/sync/sealed", token, None)?;
+        check_status(url, response.status, &response.body)?;
+        let sealed: Sealed = serde_json::from_slice(&response.body)
+            .with_context(|| format!("Malformed sealed snapshot from {}", url))?;
+        let (remote_tasks, remote_tombstones) = match &sealed.payload {
+            Some(payload) => encryption.open(payload, url)?,
+            None => (Vec::new(), Tombstones::new()),
+        };
+
+        let merged = merge_with(
+            data_path,
+            &remote_key,
+            tasks,
+            &remote_tasks,
+            &remote_tombstones,
+            prefer,
+        )?;
+        if sealed.payload.is_some()
+            && revision(&remote_tasks, &remote_tombstones)?
+                == revision(&merged.tasks, &merged.tombstones)?
+        {
+            return finish(data_path, &remote_key, tasks, merged);
+        }
+
+        let push = SealedPush {
+            base_revision: sealed.revision,
+            payload: encryption.seal(&merged.tasks, &merged.tombstones)?,
+        };
+        let body = serde_json::to_vec(&push).context("Failed to serialize sealed push")?;
+        let response = endpoint.send("PUT", "/sync/sealed", token, Some(&body))?;
+        if response.status == 409 {
+            println!("Remote changed during sync; retrying.");
+            continue;
+        }
+        check_status(url, response.status, &response.body)?;
+        return finish(data_path, &remote_key, tasks, merged);
+    }
+    bail!(
+        "Remote {} kept changing; gave up after {} attempts",
+        url,
+        MAX_PUSH_ATTEMPTS
+    )
+}
+
+fn check_status(url: &str, status: u16, body: &[u8]) -> anyhow::Result<()> {
+    let message = || {
+        serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| v["error"].as_str().map(str::to_owned))
+            .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_owned())
+    };
+    match status {
+        200 => Ok(()),
+        401 => bail!("{} rejected the sync token", url),
+        403 => bail!("{} refused the changes: {}", url, message()),
+        404 => bail!(
+            "{} does not offer sync (is it running `serve --sync`?)",
+            url
+        ),
+        _ => bail!("{} answered {}: {}", url, status, message()),
+    }
+}
+
+/// Runs the three-way merge against the stored base for `remote_key`, with
+/// the removals known on either side, and reports what changed locally.
+pub fn merge_with(
+    data_path: &Path,
+    remote_key: &str,
+    local: &[Task],
+    remote: &[Task],
+    remote_tombstones: &Tombstones,
+    prefer: Option<Side>,
+) -> anyhow::Result<MergeOutcome> {
+    let base = task::load_tasks(&base_path(data_path, remote_key))?;
+    let tombstones = crdt::union(&crdt::load_tombstones(data_path)?, remote_tombstones);
+    let outcome = merge::three_way(
+        &base,
+        local,
+        remote,
+        &tombstones,
+        &mut merge::resolver(prefer),
+    )?;
+    print_summary(local, &outcome.tasks, outcome.conflicts);
+    Ok(outcome)
+}
+
+/// Records the merged tasks as the new base for `remote_key` and adopts them
+/// locally. Call only once the remote side has accepted them.
+pub fn finish(
+    data_path: &Path,
+    remote_key: &str,
+    tasks: &mut Vec<Task>,
+    merged: MergeOutcome,
+) -> anyhow::Result<()> {
+    task::save_tasks(&base_path(data_path, remote_key), &merged.tasks)?;
+    crdt::save_tombstones(data_path, &merged.tombstones)?;
+    *tasks = merged.tasks;
+    Ok(())
+}
+
+fn base_path(data_path: &Path, remote_key: &str) -> PathBuf {
+    let name: String = remote_key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    data_path
+        .with_file_name("sync")
+        .join(format!("{}.json", name))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn print_summary(before: &[Task], after: &[Task], conflicts: usize) {
+    let before: HashMap<Uuid, &Task> = before.iter().map(|t| (t.uuid, t)).collect();
+    let mut added = 0;
+    let mut updated = 0;
+    for task in after {
+        match before.get(&task.uuid) {
+            None => added += 1,
+            Some(old) if **old != *task => updated += 1,
+            Some(_) => {}
+        }
+    }
+    let removed = before.len() + added - after.len();
+    println!(
+        "Synced: {} added, {} updated, {} removed, {} conflict(s) resolved.",
+        added, updated, removed, conflicts
+    );
+}
@@ -0,0 +1,182 @@
+//! Aligned tables for terminal output. Widths are measured in display
+//! columns, so wide and combining characters line up, and when stdout is a
+//! terminal the widest columns are truncated until the table fits.
+
+use std::{
+    io::{self, IsTerminal},
+    sync::Mutex,
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Columns are never truncated below this many display columns.
+const MIN_WIDTH: usize = 6;
+const GAP: &str = "  ";
+
+pub struct Table {
+    headers: Vec<String>,
+    right: Vec<bool>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            right: vec![false; headers.len()],
+            rows: Vec::new(),
+        }
+    }
+
+    /// Right-aligns the given columns, as suits numbers and durations.
+    pub fn align_right(mut self, columns: &[usize]) -> Self {
+        for &column in columns {
+            self.right[column] = true;
+        }
+        self
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        debug_assert_eq!(row.len(), self.headers.len());
+        self.rows.push(row);
+    }
+
+    pub fn print(&self) {
+        for line in self.render(terminal_width()) {
+            println!("{}", line);
+        }
+    }
+
+    pub fn render(&self, max_width: Option<usize>) -> Vec<String> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.width()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.width());
+            }
+        }
+        if let Some(max) = max_width {
+            let gaps = GAP.len() * widths.len().saturating_sub(1);
+            while widths.iter().sum::<usize>() + gaps > max {
+                let Some(widest) = widths
+                    .iter_mut()
+                    .filter(|w| **w > MIN_WIDTH)
+                    .max_by_key(|w| **w)
+                else {
+                    break;
+                };
+                *widest -= 1;
+            }
+        }
+
+        std::iter::once(&self.headers)
+            .chain(&self.rows)
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .zip(&self.right)
+                    .map(|((cell, &width), &right)| pad(&truncate(cell, width), width, right))
+                    .collect();
+                cells.join(GAP).trim_end().to_owned()
+            })
+            .collect()
+    }
+}
+
+/// The width `terminal_width` gives instead of looking, while the daemon
+/// prints for a client whose terminal it cannot see.
+static FORCED: Mutex<Option<Option<usize>>> = Mutex::new(None);
+
+pub fn force_width(width: Option<Option<usize>>) {
+    *FORCED.lock().unwrap_or_else(|e| e.into_inner()) = width;
+}
+
+/// The terminal's width in columns, or `None` when stdout is not a terminal
+/// (then nothing is truncated, so pipes get the full text).
+pub fn terminal_width() -> Option<usize> {
+    if let Some(width) = *FORCED.lock().unwrap_or_else(|e| e.into_inner()) {
+        return width;
+    }
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    terminal_size::terminal_size().map(|(width, _)| usize::from(width.0))
+}
+
+/// Shortens `text` to at most `width` display columns, marking the cut.
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_owned();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+/// Pads `text` with spaces to `width` display columns.
+pub fn pad(text: &str, width: usize, right: bool) -> String {
+    let fill = " ".repeat(width.saturating_sub(text.width()));
+    if right {
+        format!("{}{}", fill, text)
+    } else {
+        format!("{}{}", text, fill)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncation_counts_display_columns() {
+        assert_eq!(truncate("short", 5), "short");
+        assert_eq!(truncate("shorter", 5), "shor…");
+        // Wide characters take two columns; one that would overflow is
+        // dropped whole, leaving the cell a column short rather than over.
+        assert_eq!(truncate("日本語のメモ", 12), "日本語のメモ");
+        assert_eq!(truncate("日本語のメモ", 6), "日本…");
+        assert_eq!(truncate("日本語のメモ", 6).width(), 5);
+        assert_eq!(truncate("🎉🎉🎉🎉", 6), "🎉🎉…");
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_letter() {
+        let cafe = "cafe\u{301} au lait";
+        assert_eq!(cafe.width(), 12);
+        assert_eq!(truncate(cafe, 12), cafe);
+        assert_eq!(truncate(cafe, 5), "cafe\u{301}…");
+        assert_eq!(truncate("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}…");
+    }
+
+    #[test]
+    fn wide_cells_line_up() {
+        assert_eq!(pad("日本", 6, false), "日本  ");
+        assert_eq!(pad("e\u{301}", 3, true), "  e\u{301}");
+
+        let mut table = Table::new(&["ID", "Description"]).align_right(&[0]);
+        table.push(vec!["1".to_owned(), "日本語のメモを書く".to_owned()]);
+        table.push(vec!["12".to_owned(), "cafe\u{301}".to_owned()]);
+        assert_eq!(
+            table.render(None),
+            [
+                "ID  Description",
+                " 1  日本語のメモを書く",
+                "12  cafe\u{301}"
+            ]
+        );
+        let narrow = table.render(Some(14));
+        assert_eq!(
+            narrow,
+            ["ID  Descripti…", " 1  日本語の…", "12  cafe\u{301}"]
+        );
+        assert!(narrow.iter().all(|line| line.width() <= 14));
+    }
+}
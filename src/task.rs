@@ -1,60 +1,523 @@
+use crate::{
+    attribute, checksum,
+    crdt::{Clock, Stamp},
+    deps, duration, events,
+    filter::Filter,
+    habit::{self, Cadence},
+    index, journal, scan, storage,
+    table::Table,
+    template::{self, Template},
+};
 use anyhow::{Context, bail};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::{fs, io::Write, path::Path};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    fmt, fs,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Task {
     pub id: u32,
+    /// Stable identity across machines; files written before sync existed
+    /// get one assigned on load and keep it from the next save onwards.
+    #[serde(default = "Uuid::new_v4")]
+    pub uuid: Uuid,
     pub description: String,
     pub completed: bool,
+    /// Pinned tasks are listed first whatever the sort order.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Captured with `inbox` and not yet processed by `clarify`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inbox: bool,
+    /// Parked in the someday/maybe bucket: out of `list` and `next` until
+    /// brought back, but still shown by `review`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub someday: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// How the task was resolved, given with `done --note`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Free-form labels, lowercase, shown as `+name`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Name of the milestone this task counts towards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
+    /// The day suggested for working on the task, as set by `schedule`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<NaiveDate>,
+    /// Expected effort in minutes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<u32>,
+    /// Who the task is delegated to; while set, the task is waiting on them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waiting_on: Option<String>,
+    /// Tasks (by uuid) that must be finished before this one can start.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<Uuid>,
+    /// Position in the hand-curated order set with `move-to` and friends.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<u32>,
+    /// The task this one is a subtask of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Uuid>,
+    /// The `TODO`/`FIXME` comment this task was created from by `scan`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<scan::Location>,
+    /// Where the task came from outside this list, such as the `mid:` URL
+    /// of the email it was created from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// User-defined attributes, declared in config.json; see `attribute`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checklist: Vec<ChecklistItem>,
+    /// Set for habits, which `done` records an occurrence of instead of
+    /// completing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub habit: Option<Cadence>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub occurrences: Vec<DateTime<Utc>>,
+    /// Time actually spent, as recorded by `start`/`stop`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub time_log: Vec<Interval>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<Comment>,
+    /// Lifecycle events such as completions and reopens, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoryEntry>,
+    /// When each field was last written, for last-writer-wins merging.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub stamps: BTreeMap<String, Stamp>,
 }
 
-pub fn load_tasks(path: &Path) -> anyhow::Result<Vec<Task>> {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+
+    /// `levels` steps more urgent, stopping at `Urgent`.
+    pub fn raised(self, levels: u32) -> Priority {
+        const ORDER: [Priority; 4] = [
+            Priority::Low,
+            Priority::Medium,
+            Priority::High,
+            Priority::Urgent,
+        ];
+        let index = ORDER.iter().position(|p| *p == self).unwrap_or(0);
+        ORDER[(index + levels as usize).min(ORDER.len() - 1)]
+    }
+}
+
+/// One step of a task's checklist; lighter than a subtask, with no id,
+/// status, or metadata of its own.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    pub text: String,
+    pub done: bool,
+}
+
+/// A span of time spent on a task; `end` is `None` while the timer runs.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interval {
+    pub start: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl Interval {
+    pub fn minutes(&self) -> u32 {
+        self.minutes_within(None, None)
+    }
+
+    /// Minutes of this interval falling inside `[from, to)`; open bounds are
+    /// unlimited.
+    pub fn minutes_within(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> u32 {
+        let mut start = self.start;
+        let mut end = self.end.unwrap_or_else(Utc::now);
+        if let Some(from) = from {
+            start = start.max(from);
+        }
+        if let Some(to) = to {
+            end = end.min(to);
+        }
+        (end - start).num_minutes().max(0) as u32
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub at: DateTime<Utc>,
+    pub event: String,
+}
+
+/// A remark left on a shared task by one of its collaborators. Replies point
+/// at the comment they answer, forming threads.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: Uuid,
+    pub author: String,
+    pub at: DateTime<Utc>,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<Uuid>,
+}
+
+impl Task {
+    pub fn is_running(&self) -> bool {
+        self.time_log.last().is_some_and(|i| i.end.is_none())
+    }
+
+    /// Minutes tracked so far, counting a running timer up to now.
+    pub fn tracked_minutes(&self) -> u32 {
+        self.time_log.iter().map(Interval::minutes).sum()
+    }
+
+    /// Checklist progress as "done/total", if the task has a checklist.
+    pub fn checklist_progress(&self) -> Option<String> {
+        if self.checklist.is_empty() {
+            return None;
+        }
+        let done = self.checklist.iter().filter(|item| item.done).count();
+        Some(format!("{}/{}", done, self.checklist.len()))
+    }
+
+    /// Records that `field` was just changed on this machine. Every mutation
+    /// must call this for each field it writes, or sync will not see the edit
+    /// as newer than the other side's.
+    pub fn touch(&mut self, field: &str, clock: &Clock) {
+        self.stamps.insert(field.to_owned(), clock.tick());
+    }
+
+    /// Appends `event` to the task's history.
+    pub fn log(&mut self, event: &str, clock: &Clock) {
+        self.history.push(HistoryEntry {
+            at: Utc::now(),
+            event: event.to_owned(),
+        });
+        self.touch("history", clock);
+    }
+}
+
+/// Reads a task array one element at a time into `tasks`, so whatever
+/// parsed before an error is still there afterwards.
+struct TaskSeq<'a> {
+    tasks: &'a mut Vec<Task>,
+    started: &'a mut bool,
+}
+
+impl<'de> serde::de::DeserializeSeed<'de> for TaskSeq<'_> {
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for TaskSeq<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of tasks")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        *self.started = true;
+        while let Some(task) = seq.next_element()? {
+            self.tasks.push(task);
+        }
+        Ok(())
+    }
+}
+
+/// Where a tasks file with a damaged tail is copied before the tasks read
+/// from it can be saved over it.
+fn damaged_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "tasks.json".to_owned());
+    path.with_file_name(format!("{}.damaged", name))
+}
+
+/// Reads the tasks file as a stream rather than one string. A file cut off
+/// partway, as a full disk or a crash during a non-atomic copy leaves it,
+/// still loads the tasks before the cut, with a warning, after the file is
+/// copied aside whole; the next save writes just those. Damage anywhere
+/// else is an error, for `repair` to deal with.
+///
+/// Also returns the checksum of the file after its first line (see
+/// `checksum`), or `None` if it was damaged.
+pub fn read_tasks(path: &Path) -> anyhow::Result<(Vec<Task>, Option<checksum::Sum>)> {
     if !path.exists() {
-        return Ok(Vec::new());
+        return Ok((Vec::new(), Some(checksum::Sum::default())));
     }
 
-    let data = fs::read_to_string(path)
+    let mut file = fs::File::open(path)
+        .with_context(|| format!("Failed to read tasks file at {}", path.display()))?;
+    checksum::skip_header(&mut file)
         .with_context(|| format!("Failed to read tasks file at {}", path.display()))?;
+    let mut reader = BufReader::new(checksum::Summing::new(file));
+    let first = reader
+        .fill_buf()
+        .with_context(|| format!("Failed to read tasks file at {}", path.display()))?
+        .first()
+        .copied();
+    if first.is_some_and(storage::is_msgpack) {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .with_context(|| format!("Failed to read tasks file at {}", path.display()))?;
+        let tasks = storage::decode(&data)
+            .with_context(|| format!("Failed to parse tasks file at {}", path.display()))?;
+        return Ok((tasks, Some(reader.into_inner().sum)));
+    }
+    if first.is_some_and(events::is_log) {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .with_context(|| format!("Failed to read tasks file at {}", path.display()))?;
+        let (tasks, cut) = events::tasks(&data)
+            .with_context(|| format!("Failed to parse tasks file at {}", path.display()))?;
+        if cut {
+            eprintln!(
+                "Warning: the last event in {} is cut off; loaded the {} task(s) before it.",
+                path.display(),
+                tasks.len()
+            );
+            return Ok((tasks, None));
+        }
+        return Ok((tasks, Some(reader.into_inner().sum)));
+    }
+    let mut deserializer = serde_json::Deserializer::from_reader(&mut reader);
+    let mut tasks = Vec::new();
+    let mut started = false;
+    let seed = TaskSeq {
+        tasks: &mut tasks,
+        started: &mut started,
+    };
+    let (err, after_list) = match serde::de::DeserializeSeed::deserialize(seed, &mut deserializer) {
+        Ok(()) => match deserializer.end() {
+            Ok(()) => return Ok((tasks, Some(reader.into_inner().sum))),
+            Err(err) => (err, true),
+        },
+        // Nothing but whitespace.
+        Err(err) if err.is_eof() && !started => {
+            return Ok((Vec::new(), Some(reader.into_inner().sum)));
+        }
+        Err(err) => (err, false),
+    };
+    if err.is_io() {
+        return Err(err)
+            .with_context(|| format!("Failed to read tasks file at {}", path.display()));
+    }
+    // Cut off partway through the list, or followed by stray bytes after a
+    // complete one: either way every task read so far is whole.
+    if !(after_list || err.is_eof() && started) {
+        return Err(err).with_context(|| {
+            format!(
+                "Failed to parse tasks file at {}. Ensure it contains valid JSON or run `repair` to salvage it.",
+                path.display()
+            )
+        });
+    }
 
-    if data.trim().is_empty() {
-        return Ok(Vec::new());
+    let damaged = damaged_path(path);
+    let copied = fs::metadata(&damaged)
+        .is_ok_and(|m| fs::metadata(path).is_ok_and(|current| current.len() == m.len()));
+    if !copied {
+        fs::copy(path, &damaged).with_context(|| {
+            format!("Failed to copy damaged tasks file to {}", damaged.display())
+        })?;
     }
+    eprintln!(
+        "Warning: {} is damaged at line {}, column {} ({}); loaded the {} task(s) before it. The whole file is kept at {}.",
+        path.display(),
+        err.line(),
+        err.column(),
+        if after_list {
+            "unexpected data after the list"
+        } else {
+            "it ends early"
+        },
+        tasks.len(),
+        damaged.display()
+    );
+    Ok((tasks, None))
+}
 
-    let tasks = serde_json::from_str(&data).with_context(|| {
-        format!(
-            "Failed to parse tasks file at {}. Ensure it contains valid JSON.",
-            path.display()
-        )
-    })?;
+/// Writes `bytes` to `out`, indenting every line after the first by two
+/// spaces, as elements of a pretty-printed array are.
+fn write_indented(out: &mut dyn Write, bytes: &[u8]) -> std::io::Result<usize> {
+    let mut written = 0;
+    for (i, line) in bytes.split(|b| *b == b'\n').enumerate() {
+        if i > 0 {
+            out.write_all(b"\n  ")?;
+            written += 3;
+        }
+        out.write_all(line)?;
+        written += line.len();
+    }
+    Ok(written)
+}
+
+/// Loads the tasks file, first finishing a save a crash interrupted, and
+/// checks it against its checksum.
+pub fn load_tasks(path: &Path) -> anyhow::Result<Vec<Task>> {
+    let (mut tasks, sum) = read_tasks(path)?;
+    if journal::is_pending(path) {
+        // The checksum may be from before the interrupted save.
+        let replayed = journal::replay(path, &mut tasks)?;
+        if replayed > 0 {
+            eprintln!(
+                "Warning: the last save of {} did not finish; restored {} task(s) from its journal.",
+                path.display(),
+                replayed
+            );
+        }
+        save_tasks(path, &tasks)?;
+        return Ok(tasks);
+    }
+    checksum::verify(path, sum, &tasks)?;
     Ok(tasks)
 }
 
+/// Saves in the format the file is already stored in.
 pub fn save_tasks(path: &Path, tasks: &[Task]) -> anyhow::Result<()> {
+    save_tasks_as(path, tasks, storage::detect(path))
+}
+
+/// Saves in `format`, journaling the edits first so a crash partway
+/// through loses none of them.
+pub fn save_tasks_as(path: &Path, tasks: &[Task], format: storage::Format) -> anyhow::Result<()> {
+    journal::record(path, tasks)?;
+    write_tasks(path, tasks, format)?;
+    let latest = tasks.iter().flat_map(|t| t.stamps.values()).max().copied();
+    journal::commit(path, latest)
+}
+
+/// Writes JSON one task at a time in the layout `serde_json` gives a
+/// pretty-printed array, so only one task is ever serialized in memory.
+fn write_tasks(path: &Path, tasks: &[Task], format: storage::Format) -> anyhow::Result<()> {
+    if format == storage::Format::Msgpack {
+        let data = storage::encode(tasks)?;
+        checksum::write_sealed(path, tasks.len(), &mut |out| Ok(out.write_all(&data)?))?;
+        // Task offsets are only tracked in JSON; this drops any old index.
+        let _ = index::write(path, &[], tasks);
+        return Ok(());
+    }
+    if format == storage::Format::Events {
+        events::save(path, tasks)?;
+        let _ = index::write(path, &[], tasks);
+        return Ok(());
+    }
+    let mut spans = Vec::with_capacity(tasks.len());
+    checksum::write_sealed(path, tasks.len(), &mut |out| {
+        if tasks.is_empty() {
+            out.write_all(b"[]")?;
+            return Ok(());
+        }
+        out.write_all(b"[")?;
+        let mut at = checksum::HEADER_LEN + 1;
+        for (i, task) in tasks.iter().enumerate() {
+            let separator: &[u8] = if i == 0 { b"\n  " } else { b",\n  " };
+            out.write_all(separator)?;
+            at += separator.len();
+            let object =
+                serde_json::to_vec_pretty(task).context("Failed to serialize tasks to JSON")?;
+            let written = write_indented(out, &object)?;
+            spans.push((at, at + written));
+            at += written;
+        }
+        out.write_all(b"\n]")?;
+        Ok(())
+    })?;
+    // The tasks are safely saved; an index that cannot be written only
+    // means the next `show` or `done` parses everything.
+    let _ = index::write(path, &spans, tasks);
+    Ok(())
+}
+
+/// Writes `data` to a sibling temp file, syncs it, then renames it over
+/// `path`, so readers see either the old contents or the new, never half.
+pub fn write_atomic(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    write_atomic_with(path, &mut |out| out.write_all(data).map_err(Into::into))
+}
+
+/// `write_atomic` for contents produced piece by piece by `write`.
+pub fn write_atomic_with(
+    path: &Path,
+    write: &mut dyn FnMut(&mut dyn Write) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    write_atomic_file(path, &mut |file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.flush()?;
+        Ok(())
+    })
+}
+
+/// `write_atomic` for contents written straight to the temp file, which
+/// `write` may seek around in.
+pub fn write_atomic_file(
+    path: &Path,
+    write: &mut dyn FnMut(&mut fs::File) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .with_context(|| format!("Failed to create data directory at {}", parent.display()))?;
     }
-    let data = serde_json::to_string_pretty(tasks).context("Failed to serialize tasks to JSON")?;
 
     let tmp_path = path.with_extension("tmp");
     {
         let mut file = fs::File::create(&tmp_path).with_context(|| {
-            format!(
-                "Failed to create temporary tasks file at {}",
-                tmp_path.display()
-            )
+            format!("Failed to create temporary file at {}", tmp_path.display())
         })?;
-        file.write_all(data.as_bytes())
-            .with_context(|| format!("Failed to write tasks to {}", tmp_path.display()))?;
+        write(&mut file)
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&tmp_path);
+            })
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
         file.sync_all()
-            .with_context(|| format!("Failed to flush tasks to {}", tmp_path.display()))?;
+            .with_context(|| format!("Failed to flush {}", tmp_path.display()))?;
     }
 
-    fs::rename(&tmp_path, path)
-        .map_err(|err| {
+    replace(&tmp_path, path)
+        .inspect_err(|_| {
             let _ = fs::remove_file(&tmp_path);
-            err
         })
         .with_context(|| {
             format!(
@@ -63,57 +526,1489 @@ pub fn save_tasks(path: &Path, tasks: &[Task]) -> anyhow::Result<()> {
                 tmp_path.display()
             )
         })?;
+    sync_dir(path).with_context(|| format!("Failed to flush the directory of {}", path.display()))
+}
+
+/// Moves `from` over `to`, which readers then see whole or not at all.
+#[cfg(not(windows))]
+fn replace(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::rename(from, to)
+}
+
+/// Moves `from` over `to` with `ReplaceFileW` when `to` exists, which keeps
+/// its attributes and permissions, and otherwise with a write-through move
+/// that returns only once the move is on disk.
+#[cfg(windows)]
+fn replace(from: &Path, to: &Path) -> std::io::Result<()> {
+    use std::{ffi::c_void, os::windows::ffi::OsStrExt, ptr};
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn ReplaceFileW(
+            replaced: *const u16,
+            replacement: *const u16,
+            backup: *const u16,
+            flags: u32,
+            exclude: *mut c_void,
+            reserved: *mut c_void,
+        ) -> i32;
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+    const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+    const MOVEFILE_WRITE_THROUGH: u32 = 0x8;
+
+    let wide = |p: &Path| -> Vec<u16> { p.as_os_str().encode_wide().chain([0]).collect() };
+    let (from_wide, to_wide) = (wide(from), wide(to));
+    // SAFETY: both paths are NUL-terminated and outlive the call, and the
+    // optional arguments are null, which both functions accept.
+    let ok = unsafe {
+        if to.exists() {
+            ReplaceFileW(
+                to_wide.as_ptr(),
+                from_wide.as_ptr(),
+                ptr::null(),
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        } else {
+            MoveFileExW(
+                from_wide.as_ptr(),
+                to_wide.as_ptr(),
+                MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH,
+            )
+        }
+    };
+    if ok == 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Syncs the directory holding `path`, without which the rename that put
+/// it there can be lost on power failure even though the data is on disk.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> std::io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    fs::File::open(dir)?.sync_all()
+}
+
+/// Elsewhere directories cannot be opened to sync; on Windows `replace`
+/// has already waited for the move to reach the disk.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
-pub fn add_task(tasks: &mut Vec<Task>, description: String) -> anyhow::Result<()> {
+/// Optional attributes given when a task is created.
+#[derive(Default)]
+pub struct NewTask {
+    pub project: Option<String>,
+    pub milestone: Option<String>,
+    pub priority: Option<Priority>,
+    pub due: Option<NaiveDate>,
+    pub estimate: Option<u32>,
+    pub habit: Option<Cadence>,
+    pub tags: Vec<String>,
+}
+
+pub fn add_task(
+    tasks: &mut Vec<Task>,
+    description: String,
+    details: NewTask,
+    clock: &Clock,
+) -> anyhow::Result<u32> {
     let description = description.trim();
     if description.is_empty() {
         bail!("Task description cannot be empty");
     }
+    let project = details
+        .project
+        .map(|p| p.trim().to_owned())
+        .filter(|p| !p.is_empty());
 
     let next_id = tasks.iter().map(|t| t.id).max().unwrap_or(0) + 1;
-    tasks.push(Task {
+    let mut task = Task {
         id: next_id,
+        uuid: Uuid::new_v4(),
         description: description.to_owned(),
         completed: false,
-    });
-    Ok(())
+        pinned: false,
+        someday: false,
+        inbox: false,
+        completed_at: None,
+        note: None,
+        project,
+        tags: details.tags,
+        milestone: details.milestone,
+        assignee: None,
+        priority: details.priority,
+        due: details.due,
+        scheduled: None,
+        estimate: details.estimate,
+        waiting_on: None,
+        depends_on: Vec::new(),
+        order: None,
+        parent: None,
+        source: None,
+        link: None,
+        attributes: BTreeMap::new(),
+        checklist: Vec::new(),
+        habit: details.habit,
+        occurrences: Vec::new(),
+        time_log: Vec::new(),
+        comments: Vec::new(),
+        history: Vec::new(),
+        stamps: BTreeMap::new(),
+    };
+    task.touch("description", clock);
+    task.touch("completed", clock);
+    if task.project.is_some() {
+        task.touch("project", clock);
+    }
+    if !task.tags.is_empty() {
+        task.touch("tags", clock);
+    }
+    if task.milestone.is_some() {
+        task.touch("milestone", clock);
+    }
+    if task.priority.is_some() {
+        task.touch("priority", clock);
+    }
+    if task.due.is_some() {
+        task.touch("due", clock);
+    }
+    if task.estimate.is_some() {
+        task.touch("estimate", clock);
+    }
+    if task.habit.is_some() {
+        task.touch("habit", clock);
+    }
+    task.log(ADDED, clock);
+    tasks.push(task);
+    Ok(next_id)
 }
 
-pub fn list_tasks(tasks: &[Task], all: bool) {
-    let mut shown = false;
-    for task in tasks.iter().filter(|t| all || !t.completed) {
-        let status = if task.completed { "[x]" } else { "[ ]" };
-        println!("{} {}: {}", status, task.id, task.description);
-        shown = true;
+/// Copies a task's description, project, tags, milestone, priority,
+/// estimate, attributes, and checklist (all unticked) into a new open task and returns
+/// its id. Time, comments, and relationships stay with the original.
+pub fn clone_task(
+    tasks: &mut Vec<Task>,
+    id: u32,
+    due: Option<NaiveDate>,
+    clock: &Clock,
+) -> anyhow::Result<u32> {
+    let Some(source) = tasks.iter().find(|t| t.id == id).cloned() else {
+        bail!("No task with id {}", id);
+    };
+    let details = NewTask {
+        project: source.project,
+        milestone: source.milestone,
+        priority: source.priority,
+        due: due.or(source.due),
+        estimate: source.estimate,
+        habit: source.habit,
+        tags: source.tags,
+    };
+    let new_id = add_task(tasks, source.description, details, clock)?;
+
+    if !source.attributes.is_empty() {
+        let copy = find_task_mut(tasks, new_id)?;
+        copy.attributes = source.attributes;
+        copy.touch("attributes", clock);
     }
+    if !source.checklist.is_empty() {
+        let copy = find_task_mut(tasks, new_id)?;
+        copy.checklist = source
+            .checklist
+            .into_iter()
+            .map(|item| ChecklistItem {
+                done: false,
+                ..item
+            })
+            .collect();
+        copy.touch("checklist", clock);
+    }
+    Ok(new_id)
+}
 
-    if !shown {
-        if tasks.is_empty() {
+/// Which tasks `list` shows.
+#[derive(Clone)]
+pub struct ListFilter {
+    pub all: bool,
+    pub assignee: Option<String>,
+    pub project: Option<String>,
+    pub milestone: Option<String>,
+    /// Only delegated tasks, grouped by who they are waiting on.
+    pub delegated: bool,
+    /// Only tasks estimated at most this many minutes.
+    pub max_estimate: Option<u32>,
+    /// Only tasks waiting on an unfinished dependency.
+    pub blocked: bool,
+    /// Only tasks parked in the someday/maybe bucket.
+    pub someday: bool,
+    /// Keep someday tasks among the rest instead of leaving them out.
+    pub with_someday: bool,
+    /// Only tasks with these attribute values.
+    pub attributes: Vec<(String, String)>,
+    /// Only tasks carrying all of these tags.
+    pub tags: Vec<String>,
+    /// Only tasks matching this `--filter` expression.
+    pub expression: Option<Filter>,
+    /// Only tasks in the active context; see `focus`.
+    pub context: Option<Filter>,
+    /// Only the tasks with these ids, as a filter script picked them.
+    pub only: Option<HashSet<u32>>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum SortKey {
+    Id,
+    /// Oldest first, by when the task was added
+    Created,
+    /// Shortest estimate first
+    Estimate,
+    /// Most urgent first, then soonest due
+    Priority,
+    /// Soonest due first
+    Due,
+    /// By project name
+    Project,
+    /// By description, alphabetically
+    Description,
+    /// The hand-curated order
+    Manual,
+}
+
+/// A `--sort` order: comma-separated keys, each compared only where the
+/// ones before it tie, e.g. `priority,-due,created`. A `-` reverses a key.
+/// Tasks missing a key's value come after those that have it either way,
+/// and ties left at the end go by id.
+#[derive(Clone)]
+pub struct Sort(Vec<(SortKey, bool)>);
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort(vec![(SortKey::Id, false)])
+    }
+}
+
+impl FromStr for Sort {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> anyhow::Result<Self> {
+        let mut keys = Vec::new();
+        for word in input.split(',').map(str::trim) {
+            let (name, descending) = match word.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (word.strip_prefix('+').unwrap_or(word), false),
+            };
+            let Ok(key) = SortKey::from_str(name, true) else {
+                let names: Vec<String> = SortKey::value_variants()
+                    .iter()
+                    .filter_map(|k| k.to_possible_value())
+                    .map(|v| v.get_name().to_owned())
+                    .collect();
+                bail!("Unknown sort key {:?} (known: {})", name, names.join(", "));
+            };
+            if keys.iter().any(|(k, _)| *k == key) {
+                bail!("Sort key {} is given twice", name);
+            }
+            keys.push((key, descending));
+        }
+        Ok(Sort(keys))
+    }
+}
+
+/// Orders two optional values, missing ones last however the rest go.
+fn by<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+    }
+}
+
+/// The history event `add_task` records, which `--sort created` goes by.
+pub const ADDED: &str = "added";
+
+/// When the task was added; unknown for tasks from before that was logged.
+fn created(task: &Task) -> Option<DateTime<Utc>> {
+    task.history
+        .iter()
+        .find(|entry| entry.event == ADDED)
+        .map(|entry| entry.at)
+}
+
+impl Sort {
+    pub fn compare(&self, a: &Task, b: &Task) -> Ordering {
+        let lower = |text: &Option<String>| text.as_ref().map(|t| t.to_lowercase());
+        self.0
+            .iter()
+            .map(|(key, descending)| {
+                let descending = *descending;
+                match key {
+                    SortKey::Id => by(Some(a.id), Some(b.id), descending),
+                    SortKey::Created => by(created(a), created(b), descending),
+                    SortKey::Estimate => by(a.estimate, b.estimate, descending),
+                    SortKey::Priority => by(
+                        a.priority.map(std::cmp::Reverse),
+                        b.priority.map(std::cmp::Reverse),
+                        descending,
+                    )
+                    .then_with(|| by(a.due, b.due, descending)),
+                    SortKey::Due => by(a.due, b.due, descending),
+                    SortKey::Project => by(lower(&a.project), lower(&b.project), descending),
+                    SortKey::Description => by(
+                        Some(a.description.to_lowercase()),
+                        Some(b.description.to_lowercase()),
+                        descending,
+                    ),
+                    SortKey::Manual => by(a.order, b.order, descending),
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.id.cmp(&b.id))
+    }
+
+    pub fn sort(&self, tasks: &mut [&Task]) {
+        tasks.sort_by(|a, b| self.compare(a, b));
+    }
+
+    /// Sorts, then floats pinned tasks to the top; the sort is stable, so
+    /// pinned tasks keep this order among themselves.
+    fn sort_pinned_first(&self, tasks: &mut [&Task]) {
+        self.sort(tasks);
+        tasks.sort_by_key(|t| !t.pinned);
+    }
+}
+
+/// One stretch of a longer listing, for paging through it. Every sort
+/// breaks ties by id, so the same page always holds the same tasks.
+#[derive(Clone, Copy, Default)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Page {
+    pub fn apply<T>(&self, items: &mut Vec<T>) {
+        items.drain(..self.offset.min(items.len()));
+        if let Some(limit) = self.limit {
+            items.truncate(limit);
+        }
+    }
+}
+
+impl ListFilter {
+    pub fn matches(&self, tasks: &[Task], task: &Task) -> bool {
+        let picks_status = self.expression.as_ref().is_some_and(Filter::sets_status);
+        let someday = self.with_someday || task.someday == self.someday;
+        (self.all || picks_status || (!task.completed && someday))
+            && (!self.someday || task.someday)
+            && matches_name(self.assignee.as_deref(), task.assignee.as_deref())
+            && matches_name(self.project.as_deref(), task.project.as_deref())
+            && matches_name(self.milestone.as_deref(), task.milestone.as_deref())
+            && (!self.delegated || task.waiting_on.is_some())
+            && self
+                .max_estimate
+                .is_none_or(|max| task.estimate.is_some_and(|e| e <= max))
+            && (!self.blocked || deps::is_blocked(tasks, task))
+            && attribute::matches(task, &self.attributes)
+            && self.tags.iter().all(|tag| task.tags.contains(tag))
+            && self
+                .expression
+                .as_ref()
+                .is_none_or(|e| e.matches(tasks, task))
+            && self.context.as_ref().is_none_or(|c| c.matches(tasks, task))
+            && self.only.as_ref().is_none_or(|ids| ids.contains(&task.id))
+    }
+
+    fn is_narrowed(&self) -> bool {
+        self.assignee.is_some()
+            || self.project.is_some()
+            || self.milestone.is_some()
+            || self.delegated
+            || self.someday
+            || self.max_estimate.is_some()
+            || self.blocked
+            || !self.attributes.is_empty()
+            || !self.tags.is_empty()
+            || self.expression.is_some()
+            || self.context.is_some()
+            || self.only.is_some()
+    }
+}
+
+fn manual_order(task: &Task) -> impl Ord {
+    (task.order.is_none(), task.order, task.id)
+}
+
+fn matches_name(wanted: Option<&str>, actual: Option<&str>) -> bool {
+    wanted.is_none_or(|w| actual.is_some_and(|a| a.eq_ignore_ascii_case(w)))
+}
+
+/// How `list`, `next`, and `today` print tasks.
+pub enum Layout<'a> {
+    /// Through a `--format` template; meant for scripts, so nothing else is
+    /// printed.
+    Template(&'a Template),
+    /// An aligned table of these fields (see `template::parse_field`).
+    Table(Vec<String>),
+}
+
+/// Table columns when neither `--columns` nor config.json names any.
+pub const DEFAULT_COLUMNS: &[&str] = &[
+    "id",
+    "status",
+    "flags",
+    "priority",
+    "due",
+    "project",
+    "tags",
+    "description",
+];
+
+pub fn print_table(tasks: &[Task], shown: &[&Task], columns: &[String]) {
+    let headers: Vec<String> = columns
+        .iter()
+        .map(|c| match c.as_str() {
+            "id" => "ID".to_owned(),
+            _ => {
+                let mut header = c.replace('_', " ");
+                header[..1].make_ascii_uppercase();
+                header
+            }
+        })
+        .collect();
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    let numeric: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| ["id", "estimate", "tracked"].contains(&c.as_str()))
+        .map(|(i, _)| i)
+        .collect();
+    let mut table = Table::new(&headers).align_right(&numeric);
+    for task in shown {
+        table.push(
+            columns
+                .iter()
+                .map(|c| template::field(tasks, task, c))
+                .collect(),
+        );
+    }
+    table.print();
+}
+
+/// Prints the matching tasks in `layout`, with delegated tasks in a table
+/// per person.
+pub fn list_tasks(tasks: &[Task], filter: &ListFilter, sort: &Sort, page: Page, layout: &Layout) {
+    let mut matching: Vec<&Task> = tasks.iter().filter(|t| filter.matches(tasks, t)).collect();
+    sort.sort_pinned_first(&mut matching);
+    let total = matching.len();
+    page.apply(&mut matching);
+    let cut = page.offset > 0 || matching.len() < total;
+    let columns = match layout {
+        Layout::Template(template) => {
+            for task in matching {
+                println!("{}", template.render(tasks, task));
+            }
+            return;
+        }
+        Layout::Table(columns) => columns,
+    };
+    let shown = !matching.is_empty();
+    let last = page.offset + matching.len();
+
+    if filter.delegated {
+        let mut groups: BTreeMap<String, Vec<&Task>> = BTreeMap::new();
+        for task in matching {
+            let person = task.waiting_on.clone().unwrap_or_default();
+            groups.entry(person.to_lowercase()).or_default().push(task);
+        }
+        for (i, group) in groups.values().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", group[0].waiting_on.as_deref().unwrap_or_default());
+            print_table(tasks, group, columns);
+        }
+    } else if shown {
+        print_table(tasks, &matching, columns);
+    }
+
+    if shown && cut {
+        println!("({}-{} of {} tasks)", page.offset + 1, last, total);
+    } else if !shown {
+        if total > 0 {
+            println!("No tasks on this page; {} match in all.", total);
+        } else if tasks.is_empty() {
             println!("No tasks found.");
+        } else if filter.is_narrowed() {
+            println!("No matching tasks.");
         } else {
             println!("No tasks to show (use --all to include completed).");
         }
     }
 }
 
-pub fn mark_done(tasks: &mut Vec<Task>, id: u32) -> anyhow::Result<()> {
+pub fn format_line(tasks: &[Task], task: &Task) -> String {
+    let status = if task.completed { "[x]" } else { "[ ]" };
+    let mut extras = Vec::new();
+    if task.pinned {
+        extras.push("pinned".to_owned());
+    }
+    if task.someday {
+        extras.push("someday".to_owned());
+    }
+    if task.inbox {
+        extras.push("inbox".to_owned());
+    }
+    if let Some(project) = &task.project {
+        extras.push(format!("project: {}", project));
+    }
+    for tag in &task.tags {
+        extras.push(format!("+{}", tag));
+    }
+    if let Some(milestone) = &task.milestone {
+        extras.push(format!("milestone: {}", milestone));
+    }
+    if let Some(who) = &task.assignee {
+        extras.push(format!("@{}", who));
+    }
+    if let Some(priority) = task.priority {
+        extras.push(format!("priority: {}", priority.name()));
+    }
+    if let Some(due) = task.due.filter(|_| !task.completed) {
+        let days = (due - Local::now().date_naive()).num_days();
+        extras.push(match days {
+            0 => "due today".to_owned(),
+            d if d < 0 => format!("overdue by {} day(s)", -d),
+            _ => format!("due {}", due),
+        });
+    }
+    if let Some(day) = task.scheduled.filter(|_| !task.completed) {
+        extras.push(format!("scheduled {}", day));
+    }
+    if let Some(minutes) = task.estimate {
+        extras.push(format!("est {}", duration::format_minutes(minutes)));
+    }
+    if let Some(progress) = task.checklist_progress() {
+        extras.push(format!("checklist {}", progress));
+    }
+    if let (Some(cadence), Some(progress)) = (task.habit, habit::progress(task)) {
+        extras.push(format!("habit {}, {}", cadence, progress));
+    }
+    if !task.time_log.is_empty() {
+        extras.push(format!(
+            "tracked {}",
+            duration::format_minutes(task.tracked_minutes())
+        ));
+    }
+    if task.is_running() {
+        extras.push("running".to_owned());
+    }
+    let blockers = deps::blockers(tasks, task);
+    if !blockers.is_empty() {
+        let ids: Vec<String> = blockers.iter().map(|t| t.id.to_string()).collect();
+        extras.push(format!("blocked by {}", ids.join(", ")));
+    }
+    if let Some(who) = &task.waiting_on {
+        match task.stamps.get("waiting_on").map(|s| days_since(s.0)) {
+            Some(days) if days > 0 => {
+                extras.push(format!("waiting on {} for {} day(s)", who, days))
+            }
+            _ => extras.push(format!("waiting on {}", who)),
+        }
+    }
+
+    if extras.is_empty() {
+        format!("{} {}: {}", status, task.id, task.description)
+    } else {
+        format!(
+            "{} {}: {} ({})",
+            status,
+            task.id,
+            task.description,
+            extras.join(", ")
+        )
+    }
+}
+
+fn days_since(millis: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    now.saturating_sub(millis) / (24 * 60 * 60 * 1000)
+}
+
+/// Suggests open tasks that together fill at most `budget` minutes, packing
+/// the biggest ones first so the time goes to substantial work.
+pub fn print_fits(tasks: &[Task], budget: u32) {
+    let mut candidates: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| !t.completed && !t.someday && t.estimate.is_some_and(|e| e <= budget))
+        .filter(|t| !deps::is_blocked(tasks, t))
+        .collect();
+    candidates.sort_by_key(|t| (std::cmp::Reverse(t.estimate), t.id));
+
+    let mut used = 0;
+    for task in candidates {
+        let estimate = task.estimate.unwrap_or_default();
+        if used + estimate <= budget {
+            println!("{}", format_line(tasks, task));
+            used += estimate;
+        }
+    }
+
+    if used == 0 {
+        println!("Nothing fits in {}.", duration::format_minutes(budget));
+    } else {
+        println!(
+            "Total: {} of {}",
+            duration::format_minutes(used),
+            duration::format_minutes(budget)
+        );
+    }
+    let unestimated = tasks
+        .iter()
+        .filter(|t| !t.completed && t.estimate.is_none())
+        .count();
+    if unestimated > 0 {
+        println!("({} open task(s) have no estimate)", unestimated);
+    }
+}
+
+/// Whether a task can be worked on right now: it is open and processed,
+/// nothing it depends on is still open, and nobody else is holding it.
+pub fn is_actionable(tasks: &[Task], task: &Task) -> bool {
+    !task.completed
+        && !task.someday
+        && !task.inbox
+        && task.waiting_on.is_none()
+        && !deps::is_blocked(tasks, task)
+}
+
+/// The first `count` actionable tasks in `sort` order, of those `context`
+/// matches if given.
+pub fn print_next(
+    tasks: &[Task],
+    count: usize,
+    sort: &Sort,
+    layout: &Layout,
+    context: Option<&Filter>,
+) {
+    let mut actionable: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| is_actionable(tasks, t) && context.is_none_or(|c| c.matches(tasks, t)))
+        .collect();
+    sort.sort_pinned_first(&mut actionable);
+    actionable.truncate(count);
+    match layout {
+        Layout::Template(template) => {
+            for task in &actionable {
+                println!("{}", template.render(tasks, task));
+            }
+            return;
+        }
+        Layout::Table(columns) if !actionable.is_empty() => {
+            print_table(tasks, &actionable, columns)
+        }
+        Layout::Table(_) => {}
+    }
+    if actionable.is_empty() {
+        let blocked = tasks.iter().filter(|t| deps::is_blocked(tasks, t)).count();
+        if blocked > 0 {
+            println!(
+                "Nothing actionable; {} task(s) are blocked (see `list --blocked`).",
+                blocked
+            );
+        } else {
+            println!("Nothing to do.");
+        }
+    }
+}
+
+/// Picks one actionable task at random, optionally only from `project` or
+/// among tasks estimated at most `max_estimate` minutes.
+pub fn print_random(tasks: &[Task], project: Option<&str>, max_estimate: Option<u32>) {
+    let candidates: Vec<&Task> = tasks
+        .iter()
+        .filter(|t| is_actionable(tasks, t))
+        .filter(|t| matches_name(project, t.project.as_deref()))
+        .filter(|t| max_estimate.is_none_or(|max| t.estimate.is_some_and(|e| e <= max)))
+        .collect();
+    if candidates.is_empty() {
+        println!("No matching tasks to pick from.");
+        return;
+    }
+    // A fresh v4 uuid is random enough to pick with and saves a dependency.
+    let pick = (Uuid::new_v4().as_u128() % candidates.len() as u128) as usize;
+    println!("{}", format_line(tasks, candidates[pick]));
+}
+
+/// Completes a task, optionally noting how, and returns its uuid.
+pub fn mark_done(
+    tasks: &mut [Task],
+    id: u32,
+    note: Option<String>,
+    clock: &Clock,
+) -> anyhow::Result<Uuid> {
     match tasks.iter_mut().find(|t| t.id == id) {
         Some(task) => {
             task.completed = true;
+            task.completed_at = Some(Utc::now());
+            task.touch("completed", clock);
+            task.touch("completed_at", clock);
+            task.log("completed", clock);
+            if let Some(note) = note.map(|n| n.trim().to_owned()).filter(|n| !n.is_empty()) {
+                task.note = Some(note);
+                task.touch("note", clock);
+            }
+            Ok(task.uuid)
+        }
+        None => bail!("No task with id {}", id),
+    }
+}
+
+pub fn set_priority(
+    tasks: &mut [Task],
+    id: u32,
+    priority: Option<Priority>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let task = find_task_mut(tasks, id)?;
+    task.priority = priority;
+    task.touch("priority", clock);
+    Ok(())
+}
+
+pub fn set_due(
+    tasks: &mut [Task],
+    id: u32,
+    due: Option<NaiveDate>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let task = find_task_mut(tasks, id)?;
+    task.due = due;
+    task.touch("due", clock);
+    Ok(())
+}
+
+pub fn set_description(task: &mut Task, description: &str, clock: &Clock) -> anyhow::Result<()> {
+    let description = description.trim();
+    if description.is_empty() {
+        bail!("Task description cannot be empty");
+    }
+    task.description = description.to_owned();
+    task.touch("description", clock);
+    Ok(())
+}
+
+/// Checks and normalizes a tag as typed, with or without its leading `+`.
+pub fn parse_tag(text: &str) -> Result<String, String> {
+    let tag = text.trim().trim_start_matches('+').to_lowercase();
+    if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(format!("{:?} is not a tag (one word, no commas)", text));
+    }
+    Ok(tag)
+}
+
+/// Adds the `tags` a task lacks and removes `untag`; returns the tags that
+/// were new to it.
+pub fn retag(task: &mut Task, tags: &[String], untag: &[String], clock: &Clock) -> Vec<String> {
+    let added: Vec<String> = tags
+        .iter()
+        .filter(|t| !task.tags.contains(t))
+        .cloned()
+        .collect();
+    let before = task.tags.len();
+    task.tags.retain(|t| !untag.contains(t));
+    if !added.is_empty() || task.tags.len() != before {
+        task.tags.extend(added.iter().cloned());
+        task.touch("tags", clock);
+    }
+    added
+}
+
+/// Puts a completed task back on the open list, dropping its completion
+/// time and resolution note.
+pub fn reopen_task(tasks: &mut [Task], id: u32, clock: &Clock) -> anyhow::Result<()> {
+    let task = find_task_mut(tasks, id)?;
+    if !task.completed {
+        bail!("Task {} is not completed", id);
+    }
+    task.completed = false;
+    task.completed_at = None;
+    task.note = None;
+    task.touch("completed", clock);
+    task.touch("completed_at", clock);
+    task.touch("note", clock);
+    task.log("reopened", clock);
+    Ok(())
+}
+
+/// Folds task `id` into task `into` and removes it, returning its uuid so
+/// the removal can be recorded. Differing descriptions and notes are joined,
+/// attributes `into` lacks are taken over, and lists are combined; anything
+/// that pointed at the duplicate points at `into` afterwards.
+pub fn merge_tasks(
+    tasks: &mut Vec<Task>,
+    id: u32,
+    into: u32,
+    clock: &Clock,
+) -> anyhow::Result<Uuid> {
+    if id == into {
+        bail!("Cannot merge a task into itself");
+    }
+    let Some(index) = tasks.iter().position(|t| t.id == id) else {
+        bail!("No task with id {}", id);
+    };
+    let into_uuid = match tasks.iter().find(|t| t.id == into) {
+        Some(task) => task.uuid,
+        None => bail!("No task with id {}", into),
+    };
+    let dup_uuid = tasks[index].uuid;
+    deps::repoint(tasks, dup_uuid, into_uuid, clock);
+    let dup = tasks.remove(index);
+
+    let target = find_task_mut(tasks, into)?;
+    let join = |a: &str, b: &str| {
+        if a.eq_ignore_ascii_case(b) {
+            a.to_owned()
+        } else {
+            format!("{}; {}", a, b)
+        }
+    };
+    target.description = join(&target.description, &dup.description);
+    target.note = match (target.note.take(), dup.note) {
+        (Some(a), Some(b)) => Some(join(&a, &b)),
+        (a, b) => a.or(b),
+    };
+    target.project = target.project.take().or(dup.project);
+    for tag in dup.tags {
+        if !target.tags.contains(&tag) {
+            target.tags.push(tag);
+        }
+    }
+    target.milestone = target.milestone.take().or(dup.milestone);
+    target.assignee = target.assignee.take().or(dup.assignee);
+    target.estimate = target.estimate.or(dup.estimate);
+    target.priority = target.priority.max(dup.priority);
+    target.due = match (target.due, dup.due) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    for uuid in dup.depends_on {
+        if uuid != target.uuid && !target.depends_on.contains(&uuid) {
+            target.depends_on.push(uuid);
+        }
+    }
+    for (name, value) in dup.attributes {
+        target.attributes.entry(name).or_insert(value);
+    }
+    for item in dup.checklist {
+        if !target.checklist.iter().any(|i| i.text == item.text) {
+            target.checklist.push(item);
+        }
+    }
+    target.comments.extend(dup.comments);
+    target.comments.sort_by_key(|c| (c.at, c.id));
+    target.time_log.extend(dup.time_log);
+    target.time_log.sort_by_key(|i| i.start);
+    target.occurrences.extend(dup.occurrences);
+    target.occurrences.sort();
+
+    for field in [
+        "description",
+        "note",
+        "project",
+        "tags",
+        "milestone",
+        "assignee",
+        "estimate",
+        "priority",
+        "due",
+        "depends_on",
+        "attributes",
+        "checklist",
+        "comments",
+        "time_log",
+        "occurrences",
+    ] {
+        target.touch(field, clock);
+    }
+    target.log(&format!("merged in task {}", id), clock);
+    Ok(dup.uuid)
+}
+
+/// Turns task `id` into the parent of one new subtask per item and returns
+/// their ids. Subtasks share the parent's project, milestone, priority, and
+/// due date, and split its estimate evenly.
+pub fn split_task(
+    tasks: &mut Vec<Task>,
+    id: u32,
+    items: &[String],
+    clock: &Clock,
+) -> anyhow::Result<Vec<u32>> {
+    let items: Vec<&str> = items
+        .iter()
+        .map(|i| i.trim())
+        .filter(|i| !i.is_empty())
+        .collect();
+    if items.len() < 2 {
+        bail!("Splitting needs at least two subtasks");
+    }
+    let Some(parent) = tasks.iter().find(|t| t.id == id).cloned() else {
+        bail!("No task with id {}", id);
+    };
+    if parent.completed {
+        bail!("Task {} is already completed", id);
+    }
+
+    let estimate = parent
+        .estimate
+        .map(|e| e.div_ceil(items.len() as u32).max(1));
+    let mut ids = Vec::new();
+    for item in items {
+        let details = NewTask {
+            project: parent.project.clone(),
+            milestone: parent.milestone.clone(),
+            priority: parent.priority,
+            due: parent.due,
+            estimate,
+            habit: None,
+            tags: parent.tags.clone(),
+        };
+        let child_id = add_task(tasks, item.to_owned(), details, clock)?;
+        let child = find_task_mut(tasks, child_id)?;
+        child.parent = Some(parent.uuid);
+        child.touch("parent", clock);
+        ids.push(child_id);
+    }
+    find_task_mut(tasks, id)?.log(&format!("split into {} subtasks", ids.len()), clock);
+    Ok(ids)
+}
+
+pub fn set_someday(
+    tasks: &mut [Task],
+    id: u32,
+    someday: bool,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let task = find_task_mut(tasks, id)?;
+    task.someday = someday;
+    task.touch("someday", clock);
+    Ok(())
+}
+
+pub fn set_pinned(tasks: &mut [Task], id: u32, pinned: bool, clock: &Clock) -> anyhow::Result<()> {
+    let task = find_task_mut(tasks, id)?;
+    task.pinned = pinned;
+    task.touch("pinned", clock);
+    Ok(())
+}
+
+/// Where a reordered task should go.
+pub enum Placement {
+    Up,
+    Down,
+    /// 1-based position among open tasks.
+    At(usize),
+}
+
+/// Moves an open task within the manual order and returns its new 1-based
+/// position. Open tasks are renumbered so the order stays dense.
+pub fn reorder_task(
+    tasks: &mut [Task],
+    id: u32,
+    placement: Placement,
+    clock: &Clock,
+) -> anyhow::Result<usize> {
+    let mut sequence: Vec<(u32, Option<u32>)> = {
+        let mut open: Vec<&Task> = tasks.iter().filter(|t| !t.completed).collect();
+        open.sort_by_key(|t| manual_order(t));
+        open.iter().map(|t| (t.id, t.order)).collect()
+    };
+    let Some(from) = sequence.iter().position(|(task_id, _)| *task_id == id) else {
+        find_task_mut(tasks, id)?;
+        bail!("Task {} is completed; only open tasks can be reordered", id);
+    };
+    let to = match placement {
+        Placement::Up => from.saturating_sub(1),
+        Placement::Down => (from + 1).min(sequence.len() - 1),
+        Placement::At(0) => bail!("Positions start at 1"),
+        Placement::At(position) => (position - 1).min(sequence.len() - 1),
+    };
+    let entry = sequence.remove(from);
+    sequence.insert(to, entry);
+
+    for (i, (task_id, old)) in sequence.into_iter().enumerate() {
+        let new = Some(i as u32 + 1);
+        if old != new {
+            let task = find_task_mut(tasks, task_id)?;
+            task.order = new;
+            task.touch("order", clock);
+        }
+    }
+    Ok(to + 1)
+}
+
+/// Moves a task to another project, or out of any with `None`.
+pub fn set_project(
+    tasks: &mut [Task],
+    id: u32,
+    project: Option<String>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let project = project
+        .map(|p| p.trim().to_owned())
+        .filter(|p| !p.is_empty());
+    let task = find_task_mut(tasks, id)?;
+    if task.project == project {
+        return Ok(());
+    }
+    task.log(
+        &match &project {
+            Some(p) => format!("moved to project {}", p),
+            None => "removed from its project".to_owned(),
+        },
+        clock,
+    );
+    task.project = project;
+    task.touch("project", clock);
+    Ok(())
+}
+
+/// Sets or, with `None`, clears who is responsible for a task.
+pub fn assign_task(
+    tasks: &mut [Task],
+    id: u32,
+    assignee: Option<String>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let assignee = assignee
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty());
+    match tasks.iter_mut().find(|t| t.id == id) {
+        Some(task) => {
+            task.assignee = assignee;
+            task.touch("assignee", clock);
             Ok(())
         }
         None => bail!("No task with id {}", id),
     }
 }
 
-pub fn remove_task(tasks: &mut Vec<Task>, id: u32) -> anyhow::Result<()> {
-    let len_before = tasks.len();
-    tasks.retain(|t| t.id != id);
-    if tasks.len() < len_before {
-        Ok(())
-    } else {
-        bail!("No task with id {}", id)
+/// Sets or, with `None`, clears a task's effort estimate in minutes.
+pub fn estimate_task(
+    tasks: &mut [Task],
+    id: u32,
+    minutes: Option<u32>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let task = find_task_mut(tasks, id)?;
+    task.estimate = minutes;
+    task.touch("estimate", clock);
+    Ok(())
+}
+
+/// Puts a task towards a milestone, or takes it out of one with `None`.
+pub fn set_milestone(
+    tasks: &mut [Task],
+    id: u32,
+    milestone: Option<String>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let task = find_task_mut(tasks, id)?;
+    task.milestone = milestone;
+    task.touch("milestone", clock);
+    Ok(())
+}
+
+/// Starts the timer on a task, stopping whichever timer was running; returns
+/// the id of the task that was stopped, if any.
+pub fn start_task(tasks: &mut [Task], id: u32, clock: &Clock) -> anyhow::Result<Option<u32>> {
+    if find_task_mut(tasks, id)?.is_running() {
+        bail!("Task {} is already running", id);
+    }
+    let stopped = match tasks.iter().find(|t| t.is_running()) {
+        Some(running) => Some(stop_task(tasks, Some(running.id), clock)?),
+        None => None,
+    };
+
+    let task = find_task_mut(tasks, id)?;
+    task.time_log.push(Interval {
+        start: Utc::now(),
+        end: None,
+    });
+    task.touch("time_log", clock);
+    Ok(stopped)
+}
+
+/// Stops the timer on `id`, or on whichever task is running; returns the id
+/// of the stopped task.
+pub fn stop_task(tasks: &mut [Task], id: Option<u32>, clock: &Clock) -> anyhow::Result<u32> {
+    let task = match id {
+        Some(id) => find_task_mut(tasks, id)?,
+        None => match tasks.iter_mut().find(|t| t.is_running()) {
+            Some(task) => task,
+            None => bail!("No task is running"),
+        },
+    };
+    match task.time_log.last_mut() {
+        Some(interval) if interval.end.is_none() => interval.end = Some(Utc::now()),
+        _ => bail!("Task {} is not running", task.id),
+    }
+    task.touch("time_log", clock);
+    Ok(task.id)
+}
+
+/// Hands a task to `person` and marks it as waiting on them; `None` takes it
+/// back.
+pub fn delegate_task(
+    tasks: &mut [Task],
+    id: u32,
+    person: Option<String>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let person = person
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty());
+    match tasks.iter_mut().find(|t| t.id == id) {
+        Some(task) => {
+            task.waiting_on = person;
+            task.touch("waiting_on", clock);
+            Ok(())
+        }
+        None => bail!("No task with id {}", id),
+    }
+}
+
+pub fn find_task_mut(tasks: &mut [Task], id: u32) -> anyhow::Result<&mut Task> {
+    match tasks.iter_mut().find(|t| t.id == id) {
+        Some(task) => Ok(task),
+        None => bail!("No task with id {}", id),
+    }
+}
+
+/// Appends a comment, optionally as a reply to an existing one.
+pub fn add_comment(
+    task: &mut Task,
+    author: &str,
+    text: &str,
+    reply_to: Option<Uuid>,
+    clock: &Clock,
+) -> anyhow::Result<Comment> {
+    let text = text.trim();
+    if text.is_empty() {
+        bail!("Comment cannot be empty");
+    }
+    if let Some(parent) = reply_to
+        && !task.comments.iter().any(|c| c.id == parent)
+    {
+        bail!("No comment {} on task {}", parent, task.id);
+    }
+
+    let comment = Comment {
+        id: Uuid::new_v4(),
+        author: author.to_owned(),
+        at: Utc::now(),
+        text: text.to_owned(),
+        reply_to,
+    };
+    task.comments.push(comment.clone());
+    task.touch("comments", clock);
+    Ok(comment)
+}
+
+pub fn add_check_item(task: &mut Task, text: &str, clock: &Clock) -> anyhow::Result<()> {
+    let text = text.trim();
+    if text.is_empty() {
+        bail!("Checklist item cannot be empty");
+    }
+    task.checklist.push(ChecklistItem {
+        text: text.to_owned(),
+        done: false,
+    });
+    task.touch("checklist", clock);
+    Ok(())
+}
+
+/// Ticks off the checklist item with the 1-based number `show` prints.
+pub fn complete_check_item(task: &mut Task, number: usize, clock: &Clock) -> anyhow::Result<()> {
+    match number
+        .checked_sub(1)
+        .and_then(|i| task.checklist.get_mut(i))
+    {
+        Some(item) => item.done = true,
+        None => bail!("Task {} has no checklist item {}", task.id, number),
+    }
+    task.touch("checklist", clock);
+    Ok(())
+}
+
+/// Looks up a comment by the 1-based number `show` prints next to it.
+pub fn comment_by_number(task: &Task, number: usize) -> anyhow::Result<Uuid> {
+    match number.checked_sub(1).and_then(|i| task.comments.get(i)) {
+        Some(comment) => Ok(comment.id),
+        None => bail!("Task {} has no comment #{}", task.id, number),
+    }
+}
+
+pub fn show_task(tasks: &[Task], id: u32) -> anyhow::Result<()> {
+    let Some(task) = tasks.iter().find(|t| t.id == id) else {
+        bail!("No task with id {}", id);
+    };
+    print_task(task, &|uuid| {
+        tasks.iter().find(|t| t.uuid == uuid).map(|t| t.id)
+    });
+    Ok(())
+}
+
+/// Prints every detail of `task`, naming related tasks by the id `id_of`
+/// finds for their uuid.
+pub fn print_task(task: &Task, id_of: &dyn Fn(Uuid) -> Option<u32>) {
+    println!("Task {}: {}", task.id, task.description);
+    match task.completed_at.filter(|_| task.completed) {
+        Some(at) => println!(
+            "  Status:     completed {}",
+            at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        ),
+        None => println!(
+            "  Status:     {}",
+            if task.completed { "completed" } else { "open" }
+        ),
+    }
+    if let Some(note) = &task.note {
+        println!("  Resolution: {}", note);
+    }
+    if let Some(project) = &task.project {
+        println!("  Project:    {}", project);
+    }
+    if !task.tags.is_empty() {
+        let tags: Vec<String> = task.tags.iter().map(|t| format!("+{}", t)).collect();
+        println!("  Tags:       {}", tags.join(" "));
+    }
+    if let Some(milestone) = &task.milestone {
+        println!("  Milestone:  {}", milestone);
+    }
+    if let Some(who) = &task.assignee {
+        println!("  Assignee:   {}", who);
+    }
+    if let Some(who) = &task.waiting_on {
+        println!("  Waiting on: {}", who);
+    }
+    if let Some(priority) = task.priority {
+        println!("  Priority:   {}", priority.name());
+    }
+    if let Some(due) = task.due {
+        println!("  Due:        {}", due);
+    }
+    if let Some(day) = task.scheduled {
+        println!("  Scheduled:  {}", day);
+    }
+    if let Some(minutes) = task.estimate {
+        println!("  Estimate:   {}", duration::format_minutes(minutes));
+    }
+    if let (Some(cadence), Some(progress)) = (task.habit, habit::progress(task)) {
+        println!("  Habit:      {} ({})", cadence, progress);
+    }
+    if !task.time_log.is_empty() {
+        println!(
+            "  Tracked:    {}{}",
+            duration::format_minutes(task.tracked_minutes()),
+            if task.is_running() { " (running)" } else { "" }
+        );
+    }
+    let ids = |uuids: &mut dyn Iterator<Item = &Uuid>| {
+        uuids
+            .filter_map(|u| id_of(*u))
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+    };
+    if let Some(parent) = ids(&mut task.parent.iter()).first() {
+        println!("  Parent:     {}", parent);
+    }
+    let depends = ids(&mut task.depends_on.iter());
+    if !depends.is_empty() {
+        println!("  Depends on: {}", depends.join(", "));
+    }
+    if let Some(source) = &task.source {
+        println!("  Source:     {}", source);
+    }
+    if let Some(link) = &task.link {
+        println!("  Link:       {}", link);
+    }
+    for (name, value) in &task.attributes {
+        println!("  {:<11} {}", format!("{}:", name), value);
+    }
+    println!("  UUID:       {}", task.uuid);
+
+    if let Some(progress) = task.checklist_progress() {
+        println!("Checklist ({}):", progress);
+        for (i, item) in task.checklist.iter().enumerate() {
+            let mark = if item.done { "[x]" } else { "[ ]" };
+            println!("  {}. {} {}", i + 1, mark, item.text);
+        }
+    }
+
+    if !task.comments.is_empty() {
+        println!("Comments:");
+        print_thread(task, None, 1);
+    }
+    if !task.history.is_empty() {
+        println!("History:");
+        for entry in &task.history {
+            println!(
+                "  {}  {}",
+                entry.at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                entry.event
+            );
+        }
+    }
+}
+
+/// Prints the replies to `parent` (top-level comments for `None`), each
+/// followed by its own replies one level deeper.
+fn print_thread(task: &Task, parent: Option<Uuid>, depth: usize) {
+    let known = |id: &Uuid| task.comments.iter().any(|c| c.id == *id);
+    for (i, comment) in task.comments.iter().enumerate() {
+        // Orphaned replies (parent lost in a merge) are shown at the top level.
+        let comment_parent = comment.reply_to.filter(known);
+        if comment_parent != parent {
+            continue;
+        }
+        println!(
+            "{}#{} {} ({}): {}",
+            "  ".repeat(depth),
+            i + 1,
+            comment.author,
+            comment.at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            comment.text
+        );
+        print_thread(task, Some(comment.id), depth + 1);
+    }
+}
+
+/// Removes the task and returns its uuid so the caller can record a tombstone.
+pub fn remove_task(tasks: &mut Vec<Task>, id: u32) -> anyhow::Result<Uuid> {
+    match tasks.iter().position(|t| t.id == id) {
+        Some(index) => Ok(tasks.remove(index).uuid),
+        None => bail!("No task with id {}", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(id: u32, added: Option<&str>, priority: Option<&str>, due: Option<&str>) -> Task {
+        let history: Vec<_> = added
+            .map(|at| json!({ "at": at, "event": ADDED }))
+            .into_iter()
+            .collect();
+        serde_json::from_value(json!({
+            "id": id,
+            "uuid": format!("00000000-0000-4000-8000-{:012}", id),
+            "description": format!("task {}", id),
+            "completed": false,
+            "priority": priority,
+            "due": due,
+            "history": history,
+        }))
+        .unwrap()
+    }
+
+    fn order(sort: &str, tasks: &[Task]) -> Vec<u32> {
+        let sort: Sort = sort.parse().unwrap();
+        let mut shown: Vec<&Task> = tasks.iter().collect();
+        sort.sort(&mut shown);
+        shown.iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn sort_keys_parse() {
+        assert!("priority,-due,+created".parse::<Sort>().is_ok());
+        assert!("Due".parse::<Sort>().is_ok());
+        let err = "size".parse::<Sort>().err().unwrap().to_string();
+        assert!(err.contains("Unknown sort key \"size\""), "{}", err);
+        assert!("due,-due".parse::<Sort>().is_err());
+        assert!("due,".parse::<Sort>().is_err());
+    }
+
+    #[test]
+    fn created_goes_by_when_tasks_were_added() {
+        let tasks = [
+            task(1, Some("2026-03-02T00:00:00Z"), None, None),
+            task(2, Some("2026-03-01T00:00:00Z"), None, None),
+            task(3, None, None, None),
+            task(4, Some("2026-03-03T00:00:00Z"), None, None),
+        ];
+        // Tasks with no record come last either way.
+        assert_eq!(order("created", &tasks), [2, 1, 4, 3]);
+        assert_eq!(order("-created", &tasks), [4, 1, 2, 3]);
+    }
+
+    #[test]
+    fn later_keys_break_ties_and_ids_break_the_rest() {
+        let tasks = [
+            task(1, None, Some("low"), Some("2026-05-01")),
+            task(2, None, Some("high"), Some("2026-06-01")),
+            task(3, None, Some("high"), Some("2026-04-01")),
+            task(4, None, None, Some("2026-04-01")),
+            task(5, None, Some("high"), Some("2026-04-01")),
+        ];
+        assert_eq!(order("priority", &tasks), [3, 5, 2, 1, 4]);
+        assert_eq!(order("due,-id", &tasks), [5, 4, 3, 1, 2]);
+        assert_eq!(order("-priority", &tasks), [1, 2, 3, 5, 4]);
+    }
+
+    #[test]
+    fn default_columns_flag_what_needs_noticing() {
+        let mut tasks = [
+            task(1, None, None, Some("2020-01-01")),
+            task(2, None, None, None),
+            task(3, None, None, None),
+        ];
+        tasks[0].pinned = true;
+        tasks[0].waiting_on = Some("sam".to_owned());
+        tasks[1].depends_on = vec![tasks[2].uuid];
+        tasks[1].tags = vec!["home".to_owned(), "errand".to_owned()];
+        let row = |task: &Task| -> Vec<String> {
+            DEFAULT_COLUMNS
+                .iter()
+                .map(|c| template::field(&tasks, task, c))
+                .collect()
+        };
+        let flags = DEFAULT_COLUMNS.iter().position(|c| *c == "flags").unwrap();
+        let tags = DEFAULT_COLUMNS.iter().position(|c| *c == "tags").unwrap();
+        assert_eq!(row(&tasks[0])[flags], "pinned,overdue,waiting");
+        assert_eq!(row(&tasks[1])[flags], "blocked");
+        assert_eq!(row(&tasks[1])[tags], "home,errand");
+        assert_eq!(row(&tasks[2])[flags], "");
+        let mut done = tasks[0].clone();
+        done.completed = true;
+        assert_eq!(row(&done)[flags], "");
+    }
+
+    #[test]
+    fn adding_records_when() {
+        let dir =
+            std::env::temp_dir().join(format!("cli_task_manager-task-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let clock = Clock::load(&dir.join("tasks.json"), &[]).unwrap();
+        let mut tasks = Vec::new();
+        add_task(&mut tasks, "first".to_owned(), NewTask::default(), &clock).unwrap();
+        assert!(created(&tasks[0]).is_some());
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,239 @@
+//! `--format` templates: text with `{field}` placeholders filled in per task,
+//! e.g. `"{id}\t{priority}\t{description} ({due})"`. `{{` and `}}` are
+//! literal braces, and `\t` and `\n` stand for a tab and a newline so they
+//! can be typed in a shell without quoting tricks. `{attr.<name>}` is a
+//! user-defined attribute. Fields a task does not have render as nothing.
+
+use crate::{
+    deps, duration, habit,
+    task::{self, Task},
+};
+use anyhow::bail;
+use chrono::Local;
+use std::str::FromStr;
+
+const FIELDS: &[&str] = &[
+    "id",
+    "uuid",
+    "description",
+    "status",
+    "flags",
+    "project",
+    "tags",
+    "milestone",
+    "assignee",
+    "priority",
+    "due",
+    "estimate",
+    "tracked",
+    "waiting_on",
+    "checklist",
+    "habit",
+    "blocked_by",
+    "note",
+    "line",
+];
+
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    Field(String),
+}
+
+#[derive(Clone)]
+pub struct Template(Vec<Piece>);
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> anyhow::Result<Self> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = input.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => bail!("Unclosed {{{} in format", name),
+                        }
+                    }
+                    let name = parse_field(&name)?;
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Field(name));
+                }
+                '}' => bail!("Unmatched }} in format (write }}}} for a literal brace)"),
+                '\\' if chars.peek() == Some(&'t') => {
+                    chars.next();
+                    text.push('\t');
+                }
+                '\\' if chars.peek() == Some(&'n') => {
+                    chars.next();
+                    text.push('\n');
+                }
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Template(pieces))
+    }
+}
+
+impl Template {
+    pub fn render(&self, tasks: &[Task], task: &Task) -> String {
+        let mut out = String::new();
+        for piece in &self.0 {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Field(name) => out.push_str(&field(tasks, task, name)),
+            }
+        }
+        out
+    }
+}
+
+/// Checks that `name` is a known field, for templates and table columns.
+pub fn parse_field(name: &str) -> anyhow::Result<String> {
+    let name = name.trim();
+    if !FIELDS.contains(&name) && name.strip_prefix("attr.").is_none_or(str::is_empty) {
+        bail!(
+            "Unknown field {} (known: {}, attr.<name>)",
+            name,
+            FIELDS.join(", ")
+        );
+    }
+    Ok(name.to_owned())
+}
+
+/// What needs noticing about an open task at a glance: pinned, overdue,
+/// waiting on someone, blocked, or running.
+fn flags(tasks: &[Task], task: &Task) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    if task.completed {
+        return flags;
+    }
+    if task.pinned {
+        flags.push("pinned");
+    }
+    if task.due.is_some_and(|due| due < Local::now().date_naive()) {
+        flags.push("overdue");
+    }
+    if task.waiting_on.is_some() {
+        flags.push("waiting");
+    }
+    if deps::is_blocked(tasks, task) {
+        flags.push("blocked");
+    }
+    if task.is_running() {
+        flags.push("running");
+    }
+    flags
+}
+
+/// The value of field `name` for `task`, as text.
+pub fn field(tasks: &[Task], task: &Task, name: &str) -> String {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    match name {
+        "id" => task.id.to_string(),
+        "uuid" => task.uuid.to_string(),
+        "description" => task.description.clone(),
+        "status" => if task.completed { "done" } else { "open" }.to_owned(),
+        "flags" => flags(tasks, task).join(","),
+        "project" => text(&task.project),
+        "tags" => task.tags.join(","),
+        "milestone" => text(&task.milestone),
+        "assignee" => text(&task.assignee),
+        "priority" => task
+            .priority
+            .map(|p| p.name().to_owned())
+            .unwrap_or_default(),
+        "due" => task.due.map(|d| d.to_string()).unwrap_or_default(),
+        "estimate" => task
+            .estimate
+            .map(duration::format_minutes)
+            .unwrap_or_default(),
+        "tracked" if task.time_log.is_empty() => String::new(),
+        "tracked" => duration::format_minutes(task.tracked_minutes()),
+        "waiting_on" => text(&task.waiting_on),
+        "checklist" => task.checklist_progress().unwrap_or_default(),
+        "habit" => habit::progress(task).unwrap_or_default(),
+        "blocked_by" => deps::blockers(tasks, task)
+            .iter()
+            .map(|t| t.id.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        "note" => text(&task.note),
+        "line" => task::format_line(tasks, task),
+        _ if name.starts_with("attr.") => task
+            .attributes
+            .get(&name["attr.".len()..])
+            .cloned()
+            .unwrap_or_default(),
+        _ => unreachable!("fields are checked when the template is parsed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(format: &str) -> String {
+        let task: Task = serde_json::from_value(json!({
+            "id": 7,
+            "uuid": "00000000-0000-4000-8000-000000000007",
+            "description": "Call {Ann}",
+            "completed": false,
+            "priority": "high",
+            "tags": ["phone", "work"],
+            "attributes": { "customer": "Acme" },
+        }))
+        .unwrap();
+        let template: Template = format.parse().unwrap();
+        template.render(std::slice::from_ref(&task), &task)
+    }
+
+    #[test]
+    fn placeholders_are_filled_per_task() {
+        assert_eq!(render("{id}: {description}"), "7: Call {Ann}");
+        assert_eq!(
+            render("{ priority }|{tags}|{status}"),
+            "high|phone,work|open"
+        );
+        assert_eq!(render("{attr.customer}"), "Acme");
+        assert_eq!(render("[{due}{project}{attr.missing}]"), "[]");
+        assert_eq!(render("plain"), "plain");
+        assert_eq!(render(""), "");
+    }
+
+    #[test]
+    fn braces_and_escapes_are_literal() {
+        assert_eq!(render("{{id}} {{{id}}}"), "{id} {7}");
+        assert_eq!(render(r"{id}\t{priority}\n"), "7\thigh\n");
+        assert_eq!(render(r"a\b \\t"), "a\\b \\\t");
+    }
+
+    #[test]
+    fn bad_templates_are_refused() {
+        for bad in ["{colour}", "{attr.}", "{}", "{id", "id}", "{id}}"] {
+            assert!(bad.parse::<Template>().is_err(), "{} parsed", bad);
+        }
+        let err = "{colour}".parse::<Template>().err().unwrap();
+        assert!(err.to_string().contains("Unknown field colour"));
+    }
+}
@@ -0,0 +1,217 @@
+//! `undo` and `redo`, which step back and forth through the changes
+//! commands made, across runs. Each command that changes tasks pushes a step
+//! holding the tasks it touched as they were before and after, to
+//! `tasks.undo.json` beside the tasks file; the last `DEPTH` are kept.
+//! Undoing puts the step's tasks back as they were with fresh stamps, so the
+//! undo wins over the change it reverses when replicas sync.
+//!
+//! A step undone stays available to `redo` until another command changes
+//! tasks.
+
+use crate::{
+    crdt::{self, Clock},
+    task::{self, Task},
+};
+use anyhow::Context;
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+const DEPTH: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+struct Step {
+    command: String,
+    at: DateTime<Utc>,
+    /// The tasks the command changed or removed, as they were.
+    before: Vec<Task>,
+    /// The tasks the command changed or added, as it left them.
+    after: Vec<Task>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Stacks {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+fn stacks_path(tasks_path: &Path) -> PathBuf {
+    tasks_path.with_extension("undo.json")
+}
+
+fn load(tasks_path: &Path) -> anyhow::Result<Stacks> {
+    let path = stacks_path(tasks_path);
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Stacks::default()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    serde_json::from_slice(&data).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn save(tasks_path: &Path, stacks: &Stacks) -> anyhow::Result<()> {
+    let data = serde_json::to_vec(stacks).context("Failed to serialize the undo history")?;
+    task::write_atomic(&stacks_path(tasks_path), &data)
+}
+
+/// The command line, as the step is labelled.
+fn invocation() -> String {
+    std::env::args().skip(1).collect::<Vec<_>>().join(" ")
+}
+
+/// Records the change a command made from `before` to `after` as a step
+/// to undo, and forgets what could be redone. Does nothing if no task
+/// changed.
+pub fn record(tasks_path: &Path, before: &[Task], after: &[Task]) -> anyhow::Result<()> {
+    record_as(tasks_path, invocation(), before, after)
+}
+
+/// `record`, with the step labelled `command` rather than the command line.
+pub fn record_as(
+    tasks_path: &Path,
+    command: String,
+    before: &[Task],
+    after: &[Task],
+) -> anyhow::Result<()> {
+    let changed = |from: &[Task], to: &[Task]| -> Vec<Task> {
+        let to: HashMap<Uuid, &Task> = to.iter().map(|t| (t.uuid, t)).collect();
+        from.iter()
+            .filter(|task| to.get(&task.uuid) != Some(task))
+            .cloned()
+            .collect()
+    };
+    let step = Step {
+        command,
+        at: Utc::now(),
+        before: changed(before, after),
+        after: changed(after, before),
+    };
+    if step.before.is_empty() && step.after.is_empty() {
+        return Ok(());
+    }
+    let mut stacks = load(tasks_path)?;
+    stacks.undo.push(step);
+    if stacks.undo.len() > DEPTH {
+        stacks.undo.drain(..stacks.undo.len() - DEPTH);
+    }
+    stacks.redo.clear();
+    save(tasks_path, &stacks)
+}
+
+/// Puts the tasks in `to` back in place of those in `from`.
+fn restore(
+    tasks_path: &Path,
+    tasks: &mut Vec<Task>,
+    from: &[Task],
+    to: &[Task],
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let kept: HashSet<Uuid> = to.iter().map(|t| t.uuid).collect();
+    for task in from.iter().filter(|t| !kept.contains(&t.uuid)) {
+        if let Some(position) = tasks.iter().position(|t| t.uuid == task.uuid) {
+            tasks.remove(position);
+            crdt::bury(tasks_path, task.uuid, clock.tick())?;
+        }
+    }
+    let mut tombstones = crdt::load_tombstones(tasks_path)?;
+    for task in to {
+        let mut task = task.clone();
+        let fields: Vec<String> = match task.stamps.keys().next() {
+            Some(_) => task.stamps.keys().cloned().collect(),
+            None => vec!["description".to_owned()],
+        };
+        for field in fields {
+            task.touch(&field, clock);
+        }
+        match tasks.iter().position(|t| t.uuid == task.uuid) {
+            Some(position) => tasks[position] = task,
+            None => {
+                // Removed tasks come back under their id unless it was reused.
+                if tasks.iter().any(|t| t.id == task.id) {
+                    task.id = tasks.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+                }
+                tombstones.remove(&task.uuid);
+                tasks.push(task);
+            }
+        }
+    }
+    crdt::save_tombstones(tasks_path, &tombstones)
+}
+
+/// Undoes the last `count` steps, or as many as there are.
+pub fn undo(tasks_path: &Path, tasks: &mut Vec<Task>, count: usize) -> anyhow::Result<()> {
+    step(tasks_path, tasks, count, true)
+}
+
+/// Redoes the last `count` steps undone.
+pub fn redo(tasks_path: &Path, tasks: &mut Vec<Task>, count: usize) -> anyhow::Result<()> {
+    step(tasks_path, tasks, count, false)
+}
+
+fn step(tasks_path: &Path, tasks: &mut Vec<Task>, count: usize, back: bool) -> anyhow::Result<()> {
+    let mut stacks = load(tasks_path)?;
+    let clock = Clock::load(tasks_path, tasks)?;
+    let mut done = 0;
+    while done < count {
+        let popped = if back {
+            stacks.undo.pop()
+        } else {
+            stacks.redo.pop()
+        };
+        let Some(step) = popped else {
+            break;
+        };
+        if back {
+            restore(tasks_path, tasks, &step.after, &step.before, &clock)?;
+            println!("Undid `{}`.", step.command);
+            stacks.redo.push(step);
+        } else {
+            restore(tasks_path, tasks, &step.before, &step.after, &clock)?;
+            println!("Redid `{}`.", step.command);
+            stacks.undo.push(step);
+        }
+        done += 1;
+    }
+    if done == 0 {
+        println!("Nothing to {}.", if back { "undo" } else { "redo" });
+        return Ok(());
+    }
+    task::save_tasks(tasks_path, tasks)?;
+    save(tasks_path, &stacks)
+}
+
+/// Lists the steps `undo` would take back, latest first, then those `redo`
+/// would make again.
+pub fn print_history(tasks_path: &Path) -> anyhow::Result<()> {
+    let stacks = load(tasks_path)?;
+    if stacks.undo.is_empty() && stacks.redo.is_empty() {
+        println!("Nothing to undo.");
+        return Ok(());
+    }
+    for (label, steps) in [("undo", &stacks.undo), ("redo", &stacks.redo)] {
+        for (n, step) in steps.iter().rev().enumerate() {
+            let tasks: HashSet<Uuid> = step
+                .before
+                .iter()
+                .chain(&step.after)
+                .map(|t| t.uuid)
+                .collect();
+            println!(
+                "{} {}: {}  `{}` ({} task(s))",
+                label,
+                n + 1,
+                step.at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                step.command,
+                tasks.len()
+            );
+        }
+    }
+    Ok(())
+}
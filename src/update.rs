@@ -0,0 +1,176 @@
+//! `self-update`: replaces this program with the latest prebuilt release
+//! from GitHub, for installs that did not come through cargo. The release
+//! must carry a binary named for this platform, such as
+//! `cli_task_manager-x86_64-linux`, and a `SHA256SUMS` file listing it; the
+//! download is refused unless its SHA-256 matches.
+//!
+//! Downloads go through the same HTTP client as sync and webhooks.
+//! `CLI_TASK_MANAGER_RELEASES` points at another copy of the GitHub
+//! releases API, such as a mirror.
+
+use crate::http;
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const RELEASES: &str = "https://api.github.com/repos/amerucandior/cli_task_manager/releases/latest";
+const SUMS: &str = "SHA256SUMS";
+/// The most of a release listing or checksum file read into memory.
+const MAX_RESPONSE: u64 = 16 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Fetches `url`, following redirects, to `to` or else into memory.
+fn fetch(url: &str, to: Option<&Path>) -> anyhow::Result<Vec<u8>> {
+    let mut response = http::agent(Duration::from_secs(300))
+        .get(url)
+        .header("Accept", "application/vnd.github+json")
+        .call()
+        .with_context(|| format!("Failed to fetch {}", url))?;
+    if response.status().as_u16() >= 400 {
+        bail!("Failed to fetch {}: {}", url, response.status());
+    }
+    let body = response.body_mut();
+    let Some(path) = to else {
+        return body
+            .with_config()
+            .limit(MAX_RESPONSE)
+            .read_to_vec()
+            .with_context(|| format!("Failed to fetch {}", url));
+    };
+    let mut file =
+        fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    io::copy(&mut body.as_reader(), &mut file)
+        .with_context(|| format!("Failed to download {} to {}", url, path.display()))?;
+    Ok(Vec::new())
+}
+
+/// `1.2.3` from `v1.2.3`, as numbers to compare.
+fn version(text: &str) -> Option<Vec<u64>> {
+    text.trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .take(3)
+        .map(|part| part.parse().ok())
+        .collect()
+}
+
+/// The release binary for this platform.
+fn asset_name() -> String {
+    format!(
+        "{}-{}-{}",
+        env!("CARGO_PKG_NAME"),
+        env::consts::ARCH,
+        env::consts::OS
+    )
+}
+
+/// Installs through cargo are updated through cargo.
+fn installed_by_cargo(exe: &Path) -> bool {
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+    cargo_home.is_some_and(|home| exe.starts_with(home.join("bin")))
+}
+
+pub fn run(check: bool) -> anyhow::Result<()> {
+    let url = env::var("CLI_TASK_MANAGER_RELEASES").unwrap_or_else(|_| RELEASES.to_owned());
+    let release: Release = serde_json::from_slice(&fetch(&url, None)?)
+        .with_context(|| format!("Malformed release from {}", url))?;
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = version(&release.tag_name)
+        .with_context(|| format!("Release {} does not name a version", release.tag_name))?;
+    if version(current).is_some_and(|current| latest <= current) {
+        println!("Already up to date ({}).", current);
+        return Ok(());
+    }
+    if check {
+        println!(
+            "Version {} is available (this is {}); run `self-update` to install it.",
+            release.tag_name, current
+        );
+        return Ok(());
+    }
+
+    let exe = env::current_exe().context("Failed to find this program's path")?;
+    let exe = fs::canonicalize(&exe).unwrap_or(exe);
+    if installed_by_cargo(&exe) {
+        bail!(
+            "{} was installed with cargo; update it with `cargo install cli_task_manager`",
+            exe.display()
+        );
+    }
+    let name = asset_name();
+    let find = |wanted: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == wanted)
+            .with_context(|| format!("Release {} has no {}", release.tag_name, wanted))
+    };
+    let (binary, sums) = (find(&name)?, find(SUMS)?);
+
+    let sums = String::from_utf8(fetch(&sums.browser_download_url, None)?)
+        .with_context(|| format!("{} is not text", SUMS))?;
+    let expected = sums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(sum, _)| sum.to_ascii_lowercase())
+        .with_context(|| format!("{} does not list {}", SUMS, name))?;
+
+    // Downloaded beside the program, so the rename below cannot cross
+    // file systems.
+    let download = exe.with_file_name(format!(".{}.download", name));
+    let result = fetch(&binary.browser_download_url, Some(&download)).and_then(|_| {
+        let data = fs::read(&download)
+            .with_context(|| format!("Failed to read {}", download.display()))?;
+        let actual = hex(&Sha256::digest(&data));
+        if actual != expected {
+            bail!(
+                "The download of {} does not match its checksum (expected {}, got {}); nothing was changed",
+                name,
+                expected,
+                actual
+            );
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&download, fs::Permissions::from_mode(0o755))
+                .with_context(|| format!("Failed to make {} executable", download.display()))?;
+        }
+        fs::rename(&download, &exe)
+            .with_context(|| format!("Failed to replace {}", exe.display()))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&download);
+    }
+    result?;
+    println!(
+        "Updated {} from {} to {}.",
+        exe.display(),
+        current,
+        release.tag_name
+    );
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
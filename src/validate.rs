@@ -0,0 +1,234 @@
+//! `validate`: checks a tasks file without changing it, for CI jobs and cron
+//! scripts. Each task is checked on its own, so one malformed task does not
+//! hide problems in the rest. The exit code says what was found:
+//!
+//! - 0: the file is valid (or does not exist yet)
+//! - 1: the file was read, but has problems, each printed on its own line
+//! - 2: the file could not be read or parsed at all; see `repair`
+
+use crate::{checksum, events, storage, task::Task};
+use anyhow::{Context, bail};
+use chrono::Utc;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+use uuid::Uuid;
+
+const VALID: i32 = 0;
+const PROBLEMS: i32 = 1;
+const UNREADABLE: i32 = 2;
+
+/// How a task is named in a problem: by id if it has a usable one, else by
+/// its position in the file.
+fn label(position: usize, value: &Value) -> String {
+    match value.get("id").and_then(Value::as_u64) {
+        Some(id) => format!("task {}", id),
+        None => format!("entry {} of the file", position + 1),
+    }
+}
+
+/// The field that keeps `value` from parsing as a task, found by leaving
+/// out each in turn, since serde's errors do not name it.
+fn culprit(value: &Value) -> Option<&str> {
+    let fields = value.as_object()?;
+    fields.keys().map(String::as_str).find(|key| {
+        let mut rest = fields.clone();
+        rest.remove(*key);
+        serde_json::from_value::<Task>(Value::Object(rest)).is_ok()
+    })
+}
+
+/// The problems with `tasks`, which all parsed.
+fn check(tasks: &[Task]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut ids: HashMap<u32, usize> = HashMap::new();
+    let mut uuids: HashMap<Uuid, u32> = HashMap::new();
+    for task in tasks {
+        *ids.entry(task.id).or_default() += 1;
+        if let Some(first) = uuids.insert(task.uuid, task.id) {
+            problems.push(format!(
+                "task {}: has the same uuid as task {} ({})",
+                task.id, first, task.uuid
+            ));
+        }
+    }
+    let mut duplicated: Vec<(&u32, &usize)> = ids.iter().filter(|(_, n)| **n > 1).collect();
+    duplicated.sort();
+    for (id, count) in duplicated {
+        problems.push(format!("task {}: the id is used by {} tasks", id, count));
+    }
+    if ids.contains_key(&0) {
+        problems.push("task 0: ids start at 1".to_owned());
+    }
+
+    let known: HashSet<Uuid> = tasks.iter().map(|t| t.uuid).collect();
+    let now = Utc::now();
+    for task in tasks {
+        let id = task.id;
+        match task.parent {
+            Some(parent) if parent == task.uuid => {
+                problems.push(format!("task {}: is its own parent", id))
+            }
+            Some(parent) if !known.contains(&parent) => {
+                problems.push(format!("task {}: parent {} does not exist", id, parent))
+            }
+            _ => {}
+        }
+        for dependency in &task.depends_on {
+            if *dependency == task.uuid {
+                problems.push(format!("task {}: depends on itself", id));
+            } else if !known.contains(dependency) {
+                problems.push(format!(
+                    "task {}: depends on {}, which does not exist",
+                    id, dependency
+                ));
+            }
+        }
+        let comments: HashSet<Uuid> = task.comments.iter().map(|c| c.id).collect();
+        for comment in &task.comments {
+            if comment.reply_to.is_some_and(|to| !comments.contains(&to)) {
+                problems.push(format!(
+                    "task {}: comment {} replies to one that does not exist",
+                    id, comment.id
+                ));
+            }
+        }
+
+        if let Some(at) = task.completed_at {
+            if !task.completed {
+                problems.push(format!("task {}: is open but has a completion time", id));
+            }
+            if at > now {
+                problems.push(format!("task {}: completed in the future ({})", id, at));
+            }
+        }
+        for (i, interval) in task.time_log.iter().enumerate() {
+            match interval.end {
+                Some(end) if end < interval.start => problems.push(format!(
+                    "task {}: time entry {} ends before it starts",
+                    id,
+                    i + 1
+                )),
+                None if i + 1 < task.time_log.len() => problems.push(format!(
+                    "task {}: time entry {} is still running but is not the last",
+                    id,
+                    i + 1
+                )),
+                _ => {}
+            }
+            if interval.start > now {
+                problems.push(format!(
+                    "task {}: time entry {} starts in the future",
+                    id,
+                    i + 1
+                ));
+            }
+        }
+        if let Some(at) = task.occurrences.iter().find(|at| **at > now) {
+            problems.push(format!(
+                "task {}: habit recorded in the future ({})",
+                id, at
+            ));
+        }
+    }
+    problems
+}
+
+/// What `inspect` found in a tasks file it could parse.
+pub struct Report {
+    /// The tasks that parsed.
+    pub tasks: Vec<Task>,
+    /// How many entries the file holds, parsed or not.
+    pub entries: usize,
+    pub problems: Vec<String>,
+    /// The checksum of the file after its first line, as `checksum::verify`
+    /// takes it.
+    pub sum: checksum::Sum,
+}
+
+/// Checks the tasks file at `path` without changing anything: `None` when
+/// it does not exist, an error when it cannot be read or parsed at all.
+pub fn inspect(path: &Path) -> anyhow::Result<Option<Report>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let (_, data) = checksum::split(&data);
+    let values: Vec<Value> = if data.first().copied().is_some_and(storage::is_msgpack) {
+        storage::decode(data)
+            .with_context(|| format!("{} is not valid MessagePack", path.display()))?
+    } else if data.first().copied().is_some_and(events::is_log) {
+        events::values(data)
+            .with_context(|| format!("{} is not a valid event log", path.display()))?
+            .0
+    } else if data.iter().all(u8::is_ascii_whitespace) {
+        Vec::new()
+    } else {
+        match serde_json::from_slice(data) {
+            Ok(Value::Array(values)) => values,
+            Ok(_) => bail!("{} does not hold a list of tasks", path.display()),
+            Err(err) => bail!(
+                "{} is not valid JSON ({}); run `repair` to salvage it",
+                path.display(),
+                err
+            ),
+        }
+    };
+
+    let mut problems = Vec::new();
+    let mut tasks = Vec::new();
+    for (position, value) in values.iter().enumerate() {
+        match serde_json::from_value::<Task>(value.clone()) {
+            Ok(task) => tasks.push(task),
+            Err(err) => problems.push(match culprit(value) {
+                Some(field) => format!("{}: invalid {}: {}", label(position, value), field, err),
+                None => format!("{}: {}", label(position, value), err),
+            }),
+        }
+    }
+    problems.extend(check(&tasks));
+    Ok(Some(Report {
+        tasks,
+        entries: values.len(),
+        problems,
+        sum: checksum::Sum::of(data),
+    }))
+}
+
+/// Checks the tasks file at `path`, printing each problem, and returns the
+/// exit code.
+pub fn run(path: &Path) -> i32 {
+    let report = match inspect(path) {
+        Ok(Some(report)) => report,
+        Ok(None) => {
+            println!("{} does not exist yet; nothing to check.", path.display());
+            return VALID;
+        }
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            return UNREADABLE;
+        }
+    };
+    if report.problems.is_empty() {
+        println!(
+            "{}: {} task(s), no problems.",
+            path.display(),
+            report.tasks.len()
+        );
+        return VALID;
+    }
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    println!(
+        "{}: {} problem(s) in {} task(s).",
+        path.display(),
+        report.problems.len(),
+        report.entries
+    );
+    PROBLEMS
+}
@@ -0,0 +1,187 @@
+//! Saved views: `list` arguments kept by name under `"views"` in
+//! config.json, so a common query is one short invocation:
+//!
+//! ```text
+//! view save urgent '--filter "priority:high status:open" --sort due'
+//! list --view urgent
+//! ```
+//!
+//! `export --view` uses them too, when they only hold options it shares
+//! with `list`, such as `--filter` and `--sort`.
+//!
+//! The arguments are split as a shell would, honoring quotes. Options given
+//! alongside `--view` are added to the view's, but one the view already
+//! sets, other than a repeatable one such as `--tag`, cannot be given again.
+
+use crate::config;
+use anyhow::{Context, bail};
+use serde_json::{Map, Value};
+use std::{ffi::OsString, path::Path};
+
+/// Splits `text` into words as a POSIX shell would, without expanding
+/// anything.
+pub fn split(text: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("Unclosed ' in {:?}", text),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => bail!("Unclosed \" in {:?}", text),
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("Unclosed \" in {:?}", text),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_default().push(c);
+                }
+            }
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn check_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("View names may only use letters, digits, - and _");
+    }
+    Ok(())
+}
+
+/// The `list` arguments view `name` stands for.
+pub fn lookup(config_dir: &Path, name: &str) -> anyhow::Result<Vec<String>> {
+    let views = config::load(config_dir)?.views;
+    let Some(args) = views.get(name) else {
+        bail!("No view named {} (save one with `view save`)", name);
+    };
+    split(args).with_context(|| format!("Invalid view {}", name))
+}
+
+/// `argv` with `--view name` replaced by the view's arguments.
+pub fn expand(argv: Vec<OsString>, name: &str, args: Vec<String>) -> Vec<OsString> {
+    let joined = OsString::from(format!("--view={}", name));
+    let mut expanded = Vec::with_capacity(argv.len() + args.len());
+    let mut argv = argv.into_iter().peekable();
+    let mut args = Some(args);
+    while let Some(arg) = argv.next() {
+        let is_view = args.is_some()
+            && (arg == joined || arg == "--view" && argv.next_if(|next| next == name).is_some());
+        match args.take_if(|_| is_view) {
+            Some(args) => expanded.extend(args.into_iter().map(OsString::from)),
+            None => expanded.push(arg),
+        }
+    }
+    expanded
+}
+
+/// Saves `args` as view `name`, replacing any view of that name.
+pub fn save(config_dir: &Path, name: &str, args: &str) -> anyhow::Result<()> {
+    check_name(name)?;
+    config::update(config_dir, |root| {
+        let views = root
+            .entry("views")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(views) = views {
+            views.insert(name.to_owned(), Value::String(args.to_owned()));
+        }
+    })?;
+    println!("Saved view {}: list {}", name, args);
+    Ok(())
+}
+
+pub fn remove(config_dir: &Path, name: &str) -> anyhow::Result<()> {
+    if !config::load(config_dir)?.views.contains_key(name) {
+        bail!("No view named {}", name);
+    }
+    config::update(config_dir, |root| {
+        if let Some(Value::Object(views)) = root.get_mut("views") {
+            views.remove(name);
+        }
+    })?;
+    println!("Removed view {}.", name);
+    Ok(())
+}
+
+pub fn print_views(config_dir: &Path) -> anyhow::Result<()> {
+    let views = config::load(config_dir)?.views;
+    if views.is_empty() {
+        println!("No saved views.");
+    }
+    for (name, args) in views {
+        println!("{}: list {}", name, args);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        split(text).unwrap()
+    }
+
+    #[test]
+    fn splits_on_unquoted_whitespace() {
+        assert_eq!(
+            words("  --filter  +work\t--sort due "),
+            ["--filter", "+work", "--sort", "due"]
+        );
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_words_together() {
+        assert_eq!(
+            words(r#"--filter "+work priority:high""#),
+            ["--filter", "+work priority:high"]
+        );
+        assert_eq!(words("'it''s' a\"b\"c"), ["its", "abc"]);
+        assert_eq!(words(r#"'' """#), ["", ""]);
+        assert_eq!(words(r#"'a "b" \c'"#), [r#"a "b" \c"#]);
+    }
+
+    #[test]
+    fn backslashes_escape() {
+        assert_eq!(words(r"a\ b \'c"), ["a b", "'c"]);
+        assert_eq!(words(r#""say \"hi\" \$HOME \n""#), [r#"say "hi" $HOME \n"#]);
+        assert_eq!(words(r"trailing\"), ["trailing"]);
+    }
+
+    #[test]
+    fn unclosed_quotes_are_errors() {
+        assert!(split("'open").is_err());
+        assert!(split(r#"say "hi"#).is_err());
+        assert!(split(r#""escaped end\""#).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Live-refreshing output for a spare terminal pane. The tasks file is
+//! polled for changes rather than watched through the OS, which also catches
+//! files replaced by a sync or edited on a network share.
+
+use chrono::Local;
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+const POLL: Duration = Duration::from_millis(250);
+
+/// The parts of the file's metadata that change on every save.
+pub fn signature(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Clears the screen and calls `render` whenever `path` changes, and at
+/// least every `interval` so relative dates and running timers stay fresh.
+/// Runs until interrupted.
+pub fn run(
+    path: &Path,
+    interval: Duration,
+    title: &str,
+    mut render: impl FnMut() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    loop {
+        let seen = signature(path);
+        print!("\x1b[2J\x1b[H");
+        println!(
+            "Every {}s or on change: {}    {}\n",
+            interval.as_secs(),
+            title,
+            Local::now().format("%H:%M:%S")
+        );
+        // A file caught halfway through a non-atomic write by another tool
+        // fails to parse; show why and try again on the next change.
+        if let Err(err) = render() {
+            println!("Error: {:#}", err);
+        }
+        io::stdout().flush()?;
+
+        let started = Instant::now();
+        while started.elapsed() < interval && signature(path) == seen {
+            thread::sleep(POLL);
+        }
+    }
+}